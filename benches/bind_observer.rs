@@ -0,0 +1,48 @@
+//! Decoding a Bind with one large text parameter, with and without the
+//! UTF-8 pass (`[server] validate_text_params`).
+//!
+//!     cargo bench --bench bind_observer
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use pgcrab::wire::observers::bind::BindFrameObserver;
+
+const PARAM_BYTES: usize = 1024 * 1024;
+const ITERATIONS: u32 = 2_000;
+
+fn main() {
+    let frame = bind_frame(&"é".repeat(PARAM_BYTES / 2));
+    measure("validated", &frame, true);
+    measure("unvalidated", &frame, false);
+}
+
+fn measure(mode: &str, frame: &[u8], validate: bool) {
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        let frame = black_box(frame);
+        let observer = if validate {
+            BindFrameObserver::new(frame)
+        } else {
+            BindFrameObserver::new_unvalidated(frame)
+        };
+        black_box(observer.expect("valid Bind").params_raw().count());
+    }
+    let per_frame = started.elapsed() / ITERATIONS;
+    println!("{mode:<12} {per_frame:>10.2?} per {PARAM_BYTES}-byte param");
+}
+
+/// Unnamed portal and statement, one text parameter, no result formats.
+fn bind_frame(value: &str) -> Vec<u8> {
+    let mut body = vec![0, 0];
+    body.extend_from_slice(&0u16.to_be_bytes());
+    body.extend_from_slice(&1u16.to_be_bytes());
+    body.extend_from_slice(&(value.len() as i32).to_be_bytes());
+    body.extend_from_slice(value.as_bytes());
+    body.extend_from_slice(&0u16.to_be_bytes());
+
+    let mut frame = vec![b'B'];
+    frame.extend_from_slice(&(4 + body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
@@ -0,0 +1,69 @@
+//! Reading the config and checking a password, as each new client does,
+//! with a cloned `Config::snapshot` and with a shared `Config::handle`.
+//!
+//!     cargo bench --bench config_handle
+
+use std::hint::black_box;
+use std::io::Write;
+use std::time::Instant;
+
+use pgcrab::Config;
+use pgcrab::config::types::{ListenAddr, LogFormat, LogLevel};
+use pgcrab::config::users::UsersConfig;
+use tempfile::NamedTempFile;
+
+const ITERATIONS: u32 = 200_000;
+
+const CONFIG: &str = r#"
+[server]
+retry_after_hint = "try again in a minute"
+
+[[users]]
+username = "app"
+password = "secret"
+
+[[shards]]
+name = "alpha"
+host = "127.0.0.1"
+port = 5432
+user = "postgres"
+password = "postgres"
+"#;
+
+fn main() {
+    let mut file = NamedTempFile::new().expect("temp config");
+    file.write_all(CONFIG.as_bytes()).expect("write config");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("runtime");
+    runtime.block_on(Config::init(
+        ListenAddr::Default("127.0.0.1:6432".parse().unwrap()),
+        LogLevel::Error,
+        LogFormat::Text,
+        16,
+        false,
+        file.path().to_path_buf(),
+    ));
+
+    measure("snapshot", || {
+        let config = Config::snapshot();
+        black_box(config.strict_parse);
+    });
+    measure("handle", || {
+        let config = Config::handle();
+        black_box(config.strict_parse);
+    });
+}
+
+fn measure(mode: &str, read_config: impl Fn()) {
+    let users = UsersConfig::handle();
+    let started = Instant::now();
+    for _ in 0..ITERATIONS {
+        read_config();
+        let user = users.authenticate(black_box("app"), black_box("secret"), "alpha");
+        black_box(user.expect("valid password"));
+    }
+    let per_auth = started.elapsed() / ITERATIONS;
+    println!("{mode:<10} {per_auth:>10.2?} per authentication");
+}
@@ -0,0 +1,43 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pgcrab::shared_types::AuthStage;
+use pgcrab::wire::observers::{
+    bind::BindFrameObserver, close::CloseFrameObserver, describe::DescribeFrameObserver,
+    execute::ExecuteFrameObserver, parse::ParseFrameObserver, query::QueryFrameObserver,
+    startup::StartupFrameObserver,
+};
+use pgcrab::wire::types::MessageType;
+use pgcrab::wire::utils::peek_frontend;
+
+// Frames the input the way a client inbox does, in every auth stage, and
+// decodes the frames the relay looks inside. Framing must always move
+// forward and stay in bounds; decoding may fail but must not panic.
+fuzz_target!(|data: &[u8]| {
+    for stage in [
+        AuthStage::Startup,
+        AuthStage::Authenticating,
+        AuthStage::Ready,
+    ] {
+        let mut rest = data;
+        while let Some(peek) = peek_frontend(stage, rest) {
+            assert!(peek.len > 0 && peek.len <= rest.len());
+            let (frame, tail) = rest.split_at(peek.len);
+            decode(peek.message_type, frame);
+            rest = tail;
+        }
+    }
+});
+
+fn decode(message_type: MessageType, frame: &[u8]) {
+    match message_type {
+        MessageType::Startup => drop(StartupFrameObserver::new(frame)),
+        MessageType::Query => drop(QueryFrameObserver::new(frame)),
+        MessageType::Parse => drop(ParseFrameObserver::new(frame)),
+        MessageType::Bind => drop(BindFrameObserver::new(frame)),
+        MessageType::Describe => drop(DescribeFrameObserver::new(frame)),
+        MessageType::Execute => drop(ExecuteFrameObserver::new(frame)),
+        MessageType::Close => drop(CloseFrameObserver::new(frame)),
+        _ => {}
+    }
+}
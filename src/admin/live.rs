@@ -0,0 +1,104 @@
+use serde_json::{Map, Value};
+#[cfg(feature = "live-stats")]
+use tokio_postgres::{NoTls, SimpleQueryMessage};
+
+// -----------------------------------------------------------------------------
+// ----- Live Stats ------------------------------------------------------------
+
+/// Where `pgcrab admin stats --connect` finds a running pgcrab, and the
+/// admin user it logs in as.
+#[derive(Debug, Clone)]
+pub struct LiveTarget {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: String,
+    pub dbname: String,
+}
+
+/// `SHOW PGCRAB ANALYTICS` from a running pgcrab, as `(metric, value)` rows.
+/// The counters live in the server process, so this is the only way to see
+/// them from outside it.
+#[cfg(feature = "live-stats")]
+pub async fn fetch_analytics(target: &LiveTarget) -> Result<Vec<(String, String)>, String> {
+    let (client, connection) = tokio_postgres::Config::new()
+        .host(&target.host)
+        .port(target.port)
+        .user(&target.user)
+        .password(&target.password)
+        .dbname(&target.dbname)
+        .connect(NoTls)
+        .await
+        .map_err(|e| format!("connect to {}:{} failed: {e}", target.host, target.port))?;
+    tokio::spawn(connection);
+
+    let messages = client
+        .simple_query("SHOW PGCRAB ANALYTICS")
+        .await
+        .map_err(|e| format!("SHOW PGCRAB ANALYTICS failed: {e}"))?;
+
+    let rows = messages
+        .iter()
+        .filter_map(|message| match message {
+            SimpleQueryMessage::Row(row) => Some(row),
+            _ => None,
+        })
+        .map(|row| {
+            let metric = row.get(0).unwrap_or_default().to_string();
+            let value = row.get(1).unwrap_or_default().to_string();
+            (metric, value)
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// `metric=value` lines, like `format_parse_cache_stats`.
+pub fn format_metrics(rows: &[(String, String)]) -> String {
+    rows.iter()
+        .map(|(metric, value)| format!("{metric}={value}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// One JSON object; counters are numbers.
+pub fn format_metrics_json(rows: &[(String, String)]) -> String {
+    let object: Map<String, Value> = rows
+        .iter()
+        .map(|(metric, value)| {
+            let value = match value.parse::<u64>() {
+                Ok(number) => Value::from(number),
+                Err(_) => Value::from(value.as_str()),
+            };
+            (metric.clone(), value)
+        })
+        .collect();
+    Value::Object(object).to_string()
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_rows_as_text_and_json() {
+        let rows = vec![
+            ("parse_cache_hits".to_string(), "12".to_string()),
+            ("parse_cache_misses".to_string(), "3".to_string()),
+        ];
+
+        assert_eq!(
+            format_metrics(&rows),
+            "parse_cache_hits=12\nparse_cache_misses=3"
+        );
+
+        let json: Value = serde_json::from_str(&format_metrics_json(&rows)).unwrap();
+        assert_eq!(json["parse_cache_hits"], 12);
+        assert_eq!(json["parse_cache_misses"], 3);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
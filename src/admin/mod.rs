@@ -1,23 +1,52 @@
 use bytes::{BufMut, Bytes, BytesMut};
+use serde::Serialize;
 
+use std::time::SystemTime;
+
+use crate::ErrorResponse;
 use crate::analytics;
+use crate::frontend::client_registry;
 use crate::frontend::context::FrontendContext;
 use crate::gateway::GatewayPools;
 use crate::parser;
 use crate::shared_types::AuthStage;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+mod live;
+
+#[cfg(feature = "live-stats")]
+pub use live::fetch_analytics;
+pub use live::{LiveTarget, format_metrics, format_metrics_json};
+
+/// Serialized with the same keys as the text format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct CacheStats {
+    #[serde(rename = "parse_cache_hits")]
     pub hits: u64,
+    #[serde(rename = "parse_cache_misses")]
     pub misses: u64,
-    pub evictions: u64,
+    #[serde(rename = "parse_cache_evictions_capacity")]
+    pub evictions_capacity: u64,
+    #[serde(rename = "parse_cache_evictions_manual")]
+    pub evictions_manual: u64,
+    #[serde(rename = "parse_cache_size")]
     pub len: usize,
+    #[serde(rename = "parse_cache_capacity")]
     pub capacity: usize,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AdminCommand {
+    ClearParseCache,
+    /// `PAUSE PGCRAB POOL <name>`: no new checkouts from the shard.
+    DrainPool {
+        name: String,
+    },
+    /// `RESUME PGCRAB POOL <name>`.
+    ResumePool {
+        name: String,
+    },
     ShowAnalytics,
+    ShowClients,
     ShowPools,
     ShowSession,
 }
@@ -29,7 +58,8 @@ pub fn parse_cache_stats() -> CacheStats {
     CacheStats {
         hits: counters.hits,
         misses: counters.misses,
-        evictions: counters.evictions,
+        evictions_capacity: counters.evictions_capacity,
+        evictions_manual: counters.evictions_manual,
         len: cache.len,
         capacity: cache.capacity,
     }
@@ -37,21 +67,39 @@ pub fn parse_cache_stats() -> CacheStats {
 
 pub fn format_parse_cache_stats(stats: CacheStats) -> String {
     format!(
-        "parse_cache_hits={}\nparse_cache_misses={}\nparse_cache_evictions={}\nparse_cache_size={}\nparse_cache_capacity={}",
-        stats.hits, stats.misses, stats.evictions, stats.len, stats.capacity
+        "parse_cache_hits={}\nparse_cache_misses={}\nparse_cache_evictions_capacity={}\nparse_cache_evictions_manual={}\nparse_cache_size={}\nparse_cache_capacity={}",
+        stats.hits,
+        stats.misses,
+        stats.evictions_capacity,
+        stats.evictions_manual,
+        stats.len,
+        stats.capacity
     )
 }
 
+/// One JSON object, for scripts; `format_parse_cache_stats` is for people.
+pub fn format_parse_cache_stats_json(stats: CacheStats) -> String {
+    serde_json::to_string(&stats).expect("cache stats serialize to JSON")
+}
+
 pub fn parse_admin_command(query: &str) -> Option<AdminCommand> {
     let mut trimmed = query.trim();
     if let Some(without_semicolon) = trimmed.strip_suffix(';') {
         trimmed = without_semicolon.trim();
     }
 
+    if trimmed.eq_ignore_ascii_case("CLEAR PGCRAB PARSE CACHE") {
+        return Some(AdminCommand::ClearParseCache);
+    }
+
     if trimmed.eq_ignore_ascii_case("SHOW PGCRAB ANALYTICS") {
         return Some(AdminCommand::ShowAnalytics);
     }
 
+    if trimmed.eq_ignore_ascii_case("SHOW PGCRAB CLIENTS") {
+        return Some(AdminCommand::ShowClients);
+    }
+
     if trimmed.eq_ignore_ascii_case("SHOW PGCRAB POOLS") {
         return Some(AdminCommand::ShowPools);
     }
@@ -60,29 +108,99 @@ pub fn parse_admin_command(query: &str) -> Option<AdminCommand> {
         return Some(AdminCommand::ShowSession);
     }
 
+    if let Some(name) = strip_prefix_ignore_case(trimmed, "PAUSE PGCRAB POOL ") {
+        return Some(AdminCommand::DrainPool {
+            name: name.trim().to_string(),
+        });
+    }
+
+    if let Some(name) = strip_prefix_ignore_case(trimmed, "RESUME PGCRAB POOL ") {
+        return Some(AdminCommand::ResumePool {
+            name: name.trim().to_string(),
+        });
+    }
+
     None
 }
 
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = value.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &value[prefix.len()..])
+}
+
 pub(crate) async fn command_responses(
     command: AdminCommand,
     context: &FrontendContext,
     pools: &GatewayPools,
 ) -> Vec<Bytes> {
     match command {
+        AdminCommand::ClearParseCache => clear_parse_cache_responses(),
+        AdminCommand::DrainPool { name } => pause_pool_responses(pools, &name, true),
+        AdminCommand::ResumePool { name } => pause_pool_responses(pools, &name, false),
         AdminCommand::ShowAnalytics => analytics_responses(),
+        AdminCommand::ShowClients => clients_responses(),
         AdminCommand::ShowPools => pools_responses(pools).await,
         AdminCommand::ShowSession => session_responses(context),
     }
 }
 
+/// Answers like a utility command: a lone `CLEAR <n>` tag.
+fn clear_parse_cache_responses() -> Vec<Bytes> {
+    let dropped = parser::clear_cache();
+    vec![command_complete(&format!("CLEAR {dropped}"))]
+}
+
+/// Applies to every pool on the shard, whatever role it logs in as.
+/// Answers with a `PAUSE` or `RESUME` tag, or 42704 for an unknown pool.
+fn pause_pool_responses(pools: &GatewayPools, name: &str, pause: bool) -> Vec<Bytes> {
+    let shard_pools = pools.shard_pools(name);
+    if shard_pools.is_empty() {
+        let error = ErrorResponse::undefined_object(format!("pool \"{name}\" does not exist"));
+        return vec![error.to_bytes()];
+    }
+
+    for pool in &shard_pools {
+        if pause {
+            pool.pause();
+        } else {
+            pool.resume();
+        }
+    }
+
+    let tag = if pause { "PAUSE" } else { "RESUME" };
+    vec![command_complete(tag)]
+}
+
 fn analytics_responses() -> Vec<Bytes> {
     let stats = parse_cache_stats();
+    let bytes = analytics::bytes_snapshot();
+    let queries = analytics::query_counts_snapshot();
+    let transactions = analytics::transaction_counts_snapshot();
     let rows = [
         ("parse_cache_hits", stats.hits.to_string()),
         ("parse_cache_misses", stats.misses.to_string()),
-        ("parse_cache_evictions", stats.evictions.to_string()),
+        (
+            "parse_cache_evictions_capacity",
+            stats.evictions_capacity.to_string(),
+        ),
+        (
+            "parse_cache_evictions_manual",
+            stats.evictions_manual.to_string(),
+        ),
         ("parse_cache_size", stats.len.to_string()),
         ("parse_cache_capacity", stats.capacity.to_string()),
+        ("client_bytes_in", bytes.client_bytes_in.to_string()),
+        ("client_bytes_out", bytes.client_bytes_out.to_string()),
+        ("backend_bytes_in", bytes.backend_bytes_in.to_string()),
+        ("backend_bytes_out", bytes.backend_bytes_out.to_string()),
+        ("queries_select", queries.select.to_string()),
+        ("queries_insert", queries.insert.to_string()),
+        ("queries_update", queries.update.to_string()),
+        ("queries_delete", queries.delete.to_string()),
+        ("queries_other", queries.other.to_string()),
+        ("transactions_started", transactions.started.to_string()),
+        ("transactions_failed", transactions.failed.to_string()),
     ];
 
     let mut responses = Vec::with_capacity(2 + rows.len());
@@ -94,11 +212,48 @@ fn analytics_responses() -> Vec<Bytes> {
     responses
 }
 
+fn clients_responses() -> Vec<Bytes> {
+    let clients = client_registry::clients_snapshot();
+    let row_count = clients.len();
+    let columns = [
+        "id",
+        "peer",
+        "username",
+        "database",
+        "pool",
+        "connected_at",
+        "last_activity",
+    ];
+
+    let mut responses = Vec::with_capacity(2 + clients.len());
+    responses.push(row_description(&columns));
+    for client in clients {
+        let id = format!("{:016x}", client.id);
+        let peer = client
+            .peer
+            .map_or_else(|| "none".to_string(), |peer| peer.to_string());
+        let connected_at = timestamp(client.connected_at);
+        let last_activity = timestamp(client.last_activity);
+        responses.push(data_row(&[
+            &id,
+            &peer,
+            client.username.as_deref().unwrap_or("none"),
+            client.database.as_deref().unwrap_or("none"),
+            client.pool.as_deref().unwrap_or("none"),
+            &connected_at,
+            &last_activity,
+        ]));
+    }
+    responses.push(command_complete(&format!("SELECT {}", row_count)));
+    responses
+}
+
 async fn pools_responses(pools: &GatewayPools) -> Vec<Bytes> {
     let stats = pools.snapshot().await;
     let row_count = stats.len();
     let columns = [
         "name",
+        "user",
         "host",
         "port",
         "min",
@@ -106,6 +261,13 @@ async fn pools_responses(pools: &GatewayPools) -> Vec<Bytes> {
         "idle",
         "in_use",
         "available",
+        "created",
+        "closed",
+        "paused",
+        "waiters",
+        "healthy",
+        "avg_latency_micros",
+        "error_rate",
     ];
 
     let mut responses = Vec::with_capacity(2 + stats.len());
@@ -117,8 +279,16 @@ async fn pools_responses(pools: &GatewayPools) -> Vec<Bytes> {
         let idle = stat.idle.to_string();
         let in_use = stat.in_use.to_string();
         let available = stat.available.to_string();
+        let created = stat.created.to_string();
+        let closed = stat.closed.to_string();
+        let paused = stat.paused.to_string();
+        let waiters = stat.waiters.to_string();
+        let healthy = stat.healthy.to_string();
+        let avg_latency_micros = stat.avg_latency_micros.to_string();
+        let error_rate = format!("{:.3}", stat.error_rate);
         responses.push(data_row(&[
             stat.name.as_str(),
+            stat.user.as_str(),
             stat.host.as_str(),
             &port,
             &min,
@@ -126,6 +296,13 @@ async fn pools_responses(pools: &GatewayPools) -> Vec<Bytes> {
             &idle,
             &in_use,
             &available,
+            &created,
+            &closed,
+            &paused,
+            &waiters,
+            &healthy,
+            &avg_latency_micros,
+            &error_rate,
         ]));
     }
     responses.push(command_complete(&format!("SELECT {}", row_count)));
@@ -135,6 +312,8 @@ async fn pools_responses(pools: &GatewayPools) -> Vec<Bytes> {
 fn session_responses(context: &FrontendContext) -> Vec<Bytes> {
     let stage = auth_stage_label(context.stage);
     let is_admin = context.is_admin.to_string();
+    let username = context.username.as_deref().unwrap_or("none");
+    let database = context.database.as_deref().unwrap_or("none");
     let gateway_session = if context.gateway_session.is_some() {
         "connected"
     } else {
@@ -143,19 +322,54 @@ fn session_responses(context: &FrontendContext) -> Vec<Bytes> {
     let pool = context.current_pool.as_deref().unwrap_or("none");
     let backend_pid = context.backend_identity.process_id.to_string();
     let backend_key = context.backend_identity.secret_key.to_string();
-
-    let mut responses = Vec::with_capacity(2 + 6);
+    let real_identity = context
+        .gateway_session
+        .as_ref()
+        .and_then(|session| session.backend_identity());
+    let (real_pid, real_key) = match real_identity {
+        Some(identity) => (
+            identity.process_id.to_string(),
+            identity.secret_key.to_string(),
+        ),
+        None => ("none".to_string(), "none".to_string()),
+    };
+    let traffic = context.traffic;
+    let client_bytes_in = traffic.client_bytes_in.to_string();
+    let client_bytes_out = traffic.client_bytes_out.to_string();
+    let backend_bytes_in = traffic.backend_bytes_in.to_string();
+    let backend_bytes_out = traffic.backend_bytes_out.to_string();
+    let clients = analytics::clients_snapshot();
+    let current_clients = clients.current.to_string();
+    let max_clients = clients
+        .max
+        .map_or_else(|| "unlimited".to_string(), |max| max.to_string());
+
+    let mut responses = Vec::with_capacity(2 + 16);
     responses.push(row_description(&["field", "value"]));
     responses.push(data_row(&["auth_stage", stage]));
     responses.push(data_row(&["is_admin", &is_admin]));
+    responses.push(data_row(&["username", username]));
+    responses.push(data_row(&["database", database]));
     responses.push(data_row(&["gateway_session", gateway_session]));
     responses.push(data_row(&["pool", pool]));
     responses.push(data_row(&["backend_identity_pid", &backend_pid]));
     responses.push(data_row(&["backend_identity_key", &backend_key]));
-    responses.push(command_complete("SELECT 6"));
+    responses.push(data_row(&["backend_real_pid", &real_pid]));
+    responses.push(data_row(&["backend_real_key", &real_key]));
+    responses.push(data_row(&["client_bytes_in", &client_bytes_in]));
+    responses.push(data_row(&["client_bytes_out", &client_bytes_out]));
+    responses.push(data_row(&["backend_bytes_in", &backend_bytes_in]));
+    responses.push(data_row(&["backend_bytes_out", &backend_bytes_out]));
+    responses.push(data_row(&["current_clients", &current_clients]));
+    responses.push(data_row(&["max_clients", &max_clients]));
+    responses.push(command_complete("SELECT 16"));
     responses
 }
 
+fn timestamp(at: SystemTime) -> String {
+    humantime::format_rfc3339_millis(at).to_string()
+}
+
 fn auth_stage_label(stage: AuthStage) -> &'static str {
     match stage {
         AuthStage::Startup => "startup",
@@ -219,12 +433,47 @@ mod tests {
     use bytes::Bytes;
     use secrecy::SecretString;
 
+    #[test]
+    fn parses_clear_parse_cache_command() {
+        let cmd = parse_admin_command("clear pgcrab parse cache;");
+        assert_eq!(cmd, Some(AdminCommand::ClearParseCache));
+    }
+
     #[test]
     fn parses_show_analytics_command() {
         let cmd = parse_admin_command("SHOW PGCRAB ANALYTICS;");
         assert_eq!(cmd, Some(AdminCommand::ShowAnalytics));
     }
 
+    #[test]
+    fn parse_cache_stats_json_uses_the_text_keys() {
+        let stats = CacheStats {
+            hits: 3,
+            misses: 1,
+            evictions_capacity: 0,
+            evictions_manual: 2,
+            len: 1,
+            capacity: 16,
+        };
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_parse_cache_stats_json(stats)).unwrap();
+        assert_eq!(json["parse_cache_hits"], 3);
+        assert_eq!(json["parse_cache_evictions_manual"], 2);
+        assert_eq!(json["parse_cache_capacity"], 16);
+
+        let text = format_parse_cache_stats(stats);
+        for key in json.as_object().unwrap().keys() {
+            assert!(text.contains(&format!("{key}=")), "{key}");
+        }
+    }
+
+    #[test]
+    fn parses_show_clients_command() {
+        let cmd = parse_admin_command("show pgcrab clients;");
+        assert_eq!(cmd, Some(AdminCommand::ShowClients));
+    }
+
     #[test]
     fn parses_show_pools_command() {
         let cmd = parse_admin_command("show pgcrab pools");
@@ -237,17 +486,33 @@ mod tests {
         assert_eq!(cmd, Some(AdminCommand::ShowSession));
     }
 
-    #[tokio::test]
-    async fn builds_show_pools_response() {
-        let pools = GatewayPools::new(vec![ShardRecord {
+    #[test]
+    fn parses_pause_and_resume_pool_commands() {
+        let cmd = parse_admin_command("pause pgcrab pool alpha;");
+        let name = "alpha".to_string();
+        assert_eq!(cmd, Some(AdminCommand::DrainPool { name: name.clone() }));
+
+        let cmd = parse_admin_command("RESUME PGCRAB POOL alpha");
+        assert_eq!(cmd, Some(AdminCommand::ResumePool { name }));
+
+        assert_eq!(parse_admin_command("PAUSE PGCRAB POOL"), None);
+    }
+
+    fn alpha_pools() -> GatewayPools {
+        GatewayPools::new(vec![ShardRecord {
             shard_name: "alpha".to_string(),
             host: "127.0.0.1".to_string(),
-            port: 5432,
             user: "user".to_string(),
             password: SecretString::new("secret".to_string().into_boxed_str()),
             min_connections: 1,
             max_connections: 2,
-        }]);
+            ..ShardRecord::default()
+        }])
+    }
+
+    #[tokio::test]
+    async fn builds_show_pools_response() {
+        let pools = alpha_pools();
         let context = FrontendContext::new();
         let responses = command_responses(AdminCommand::ShowPools, &context, &pools).await;
 
@@ -255,6 +520,7 @@ mod tests {
         assert_eq!(responses[0][0], b'T');
         for column in [
             "name",
+            "user",
             "host",
             "port",
             "min",
@@ -262,6 +528,13 @@ mod tests {
             "idle",
             "in_use",
             "available",
+            "created",
+            "closed",
+            "paused",
+            "waiters",
+            "healthy",
+            "avg_latency_micros",
+            "error_rate",
         ] {
             assert!(contains_bytes(&responses[0], column.as_bytes()));
         }
@@ -270,12 +543,42 @@ mod tests {
         assert!(contains_bytes(&responses[2], b"SELECT 1"));
     }
 
+    #[tokio::test]
+    async fn pause_and_resume_toggle_the_pool() {
+        let pools = alpha_pools();
+        let context = FrontendContext::new();
+        let name = "alpha".to_string();
+
+        let pause = AdminCommand::DrainPool { name: name.clone() };
+        let responses = command_responses(pause, &context, &pools).await;
+        assert_eq!(responses.len(), 1);
+        assert!(contains_bytes(&responses[0], b"PAUSE"));
+        assert!(pools.get("alpha").unwrap().is_paused());
+
+        let responses = command_responses(AdminCommand::ShowPools, &context, &pools).await;
+        assert!(contains_bytes(&responses[1], b"true"));
+
+        let resume = AdminCommand::ResumePool { name };
+        let responses = command_responses(resume, &context, &pools).await;
+        assert!(contains_bytes(&responses[0], b"RESUME"));
+        assert!(!pools.get("alpha").unwrap().is_paused());
+
+        let missing = AdminCommand::DrainPool {
+            name: "omega".to_string(),
+        };
+        let responses = command_responses(missing, &context, &pools).await;
+        assert_eq!(responses[0][0], b'E');
+        assert!(contains_bytes(&responses[0], b"42704"));
+    }
+
     #[tokio::test]
     async fn builds_show_session_response() {
         let pools = GatewayPools::new(Vec::new());
         let mut context = FrontendContext::new();
         context.stage = AuthStage::Ready;
         context.is_admin = true;
+        context.username = Some("krabs".to_string());
+        context.database = Some("bikini_bottom".to_string());
         context.current_pool = Some("alpha".to_string());
         context.backend_identity = BackendIdentity {
             process_id: 10,
@@ -284,21 +587,71 @@ mod tests {
 
         let responses = command_responses(AdminCommand::ShowSession, &context, &pools).await;
 
-        assert_eq!(responses.len(), 8);
+        assert_eq!(responses.len(), 18);
         assert_eq!(responses[0][0], b'T');
         assert!(contains_bytes(&responses[1], b"auth_stage"));
         assert!(contains_bytes(&responses[1], b"ready"));
         assert!(contains_bytes(&responses[2], b"is_admin"));
         assert!(contains_bytes(&responses[2], b"true"));
-        assert!(contains_bytes(&responses[3], b"gateway_session"));
-        assert!(contains_bytes(&responses[3], b"none"));
-        assert!(contains_bytes(&responses[4], b"pool"));
-        assert!(contains_bytes(&responses[4], b"alpha"));
-        assert!(contains_bytes(&responses[5], b"backend_identity_pid"));
-        assert!(contains_bytes(&responses[5], b"10"));
-        assert!(contains_bytes(&responses[6], b"backend_identity_key"));
-        assert!(contains_bytes(&responses[6], b"20"));
-        assert!(contains_bytes(&responses[7], b"SELECT 6"));
+        assert!(contains_bytes(&responses[3], b"username"));
+        assert!(contains_bytes(&responses[3], b"krabs"));
+        assert!(contains_bytes(&responses[4], b"database"));
+        assert!(contains_bytes(&responses[4], b"bikini_bottom"));
+        assert!(contains_bytes(&responses[5], b"gateway_session"));
+        assert!(contains_bytes(&responses[5], b"none"));
+        assert!(contains_bytes(&responses[6], b"pool"));
+        assert!(contains_bytes(&responses[6], b"alpha"));
+        assert!(contains_bytes(&responses[7], b"backend_identity_pid"));
+        assert!(contains_bytes(&responses[7], b"10"));
+        assert!(contains_bytes(&responses[8], b"backend_identity_key"));
+        assert!(contains_bytes(&responses[8], b"20"));
+        assert!(contains_bytes(&responses[9], b"backend_real_pid"));
+        assert!(contains_bytes(&responses[9], b"none"));
+        assert!(contains_bytes(&responses[10], b"backend_real_key"));
+        assert!(contains_bytes(&responses[11], b"client_bytes_in"));
+        assert!(contains_bytes(&responses[14], b"backend_bytes_out"));
+        assert!(contains_bytes(&responses[15], b"current_clients"));
+        assert!(contains_bytes(&responses[16], b"max_clients"));
+        assert!(contains_bytes(&responses[17], b"SELECT 16"));
+    }
+
+    #[tokio::test]
+    async fn show_clients_lists_live_connections() {
+        use crate::frontend::client_registry::ClientRegistration;
+
+        let pools = GatewayPools::new(Vec::new());
+        let context = FrontendContext::new();
+        let (first_id, second_id): (u64, u64) = (rand::random(), rand::random());
+        let first = ClientRegistration::register(first_id, None);
+        let second = ClientRegistration::register(second_id, None);
+
+        let mut session = FrontendContext::new();
+        session.username = Some("krabs".to_string());
+        session.database = Some("bikini_bottom".to_string());
+        first.update(&session);
+
+        let listed = |responses: &[Bytes], id: u64| {
+            let id = format!("{id:016x}");
+            responses[1..responses.len() - 1]
+                .iter()
+                .any(|row| contains_bytes(row, id.as_bytes()))
+        };
+
+        let responses = command_responses(AdminCommand::ShowClients, &context, &pools).await;
+        assert!(contains_bytes(&responses[0], b"last_activity"));
+        assert!(listed(&responses, first_id));
+        assert!(listed(&responses, second_id));
+        assert!(
+            responses
+                .iter()
+                .any(|row| contains_bytes(row, b"bikini_bottom"))
+        );
+
+        drop(second);
+        let responses = command_responses(AdminCommand::ShowClients, &context, &pools).await;
+        assert!(listed(&responses, first_id));
+        assert!(!listed(&responses, second_id));
+        drop(first);
     }
 
     fn contains_bytes(haystack: &Bytes, needle: &[u8]) -> bool {
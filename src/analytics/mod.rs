@@ -1,15 +1,83 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use crate::parser::StatementType;
+use crate::shared_types::ReadyStatus;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ParseCacheStats {
     pub hits: u64,
     pub misses: u64,
-    pub evictions: u64,
+    /// Entries pushed out by the LRU to make room.
+    pub evictions_capacity: u64,
+    /// Entries dropped by an explicit cache clear.
+    pub evictions_manual: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientCounts {
+    pub current: u64,
+    pub max: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ByteCounters {
+    pub client_bytes_in: u64,
+    pub client_bytes_out: u64,
+    pub backend_bytes_in: u64,
+    pub backend_bytes_out: u64,
+}
+
+/// Rows reported by backend CommandComplete tags, by statement type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AffectedRows {
+    pub select: u64,
+    pub insert: u64,
+    pub update: u64,
+    pub delete: u64,
+    pub other: u64,
+}
+
+/// Queries and Parses seen from clients, by statement type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryCounts {
+    pub select: u64,
+    pub insert: u64,
+    pub update: u64,
+    pub delete: u64,
+    pub other: u64,
+}
+
+/// Transactions seen through backend ReadyForQuery status changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransactionCounts {
+    pub started: u64,
+    /// Reached the failed (`E`) state; these end in a rollback.
+    pub failed: u64,
 }
 
 static PARSE_CACHE_HIT: AtomicU64 = AtomicU64::new(0);
 static PARSE_CACHE_MISS: AtomicU64 = AtomicU64::new(0);
-static PARSE_CACHE_EVICTION: AtomicU64 = AtomicU64::new(0);
+static PARSE_CACHE_EVICTION_CAPACITY: AtomicU64 = AtomicU64::new(0);
+static PARSE_CACHE_EVICTION_MANUAL: AtomicU64 = AtomicU64::new(0);
+static CLIENT_BYTES_IN: AtomicU64 = AtomicU64::new(0);
+static CLIENT_BYTES_OUT: AtomicU64 = AtomicU64::new(0);
+static BACKEND_BYTES_IN: AtomicU64 = AtomicU64::new(0);
+static BACKEND_BYTES_OUT: AtomicU64 = AtomicU64::new(0);
+static ROWS_SELECT: AtomicU64 = AtomicU64::new(0);
+static ROWS_INSERT: AtomicU64 = AtomicU64::new(0);
+static ROWS_UPDATE: AtomicU64 = AtomicU64::new(0);
+static ROWS_DELETE: AtomicU64 = AtomicU64::new(0);
+static ROWS_OTHER: AtomicU64 = AtomicU64::new(0);
+static QUERIES_SELECT: AtomicU64 = AtomicU64::new(0);
+static QUERIES_INSERT: AtomicU64 = AtomicU64::new(0);
+static QUERIES_UPDATE: AtomicU64 = AtomicU64::new(0);
+static QUERIES_DELETE: AtomicU64 = AtomicU64::new(0);
+static QUERIES_OTHER: AtomicU64 = AtomicU64::new(0);
+static TRANSACTIONS_STARTED: AtomicU64 = AtomicU64::new(0);
+static TRANSACTIONS_FAILED: AtomicU64 = AtomicU64::new(0);
+static CURRENT_CLIENTS: AtomicU64 = AtomicU64::new(0);
+/// Zero means no `max_clients` limit.
+static MAX_CLIENTS: AtomicU64 = AtomicU64::new(0);
 
 pub fn inc_parse_cache_hit() {
     PARSE_CACHE_HIT.fetch_add(1, Ordering::Relaxed);
@@ -20,14 +88,124 @@ pub fn inc_parse_cache_miss() {
 }
 
 pub fn inc_parse_cache_eviction() {
-    PARSE_CACHE_EVICTION.fetch_add(1, Ordering::Relaxed);
+    PARSE_CACHE_EVICTION_CAPACITY.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn add_parse_cache_manual_evictions(n: usize) {
+    PARSE_CACHE_EVICTION_MANUAL.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+pub fn add_client_bytes_in(n: usize) {
+    CLIENT_BYTES_IN.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+pub fn add_client_bytes_out(n: usize) {
+    CLIENT_BYTES_OUT.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+pub fn add_backend_bytes_in(n: usize) {
+    BACKEND_BYTES_IN.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+pub fn add_backend_bytes_out(n: usize) {
+    BACKEND_BYTES_OUT.fetch_add(n as u64, Ordering::Relaxed);
+}
+
+pub fn add_affected_rows(statement_type: StatementType, n: u64) {
+    let counter = match statement_type {
+        StatementType::Select => &ROWS_SELECT,
+        StatementType::Insert => &ROWS_INSERT,
+        StatementType::Update => &ROWS_UPDATE,
+        StatementType::Delete => &ROWS_DELETE,
+        StatementType::Other => &ROWS_OTHER,
+    };
+    counter.fetch_add(n, Ordering::Relaxed);
+}
+
+pub fn inc_query(statement_type: StatementType) {
+    let counter = match statement_type {
+        StatementType::Select => &QUERIES_SELECT,
+        StatementType::Insert => &QUERIES_INSERT,
+        StatementType::Update => &QUERIES_UPDATE,
+        StatementType::Delete => &QUERIES_DELETE,
+        StatementType::Other => &QUERIES_OTHER,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// One ReadyForQuery status change on a client's backend: leaving `I`
+/// starts a transaction, reaching `E` fails one. `I` straight to `E` is both.
+pub fn record_ready_transition(from: ReadyStatus, to: ReadyStatus) {
+    if from == ReadyStatus::Idle && to.in_transaction() {
+        TRANSACTIONS_STARTED.fetch_add(1, Ordering::Relaxed);
+    }
+    if from != ReadyStatus::FailedTransaction && to == ReadyStatus::FailedTransaction {
+        TRANSACTIONS_FAILED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+pub fn client_connected() {
+    CURRENT_CLIENTS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn client_disconnected() {
+    CURRENT_CLIENTS.fetch_sub(1, Ordering::Relaxed);
+}
+
+pub fn set_max_clients(max: Option<usize>) {
+    MAX_CLIENTS.store(max.map_or(0, |max| max as u64), Ordering::Relaxed);
+}
+
+pub fn clients_snapshot() -> ClientCounts {
+    let max = MAX_CLIENTS.load(Ordering::Relaxed);
+    ClientCounts {
+        current: CURRENT_CLIENTS.load(Ordering::Relaxed),
+        max: (max > 0).then_some(max),
+    }
+}
+
+pub fn bytes_snapshot() -> ByteCounters {
+    ByteCounters {
+        client_bytes_in: CLIENT_BYTES_IN.load(Ordering::Relaxed),
+        client_bytes_out: CLIENT_BYTES_OUT.load(Ordering::Relaxed),
+        backend_bytes_in: BACKEND_BYTES_IN.load(Ordering::Relaxed),
+        backend_bytes_out: BACKEND_BYTES_OUT.load(Ordering::Relaxed),
+    }
+}
+
+pub fn affected_rows_snapshot() -> AffectedRows {
+    AffectedRows {
+        select: ROWS_SELECT.load(Ordering::Relaxed),
+        insert: ROWS_INSERT.load(Ordering::Relaxed),
+        update: ROWS_UPDATE.load(Ordering::Relaxed),
+        delete: ROWS_DELETE.load(Ordering::Relaxed),
+        other: ROWS_OTHER.load(Ordering::Relaxed),
+    }
+}
+
+pub fn query_counts_snapshot() -> QueryCounts {
+    QueryCounts {
+        select: QUERIES_SELECT.load(Ordering::Relaxed),
+        insert: QUERIES_INSERT.load(Ordering::Relaxed),
+        update: QUERIES_UPDATE.load(Ordering::Relaxed),
+        delete: QUERIES_DELETE.load(Ordering::Relaxed),
+        other: QUERIES_OTHER.load(Ordering::Relaxed),
+    }
+}
+
+pub fn transaction_counts_snapshot() -> TransactionCounts {
+    TransactionCounts {
+        started: TRANSACTIONS_STARTED.load(Ordering::Relaxed),
+        failed: TRANSACTIONS_FAILED.load(Ordering::Relaxed),
+    }
 }
 
 pub fn snapshot() -> ParseCacheStats {
     ParseCacheStats {
         hits: PARSE_CACHE_HIT.load(Ordering::Relaxed),
         misses: PARSE_CACHE_MISS.load(Ordering::Relaxed),
-        evictions: PARSE_CACHE_EVICTION.load(Ordering::Relaxed),
+        evictions_capacity: PARSE_CACHE_EVICTION_CAPACITY.load(Ordering::Relaxed),
+        evictions_manual: PARSE_CACHE_EVICTION_MANUAL.load(Ordering::Relaxed),
     }
 }
 
@@ -35,7 +213,7 @@ pub fn snapshot() -> ParseCacheStats {
 pub(crate) fn reset_parse_cache_counts() {
     PARSE_CACHE_HIT.store(0, Ordering::Relaxed);
     PARSE_CACHE_MISS.store(0, Ordering::Relaxed);
-    PARSE_CACHE_EVICTION.store(0, Ordering::Relaxed);
+    PARSE_CACHE_EVICTION_CAPACITY.store(0, Ordering::Relaxed);
 }
 
 #[cfg(test)]
@@ -52,6 +230,72 @@ mod tests {
         let stats = snapshot();
         assert_eq!(stats.hits, 1);
         assert_eq!(stats.misses, 2);
-        assert_eq!(stats.evictions, 1);
+        assert_eq!(stats.evictions_capacity, 1);
+    }
+
+    #[test]
+    fn affected_rows_are_split_by_statement_type() {
+        let before = affected_rows_snapshot();
+        add_affected_rows(StatementType::Insert, 5);
+        add_affected_rows(StatementType::Update, 3);
+        add_affected_rows(StatementType::Other, 7);
+        let after = affected_rows_snapshot();
+        assert!(after.insert >= before.insert + 5);
+        assert!(after.update >= before.update + 3);
+        assert!(after.other >= before.other + 7);
+    }
+
+    #[test]
+    fn failed_transaction_is_counted_once() {
+        use ReadyStatus::{FailedTransaction, Idle, InTransaction};
+
+        let before = transaction_counts_snapshot();
+        let statuses = [Idle, InTransaction, InTransaction, FailedTransaction];
+        for pair in statuses.windows(2) {
+            record_ready_transition(pair[0], pair[1]);
+        }
+        // More statements in the failed block, then its ROLLBACK.
+        for (from, to) in [
+            (FailedTransaction, FailedTransaction),
+            (FailedTransaction, Idle),
+        ] {
+            record_ready_transition(from, to);
+        }
+        let after = transaction_counts_snapshot();
+
+        assert_eq!(after.started - before.started, 1);
+        assert_eq!(after.failed - before.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn backend_traffic_advances_byte_counters() {
+        use crate::backend::BackendConnection;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let peer = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = [0u8; 5];
+            stream.read_exact(&mut received).await.unwrap();
+            stream.write_all(b"world!").await.unwrap();
+            received
+        });
+
+        let before = bytes_snapshot();
+        let mut backend = BackendConnection::connect("127.0.0.1", port, None)
+            .await
+            .unwrap();
+        backend.send(b"hello").await.unwrap();
+        let mut read = 0;
+        while read < 6 {
+            read += backend.read().await.unwrap();
+        }
+        assert_eq!(&peer.await.unwrap(), b"hello");
+
+        let after = bytes_snapshot();
+        assert_eq!(after.backend_bytes_out - before.backend_bytes_out, 5);
+        assert_eq!(after.backend_bytes_in - before.backend_bytes_in, 6);
     }
 }
@@ -1,45 +1,149 @@
 use bytes::{Buf, BufMut, BytesMut};
-use std::{collections::HashMap, net::SocketAddr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    time::Duration,
+};
+use thiserror::Error;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
 
-use crate::shared_types::StatementSignature;
-use crate::wire::utils::peek_backend;
+use crate::analytics;
+use crate::backend::server_params;
+use crate::shared_types::{BackendIdentity, StatementSignature};
+use crate::wire::types::MessageType;
+use crate::wire::utils::try_peek_backend;
+
+/// SSLRequest: length 8, then the magic code 80877103.
+const SSL_REQUEST: [u8; 8] = [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f];
 
 #[derive(Debug)]
 pub struct BackendConnection {
-    stream: TcpStream,
+    stream: BackendStream,
     buffer: BytesMut,
+    identity: Option<BackendIdentity>,
     prepared_by_signature: HashMap<StatementSignature, String>,
     signature_by_name: HashMap<String, StatementSignature>,
+    /// Client statements relying on each prepare, when they're shared.
+    prepared_refs: HashMap<StatementSignature, u32>,
+    share_prepared: bool,
+    /// When each prepare was last used, on `use_clock`; the lowest is the
+    /// first to go once `max_prepared` is reached.
+    prepared_last_used: HashMap<String, u64>,
+    use_clock: u64,
+    max_prepared: Option<usize>,
+    on_connect: Vec<String>,
     epoch: u64,
     next_statement_id: u64,
     next_portal_id: u64,
+    connect_timings: ConnectTimings,
+    /// Latest ParameterStatus value of each reported setting, by lowercased
+    /// name.
+    reported: HashMap<String, String>,
+}
+
+/// How long opening a backend took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectTimings {
+    /// TCP connect and TLS negotiation, retries included.
+    pub connect: Duration,
+    /// StartupMessage through the first ReadyForQuery, auth included.
+    pub startup: Duration,
+}
+
+/// Why `startup` didn't get the backend to its first ReadyForQuery.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BackendStartupError {
+    /// The socket failed or closed mid-startup.
+    #[error("{0}")]
+    Io(String),
+    /// The server refused the login (SQLSTATE class 28), or asked for an
+    /// auth method pgcrab can't answer.
+    #[error("{0}")]
+    Auth(String),
+    /// Turned away for another reason, e.g. a missing database or a server
+    /// out of connection slots.
+    #[error("{0}")]
+    Refused(String),
+    /// A reply startup can't follow.
+    #[error("{0}")]
+    Protocol(String),
+}
+
+/// Why `run_silently` didn't end in a clean ReadyForQuery.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SilentRunError {
+    /// The socket failed or closed, or the backend sent something unreadable.
+    #[error("{0}")]
+    Io(String),
+    /// The server ran the query and answered with an ErrorResponse.
+    #[error("{0}")]
+    Failed(String),
+}
+
+#[derive(Debug)]
+enum BackendStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
 }
 
 impl BackendConnection {
-    pub async fn connect(host: &str, port: u16) -> std::io::Result<Self> {
+    /// With a `tls` connector, asks the server for SSL first and fails if it
+    /// declines; `host` is the name the certificate is checked against.
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        tls: Option<&TlsConnector>,
+    ) -> std::io::Result<Self> {
         let addr = format!("{}:{}", host, port);
         let stream = TcpStream::connect(addr).await?;
         stream.set_nodelay(true)?;
 
+        let stream = match tls {
+            Some(connector) => {
+                BackendStream::Tls(Box::new(negotiate_tls(stream, host, connector).await?))
+            }
+            None => BackendStream::Plain(stream),
+        };
+
         Ok(Self {
             stream,
             buffer: BytesMut::with_capacity(8192),
+            identity: None,
             prepared_by_signature: HashMap::new(),
             signature_by_name: HashMap::new(),
+            prepared_refs: HashMap::new(),
+            share_prepared: false,
+            prepared_last_used: HashMap::new(),
+            use_clock: 0,
+            max_prepared: None,
+            on_connect: Vec::new(),
             epoch: 0,
             next_statement_id: 0,
             next_portal_id: 0,
+            connect_timings: ConnectTimings::default(),
+            reported: HashMap::new(),
         })
     }
 
     pub async fn send(&mut self, data: &[u8]) -> std::io::Result<()> {
-        self.stream.write_all(data).await
+        analytics::add_backend_bytes_out(data.len());
+        match &mut self.stream {
+            BackendStream::Plain(stream) => stream.write_all(data).await,
+            BackendStream::Tls(stream) => stream.write_all(data).await,
+        }
     }
 
     pub async fn read(&mut self) -> std::io::Result<usize> {
-        self.stream.read_buf(&mut self.buffer).await
+        let n = match &mut self.stream {
+            BackendStream::Plain(stream) => stream.read_buf(&mut self.buffer).await?,
+            BackendStream::Tls(stream) => stream.read_buf(&mut self.buffer).await?,
+        };
+        analytics::add_backend_bytes_in(n);
+        Ok(n)
     }
 
     pub fn buffer(&self) -> &[u8] {
@@ -50,34 +154,113 @@ impl BackendConnection {
         self.buffer.advance(n);
     }
 
+    /// What the backend last reported `name` as; `None` for settings it
+    /// doesn't report, like `search_path` before Postgres 18.
+    pub fn reported(&self, name: &str) -> Option<&str> {
+        self.reported
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// Keeps up with a ParameterStatus frame the backend sent.
+    pub fn note_parameter_status(&mut self, frame: &[u8]) {
+        if let Some((name, value)) = parameter_status(frame) {
+            self.reported
+                .insert(name.to_ascii_lowercase(), value.to_string());
+        }
+    }
+
+    /// Server process id from BackendKeyData, once startup has seen it.
+    pub fn process_id(&self) -> Option<i32> {
+        self.identity.map(|identity| identity.process_id)
+    }
+
+    /// The server's own BackendKeyData, which a CancelRequest for this
+    /// backend has to carry; clients only ever see pgcrab's.
+    pub fn identity(&self) -> Option<BackendIdentity> {
+        self.identity
+    }
+
     pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
-        self.stream.peer_addr()
+        match &self.stream {
+            BackendStream::Plain(stream) => stream.peer_addr(),
+            BackendStream::Tls(stream) => stream.get_ref().0.peer_addr(),
+        }
     }
 
-    pub async fn reset_session(&mut self) -> Result<(), String> {
-        let reset = build_query_message("DISCARD ALL");
-        self.send(&reset)
+    /// Runs `reset_query` and waits for ReadyForQuery. An empty query skips
+    /// the round trip and leaves server state (and our prepared map) as is.
+    /// With shared prepares, the map only survives a query that can't drop
+    /// statements, e.g. `RESET ALL`. The `on_connect` statements run again
+    /// afterwards, since the reset may have undone them, at a round trip
+    /// each.
+    pub async fn reset_session(&mut self, reset_query: &str) -> Result<(), String> {
+        if reset_query.trim().is_empty() {
+            return Ok(());
+        }
+
+        self.run_silently(reset_query, "reset")
             .await
-            .map_err(|e| format!("backend reset send failed: {e}"))?;
+            .map_err(|e| e.to_string())?;
+        if !self.share_prepared || drops_prepared_statements(reset_query) {
+            self.prepared_reset();
+        }
+        self.run_on_connect().await.map_err(|e| e.to_string())
+    }
+
+    /// Statements that set a new backend up; set from the shard's
+    /// `on_connect`.
+    pub fn set_on_connect(&mut self, statements: Vec<String>) {
+        self.on_connect = statements;
+    }
+
+    /// Runs the `on_connect` statements in order, stopping at the first
+    /// that fails.
+    pub async fn run_on_connect(&mut self) -> Result<(), SilentRunError> {
+        let statements = std::mem::take(&mut self.on_connect);
+        let mut result = Ok(());
+        for statement in &statements {
+            result = self.run_silently(statement, "on_connect").await;
+            if result.is_err() {
+                break;
+            }
+        }
+        self.on_connect = statements;
+        result
+    }
 
-        let mut saw_error = false;
+    /// Runs `query` for its side effects, dropping every reply up to
+    /// ReadyForQuery. `what` names the query in errors.
+    pub async fn run_silently(&mut self, query: &str, what: &str) -> Result<(), SilentRunError> {
+        let message = build_query_message(query);
+        self.send(&message)
+            .await
+            .map_err(|e| SilentRunError::Io(format!("backend {what} send failed: {e}")))?;
+
+        let mut error = None;
         loop {
-            loop {
-                let Some((tag, len)) = peek_backend(self.buffer()) else {
-                    break;
-                };
+            while let Some((message_type, len)) =
+                try_peek_backend(self.buffer()).map_err(|e| SilentRunError::Io(e.to_string()))?
+            {
                 let total_len = 1 + len;
-                match tag {
-                    b'E' => {
-                        saw_error = true;
+                match message_type {
+                    MessageType::ErrorResponse => {
+                        let message = error_field(&self.buffer()[..total_len], b'M')
+                            .unwrap_or("error response");
+                        error = Some(format!("backend {what} failed: {message}"));
                     }
-                    b'Z' => {
-                        self.consume(total_len);
-                        if saw_error {
-                            return Err("backend reset error response".to_string());
+                    MessageType::ParameterStatus => {
+                        if let Some((name, value)) = parameter_status(&self.buffer()[..total_len]) {
+                            let (name, value) = (name.to_ascii_lowercase(), value.to_string());
+                            self.reported.insert(name, value);
                         }
-                        self.prepared_reset();
-                        return Ok(());
+                    }
+                    MessageType::ReadyForQuery => {
+                        self.consume(total_len);
+                        return match error {
+                            Some(error) => Err(SilentRunError::Failed(error)),
+                            None => Ok(()),
+                        };
                     }
                     _ => {}
                 }
@@ -87,29 +270,38 @@ impl BackendConnection {
             let n = self
                 .read()
                 .await
-                .map_err(|e| format!("backend reset read failed: {e}"))?;
+                .map_err(|e| SilentRunError::Io(format!("backend {what} read failed: {e}")))?;
             if n == 0 {
-                return Err("backend closed during reset".to_string());
+                return Err(SilentRunError::Io(format!("backend closed during {what}")));
             }
         }
     }
 
-    pub fn prepared_lookup(&self, signature: &StatementSignature) -> Option<&str> {
-        self.prepared_by_signature
-            .get(signature)
-            .map(|name| name.as_str())
+    /// Also marks the prepare as just used.
+    pub fn prepared_lookup(&mut self, signature: &StatementSignature) -> Option<&str> {
+        let name = self.prepared_by_signature.get(signature)?;
+        self.use_clock = self.use_clock.wrapping_add(1);
+        if let Some(last_used) = self.prepared_last_used.get_mut(name) {
+            *last_used = self.use_clock;
+        }
+        Some(name.as_str())
     }
 
     pub fn prepared_insert(&mut self, signature: StatementSignature, name: String) {
         if let Some(existing) = self.prepared_by_signature.insert(signature, name.clone()) {
             self.signature_by_name.remove(&existing);
+            self.prepared_last_used.remove(&existing);
         }
+        self.use_clock = self.use_clock.wrapping_add(1);
+        self.prepared_last_used.insert(name.clone(), self.use_clock);
         self.signature_by_name.insert(name, signature);
     }
 
     pub fn prepared_remove_name(&mut self, name: &str) {
         if let Some(signature) = self.signature_by_name.remove(name) {
             self.prepared_by_signature.remove(&signature);
+            self.prepared_refs.remove(&signature);
+            self.prepared_last_used.remove(name);
         }
     }
 
@@ -119,6 +311,87 @@ impl BackendConnection {
         self.next_portal_id = 0;
         self.prepared_by_signature.clear();
         self.signature_by_name.clear();
+        self.prepared_refs.clear();
+        self.prepared_last_used.clear();
+    }
+
+    /// Cap on prepares kept on this backend; set from the shard's
+    /// `max_prepared_statements`.
+    pub fn set_max_prepared(&mut self, max: Option<usize>) {
+        self.max_prepared = max;
+    }
+
+    /// Forgets the least recently used prepares until one more, on top of
+    /// `in_flight` not yet acknowledged, fits under the cap. Returns their
+    /// names for the caller to close on the backend. Shared references are
+    /// kept: a client still using an evicted statement prepares it again.
+    /// Prepares `pinned` says are in use are skipped, even if that leaves
+    /// the backend over the cap until the next eviction.
+    pub fn prepared_evict(
+        &mut self,
+        in_flight: usize,
+        pinned: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let Some(max) = self.max_prepared else {
+            return Vec::new();
+        };
+
+        let mut evicted = Vec::new();
+        while self.prepared_by_signature.len() + in_flight >= max {
+            let Some(name) = self
+                .prepared_last_used
+                .iter()
+                .filter(|(name, _)| !pinned(name))
+                .min_by_key(|(_, last_used)| **last_used)
+                .map(|(name, _)| name.clone())
+            else {
+                break;
+            };
+            self.prepared_last_used.remove(&name);
+            if let Some(signature) = self.signature_by_name.remove(&name) {
+                self.prepared_by_signature.remove(&signature);
+            }
+            evicted.push(name);
+        }
+        evicted
+    }
+
+    /// Whether sessions on this backend reuse each other's prepares; set from
+    /// the shard's `shared_prepared_statements`.
+    pub fn share_prepared(&self) -> bool {
+        self.share_prepared
+    }
+
+    pub fn set_share_prepared(&mut self, share: bool) {
+        self.share_prepared = share;
+    }
+
+    /// Another client statement now relies on the prepare for `signature`.
+    pub fn prepared_retain(&mut self, signature: StatementSignature) {
+        *self.prepared_refs.entry(signature).or_insert(0) += 1;
+    }
+
+    /// Drops one reference; true once no client statement relies on the
+    /// prepare any more, so it can be closed on the backend.
+    pub fn prepared_release(&mut self, signature: &StatementSignature) -> bool {
+        match self.prepared_refs.get_mut(signature) {
+            Some(refs) if *refs > 1 => {
+                *refs -= 1;
+                false
+            }
+            _ => {
+                self.prepared_refs.remove(signature);
+                true
+            }
+        }
+    }
+
+    pub fn connect_timings(&self) -> ConnectTimings {
+        self.connect_timings
+    }
+
+    pub fn set_connect_timings(&mut self, timings: ConnectTimings) {
+        self.connect_timings = timings;
     }
 
     pub fn allocate_statement_name(&mut self) -> String {
@@ -138,62 +411,87 @@ impl BackendConnection {
         user: &str,
         database: &str,
         password: &str,
-    ) -> Result<(), String> {
-        let startup = build_startup_message(user, database);
+        application_name: Option<&str>,
+        options: &BTreeMap<String, String>,
+    ) -> Result<(), BackendStartupError> {
+        let startup = build_startup_message(user, database, application_name, options);
         self.send(&startup)
             .await
-            .map_err(|e| format!("backend startup send failed: {e}"))?;
+            .map_err(|e| BackendStartupError::Io(format!("backend startup send failed: {e}")))?;
 
         let mut requested_password = false;
         loop {
-            let n = self
-                .read()
-                .await
-                .map_err(|e| format!("backend startup read failed: {e}"))?;
+            let n = self.read().await.map_err(|e| {
+                BackendStartupError::Io(format!("backend startup read failed: {e}"))
+            })?;
             if n == 0 {
-                return Err("backend closed during startup".to_string());
+                return Err(BackendStartupError::Io(
+                    "backend closed during startup".to_string(),
+                ));
             }
 
-            loop {
-                let (tag, len) = match peek_backend(self.buffer()) {
-                    Some(frame) => frame,
-                    None => break,
-                };
-
+            while let Some((message_type, len)) = try_peek_backend(self.buffer())
+                .map_err(|e| BackendStartupError::Protocol(e.to_string()))?
+            {
                 let total_len = 1 + len;
                 let frame = &self.buffer()[..total_len];
 
-                match tag {
-                    b'R' => {
+                match message_type {
+                    MessageType::Authentication => {
                         if frame.len() < 9 {
-                            return Err("backend auth response too short".to_string());
+                            return Err(BackendStartupError::Protocol(
+                                "backend auth response too short".to_string(),
+                            ));
                         }
                         let code = i32::from_be_bytes([frame[5], frame[6], frame[7], frame[8]]);
                         match code {
                             0 => {}
                             3 => {
                                 if requested_password {
-                                    return Err("backend requested password twice".to_string());
+                                    return Err(BackendStartupError::Protocol(
+                                        "backend requested password twice".to_string(),
+                                    ));
                                 }
                                 if password.is_empty() {
-                                    return Err("backend requested password but none configured"
-                                        .to_string());
+                                    return Err(BackendStartupError::Auth(
+                                        "backend requested password but none configured"
+                                            .to_string(),
+                                    ));
                                 }
                                 let password_message = build_password_message(password);
-                                self.send(&password_message)
-                                    .await
-                                    .map_err(|e| format!("backend password send failed: {e}"))?;
+                                self.send(&password_message).await.map_err(|e| {
+                                    BackendStartupError::Io(format!(
+                                        "backend password send failed: {e}"
+                                    ))
+                                })?;
                                 requested_password = true;
                             }
                             _ => {
-                                return Err(format!("unsupported backend auth method: {code}"));
+                                return Err(BackendStartupError::Auth(format!(
+                                    "unsupported backend auth method: {code}"
+                                )));
                             }
                         }
                     }
-                    b'E' => {
-                        return Err("backend startup error response".to_string());
+                    MessageType::ParameterStatus => {
+                        if let Some((name, value)) = parameter_status(frame) {
+                            server_params::record(name, value);
+                            let (name, value) = (name.to_ascii_lowercase(), value.to_string());
+                            self.reported.insert(name, value);
+                        }
+                    }
+                    MessageType::BackendKeyData if frame.len() >= 13 => {
+                        self.identity = Some(BackendIdentity {
+                            process_id: i32::from_be_bytes([
+                                frame[5], frame[6], frame[7], frame[8],
+                            ]),
+                            secret_key: i32::from_be_bytes([
+                                frame[9], frame[10], frame[11], frame[12],
+                            ]),
+                        });
                     }
-                    b'Z' => {
+                    MessageType::ErrorResponse => return Err(startup_error(frame)),
+                    MessageType::ReadyForQuery => {
                         self.consume(total_len);
                         return Ok(());
                     }
@@ -206,7 +504,78 @@ impl BackendConnection {
     }
 }
 
-fn build_startup_message(user: &str, database: &str) -> BytesMut {
+async fn negotiate_tls(
+    mut stream: TcpStream,
+    host: &str,
+    connector: &TlsConnector,
+) -> std::io::Result<TlsStream<TcpStream>> {
+    stream.write_all(&SSL_REQUEST).await?;
+    match stream.read_u8().await? {
+        b'S' => {}
+        b'N' => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                "server does not support SSL, but sslmode requires it",
+            ));
+        }
+        other => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unexpected SSLRequest response {:?}", other as char),
+            ));
+        }
+    }
+
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    connector.connect(server_name, stream).await
+}
+
+/// The backend's ErrorResponse during startup, classified by SQLSTATE.
+fn startup_error(frame: &[u8]) -> BackendStartupError {
+    let field = |tag: u8| error_field(frame, tag);
+    let code = field(b'C').unwrap_or("");
+    let message = format!(
+        "backend startup failed: {} ({code})",
+        field(b'M').unwrap_or("error response")
+    );
+    if code.starts_with("28") {
+        BackendStartupError::Auth(message)
+    } else {
+        BackendStartupError::Refused(message)
+    }
+}
+
+/// One field of an ErrorResponse frame, e.g. `b'M'` for its message.
+fn error_field(frame: &[u8], tag: u8) -> Option<&str> {
+    frame
+        .get(5..)?
+        .split(|&b| b == 0)
+        .find(|field| field.first() == Some(&tag))
+        .and_then(|field| std::str::from_utf8(&field[1..]).ok())
+}
+
+/// Conservative: any DISCARD or DEALLOCATE may have dropped statements.
+fn drops_prepared_statements(query: &str) -> bool {
+    let upper = query.to_ascii_uppercase();
+    upper.contains("DISCARD") || upper.contains("DEALLOCATE")
+}
+
+/// Name and value of a ParameterStatus frame.
+fn parameter_status(frame: &[u8]) -> Option<(&str, &str)> {
+    let mut fields = frame.get(5..)?.split(|&b| b == 0);
+    let name = std::str::from_utf8(fields.next()?).ok()?;
+    let value = std::str::from_utf8(fields.next()?).ok()?;
+    Some((name, value))
+}
+
+/// An `application_name` in `options` wins over the pool's.
+fn build_startup_message(
+    user: &str,
+    database: &str,
+    application_name: Option<&str>,
+    options: &BTreeMap<String, String>,
+) -> BytesMut {
     let mut buf = BytesMut::with_capacity(128);
     buf.put_u32(0);
     buf.put_u32(196608);
@@ -218,6 +587,20 @@ fn build_startup_message(user: &str, database: &str) -> BytesMut {
     buf.put_u8(0);
     buf.extend_from_slice(database.as_bytes());
     buf.put_u8(0);
+    if let Some(application_name) = application_name
+        && !options.contains_key("application_name")
+    {
+        buf.extend_from_slice(b"application_name");
+        buf.put_u8(0);
+        buf.extend_from_slice(application_name.as_bytes());
+        buf.put_u8(0);
+    }
+    for (key, value) in options {
+        buf.extend_from_slice(key.as_bytes());
+        buf.put_u8(0);
+        buf.extend_from_slice(value.as_bytes());
+        buf.put_u8(0);
+    }
     buf.put_u8(0);
     let len = buf.len() as u32;
     buf[0..4].copy_from_slice(&len.to_be_bytes());
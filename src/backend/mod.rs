@@ -1,3 +1,8 @@
 pub mod backend_connection;
+pub mod sequence_tracker;
+pub mod server_params;
 
-pub use backend_connection::BackendConnection;
+pub use backend_connection::{
+    BackendConnection, BackendStartupError, ConnectTimings, SilentRunError,
+};
+pub use sequence_tracker::BackendSequenceTracker;
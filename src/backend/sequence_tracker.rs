@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use crate::wire::types::MessageType;
+
+// -----------------------------------------------------------------------------
+// ----- BackendSequenceTracker ------------------------------------------------
+
+/// Backend counterpart of the frontend `SequenceTracker`: records the frames
+/// `peek_backend` splits off and hands them back one response at a time,
+/// each batch ending with its ReadyForQuery.
+#[derive(Debug, Default)]
+pub struct BackendSequenceTracker {
+    frames: VecDeque<FrameSummary>,
+}
+
+#[derive(Debug)]
+pub struct FrameSummary {
+    pub message_type: MessageType,
+    /// Whole frame, tag byte included.
+    pub len: usize,
+}
+
+// -----------------------------------------------------------------------------
+// ----- BackendSequenceTracker: Static ----------------------------------------
+
+impl BackendSequenceTracker {
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::new(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- BackendSequenceTracker: Public ----------------------------------------
+
+impl BackendSequenceTracker {
+    /// `len` is what `peek_backend` returned, so without the tag byte.
+    pub fn push(&mut self, message_type: MessageType, len: usize) {
+        self.frames.push_back(FrameSummary {
+            message_type,
+            len: 1 + len,
+        });
+    }
+
+    /// Bytes up to and including the first ReadyForQuery, or `None` while the
+    /// backend is still answering.
+    pub fn take_until_ready(&mut self) -> Option<usize> {
+        let index = self
+            .frames
+            .iter()
+            .position(|meta| meta.message_type == MessageType::ReadyForQuery)?;
+        Some(self.frames.drain(..=index).map(|meta| meta.len).sum())
+    }
+
+    /// Whether the pending batch has a frame of this type, e.g. an
+    /// ErrorResponse ahead of the ReadyForQuery.
+    pub fn contains(&self, message_type: MessageType) -> bool {
+        self.frames
+            .iter()
+            .any(|meta| meta.message_type == message_type)
+    }
+
+    /// Length of all frames in the tracker, in bytes
+    pub fn len(&self) -> usize {
+        self.frames.iter().map(|meta| meta.len).sum()
+    }
+
+    /// Count the number of frames in the tracker
+    pub fn count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Check if the tracker is empty
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::utils::peek_backend;
+
+    fn frame(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        out.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    /// Splits every complete frame off `stream` into the tracker; returns the
+    /// bytes consumed.
+    fn feed(tracker: &mut BackendSequenceTracker, stream: &[u8]) -> usize {
+        let mut cursor = 0;
+        while let Some((message_type, len)) = peek_backend(&stream[cursor..]) {
+            tracker.push(message_type, len);
+            cursor += 1 + len;
+        }
+        cursor
+    }
+
+    #[test]
+    fn batches_end_at_each_ready_for_query() {
+        let first = [
+            frame(b'T', &[0, 0]),
+            frame(b'N', b"SNOTICE\0\0"),
+            frame(b'D', &[0, 0]),
+            frame(b'S', b"TimeZone\0UTC\0"),
+            frame(b'C', b"SELECT 1\0"),
+            frame(b'Z', b"I"),
+        ]
+        .concat();
+        let second = [frame(b'E', b"SERROR\0\0"), frame(b'Z', b"I")].concat();
+        let stream = [first.as_slice(), second.as_slice()].concat();
+
+        let mut tracker = BackendSequenceTracker::new();
+        assert_eq!(feed(&mut tracker, &stream), stream.len());
+        assert_eq!(tracker.count(), 8);
+        assert!(tracker.contains(MessageType::ErrorResponse));
+
+        assert_eq!(tracker.take_until_ready(), Some(first.len()));
+        assert!(tracker.contains(MessageType::ErrorResponse));
+        assert!(!tracker.contains(MessageType::ParameterStatus));
+
+        assert_eq!(tracker.take_until_ready(), Some(second.len()));
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.take_until_ready(), None);
+    }
+
+    #[test]
+    fn partial_response_waits_for_ready_for_query() {
+        let stream = [
+            frame(b'1', &[]),
+            frame(b'2', &[]),
+            frame(b'D', &[0, 1, 0, 0, 0, 1, b'x']),
+            frame(b'A', b"\0\0\0\x07chan\0payload\0"),
+            frame(b'C', b"SELECT 1\0"),
+            frame(b'Z', b"T"),
+        ]
+        .concat();
+        let split = stream.len() - 3;
+
+        let mut tracker = BackendSequenceTracker::new();
+        let consumed = feed(&mut tracker, &stream[..split]);
+        assert_eq!(tracker.count(), 5);
+        assert_eq!(tracker.len(), consumed);
+        assert_eq!(tracker.take_until_ready(), None);
+
+        feed(&mut tracker, &stream[consumed..]);
+        assert_eq!(tracker.take_until_ready(), Some(stream.len()));
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn simple_query_response_arriving_in_pieces() {
+        let mut row_description = 1u16.to_be_bytes().to_vec();
+        row_description.extend_from_slice(b"?column?\0");
+        row_description.extend_from_slice(&0u32.to_be_bytes());
+        row_description.extend_from_slice(&0u16.to_be_bytes());
+        row_description.extend_from_slice(&23u32.to_be_bytes());
+        row_description.extend_from_slice(&4i16.to_be_bytes());
+        row_description.extend_from_slice(&(-1i32).to_be_bytes());
+        row_description.extend_from_slice(&0u16.to_be_bytes());
+
+        let mut data_row = 1u16.to_be_bytes().to_vec();
+        data_row.extend_from_slice(&1u32.to_be_bytes());
+        data_row.push(b'1');
+
+        let stream = [
+            frame(b'T', &row_description),
+            frame(b'D', &data_row),
+            frame(b'C', b"SELECT 1\0"),
+            frame(b'Z', b"I"),
+        ]
+        .concat();
+
+        // A few bytes per read; only whole frames are tracked.
+        let mut tracker = BackendSequenceTracker::new();
+        let mut received = Vec::new();
+        let mut consumed = 0;
+        for chunk in stream.chunks(3) {
+            received.extend_from_slice(chunk);
+            consumed += feed(&mut tracker, &received[consumed..]);
+            assert_eq!(tracker.len(), consumed);
+        }
+
+        assert_eq!(tracker.count(), 4);
+        assert!(tracker.contains(MessageType::DataRow));
+        assert_eq!(tracker.take_until_ready(), Some(stream.len()));
+    }
+
+    #[test]
+    fn million_small_frames() {
+        const FRAMES: usize = 1_000_000;
+        let stream = [frame(b'n', b"").repeat(FRAMES), frame(b'Z', b"I")].concat();
+
+        let mut tracker = BackendSequenceTracker::new();
+        assert_eq!(feed(&mut tracker, &stream), stream.len());
+        assert_eq!(tracker.count(), FRAMES + 1);
+        assert_eq!(tracker.take_until_ready(), Some(stream.len()));
+    }
+
+    #[test]
+    fn copy_out_frames_stay_in_their_batch() {
+        let stream = [
+            frame(b'H', &[0, 0, 0]),
+            frame(b'd', b"1\n"),
+            frame(b'd', b"2\n"),
+            frame(b'c', &[]),
+            frame(b'C', b"COPY 2\0"),
+            frame(b'Z', b"I"),
+        ]
+        .concat();
+
+        let mut tracker = BackendSequenceTracker::new();
+        feed(&mut tracker, &stream);
+        assert!(tracker.contains(MessageType::CopyOutResponse));
+        assert_eq!(tracker.take_until_ready(), Some(stream.len()));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
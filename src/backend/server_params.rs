@@ -0,0 +1,51 @@
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// ParameterStatus reported by backend startups that describes the server
+/// rather than one session, so clients are told the same.
+const SERVER_WIDE_PARAMS: &[&str] = &[
+    "server_version",
+    "server_encoding",
+    "DateStyle",
+    "IntervalStyle",
+    "TimeZone",
+    "integer_datetimes",
+    "standard_conforming_strings",
+];
+
+// -----------------------------------------------------------------------------
+// ----- Global Registry -------------------------------------------------------
+
+/// Latest values seen from any backend.
+static SERVER_PARAMS: OnceLock<RwLock<BTreeMap<String, String>>> = OnceLock::new();
+
+// -----------------------------------------------------------------------------
+// ----- Public ----------------------------------------------------------------
+
+/// Keeps a backend's ParameterStatus if it's one clients are told about.
+pub fn record(name: &str, value: &str) {
+    if !SERVER_WIDE_PARAMS.contains(&name) {
+        return;
+    }
+    server_params()
+        .write()
+        .insert(name.to_string(), value.to_string());
+}
+
+pub fn snapshot() -> BTreeMap<String, String> {
+    server_params().read().clone()
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+fn server_params() -> &'static RwLock<BTreeMap<String, String>> {
+    SERVER_PARAMS.get_or_init(Default::default)
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,188 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use super::{
+    document::ConfigDocument, parser::ParserConfig, policy::PolicyConfig,
+    query_log::QueryLogConfig, routing::RoutingConfig, server::ServerConfig, shards::ShardsConfig,
+    startup::StartupConfig, users::UsersConfig,
+};
+
+// -----------------------------------------------------------------------------
+// ----- ConfigReport ----------------------------------------------------------
+
+/// What `pgcrab admin check-config` found: each section of the config file
+/// run through the same parsing and validation as startup.
+#[derive(Debug)]
+pub struct ConfigReport {
+    pub path: PathBuf,
+    /// Set when the file couldn't be read or isn't TOML; no section was
+    /// checked.
+    pub read_error: Option<String>,
+    /// `(section, error)`, in the order they are loaded.
+    pub sections: Vec<(&'static str, Option<String>)>,
+}
+
+// -----------------------------------------------------------------------------
+// ----- ConfigReport: Static --------------------------------------------------
+
+impl ConfigReport {
+    /// Checks every section of the file without touching the global config,
+    /// so it's safe to run next to (or instead of) a server.
+    pub async fn check(path: &Path) -> ConfigReport {
+        let raw = match fs::read_to_string(path).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                return ConfigReport {
+                    path: path.to_path_buf(),
+                    read_error: Some(e.to_string()),
+                    sections: Vec::new(),
+                };
+            }
+        };
+
+        let document = match ConfigDocument::parse(&raw) {
+            Ok(document) => document,
+            Err(e) => {
+                return ConfigReport {
+                    path: path.to_path_buf(),
+                    read_error: Some(e.to_string()),
+                    sections: Vec::new(),
+                };
+            }
+        };
+
+        let sections = vec![
+            ("server", error_of(ServerConfig::from_document(&document))),
+            ("users", error_of(UsersConfig::parse(&raw))),
+            ("shards", error_of(ShardsConfig::parse(&raw))),
+            ("parser", error_of(ParserConfig::from_document(&document))),
+            (
+                "query_log",
+                error_of(QueryLogConfig::from_document(&document)),
+            ),
+            ("routing", error_of(RoutingConfig::from_document(&document))),
+            ("startup", error_of(StartupConfig::from_document(&document))),
+            ("policy", error_of(PolicyConfig::from_document(&document))),
+        ];
+
+        ConfigReport {
+            path: path.to_path_buf(),
+            read_error: None,
+            sections,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- ConfigReport: Public --------------------------------------------------
+
+impl ConfigReport {
+    pub fn error_count(&self) -> usize {
+        let section_errors = self
+            .sections
+            .iter()
+            .filter(|(_, error)| error.is_some())
+            .count();
+        section_errors + usize::from(self.read_error.is_some())
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error_count() == 0
+    }
+}
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.path.display())?;
+        if let Some(error) = &self.read_error {
+            writeln!(f, "  error: {error}")?;
+        }
+        for (section, error) in &self.sections {
+            match error {
+                Some(error) => writeln!(f, "  {section:<10} error: {error}")?,
+                None => writeln!(f, "  {section:<10} ok")?,
+            }
+        }
+
+        match self.error_count() {
+            0 => write!(f, "config is valid"),
+            1 => write!(f, "1 error"),
+            errors => write!(f, "{errors} errors"),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+fn error_of<T, E: fmt::Display>(result: Result<T, E>) -> Option<String> {
+    result.err().map(|e| e.to_string())
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    async fn check(raw: &str) -> ConfigReport {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(raw.as_bytes()).unwrap();
+        ConfigReport::check(file.path()).await
+    }
+
+    #[tokio::test]
+    async fn reports_every_failing_section() {
+        let report = check(
+            r#"
+            [[users]]
+            username = "pgcrab"
+            password = "pgcrab"
+
+            [[users]]
+            username = "pgcrab"
+            password = "other"
+
+            [[shards]]
+            name = "alpha"
+            host = "127.0.0.1"
+            port = 5432
+            user = "user"
+            password = "secret"
+            connect_timeout = 0
+
+            [routing]
+            read_replicas = ["alpha"]
+            "#,
+        )
+        .await;
+
+        assert_eq!(report.error_count(), 3);
+        let failed: Vec<_> = report
+            .sections
+            .iter()
+            .filter(|(_, error)| error.is_some())
+            .map(|(section, _)| *section)
+            .collect();
+        assert_eq!(failed, ["users", "shards", "routing"]);
+
+        let text = report.to_string();
+        assert!(text.contains("duplicate [[users]] entry for user 'pgcrab'"));
+        assert!(text.contains("  server     ok"));
+        assert!(text.ends_with("3 errors"));
+    }
+
+    #[tokio::test]
+    async fn missing_file_is_an_error() {
+        let report = ConfigReport::check(Path::new("/nonexistent/pgcrab.toml")).await;
+        assert!(!report.is_ok());
+        assert!(report.sections.is_empty());
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -1,17 +1,34 @@
 use parking_lot::RwLock;
 use std::{
     net::SocketAddr,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, OnceLock},
 };
+use thiserror::Error;
+use tracing::{error, info, warn};
 
-use super::{shards::ShardsConfig, types::LogLevel, users::UsersConfig};
+use super::{
+    document::{ConfigDocument, DocumentError},
+    parser::{ParserConfig, ParserError},
+    policy::{PolicyConfig, PolicyError},
+    query_log::{QueryLogConfig, QueryLogError},
+    routing::{RoutingConfig, RoutingError},
+    server::{ServerConfig, ServerError},
+    shards::ShardsConfig,
+    startup::{StartupConfig, StartupError},
+    types::{ListenAddr, LogFormat, LogLevel},
+    users::UsersConfig,
+};
+use crate::{logging, parser};
 
 // -----------------------------------------------------------------------------
 // ----- Global Singleton ------------------------------------------------------
 
 static CONFIG_FILE_PATH: OnceLock<PathBuf> = OnceLock::new();
-static CONFIG: OnceLock<Arc<RwLock<Config>>> = OnceLock::new();
+static CONFIG: OnceLock<RwLock<Arc<Config>>> = OnceLock::new();
+
+/// `--host`/`--port` and `--log`, used where `[server]` doesn't override them.
+static CLI_DEFAULTS: OnceLock<(ListenAddr, LogLevel)> = OnceLock::new();
 
 // -----------------------------------------------------------------------------
 // ----- Config ----------------------------------------------------------------
@@ -20,46 +37,138 @@ static CONFIG: OnceLock<Arc<RwLock<Config>>> = OnceLock::new();
 pub struct Config {
     pub listen_addr: SocketAddr,
     pub log_level: LogLevel,
+    pub log_format: LogFormat,
     pub parser_cache_capacity: usize,
+    pub strict_parse: bool,
+    pub server: ServerConfig,
+    pub parser: ParserConfig,
+    pub query_log: QueryLogConfig,
+    pub routing: RoutingConfig,
+    pub startup: StartupConfig,
+    pub policy: PolicyConfig,
     pub users: &'static UsersConfig,
     pub shards: &'static ShardsConfig,
 }
 
+/// The optional tables read from the config file alongside users and shards.
+#[derive(Debug)]
+struct FileSections {
+    server: ServerConfig,
+    parser: ParserConfig,
+    query_log: QueryLogConfig,
+    routing: RoutingConfig,
+    startup: StartupConfig,
+    policy: PolicyConfig,
+}
+
 // -----------------------------------------------------------------------------
 // ----- Config: Static --------------------------------------------------------
 
 impl Config {
     /// Async because UsersConfig::init() is async (non-blocking IO).
     pub async fn init(
-        listen_addr: SocketAddr,
+        listen_addr: ListenAddr,
         log_level: LogLevel,
+        log_format: LogFormat,
         parser_cache_capacity: usize,
+        strict_parse: bool,
         config_path: PathBuf,
     ) {
         CONFIG_FILE_PATH
             .set(config_path)
             .unwrap_or_else(|_| panic!("Config::init called twice"));
+        let _ = CLI_DEFAULTS.set((listen_addr, log_level));
 
         let path = config_path_handle();
         UsersConfig::init(path).await;
         ShardsConfig::init(path).await;
+        let sections = FileSections::read(path)
+            .await
+            .unwrap_or_else(|e| panic!("failed to load config from {:?}: {e}", path));
 
-        Self::load(listen_addr, log_level, parser_cache_capacity).await;
+        let (listen_addr, log_level) = effective_overrides(&sections.server);
+
+        Self::load(
+            listen_addr,
+            log_level,
+            log_format,
+            parser_cache_capacity,
+            strict_parse,
+            sections,
+        )
+        .await;
     }
 
-    /// Pure in-memory reload. Call this after you've reloaded sub-configs.
+    /// Re-reads the config file and swaps the snapshot. A new log level is
+    /// applied to the running subscriber; a new listen address only takes
+    /// effect after a restart, so the snapshot keeps the bound one. If any
+    /// optional table fails to load, all of them keep their previous values.
     pub async fn reload() {
-        let current = Self::snapshot();
+        let current = Self::handle();
+
+        let path = config_path_handle();
+        let sections = match FileSections::read(path).await {
+            Ok(sections) => sections,
+            Err(e) => {
+                error!(
+                    "reload failed; keeping previous config. path={:?} error={}",
+                    path, e
+                );
+                FileSections {
+                    server: current.server.clone(),
+                    parser: current.parser.clone(),
+                    query_log: current.query_log.clone(),
+                    routing: current.routing.clone(),
+                    startup: current.startup.clone(),
+                    policy: current.policy.clone(),
+                }
+            }
+        };
+        let (listen_addr, log_level) = effective_overrides(&sections.server);
+        if listen_addr != current.listen_addr {
+            warn!(
+                "listen_addr changed from {} to {}; requires restart",
+                current.listen_addr, listen_addr
+            );
+        }
+        if log_level != current.log_level {
+            logging::set_level(&log_level);
+            info!(
+                "log level changed from {} to {}",
+                current.log_level.clone().as_str(),
+                log_level.clone().as_str()
+            );
+        }
+
         Self::load(
             current.listen_addr,
-            current.log_level,
+            log_level,
+            current.log_format.clone(),
             current.parser_cache_capacity,
+            current.strict_parse,
+            sections,
         )
         .await;
     }
 
+    /// Whether `init` already ran; it can only run once per process.
+    pub fn is_initialized() -> bool {
+        CONFIG_FILE_PATH.get().is_some()
+    }
+
+    /// Current config, shared: callers borrow from it rather than cloning.
+    /// Every call between two reloads returns the same `Arc`.
+    pub fn handle() -> Arc<Config> {
+        CONFIG
+            .get()
+            .expect("Config not initialized; call Config::init().await first")
+            .read()
+            .clone()
+    }
+
+    /// Owned copy, for callers that keep or change it (admin output).
     pub fn snapshot() -> Config {
-        Self::handle().read().clone()
+        Config::clone(&Self::handle())
     }
 }
 
@@ -67,7 +176,14 @@ impl Config {
 // ----- Config: Private -------------------------------------------------------
 
 impl Config {
-    async fn load(listen_addr: SocketAddr, log_level: LogLevel, parser_cache_capacity: usize) {
+    async fn load(
+        listen_addr: SocketAddr,
+        log_level: LogLevel,
+        log_format: LogFormat,
+        parser_cache_capacity: usize,
+        strict_parse: bool,
+        sections: FileSections,
+    ) {
         let users = UsersConfig::handle();
         let shards = ShardsConfig::handle();
 
@@ -75,26 +191,46 @@ impl Config {
         UsersConfig::reload(path).await;
         ShardsConfig::reload(path).await;
 
+        parser::set_normalized_keys(sections.parser.normalize);
+
         let next = Config {
             listen_addr,
             log_level,
+            log_format,
             parser_cache_capacity,
+            strict_parse,
+            server: sections.server,
+            parser: sections.parser,
+            query_log: sections.query_log,
+            routing: sections.routing,
+            startup: sections.startup,
+            policy: sections.policy,
             users,
             shards,
         };
 
         if let Some(handle) = CONFIG.get() {
-            *handle.write() = next;
+            *handle.write() = Arc::new(next);
         } else {
-            let _ = CONFIG.set(Arc::new(RwLock::new(next)));
+            let _ = CONFIG.set(RwLock::new(Arc::new(next)));
         }
     }
+}
 
-    fn handle() -> Arc<RwLock<Config>> {
-        CONFIG
-            .get()
-            .expect("Config not initialized; call Config::init().await first")
-            .clone()
+// -----------------------------------------------------------------------------
+// ----- FileSections ----------------------------------------------------------
+
+impl FileSections {
+    async fn read(path: &Path) -> Result<FileSections, SectionsError> {
+        let document = ConfigDocument::read(path).await?;
+        Ok(FileSections {
+            server: ServerConfig::from_document(&document)?,
+            parser: ParserConfig::from_document(&document)?,
+            query_log: QueryLogConfig::from_document(&document)?,
+            routing: RoutingConfig::from_document(&document)?,
+            startup: StartupConfig::from_document(&document)?,
+            policy: PolicyConfig::from_document(&document)?,
+        })
     }
 }
 
@@ -107,5 +243,104 @@ fn config_path_handle() -> &'static PathBuf {
         .expect("config path not initialized; call Config::init() first")
 }
 
+/// Listen address and log level: `[server]` wins over the CLI, but not over
+/// an embedder's fixed address.
+fn effective_overrides(server: &ServerConfig) -> (SocketAddr, LogLevel) {
+    let (listen_addr, log_level) = CLI_DEFAULTS
+        .get()
+        .expect("CLI defaults not initialized; call Config::init() first");
+
+    (
+        listen_addr.resolve(server.listen_addr),
+        server
+            .log_level
+            .clone()
+            .unwrap_or_else(|| log_level.clone()),
+    )
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+enum SectionsError {
+    #[error(transparent)]
+    Document(#[from] DocumentError),
+
+    #[error(transparent)]
+    Server(#[from] ServerError),
+
+    #[error(transparent)]
+    Parser(#[from] ParserError),
+
+    #[error(transparent)]
+    QueryLog(#[from] QueryLogError),
+
+    #[error(transparent)]
+    Routing(#[from] RoutingError),
+
+    #[error(transparent)]
+    Startup(#[from] StartupError),
+
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    // The only test that initializes the global config.
+    #[tokio::test]
+    async fn handle_is_shared_until_a_reload() {
+        let mut file = NamedTempFile::new().unwrap();
+        let raw = r#"
+            [[users]]
+            username = "pgcrab"
+            password = "pgcrab"
+
+            [[shards]]
+            name = "alpha"
+            host = "127.0.0.1"
+            port = 5432
+            user = "user"
+            password = "secret"
+        "#;
+        file.write_all(raw.as_bytes()).unwrap();
+
+        let addr = "127.0.0.1:6432".parse().unwrap();
+        assert!(!Config::is_initialized());
+        Config::init(
+            ListenAddr::Default(addr),
+            LogLevel::Info,
+            LogFormat::Text,
+            16,
+            false,
+            file.path().to_path_buf(),
+        )
+        .await;
+
+        assert!(Config::is_initialized());
+
+        let first = Config::handle();
+        let second = Config::handle();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(Config::snapshot().listen_addr, first.listen_addr);
+
+        Config::reload().await;
+        let reloaded = Config::handle();
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+        assert!(Arc::ptr_eq(&reloaded, &Config::handle()));
+
+        // Handles taken before the reload stay valid.
+        assert_eq!(first.listen_addr, reloaded.listen_addr);
+    }
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
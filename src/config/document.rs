@@ -0,0 +1,63 @@
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::fs;
+
+// -----------------------------------------------------------------------------
+// ----- ConfigDocument --------------------------------------------------------
+
+/// The config file, read and parsed as TOML once. Each optional section
+/// (`[server]`, `[routing]`, …) deserializes what it needs from it.
+#[derive(Debug, Clone)]
+pub struct ConfigDocument {
+    table: toml::Table,
+}
+
+// -----------------------------------------------------------------------------
+// ----- ConfigDocument: Static ------------------------------------------------
+
+impl ConfigDocument {
+    pub async fn read(path: &Path) -> Result<ConfigDocument, DocumentError> {
+        let raw = fs::read_to_string(path)
+            .await
+            .map_err(|e| DocumentError::Io {
+                path: path.to_path_buf(),
+                source: e,
+            })?;
+        Self::parse(&raw)
+    }
+
+    pub fn parse(raw: &str) -> Result<ConfigDocument, DocumentError> {
+        let table = toml::from_str(raw).map_err(|e| DocumentError::Toml { source: e })?;
+        Ok(ConfigDocument { table })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- ConfigDocument: Public ------------------------------------------------
+
+impl ConfigDocument {
+    /// The whole document as `T`, which picks out the tables it reads and
+    /// ignores the rest.
+    pub(super) fn section<T: DeserializeOwned>(&self) -> Result<T, toml::de::Error> {
+        self.table.clone().try_into()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum DocumentError {
+    #[error("read error for {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("toml parse error: {source}")]
+    Toml { source: toml::de::Error },
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
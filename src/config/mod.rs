@@ -1,5 +1,13 @@
+pub mod check;
 pub mod config;
+pub mod document;
+pub mod parser;
+pub mod policy;
+pub mod query_log;
+pub mod routing;
+pub mod server;
 pub mod shards;
+pub mod startup;
 pub mod types;
 pub mod users;
 
@@ -0,0 +1,86 @@
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::document::ConfigDocument;
+
+// -----------------------------------------------------------------------------
+// ----- ParserConfig ----------------------------------------------------------
+
+/// SQL parser options from the optional `[parser]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParserConfig {
+    /// Key the parse cache on the query's fingerprint instead of its exact
+    /// text, so queries differing only in whitespace, comments or literal
+    /// values share an entry.
+    pub normalize: bool,
+}
+
+// -----------------------------------------------------------------------------
+// ----- ParserConfig: Static --------------------------------------------------
+
+impl ParserConfig {
+    pub fn from_document(document: &ConfigDocument) -> Result<ParserConfig, ParserError> {
+        let doc: ParserFile = document
+            .section()
+            .map_err(|e| ParserError::Toml { source: e })?;
+        let Some(parser) = doc.parser else {
+            return Ok(ParserConfig::default());
+        };
+
+        Ok(ParserConfig {
+            normalize: parser.normalize.unwrap_or(false),
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: On-disk format ----------------------------------------------
+
+#[derive(Debug, Clone, Deserialize)]
+struct ParserFile {
+    #[serde(default)]
+    parser: Option<ParserFileEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ParserFileEntry {
+    normalize: Option<bool>,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum ParserError {
+    #[error("toml parse error: {source}")]
+    Toml { source: toml::de::Error },
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<ParserConfig, ParserError> {
+        ParserConfig::from_document(&ConfigDocument::parse(raw).unwrap())
+    }
+
+    #[test]
+    fn exact_keys_unless_normalize_is_set() {
+        let raw = "[[users]]\nusername = \"pgcrab\"\npassword = \"pgcrab\"\n";
+        assert!(!parse(raw).unwrap().normalize);
+
+        let config = parse("[parser]\nnormalize = true\n").unwrap();
+        assert!(config.normalize);
+
+        assert!(matches!(
+            parse("[parser]\nnormalize = \"yes\"\n"),
+            Err(ParserError::Toml { .. })
+        ));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
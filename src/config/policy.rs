@@ -0,0 +1,215 @@
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::document::ConfigDocument;
+use crate::parser::ParsedQuery;
+
+/// Statements and functions whose effect outlives the transaction, so a
+/// transaction-mode client would lose it when its backend goes back to the
+/// pool. `SET` isn't here: it's replayed on the next backend instead.
+const DEFAULT_SESSION_ONLY: &[&str] = &[
+    "ListenStmt",
+    "PrepareStmt",
+    HOLD_CURSOR,
+    "pg_advisory_lock",
+    "pg_advisory_lock_shared",
+    "pg_try_advisory_lock",
+    "pg_try_advisory_lock_shared",
+];
+
+/// How `session_only` names a `DECLARE ... WITH HOLD`; a plain
+/// `DeclareCursorStmt` is gone with its transaction.
+pub const HOLD_CURSOR: &str = "DeclareCursorStmt WITH HOLD";
+
+// -----------------------------------------------------------------------------
+// ----- PolicyConfig ----------------------------------------------------------
+
+/// Statement filtering from the optional `[policy]` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyConfig {
+    /// Parse tree node names, e.g. `DropStmt`, refused with a 42501 instead
+    /// of being forwarded.
+    pub deny_statements: Vec<String>,
+    /// Statement node names and function names refused with a 0A000 for
+    /// transaction-mode users, who'd lose their effect with the backend.
+    pub session_only: Vec<String>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        PolicyConfig {
+            deny_statements: Vec::new(),
+            session_only: DEFAULT_SESSION_ONLY.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- PolicyConfig: Static --------------------------------------------------
+
+impl PolicyConfig {
+    pub fn from_document(document: &ConfigDocument) -> Result<PolicyConfig, PolicyError> {
+        let doc: PolicyFile = document
+            .section()
+            .map_err(|e| PolicyError::Toml { source: e })?;
+        let Some(policy) = doc.policy else {
+            return Ok(PolicyConfig::default());
+        };
+
+        // Node names all end in "Stmt"; "DROP" or "drop" would never match.
+        if let Some(name) = policy
+            .deny_statements
+            .iter()
+            .find(|name| !name.ends_with("Stmt") || !name.starts_with(char::is_uppercase))
+        {
+            return Err(PolicyError::UnknownStatementType(name.clone()));
+        }
+
+        Ok(PolicyConfig {
+            deny_statements: policy.deny_statements,
+            session_only: policy
+                .session_only
+                .unwrap_or_else(|| PolicyConfig::default().session_only),
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- PolicyConfig: Public --------------------------------------------------
+
+impl PolicyConfig {
+    /// The first of `statement_types` this policy refuses.
+    pub fn denied<'a>(&self, statement_types: &'a [String]) -> Option<&'a str> {
+        statement_types
+            .iter()
+            .find(|statement_type| self.deny_statements.contains(statement_type))
+            .map(String::as_str)
+    }
+
+    /// The first statement type or function in `parsed` that only works
+    /// with a backend kept for the whole session. A listed function matches
+    /// with or without a schema: `pg_advisory_lock` also catches
+    /// `pg_catalog.pg_advisory_lock`.
+    pub fn session_only<'a>(&self, parsed: &'a ParsedQuery) -> Option<&'a str> {
+        if parsed.holds_cursor && self.lists(HOLD_CURSOR) {
+            return Some(HOLD_CURSOR);
+        }
+        if let Some(statement_type) = parsed.statement_types.iter().find(|name| self.lists(name)) {
+            return Some(statement_type);
+        }
+        parsed
+            .functions
+            .iter()
+            .find(|name| {
+                let unqualified = name.rsplit('.').next().unwrap_or(name);
+                self.lists(name) || self.lists(unqualified)
+            })
+            .map(String::as_str)
+    }
+
+    fn lists(&self, name: &str) -> bool {
+        self.session_only.iter().any(|listed| listed == name)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: On-disk format ----------------------------------------------
+
+#[derive(Debug, Clone, Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    policy: Option<PolicyFileEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PolicyFileEntry {
+    #[serde(default)]
+    deny_statements: Vec<String>,
+    session_only: Option<Vec<String>>,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("toml parse error: {source}")]
+    Toml { source: toml::de::Error },
+
+    #[error("[policy] deny_statements: '{0}' is not a statement type like \"DropStmt\"")]
+    UnknownStatementType(String),
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<PolicyConfig, PolicyError> {
+        PolicyConfig::from_document(&ConfigDocument::parse(raw).unwrap())
+    }
+
+    #[test]
+    fn denies_listed_statement_types() {
+        let raw = "[[users]]\nusername = \"pgcrab\"\npassword = \"pgcrab\"\n";
+        assert_eq!(parse(raw).unwrap(), PolicyConfig::default());
+
+        let raw = "[policy]\ndeny_statements = [\"DropStmt\", \"TruncateStmt\"]\n";
+        let policy = parse(raw).unwrap();
+        let types = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            policy.denied(&types(&["SelectStmt", "TruncateStmt"])),
+            Some("TruncateStmt")
+        );
+        assert_eq!(policy.denied(&types(&["SelectStmt"])), None);
+
+        let raw = "[policy]\ndeny_statements = [\"DROP\"]\n";
+        assert!(matches!(
+            parse(raw),
+            Err(PolicyError::UnknownStatementType(name)) if name == "DROP"
+        ));
+    }
+
+    #[test]
+    fn session_only_defaults_unless_listed() {
+        let listen = crate::parser::parse("LISTEN jobs").unwrap();
+        let set = crate::parser::parse("SET x = y").unwrap();
+
+        let policy = parse("[policy]\n").unwrap();
+        assert_eq!(policy.session_only(&listen), Some("ListenStmt"));
+        assert_eq!(policy.session_only(&set), None);
+
+        let raw = "[policy]\nsession_only = [\"VariableSetStmt\"]\n";
+        let policy = parse(raw).unwrap();
+        assert_eq!(policy.session_only(&listen), None);
+        assert_eq!(policy.session_only(&set), Some("VariableSetStmt"));
+    }
+
+    #[test]
+    fn session_only_catches_with_hold_cursors() {
+        let hold = crate::parser::parse("DECLARE c CURSOR WITH HOLD FOR SELECT 1").unwrap();
+        let plain = crate::parser::parse("DECLARE c CURSOR FOR SELECT 1").unwrap();
+
+        let policy = parse("[policy]\n").unwrap();
+        assert_eq!(policy.session_only(&hold), Some(HOLD_CURSOR));
+        assert_eq!(policy.session_only(&plain), None);
+    }
+
+    #[test]
+    fn session_only_functions_match_with_or_without_a_schema() {
+        let policy = parse("[policy]\n").unwrap();
+        let mut select = crate::parser::parse("SELECT 1").unwrap();
+        for name in ["pg_advisory_lock", "pg_catalog.pg_advisory_lock"] {
+            select.functions = vec![name.to_string()];
+            assert_eq!(policy.session_only(&select), Some(name));
+        }
+
+        select.functions = vec!["pg_catalog.now".to_string()];
+        assert_eq!(policy.session_only(&select), None);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+use super::document::ConfigDocument;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+const DEFAULT_SAMPLE_RATE: f64 = 1.0;
+
+// -----------------------------------------------------------------------------
+// ----- QueryLogConfig --------------------------------------------------------
+
+/// Sampled query log from the optional `[query_log]` table; off by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryLogConfig {
+    pub enabled: bool,
+    /// Fraction of queries considered for logging, 0.0 to 1.0.
+    pub sample_rate: f64,
+    /// Sampled queries faster than this are not logged.
+    pub min_duration: Duration,
+    /// Log literals as sent; otherwise they're replaced by `$n` placeholders.
+    pub log_parameters: bool,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            min_duration: Duration::ZERO,
+            log_parameters: false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- QueryLogConfig: Static ------------------------------------------------
+
+impl QueryLogConfig {
+    pub fn from_document(document: &ConfigDocument) -> Result<QueryLogConfig, QueryLogError> {
+        let doc: QueryLogFile = document
+            .section()
+            .map_err(|e| QueryLogError::Toml { source: e })?;
+        let Some(query_log) = doc.query_log else {
+            return Ok(QueryLogConfig::default());
+        };
+
+        let sample_rate = query_log.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+        if !(0.0..=1.0).contains(&sample_rate) {
+            return Err(QueryLogError::InvalidSampleRate(sample_rate));
+        }
+
+        Ok(QueryLogConfig {
+            enabled: query_log.enabled.unwrap_or(false),
+            sample_rate,
+            min_duration: Duration::from_millis(query_log.min_duration_ms.unwrap_or(0)),
+            log_parameters: query_log.log_parameters.unwrap_or(false),
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- QueryLogConfig: Public ------------------------------------------------
+
+impl QueryLogConfig {
+    /// Rolls the dice for one query.
+    pub fn should_sample(&self) -> bool {
+        self.enabled && self.sample_rate > 0.0 && rand::random::<f64>() < self.sample_rate
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: On-disk format ----------------------------------------------
+
+#[derive(Debug, Clone, Deserialize)]
+struct QueryLogFile {
+    #[serde(default)]
+    query_log: Option<QueryLogFileEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct QueryLogFileEntry {
+    enabled: Option<bool>,
+    sample_rate: Option<f64>,
+    min_duration_ms: Option<u64>,
+    log_parameters: Option<bool>,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum QueryLogError {
+    #[error("toml parse error: {source}")]
+    Toml { source: toml::de::Error },
+
+    #[error("[query_log] sample_rate must be between 0.0 and 1.0, got {0}")]
+    InvalidSampleRate(f64),
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<QueryLogConfig, QueryLogError> {
+        QueryLogConfig::from_document(&ConfigDocument::parse(raw).unwrap())
+    }
+
+    #[test]
+    fn missing_table_disables_the_log() {
+        let raw = "[[users]]\nusername = \"pgcrab\"\npassword = \"pgcrab\"\n";
+        let config = parse(raw).unwrap();
+        assert_eq!(config, QueryLogConfig::default());
+        assert!(!config.should_sample());
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let raw = r#"
+            [query_log]
+            enabled = true
+            sample_rate = 0.25
+            min_duration_ms = 150
+            log_parameters = true
+        "#;
+        let config = parse(raw).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.sample_rate, 0.25);
+        assert_eq!(config.min_duration, Duration::from_millis(150));
+        assert!(config.log_parameters);
+    }
+
+    #[test]
+    fn sample_rate_bounds_sampling() {
+        let raw = "[query_log]\nenabled = true\nsample_rate = 1.5\n";
+        assert!(matches!(
+            parse(raw),
+            Err(QueryLogError::InvalidSampleRate(_))
+        ));
+
+        let always = parse("[query_log]\nenabled = true\n").unwrap();
+        assert!((0..100).all(|_| always.should_sample()));
+
+        let never = parse("[query_log]\nenabled = true\nsample_rate = 0.0\n").unwrap();
+        assert!((0..100).all(|_| !never.should_sample()));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
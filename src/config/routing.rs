@@ -0,0 +1,199 @@
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::document::ConfigDocument;
+
+// -----------------------------------------------------------------------------
+// ----- RoutingConfig ---------------------------------------------------------
+
+/// Read/write splitting from the optional `[routing]` table; off unless
+/// `primary` is set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoutingConfig {
+    /// Shard taking writes, transactions and anything not known to be a read.
+    pub primary: Option<String>,
+    /// Shards a standalone `SELECT` may run on, picked at random.
+    pub read_replicas: Vec<String>,
+    /// Log each routing decision on the `pgcrab::routing` target.
+    pub trace: bool,
+}
+
+// -----------------------------------------------------------------------------
+// ----- RoutingConfig: Static -------------------------------------------------
+
+impl RoutingConfig {
+    pub fn from_document(document: &ConfigDocument) -> Result<RoutingConfig, RoutingError> {
+        let doc: RoutingFile = document
+            .section()
+            .map_err(|e| RoutingError::Toml { source: e })?;
+        let Some(routing) = doc.routing else {
+            return Ok(RoutingConfig::default());
+        };
+
+        let Some(primary) = routing.primary else {
+            if routing.read_replicas.is_empty() {
+                return Ok(RoutingConfig::default());
+            }
+            return Err(RoutingError::ReplicasWithoutPrimary);
+        };
+
+        if routing.read_replicas.contains(&primary) {
+            return Err(RoutingError::PrimaryIsReplica(primary));
+        }
+
+        let is_shard = |name: &String| doc.shards.iter().any(|shard| &shard.name == name);
+        if let Some(name) = std::iter::once(&primary)
+            .chain(&routing.read_replicas)
+            .find(|name| !is_shard(name))
+        {
+            return Err(RoutingError::UnknownShard(name.clone()));
+        }
+
+        Ok(RoutingConfig {
+            primary: Some(primary),
+            read_replicas: routing.read_replicas,
+            trace: routing.trace,
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- RoutingConfig: Public -------------------------------------------------
+
+impl RoutingConfig {
+    pub fn splits_reads(&self) -> bool {
+        self.primary.is_some() && !self.read_replicas.is_empty()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: On-disk format ----------------------------------------------
+
+#[derive(Debug, Clone, Deserialize)]
+struct RoutingFile {
+    #[serde(default)]
+    routing: Option<RoutingFileEntry>,
+    #[serde(default)]
+    shards: Vec<ShardName>,
+}
+
+/// A `[[shards]]` entry, for checking names against.
+#[derive(Debug, Clone, Deserialize)]
+struct ShardName {
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RoutingFileEntry {
+    primary: Option<String>,
+    #[serde(default)]
+    read_replicas: Vec<String>,
+    #[serde(default)]
+    trace: bool,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum RoutingError {
+    #[error("toml parse error: {source}")]
+    Toml { source: toml::de::Error },
+
+    #[error("[routing] read_replicas requires a primary")]
+    ReplicasWithoutPrimary,
+
+    #[error("[routing] shard '{0}' can't be both the primary and a read replica")]
+    PrimaryIsReplica(String),
+
+    #[error("[routing] names shard '{0}', which has no [[shards]] entry")]
+    UnknownShard(String),
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<RoutingConfig, RoutingError> {
+        RoutingConfig::from_document(&ConfigDocument::parse(raw).unwrap())
+    }
+
+    const ROUTING: &str = r#"
+        [routing]
+        primary = "main"
+        read_replicas = ["replica_a", "replica_b"]
+    "#;
+
+    /// Only the name matters here; `[[shards]]` checks the rest.
+    fn shard(name: &str) -> String {
+        format!("[[shards]]\nname = \"{name}\"\n")
+    }
+
+    #[test]
+    fn missing_table_routes_nothing() {
+        let raw = "[[users]]\nusername = \"pgcrab\"\npassword = \"pgcrab\"\n";
+        let config = parse(raw).unwrap();
+        assert_eq!(config, RoutingConfig::default());
+        assert!(!config.splits_reads());
+    }
+
+    #[test]
+    fn parses_primary_and_replicas() {
+        let raw = format!(
+            "{}{}{}{}",
+            ROUTING,
+            shard("main"),
+            shard("replica_a"),
+            shard("replica_b")
+        );
+        let config = parse(&raw).unwrap();
+        assert_eq!(config.primary.as_deref(), Some("main"));
+        assert_eq!(config.read_replicas, ["replica_a", "replica_b"]);
+        assert!(config.splits_reads());
+
+        let raw = format!("[routing]\nprimary = \"main\"\n{}", shard("main"));
+        let primary_only = parse(&raw).unwrap();
+        assert!(!primary_only.splits_reads());
+        assert!(!primary_only.trace);
+    }
+
+    #[test]
+    fn trace_needs_routing() {
+        let raw = format!(
+            "[routing]\nprimary = \"main\"\ntrace = true\n{}",
+            shard("main")
+        );
+        assert!(parse(&raw).unwrap().trace);
+
+        // Without a primary nothing is routed, so there is nothing to trace.
+        assert!(!parse("[routing]\ntrace = true\n").unwrap().trace);
+    }
+
+    #[test]
+    fn rejects_inconsistent_tables() {
+        let raw = "[routing]\nread_replicas = [\"replica_a\"]\n";
+        assert!(matches!(
+            parse(raw),
+            Err(RoutingError::ReplicasWithoutPrimary)
+        ));
+
+        let raw = "[routing]\nprimary = \"main\"\nread_replicas = [\"main\"]\n";
+        assert!(matches!(
+            parse(raw),
+            Err(RoutingError::PrimaryIsReplica(name)) if name == "main"
+        ));
+
+        // A typo'd name would otherwise fail every checkout routed to it.
+        let raw = format!("{ROUTING}{}{}", shard("main"), shard("replica_a"));
+        assert!(matches!(
+            parse(&raw),
+            Err(RoutingError::UnknownShard(name)) if name == "replica_b"
+        ));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
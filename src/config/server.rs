@@ -0,0 +1,642 @@
+use serde::Deserialize;
+use socket2::{SockRef, TcpKeepalive};
+use std::{
+    io::ErrorKind, net::SocketAddr, os::unix::fs::FileTypeExt, path::PathBuf, time::Duration,
+};
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpSocket, TcpStream, UnixListener, UnixSocket};
+
+use super::{document::ConfigDocument, types::LogLevel};
+use crate::frontend::channel_binding::{DEFAULT_SASL_MECHANISMS, sasl_mechanism};
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+const DEFAULT_BACKLOG: u32 = 1024;
+const DEFAULT_NODELAY: bool = true;
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+const DEFAULT_MAX_OUTBOX_BYTES: usize = 8 * 1024 * 1024;
+const DEFAULT_MAX_DECODE_FAILURES: u32 = 16;
+const DEFAULT_WARMUP_CONCURRENCY: u32 = 4;
+const DEFAULT_APPLICATION_NAME_PREFIX: &str = "pgcrab";
+
+// -----------------------------------------------------------------------------
+// ----- ServerConfig ----------------------------------------------------------
+
+/// Listener and client socket tuning from the optional `[server]` table.
+/// Re-read on reload, but a bound listener keeps its address and backlog,
+/// and a running server its `max_clients`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Overrides `--host`/`--port`; a change needs a restart.
+    pub listen_addr: Option<SocketAddr>,
+    /// Overrides `--log`; a change applies on reload.
+    pub log_level: Option<LogLevel>,
+    /// Also accept clients on this Unix socket; a change needs a restart.
+    pub unix_socket_path: Option<PathBuf>,
+    pub backlog: u32,
+    pub nodelay: bool,
+    /// Idle time before the first keepalive probe; `None` leaves keepalive off.
+    pub tcp_keepalive: Option<Duration>,
+    pub tcp_keepalive_interval: Option<Duration>,
+    /// On `::`, whether the listener also takes v4-mapped clients (clears
+    /// `IPV6_V6ONLY`); `None` leaves the OS default.
+    pub dual_stack: Option<bool>,
+    /// Largest client message, in bytes, the proxy will buffer.
+    pub max_message_size: usize,
+    /// Concurrent client connections; `None` is unbounded. A change needs a
+    /// restart.
+    pub max_clients: Option<usize>,
+    /// HINT on the 53300 sent to clients over `max_clients`, e.g. "retry in
+    /// 1s"; `None` sends none.
+    pub retry_after_hint: Option<String>,
+    /// Bytes queued for a client before backend reads pause until it drains.
+    pub max_outbox_bytes: usize,
+    /// Malformed client messages in a row before the connection is closed
+    /// with a protocol violation.
+    pub max_decode_failures: u32,
+    /// Check text Bind parameters for UTF-8 as they pass through. Off, the
+    /// bytes are relayed as sent and Postgres rejects bad ones instead.
+    pub validate_text_params: bool,
+    /// Backend connects in flight at once while the pools warm up at
+    /// startup, across every shard.
+    pub warmup_concurrency: u32,
+    /// Backend `application_name` is `<prefix>:<role>`, the role the backend
+    /// logs in as; `None` sends none.
+    pub application_name_prefix: Option<String>,
+    /// NoticeResponse sent to every client once it's authenticated, with
+    /// `{version}`, `{user}` and `{database}` filled in; `None` sends none.
+    pub connect_notice: Option<String>,
+    /// How long a client may sit idle inside a transaction before both its
+    /// connection and the backend's are closed; `None` never closes them.
+    /// `[[users]]` entries may override it.
+    pub idle_in_transaction_timeout: Option<Duration>,
+    /// How clients prove they know their `[[users]]` password.
+    pub client_auth: ClientAuth,
+    /// SCRAM mechanisms AuthenticationSASL offers, most preferred first.
+    pub sasl_mechanisms: Vec<&'static str>,
+}
+
+/// Client authentication, named after the `pg_hba.conf` methods.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum ClientAuth {
+    /// The password in cleartext.
+    #[default]
+    #[serde(rename = "password")]
+    Password,
+    /// SCRAM-SHA-256, or SCRAM-SHA-256-PLUS bound to the certificate for
+    /// clients on TLS.
+    #[serde(rename = "scram-sha-256")]
+    ScramSha256,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: None,
+            log_level: None,
+            unix_socket_path: None,
+            backlog: DEFAULT_BACKLOG,
+            nodelay: DEFAULT_NODELAY,
+            tcp_keepalive: None,
+            tcp_keepalive_interval: None,
+            dual_stack: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_clients: None,
+            retry_after_hint: None,
+            max_outbox_bytes: DEFAULT_MAX_OUTBOX_BYTES,
+            max_decode_failures: DEFAULT_MAX_DECODE_FAILURES,
+            validate_text_params: true,
+            warmup_concurrency: DEFAULT_WARMUP_CONCURRENCY,
+            application_name_prefix: Some(DEFAULT_APPLICATION_NAME_PREFIX.to_string()),
+            connect_notice: None,
+            idle_in_transaction_timeout: None,
+            client_auth: ClientAuth::default(),
+            sasl_mechanisms: DEFAULT_SASL_MECHANISMS.to_vec(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- ServerConfig: Static --------------------------------------------------
+
+impl ServerConfig {
+    pub fn from_document(document: &ConfigDocument) -> Result<ServerConfig, ServerError> {
+        let doc: ServerFile = document
+            .section()
+            .map_err(|e| ServerError::Toml { source: e })?;
+        let Some(server) = doc.server else {
+            return Ok(ServerConfig::default());
+        };
+
+        let tcp_keepalive = parse_duration("tcp_keepalive", server.tcp_keepalive)?;
+        let tcp_keepalive_interval =
+            parse_duration("tcp_keepalive_interval", server.tcp_keepalive_interval)?;
+
+        // Milliseconds, like the `[[users]]` override and Postgres' own
+        // setting.
+        if server.idle_in_transaction_timeout == Some(0) {
+            return Err(ServerError::ZeroIdleInTransactionTimeout);
+        }
+        let idle_in_transaction_timeout = server
+            .idle_in_transaction_timeout
+            .map(Duration::from_millis);
+
+        if tcp_keepalive.is_none() && tcp_keepalive_interval.is_some() {
+            return Err(ServerError::IntervalWithoutKeepalive);
+        }
+
+        let backlog = server.backlog.unwrap_or(DEFAULT_BACKLOG);
+        if backlog == 0 {
+            return Err(ServerError::ZeroBacklog);
+        }
+
+        let max_message_size = server.max_message_size.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+        if max_message_size == 0 {
+            return Err(ServerError::ZeroMaxMessageSize);
+        }
+
+        if server.max_clients == Some(0) {
+            return Err(ServerError::ZeroMaxClients);
+        }
+
+        let retry_after_hint = server.retry_after_hint.filter(|hint| !hint.is_empty());
+        if retry_after_hint
+            .as_ref()
+            .is_some_and(|hint| hint.contains('\0'))
+        {
+            return Err(ServerError::InvalidRetryAfterHint);
+        }
+
+        let max_outbox_bytes = server.max_outbox_bytes.unwrap_or(DEFAULT_MAX_OUTBOX_BYTES);
+        if max_outbox_bytes == 0 {
+            return Err(ServerError::ZeroMaxOutboxBytes);
+        }
+
+        let max_decode_failures = server
+            .max_decode_failures
+            .unwrap_or(DEFAULT_MAX_DECODE_FAILURES);
+        if max_decode_failures == 0 {
+            return Err(ServerError::ZeroMaxDecodeFailures);
+        }
+
+        let warmup_concurrency = server
+            .warmup_concurrency
+            .unwrap_or(DEFAULT_WARMUP_CONCURRENCY);
+        if warmup_concurrency == 0 {
+            return Err(ServerError::ZeroWarmupConcurrency);
+        }
+
+        let application_name_prefix = match server.application_name_prefix {
+            Some(prefix) if prefix.is_empty() => None,
+            Some(prefix) => Some(prefix),
+            None => Some(DEFAULT_APPLICATION_NAME_PREFIX.to_string()),
+        };
+
+        let connect_notice = server.connect_notice.filter(|notice| !notice.is_empty());
+        if connect_notice
+            .as_ref()
+            .is_some_and(|notice| notice.contains('\0'))
+        {
+            return Err(ServerError::InvalidConnectNotice);
+        }
+
+        let sasl_mechanisms = match server.sasl_mechanisms {
+            Some(names) => parse_sasl_mechanisms(names)?,
+            None => DEFAULT_SASL_MECHANISMS.to_vec(),
+        };
+
+        Ok(ServerConfig {
+            listen_addr: server.listen_addr,
+            log_level: server.log_level,
+            unix_socket_path: server.unix_socket_path,
+            backlog,
+            nodelay: server.nodelay.unwrap_or(DEFAULT_NODELAY),
+            tcp_keepalive,
+            tcp_keepalive_interval,
+            dual_stack: server.dual_stack,
+            max_message_size,
+            max_clients: server.max_clients,
+            retry_after_hint,
+            max_outbox_bytes,
+            max_decode_failures,
+            validate_text_params: server.validate_text_params.unwrap_or(true),
+            warmup_concurrency,
+            application_name_prefix,
+            connect_notice,
+            idle_in_transaction_timeout,
+            client_auth: server.client_auth.unwrap_or_default(),
+            sasl_mechanisms,
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- ServerConfig: Public --------------------------------------------------
+
+impl ServerConfig {
+    pub fn listen(&self, addr: SocketAddr) -> std::io::Result<TcpListener> {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+
+        if self.tcp_keepalive.is_some() {
+            socket.set_keepalive(true)?;
+        }
+
+        if let Some(dual_stack) = self.dual_stack
+            && addr.is_ipv6()
+            && addr.ip().is_unspecified()
+        {
+            SockRef::from(&socket).set_only_v6(!dual_stack)?;
+        }
+
+        socket.bind(addr)?;
+        socket.listen(self.backlog)
+    }
+
+    /// Binds `unix_socket_path`, if set. A socket file left behind by a
+    /// pgcrab that didn't shut down cleanly is removed first; one that still
+    /// accepts connections is in use and fails the bind.
+    pub fn listen_unix(&self) -> std::io::Result<Option<UnixListener>> {
+        let Some(path) = &self.unix_socket_path else {
+            return Ok(None);
+        };
+
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => {
+                if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                    return Err(std::io::Error::new(
+                        ErrorKind::AddrInUse,
+                        format!("{} is already accepting connections", path.display()),
+                    ));
+                }
+                std::fs::remove_file(path)?;
+            }
+            Ok(_) => {
+                return Err(std::io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let socket = UnixSocket::new_stream()?;
+        socket.bind(path)?;
+        socket.listen(self.backlog).map(Some)
+    }
+
+    /// Applies per-connection options to an accepted client stream.
+    pub fn apply_to_stream(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+
+        if let Some(keepalive) = self.keepalive() {
+            SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+        }
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- ServerConfig: Private -------------------------------------------------
+
+impl ServerConfig {
+    fn keepalive(&self) -> Option<TcpKeepalive> {
+        let mut keepalive = TcpKeepalive::new().with_time(self.tcp_keepalive?);
+        if let Some(interval) = self.tcp_keepalive_interval {
+            keepalive = keepalive.with_interval(interval);
+        }
+        Some(keepalive)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: On-disk format ----------------------------------------------
+
+#[derive(Debug, Clone, Deserialize)]
+struct ServerFile {
+    #[serde(default)]
+    server: Option<ServerFileEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ServerFileEntry {
+    listen_addr: Option<SocketAddr>,
+    log_level: Option<LogLevel>,
+    unix_socket_path: Option<PathBuf>,
+    backlog: Option<u32>,
+    nodelay: Option<bool>,
+    tcp_keepalive: Option<String>,
+    tcp_keepalive_interval: Option<String>,
+    dual_stack: Option<bool>,
+    max_message_size: Option<usize>,
+    max_clients: Option<usize>,
+    retry_after_hint: Option<String>,
+    max_outbox_bytes: Option<usize>,
+    max_decode_failures: Option<u32>,
+    validate_text_params: Option<bool>,
+    warmup_concurrency: Option<u32>,
+    application_name_prefix: Option<String>,
+    connect_notice: Option<String>,
+    idle_in_transaction_timeout: Option<u64>,
+    client_auth: Option<ClientAuth>,
+    sasl_mechanisms: Option<Vec<String>>,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: defaults/validation -----------------------------------------
+
+fn parse_sasl_mechanisms(names: Vec<String>) -> Result<Vec<&'static str>, ServerError> {
+    if names.is_empty() {
+        return Err(ServerError::NoSaslMechanisms);
+    }
+
+    let mut mechanisms = Vec::with_capacity(names.len());
+    for name in names {
+        let Some(mechanism) = sasl_mechanism(&name) else {
+            return Err(ServerError::UnknownSaslMechanism(name));
+        };
+        if mechanisms.contains(&mechanism) {
+            return Err(ServerError::DuplicateSaslMechanism(name));
+        }
+        mechanisms.push(mechanism);
+    }
+    Ok(mechanisms)
+}
+
+fn parse_duration(
+    field: &'static str,
+    value: Option<String>,
+) -> Result<Option<Duration>, ServerError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+
+    humantime::parse_duration(&value)
+        .map(Some)
+        .map_err(|source| ServerError::InvalidDuration {
+            field,
+            value,
+            source,
+        })
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("toml parse error: {source}")]
+    Toml { source: toml::de::Error },
+
+    #[error("invalid [server] {field} '{value}': {source}")]
+    InvalidDuration {
+        field: &'static str,
+        value: String,
+        source: humantime::DurationError,
+    },
+
+    #[error("[server] tcp_keepalive_interval requires tcp_keepalive")]
+    IntervalWithoutKeepalive,
+
+    #[error("[server] backlog must be greater than zero")]
+    ZeroBacklog,
+
+    #[error("[server] max_message_size must be greater than zero")]
+    ZeroMaxMessageSize,
+
+    #[error("[server] max_clients must be greater than zero")]
+    ZeroMaxClients,
+
+    #[error("[server] retry_after_hint may not contain NUL bytes")]
+    InvalidRetryAfterHint,
+
+    #[error("[server] max_outbox_bytes must be greater than zero")]
+    ZeroMaxOutboxBytes,
+
+    #[error("[server] max_decode_failures must be greater than zero")]
+    ZeroMaxDecodeFailures,
+
+    #[error("[server] warmup_concurrency must be greater than zero")]
+    ZeroWarmupConcurrency,
+
+    #[error("[server] connect_notice may not contain NUL bytes")]
+    InvalidConnectNotice,
+
+    #[error("[server] idle_in_transaction_timeout must be greater than zero")]
+    ZeroIdleInTransactionTimeout,
+
+    #[error("[server] sasl_mechanisms may not be empty")]
+    NoSaslMechanisms,
+
+    #[error("[server] sasl_mechanisms has unknown mechanism '{0}'")]
+    UnknownSaslMechanism(String),
+
+    #[error("[server] sasl_mechanisms lists '{0}' twice")]
+    DuplicateSaslMechanism(String),
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<ServerConfig, ServerError> {
+        ServerConfig::from_document(&ConfigDocument::parse(raw).unwrap())
+    }
+
+    #[test]
+    fn missing_server_table_keeps_defaults() {
+        let raw = "[[users]]\nusername = \"pgcrab\"\npassword = \"pgcrab\"\n";
+        assert_eq!(parse(raw).unwrap(), ServerConfig::default());
+        assert_eq!(ServerConfig::default().backlog, 1024);
+        assert!(ServerConfig::default().nodelay);
+        assert_eq!(ServerConfig::default().max_message_size, 64 * 1024 * 1024);
+        assert_eq!(
+            ServerConfig::default().application_name_prefix.as_deref(),
+            Some("pgcrab")
+        );
+
+        let raw = "[server]\napplication_name_prefix = \"\"\n";
+        assert_eq!(parse(raw).unwrap().application_name_prefix, None);
+    }
+
+    #[test]
+    fn rejects_interval_without_keepalive() {
+        let raw = "[server]\ntcp_keepalive_interval = \"5s\"\n";
+        assert!(matches!(
+            parse(raw),
+            Err(ServerError::IntervalWithoutKeepalive)
+        ));
+    }
+
+    #[test]
+    fn idle_in_transaction_timeout_is_positive_milliseconds() {
+        let raw = "[server]\nidle_in_transaction_timeout = 30_000\n";
+        assert_eq!(
+            parse(raw).unwrap().idle_in_transaction_timeout,
+            Some(Duration::from_secs(30))
+        );
+
+        let raw = "[server]\nidle_in_transaction_timeout = 0\n";
+        assert!(matches!(
+            parse(raw),
+            Err(ServerError::ZeroIdleInTransactionTimeout)
+        ));
+
+        let raw = "[server]\nidle_in_transaction_timeout = \"30s\"\n";
+        assert!(matches!(parse(raw), Err(ServerError::Toml { .. })));
+    }
+
+    #[test]
+    fn sasl_mechanisms_keep_the_configured_order() {
+        assert_eq!(
+            parse("").unwrap().sasl_mechanisms,
+            ["SCRAM-SHA-256-PLUS", "SCRAM-SHA-256"]
+        );
+        let raw = "[server]\nsasl_mechanisms = [\"SCRAM-SHA-256\", \"SCRAM-SHA-256-PLUS\"]\n";
+        assert_eq!(
+            parse(raw).unwrap().sasl_mechanisms,
+            ["SCRAM-SHA-256", "SCRAM-SHA-256-PLUS"]
+        );
+
+        assert!(matches!(
+            parse("[server]\nsasl_mechanisms = []\n"),
+            Err(ServerError::NoSaslMechanisms)
+        ));
+        assert!(matches!(
+            parse("[server]\nsasl_mechanisms = [\"SCRAM-SHA-1\"]\n"),
+            Err(ServerError::UnknownSaslMechanism(name)) if name == "SCRAM-SHA-1"
+        ));
+        assert!(matches!(
+            parse("[server]\nsasl_mechanisms = [\"SCRAM-SHA-256\", \"SCRAM-SHA-256\"]\n"),
+            Err(ServerError::DuplicateSaslMechanism(_))
+        ));
+    }
+
+    #[test]
+    fn client_auth_takes_pg_hba_method_names() {
+        assert_eq!(parse("").unwrap().client_auth, ClientAuth::Password);
+        let raw = "[server]\nclient_auth = \"scram-sha-256\"\n";
+        assert_eq!(parse(raw).unwrap().client_auth, ClientAuth::ScramSha256);
+        assert!(matches!(
+            parse("[server]\nclient_auth = \"md5\"\n"),
+            Err(ServerError::Toml { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_connect_notice_is_none() {
+        let raw = "[server]\nconnect_notice = \"via pgcrab {version}\"\n";
+        assert_eq!(
+            parse(raw).unwrap().connect_notice.as_deref(),
+            Some("via pgcrab {version}")
+        );
+
+        let raw = "[server]\nconnect_notice = \"\"\n";
+        assert_eq!(parse(raw).unwrap().connect_notice, None);
+    }
+
+    #[tokio::test]
+    async fn options_reach_the_accepted_stream() {
+        let raw = r#"
+            [server]
+            listen_addr = "127.0.0.1:6433"
+            log_level = "debug"
+            backlog = 16
+            nodelay = false
+            tcp_keepalive = "45s"
+            tcp_keepalive_interval = "7s"
+            max_message_size = 1048576
+            max_clients = 200
+            retry_after_hint = "retry in 1s"
+            max_outbox_bytes = 65536
+            max_decode_failures = 4
+            validate_text_params = false
+            warmup_concurrency = 2
+            application_name_prefix = "crabpool"
+        "#;
+        let config = parse(raw).unwrap();
+        assert_eq!(config.listen_addr, Some("127.0.0.1:6433".parse().unwrap()));
+        assert_eq!(config.log_level, Some(LogLevel::Debug));
+        assert_eq!(config.backlog, 16);
+        assert_eq!(config.tcp_keepalive, Some(Duration::from_secs(45)));
+        assert_eq!(config.tcp_keepalive_interval, Some(Duration::from_secs(7)));
+        assert_eq!(config.max_message_size, 1024 * 1024);
+        assert_eq!(config.max_clients, Some(200));
+        assert_eq!(config.retry_after_hint.as_deref(), Some("retry in 1s"));
+        assert_eq!(config.max_outbox_bytes, 64 * 1024);
+        assert_eq!(config.max_decode_failures, 4);
+        assert!(!config.validate_text_params);
+        assert_eq!(config.warmup_concurrency, 2);
+        assert_eq!(config.application_name_prefix.as_deref(), Some("crabpool"));
+
+        let listener = config.listen("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        config.apply_to_stream(&stream).unwrap();
+
+        let socket = SockRef::from(&stream);
+        assert!(!stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(
+            socket.tcp_keepalive_time().unwrap(),
+            Duration::from_secs(45)
+        );
+        assert_eq!(
+            socket.tcp_keepalive_interval().unwrap(),
+            Duration::from_secs(7)
+        );
+    }
+
+    #[tokio::test]
+    async fn dual_stack_sets_ipv6_only() {
+        for dual_stack in [true, false] {
+            let raw = format!("[server]\ndual_stack = {dual_stack}\n");
+            let config = parse(&raw).unwrap();
+            assert_eq!(config.dual_stack, Some(dual_stack));
+
+            // Host without IPv6: nothing to check.
+            let Ok(listener) = config.listen("[::]:0".parse().unwrap()) else {
+                return;
+            };
+            assert_eq!(SockRef::from(&listener).only_v6().unwrap(), !dual_stack);
+        }
+    }
+
+    #[tokio::test]
+    async fn unix_socket_replaces_a_stale_file_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".s.PGSQL.6432");
+        let raw = format!("[server]\nunix_socket_path = {:?}\n", path);
+        let config = parse(&raw).unwrap();
+        assert_eq!(config.unix_socket_path.as_deref(), Some(path.as_path()));
+        assert!(ServerConfig::default().listen_unix().unwrap().is_none());
+
+        // Left behind by a listener that is gone.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let listener = config.listen_unix().unwrap().expect("listener");
+        let _client = tokio::net::UnixStream::connect(&path).await.unwrap();
+        listener.accept().await.unwrap();
+
+        let err = config.listen_unix().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AddrInUse);
+
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, "not a socket").unwrap();
+        let err = config.listen_unix().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
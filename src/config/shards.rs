@@ -2,7 +2,12 @@ use once_cell::sync::OnceCell;
 use parking_lot::RwLock;
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 use thiserror::Error;
 use tokio::fs;
 use tracing::error;
@@ -12,6 +17,66 @@ use tracing::error;
 
 const DEFAULT_MIN_CONNECTIONS: u32 = 5;
 const DEFAULT_MAX_CONNECTIONS: u32 = 20;
+const DEFAULT_SERVER_RESET_QUERY: &str = "DISCARD ALL";
+const DEFAULT_CONNECT_RETRIES: u32 = 2;
+const DEFAULT_CONNECT_BACKOFF_MS: u64 = 100;
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_CHECKOUT_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_IDLE_LIFETIME_MS: u64 = 600_000;
+const DEFAULT_SERVER_LIFETIME_MS: u64 = 3_600_000;
+const DEFAULT_WEIGHT: u32 = 1;
+
+/// Startup parameters pgcrab sets itself from the shard entry.
+const RESERVED_OPTIONS: [&str; 2] = ["user", "database"];
+
+/// Startup keys that change the protocol rather than set a server setting;
+/// `_pq_.*` protocol options are refused along with them.
+const PROTOCOL_OPTIONS: [&str; 2] = ["replication", "options"];
+
+/// libpq connection keywords. Postgres reads any other startup key as a
+/// setting, so one of these would fail every connect to the shard.
+const LIBPQ_OPTIONS: &[&str] = &[
+    "host",
+    "hostaddr",
+    "port",
+    "dbname",
+    "password",
+    "passfile",
+    "require_auth",
+    "channel_binding",
+    "connect_timeout",
+    "fallback_application_name",
+    "keepalives",
+    "keepalives_idle",
+    "keepalives_interval",
+    "keepalives_count",
+    "tcp_user_timeout",
+    "sslmode",
+    "requiressl",
+    "sslnegotiation",
+    "sslcompression",
+    "sslcert",
+    "sslkey",
+    "sslpassword",
+    "sslcertmode",
+    "sslrootcert",
+    "sslcrl",
+    "sslcrldir",
+    "sslsni",
+    "requirepeer",
+    "ssl_min_protocol_version",
+    "ssl_max_protocol_version",
+    "min_protocol_version",
+    "max_protocol_version",
+    "gssencmode",
+    "krbsrvname",
+    "gsslib",
+    "gssdelegation",
+    "service",
+    "target_session_attrs",
+    "load_balance_hosts",
+];
 
 // -----------------------------------------------------------------------------
 // ----- Singleton -------------------------------------------------------------
@@ -90,7 +155,7 @@ impl ShardsConfig {
         Self::parse(&raw)
     }
 
-    fn parse(raw: &str) -> Result<ShardsConfig, ShardsError> {
+    pub(super) fn parse(raw: &str) -> Result<ShardsConfig, ShardsError> {
         let mut doc: ShardsFile =
             toml::from_str(raw).map_err(|e| ShardsError::Toml { source: e })?;
 
@@ -108,6 +173,45 @@ impl ShardsConfig {
                 password: SecretString::new(shard.password.into_boxed_str()),
                 min_connections: shard.min_connections.unwrap(),
                 max_connections: shard.max_connections.unwrap(),
+                server_reset_query: shard.server_reset_query.unwrap(),
+                connect_retry: ConnectRetryPolicy {
+                    retries: shard.connect_retries.unwrap_or(DEFAULT_CONNECT_RETRIES),
+                    backoff: Duration::from_millis(
+                        shard.connect_backoff.unwrap_or(DEFAULT_CONNECT_BACKOFF_MS),
+                    ),
+                    timeout: Duration::from_millis(
+                        shard.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT_MS),
+                    ),
+                },
+                handshake_timeout: Duration::from_millis(
+                    shard
+                        .handshake_timeout
+                        .unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT_MS),
+                ),
+                checkout_timeout: Duration::from_millis(
+                    shard
+                        .checkout_timeout
+                        .unwrap_or(DEFAULT_CHECKOUT_TIMEOUT_MS),
+                ),
+                idle_lifetime: Duration::from_millis(
+                    shard.idle_lifetime.unwrap_or(DEFAULT_IDLE_LIFETIME_MS),
+                ),
+                server_lifetime: Duration::from_millis(
+                    shard.server_lifetime.unwrap_or(DEFAULT_SERVER_LIFETIME_MS),
+                ),
+                sslmode: shard.sslmode.unwrap_or_default(),
+                sslrootcert: shard.sslrootcert,
+                shared_prepared_statements: shard.shared_prepared_statements.unwrap_or(false),
+                max_prepared_statements: shard.max_prepared_statements,
+                describe_cache_size: shard.describe_cache_size,
+                test_on_checkout: shard.test_on_checkout.unwrap_or(false),
+                weight: shard.weight.unwrap_or(DEFAULT_WEIGHT),
+                options: shard.options,
+                on_connect: shard
+                    .on_connect
+                    .into_iter()
+                    .filter(|statement| !statement.trim().is_empty())
+                    .collect(),
             };
 
             if by_name.insert(record.shard_name.clone(), record).is_some() {
@@ -147,6 +251,31 @@ struct ShardFileEntry {
     password: String,
     min_connections: Option<u32>,
     max_connections: Option<u32>,
+    server_reset_query: Option<String>,
+    connect_retries: Option<u32>,
+    /// Milliseconds.
+    connect_backoff: Option<u64>,
+    /// Milliseconds.
+    connect_timeout: Option<u64>,
+    /// Milliseconds.
+    handshake_timeout: Option<u64>,
+    /// Milliseconds.
+    checkout_timeout: Option<u64>,
+    /// Milliseconds.
+    idle_lifetime: Option<u64>,
+    /// Milliseconds.
+    server_lifetime: Option<u64>,
+    sslmode: Option<SslMode>,
+    sslrootcert: Option<PathBuf>,
+    shared_prepared_statements: Option<bool>,
+    max_prepared_statements: Option<usize>,
+    describe_cache_size: Option<usize>,
+    test_on_checkout: Option<bool>,
+    weight: Option<u32>,
+    #[serde(default)]
+    options: BTreeMap<String, String>,
+    #[serde(default)]
+    on_connect: Vec<String>,
 }
 
 // -----------------------------------------------------------------------------
@@ -161,6 +290,42 @@ pub struct ShardRecord {
     pub password: SecretString,
     pub min_connections: u32,
     pub max_connections: u32,
+    /// Run before a backend goes back to the pool; empty disables the reset.
+    pub server_reset_query: String,
+    pub connect_retry: ConnectRetryPolicy,
+    /// Bounds startup and auth with a backend once it accepted the
+    /// connection; `connect_retry.timeout` only covers reaching it.
+    pub handshake_timeout: Duration,
+    /// How long a checkout waits for a backend once the pool is at
+    /// `max_connections`.
+    pub checkout_timeout: Duration,
+    /// Idle backends above `min_connections` are closed after this long.
+    pub idle_lifetime: Duration,
+    /// Backends older than this are closed instead of reused.
+    pub server_lifetime: Duration,
+    pub sslmode: SslMode,
+    /// PEM bundle of CAs trusted for `verify-full`.
+    pub sslrootcert: Option<PathBuf>,
+    /// Sessions on one backend reuse each other's prepared statements.
+    pub shared_prepared_statements: bool,
+    /// Prepared statements kept on each backend; past it the least recently
+    /// used one is closed. `None` keeps them all.
+    pub max_prepared_statements: Option<usize>,
+    /// Statement descriptions kept for replay, so a repeated Describe of
+    /// the same statement never reaches a backend. `None` disables it.
+    pub describe_cache_size: Option<usize>,
+    /// Idle backends answer a `SELECT 1` before a client gets them; one that
+    /// doesn't is closed and a new backend opened in its place.
+    pub test_on_checkout: bool,
+    /// Share of randomly routed checkouts, relative to the other shards':
+    /// weight 2 gets twice the traffic of weight 1.
+    pub weight: u32,
+    /// Extra startup parameters for the backend, e.g. `search_path` or
+    /// `statement_timeout`.
+    pub options: BTreeMap<String, String>,
+    /// Statements run on each new backend after startup, and again after
+    /// every `server_reset_query`, e.g. `SET search_path TO app`.
+    pub on_connect: Vec<String>,
 }
 
 impl ShardRecord {
@@ -169,6 +334,81 @@ impl ShardRecord {
     }
 }
 
+/// Every optional key at its `[[shards]]` default; the name, host, user and
+/// password are left empty for the caller to fill in.
+impl Default for ShardRecord {
+    fn default() -> Self {
+        Self {
+            shard_name: String::new(),
+            host: String::new(),
+            port: 5432,
+            user: String::new(),
+            password: SecretString::new(String::new().into_boxed_str()),
+            min_connections: DEFAULT_MIN_CONNECTIONS,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            server_reset_query: DEFAULT_SERVER_RESET_QUERY.to_string(),
+            connect_retry: ConnectRetryPolicy::default(),
+            handshake_timeout: Duration::from_millis(DEFAULT_HANDSHAKE_TIMEOUT_MS),
+            checkout_timeout: Duration::from_millis(DEFAULT_CHECKOUT_TIMEOUT_MS),
+            idle_lifetime: Duration::from_millis(DEFAULT_IDLE_LIFETIME_MS),
+            server_lifetime: Duration::from_millis(DEFAULT_SERVER_LIFETIME_MS),
+            sslmode: SslMode::default(),
+            sslrootcert: None,
+            shared_prepared_statements: false,
+            max_prepared_statements: None,
+            describe_cache_size: None,
+            test_on_checkout: false,
+            weight: DEFAULT_WEIGHT,
+            options: BTreeMap::new(),
+            on_connect: Vec::new(),
+        }
+    }
+}
+
+/// TLS to the backend, named after libpq's `sslmode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    /// Plaintext.
+    #[default]
+    Disable,
+    /// Encrypt, but accept any server certificate.
+    Require,
+    /// Encrypt, and check the certificate chains to `sslrootcert` and
+    /// names the shard's `host`.
+    VerifyFull,
+}
+
+/// Retries for failed TCP connects to a shard. Waits grow as
+/// `backoff * 2^attempt` with jitter, and the whole connect, retries
+/// included, gives up after `timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectRetryPolicy {
+    pub retries: u32,
+    pub backoff: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for ConnectRetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_CONNECT_RETRIES,
+            backoff: Duration::from_millis(DEFAULT_CONNECT_BACKOFF_MS),
+            timeout: Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MS),
+        }
+    }
+}
+
+impl ConnectRetryPolicy {
+    /// Wait before retry number `attempt` (0-based): the exponential step,
+    /// minus up to half of it at random.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let step = self.backoff.saturating_mul(1u32 << attempt.min(16));
+        let jitter = rand::random_range(0.0..=0.5);
+        step.mul_f64(1.0 - jitter)
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- Internal: defaults/validation -----------------------------------------
 
@@ -180,6 +420,10 @@ fn normalize_defaults(shard: &mut ShardFileEntry) {
     if shard.max_connections.is_none() {
         shard.max_connections = Some(DEFAULT_MAX_CONNECTIONS);
     }
+
+    if shard.server_reset_query.is_none() {
+        shard.server_reset_query = Some(DEFAULT_SERVER_RESET_QUERY.to_string());
+    }
 }
 
 fn validate(shard: &ShardFileEntry) -> Result<(), ShardsError> {
@@ -194,6 +438,62 @@ fn validate(shard: &ShardFileEntry) -> Result<(), ShardsError> {
         });
     }
 
+    if shard.connect_timeout == Some(0) {
+        return Err(ShardsError::ZeroConnectTimeout {
+            name: shard.name.clone(),
+        });
+    }
+
+    if shard.handshake_timeout == Some(0) {
+        return Err(ShardsError::ZeroHandshakeTimeout {
+            name: shard.name.clone(),
+        });
+    }
+
+    if shard.checkout_timeout == Some(0) {
+        return Err(ShardsError::ZeroCheckoutTimeout {
+            name: shard.name.clone(),
+        });
+    }
+
+    if shard.sslmode == Some(SslMode::VerifyFull) && shard.sslrootcert.is_none() {
+        return Err(ShardsError::MissingRootCert {
+            name: shard.name.clone(),
+        });
+    }
+
+    if shard.max_prepared_statements == Some(0) {
+        return Err(ShardsError::ZeroMaxPreparedStatements {
+            name: shard.name.clone(),
+        });
+    }
+
+    if shard.describe_cache_size == Some(0) {
+        return Err(ShardsError::ZeroDescribeCacheSize {
+            name: shard.name.clone(),
+        });
+    }
+
+    if shard.weight == Some(0) {
+        return Err(ShardsError::ZeroWeight {
+            name: shard.name.clone(),
+        });
+    }
+
+    for key in shard.options.keys() {
+        let name = shard.name.clone();
+        let key = key.clone();
+        if RESERVED_OPTIONS.contains(&key.as_str()) {
+            return Err(ShardsError::ReservedOption { name, key });
+        }
+        if PROTOCOL_OPTIONS.contains(&key.as_str()) || key.starts_with("_pq_.") {
+            return Err(ShardsError::ProtocolOption { name, key });
+        }
+        if LIBPQ_OPTIONS.contains(&key.as_str()) {
+            return Err(ShardsError::LibpqOption { name, key });
+        }
+    }
+
     Ok(())
 }
 
@@ -216,6 +516,78 @@ pub enum ShardsError {
 
     #[error("invalid connection limits for shard '{name}': min={min} max={max}")]
     InvalidConnectionLimits { name: String, min: u32, max: u32 },
+
+    #[error("connect_timeout for shard '{name}' must be greater than zero")]
+    ZeroConnectTimeout { name: String },
+
+    #[error("handshake_timeout for shard '{name}' must be greater than zero")]
+    ZeroHandshakeTimeout { name: String },
+
+    #[error("checkout_timeout for shard '{name}' must be greater than zero")]
+    ZeroCheckoutTimeout { name: String },
+
+    #[error("max_prepared_statements for shard '{name}' must be greater than zero")]
+    ZeroMaxPreparedStatements { name: String },
+
+    #[error("describe_cache_size for shard '{name}' must be greater than zero")]
+    ZeroDescribeCacheSize { name: String },
+
+    #[error("weight for shard '{name}' must be greater than zero")]
+    ZeroWeight { name: String },
+
+    #[error("sslmode = \"verify-full\" for shard '{name}' requires sslrootcert")]
+    MissingRootCert { name: String },
+
+    #[error("options for shard '{name}' may not set '{key}'")]
+    ReservedOption { name: String, key: String },
+
+    #[error("options for shard '{name}' may not set protocol option '{key}'")]
+    ProtocolOption { name: String, key: String },
+
+    #[error(
+        "options for shard '{name}' may not set '{key}': it is a libpq connection \
+         setting, not a server one"
+    )]
+    LibpqOption { name: String, key: String },
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_option(key: &str) -> Result<ShardsConfig, ShardsError> {
+        ShardsConfig::parse(&format!(
+            "[[shards]]\nname = \"alpha\"\nhost = \"127.0.0.1\"\nport = 5432\n\
+             user = \"user\"\npassword = \"secret\"\n\
+             [shards.options]\n\"{key}\" = \"x\"\n"
+        ))
+    }
+
+    #[test]
+    fn options_only_take_server_settings() {
+        assert!(with_option("search_path").is_ok());
+        assert!(with_option("application_name").is_ok());
+
+        assert!(matches!(
+            with_option("database"),
+            Err(ShardsError::ReservedOption { .. })
+        ));
+        for key in ["replication", "options", "_pq_.compression"] {
+            assert!(
+                matches!(with_option(key), Err(ShardsError::ProtocolOption { .. })),
+                "{key}"
+            );
+        }
+        for key in ["sslmode", "connect_timeout", "target_session_attrs"] {
+            assert!(
+                matches!(with_option(key), Err(ShardsError::LibpqOption { .. })),
+                "{key}"
+            );
+        }
+    }
 }
 
 // -----------------------------------------------------------------------------
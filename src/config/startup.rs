@@ -0,0 +1,259 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use super::document::ConfigDocument;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Startup parameters passed on to the backend unless `[startup]` says
+/// otherwise.
+const DEFAULT_ALLOWED_PARAMS: &[&str] = &[
+    "application_name",
+    "client_encoding",
+    "DateStyle",
+    "TimeZone",
+    "extra_float_digits",
+    "search_path",
+];
+
+/// ParameterStatus values sent to clients after authentication, unless the
+/// backends or `[startup.parameter_status]` report otherwise. Stricter
+/// drivers refuse to connect without some of these.
+const DEFAULT_PARAMETER_STATUS: &[(&str, &str)] = &[
+    ("server_version", "16.0"),
+    ("server_encoding", "UTF8"),
+    ("client_encoding", "UTF8"),
+    ("DateStyle", "ISO, MDY"),
+    ("IntervalStyle", "postgres"),
+    ("TimeZone", "UTC"),
+    ("integer_datetimes", "on"),
+    ("standard_conforming_strings", "on"),
+];
+
+/// Startup key, or `-c` setting in `options`, naming the shard a client
+/// wants all its queries on, whatever `[routing]` says.
+pub const SHARD_PIN_PARAM: &str = "pgcrab.shard";
+
+/// Startup keys the proxy reads itself; they're never session settings.
+const PROTOCOL_PARAMS: &[&str] = &[
+    "user",
+    "database",
+    "replication",
+    "options",
+    SHARD_PIN_PARAM,
+];
+
+// -----------------------------------------------------------------------------
+// ----- StartupConfig ---------------------------------------------------------
+
+/// Client startup parameters the optional `[startup]` table lets through to
+/// the backend; the rest are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartupConfig {
+    pub allowed_params: Vec<String>,
+    /// `[startup.parameter_status]` overrides, reported to every client.
+    pub parameter_status: BTreeMap<String, String>,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            allowed_params: DEFAULT_ALLOWED_PARAMS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            parameter_status: BTreeMap::new(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- StartupConfig: Static -------------------------------------------------
+
+impl StartupConfig {
+    pub fn from_document(document: &ConfigDocument) -> Result<StartupConfig, StartupError> {
+        let doc: StartupFile = document
+            .section()
+            .map_err(|e| StartupError::Toml { source: e })?;
+        let Some(startup) = doc.startup else {
+            return Ok(StartupConfig::default());
+        };
+
+        let mut config = StartupConfig::default();
+        if let Some(allowed_params) = startup.allowed_params {
+            for name in &allowed_params {
+                if !is_setting_name(name) || is_protocol_param(name) {
+                    return Err(StartupError::InvalidParam(name.clone()));
+                }
+            }
+            config.allowed_params = allowed_params;
+        }
+
+        for (name, value) in &startup.parameter_status {
+            if !is_setting_name(name) || value.contains('\0') {
+                return Err(StartupError::InvalidParameterStatus(name.clone()));
+            }
+        }
+        config.parameter_status = startup.parameter_status;
+
+        Ok(config)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- StartupConfig: Public -------------------------------------------------
+
+impl StartupConfig {
+    /// Setting names are case-insensitive, as in Postgres.
+    pub fn allows(&self, name: &str) -> bool {
+        self.allowed_params
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(name))
+    }
+
+    /// ParameterStatus values for a client after authentication: the
+    /// defaults, then what the backends reported (`observed`), then
+    /// `[startup.parameter_status]`.
+    pub fn parameter_status(&self, observed: &BTreeMap<String, String>) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = DEFAULT_PARAMETER_STATUS
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        for (name, value) in observed.iter().chain(&self.parameter_status) {
+            params.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+            params.push((name.clone(), value.clone()));
+        }
+        params
+    }
+}
+
+/// `user`, `database` and the like, which the proxy handles itself, and
+/// `_pq_.*` protocol options.
+pub fn is_protocol_param(name: &str) -> bool {
+    name.starts_with("_pq_.") || PROTOCOL_PARAMS.contains(&name)
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: Helpers -----------------------------------------------------
+
+fn is_setting_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'.')
+}
+
+// -----------------------------------------------------------------------------
+// ----- Internal: On-disk format ----------------------------------------------
+
+#[derive(Debug, Clone, Deserialize)]
+struct StartupFile {
+    #[serde(default)]
+    startup: Option<StartupFileEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StartupFileEntry {
+    allowed_params: Option<Vec<String>>,
+    #[serde(default)]
+    parameter_status: BTreeMap<String, String>,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error("toml parse error: {source}")]
+    Toml { source: toml::de::Error },
+
+    #[error("[startup] allowed_params can't include '{0}'")]
+    InvalidParam(String),
+
+    #[error("[startup.parameter_status] can't report '{0}'")]
+    InvalidParameterStatus(String),
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<StartupConfig, StartupError> {
+        StartupConfig::from_document(&ConfigDocument::parse(raw).unwrap())
+    }
+
+    #[test]
+    fn missing_table_allows_the_defaults() {
+        let raw = "[[users]]\nusername = \"pgcrab\"\npassword = \"pgcrab\"\n";
+        let config = parse(raw).unwrap();
+        assert_eq!(config, StartupConfig::default());
+        assert!(config.allows("application_name"));
+        assert!(config.allows("datestyle"));
+        assert!(!config.allows("work_mem"));
+    }
+
+    #[test]
+    fn parses_a_custom_list() {
+        let raw = "[startup]\nallowed_params = [\"application_name\", \"myapp.tenant\"]\n";
+        let config = parse(raw).unwrap();
+        assert!(config.allows("myapp.tenant"));
+        assert!(!config.allows("search_path"));
+
+        let none = parse("[startup]\nallowed_params = []\n").unwrap();
+        assert!(!none.allows("application_name"));
+    }
+
+    #[test]
+    fn parameter_status_layers_backends_then_config_over_defaults() {
+        let raw = "[startup.parameter_status]\nserver_version = \"15.4-custom\"\ndatestyle = \"ISO, DMY\"\n";
+        let config = parse(raw).unwrap();
+        assert_eq!(
+            config.allowed_params,
+            StartupConfig::default().allowed_params
+        );
+
+        let observed = BTreeMap::from([
+            ("server_version".to_string(), "16.2".to_string()),
+            ("TimeZone".to_string(), "Europe/Paris".to_string()),
+        ]);
+        let params = config.parameter_status(&observed);
+        let get = |name: &str| {
+            let matching: Vec<_> = params
+                .iter()
+                .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .collect();
+            assert!(matching.len() <= 1, "{name} reported twice");
+            matching.first().copied()
+        };
+        assert_eq!(get("standard_conforming_strings"), Some("on"));
+        assert_eq!(get("TimeZone"), Some("Europe/Paris"));
+        assert_eq!(get("server_version"), Some("15.4-custom"));
+        assert_eq!(get("DateStyle"), Some("ISO, DMY"));
+
+        let raw = "[startup.parameter_status]\n\"bad name\" = \"on\"\n";
+        assert!(matches!(
+            parse(raw),
+            Err(StartupError::InvalidParameterStatus(name)) if name == "bad name"
+        ));
+    }
+
+    #[test]
+    fn rejects_protocol_keys_and_odd_names() {
+        for name in ["user", "_pq_.compression", "search path", ""] {
+            let raw = format!("[startup]\nallowed_params = [\"{name}\"]\n");
+            assert!(
+                matches!(parse(&raw), Err(StartupError::InvalidParam(bad)) if bad == name),
+                "{name}"
+            );
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
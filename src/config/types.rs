@@ -1,7 +1,11 @@
+use serde::Deserialize;
+use std::net::SocketAddr;
+
 // -------------------------------------------------------------------------------------------------
 // ---- LogLevel -----------------------------------------------------------------------------------
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Trace,
     Debug,
@@ -22,5 +26,47 @@ impl LogLevel {
     }
 }
 
+// -------------------------------------------------------------------------------------------------
+// ---- LogFormat ----------------------------------------------------------------------------------
+
+#[derive(clap::ValueEnum, Clone, Debug, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// ---- ListenAddr ---------------------------------------------------------------------------------
+
+/// Where the client listener binds, and whether `[server] listen_addr` may
+/// move it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListenAddr {
+    /// `--host`/`--port`: `[server] listen_addr` wins.
+    Default(SocketAddr),
+    /// Set by an embedder: wins over `[server] listen_addr`.
+    Fixed(SocketAddr),
+}
+
+impl ListenAddr {
+    /// The address to bind, given the config file's `[server] listen_addr`.
+    pub fn resolve(self, configured: Option<SocketAddr>) -> SocketAddr {
+        match self {
+            ListenAddr::Default(addr) => configured.unwrap_or(addr),
+            ListenAddr::Fixed(addr) => addr,
+        }
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 // -------------------------------------------------------------------------------------------------
@@ -70,25 +70,46 @@ impl UsersConfig {
 // ----- UsersConfig: Public ---------------------------------------------------
 
 impl UsersConfig {
+    /// Checks a cleartext password against `lookup`'s entry.
     pub fn authenticate(
         &self,
         client_username: &str,
         client_password: &str,
+        database: &str,
     ) -> Result<UserRecord, UsersError> {
-        let key = UserKey::new(client_username);
+        let user = self.lookup(client_username, database)?;
+        if user.client_password.expose_secret() != client_password {
+            return Err(UsersError::BadPassword);
+        }
 
+        Ok(user)
+    }
+
+    /// Prefers the entry bound to `database`; an entry with no `database`
+    /// matches any database.
+    pub fn lookup(&self, client_username: &str, database: &str) -> Result<UserRecord, UsersError> {
         let guard = self.inner.read();
         let user = guard
             .by_key
-            .get(&key)
-            .ok_or_else(|| UsersError::UnknownUser {
-                username: client_username.to_string(),
+            .get(&UserKey::new(client_username, Some(database)))
+            .or_else(|| guard.by_key.get(&UserKey::new(client_username, None)))
+            .ok_or_else(|| {
+                let known = guard
+                    .by_key
+                    .keys()
+                    .any(|key| key.client_username == client_username);
+                if known {
+                    UsersError::DatabaseNotAllowed {
+                        username: client_username.to_string(),
+                        database: database.to_string(),
+                    }
+                } else {
+                    UsersError::UnknownUser {
+                        username: client_username.to_string(),
+                    }
+                }
             })?;
 
-        if user.client_password.expose_secret() != client_password {
-            return Err(UsersError::BadPassword);
-        }
-
         Ok(user.clone())
     }
 }
@@ -105,7 +126,7 @@ impl UsersConfig {
         Self::parse(&raw)
     }
 
-    fn parse(raw: &str) -> Result<UsersConfig, UsersError> {
+    pub(super) fn parse(raw: &str) -> Result<UsersConfig, UsersError> {
         let mut doc: UsersFile = toml::from_str(raw).map_err(|e| UsersError::Toml { source: e })?;
 
         if doc.users.is_empty() {
@@ -113,10 +134,12 @@ impl UsersConfig {
         }
 
         let mut by_key = HashMap::with_capacity(doc.users.len());
+        let mut role_passwords: HashMap<String, String> = HashMap::new();
         for mut user in doc.users.drain(..) {
             normalize_defaults(&mut user);
             validate(&user)?;
 
+            let server_username_set = user.server_username.is_some();
             let server_username = user
                 .server_username
                 .clone()
@@ -126,20 +149,39 @@ impl UsersConfig {
                 .clone()
                 .unwrap_or_else(|| user.password.clone());
 
+            // Every entry mapped to a role shares its pools, which log in
+            // with one password.
+            if server_username_set
+                && let Some(known) =
+                    role_passwords.insert(server_username.clone(), server_password.clone())
+                && known != server_password
+            {
+                return Err(UsersError::ConflictingServerPassword {
+                    role: server_username,
+                });
+            }
+
             let record = UserRecord {
                 client_username: user.username.clone(),
+                database: user.database.clone(),
 
                 client_password: SecretString::new(user.password.into_boxed_str()),
                 server_username,
                 server_password: SecretString::new(server_password.into_boxed_str()),
+                server_username_set,
 
                 pool_size: user.pool_size,
                 pooler_mode: user.pooler_mode,
                 statement_timeout: user.statement_timeout,
+                idle_in_transaction_timeout: user.idle_in_transaction_timeout,
                 admin: user.admin,
+                rate_limit: user.queries_per_second.map(|queries_per_second| RateLimit {
+                    queries_per_second,
+                    burst: user.burst.unwrap_or(queries_per_second),
+                }),
             };
 
-            let key = UserKey::new(&record.client_username);
+            let key = UserKey::new(&record.client_username, record.database.as_deref());
             if by_key.insert(key, record).is_some() {
                 return Err(UsersError::DuplicateUser {
                     username: user.username,
@@ -161,15 +203,18 @@ struct UsersMap {
     by_key: HashMap<UserKey, UserRecord>,
 }
 
+/// `database: None` is the entry for any database.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct UserKey {
     client_username: String,
+    database: Option<String>,
 }
 
 impl UserKey {
-    fn new(client_username: &str) -> Self {
+    fn new(client_username: &str, database: Option<&str>) -> Self {
         Self {
             client_username: client_username.to_string(),
+            database: database.map(str::to_string),
         }
     }
 }
@@ -184,6 +229,17 @@ pub enum PoolerMode {
     Session,
 }
 
+// -----------------------------------------------------------------------------
+// ----- Internal: RateLimit ---------------------------------------------------
+
+/// Token bucket for one user's queries: up to `burst` back to back, refilled
+/// at `queries_per_second`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub queries_per_second: u32,
+    pub burst: u32,
+}
+
 // -----------------------------------------------------------------------------
 // ----- Internal: On-disk format ----------------------------------------------
 
@@ -200,6 +256,9 @@ struct UsersFileEntry {
 
     password: String,
 
+    #[serde(default)]
+    database: Option<String>,
+
     #[serde(default)]
     pool_size: Option<u32>,
 
@@ -215,8 +274,17 @@ struct UsersFileEntry {
     #[serde(default, deserialize_with = "de_ms")]
     statement_timeout: Option<Duration>,
 
+    #[serde(default, deserialize_with = "de_ms")]
+    idle_in_transaction_timeout: Option<Duration>,
+
     #[serde(default)]
     admin: bool,
+
+    #[serde(default)]
+    queries_per_second: Option<u32>,
+
+    #[serde(default)]
+    burst: Option<u32>,
 }
 
 // -----------------------------------------------------------------------------
@@ -225,15 +293,26 @@ struct UsersFileEntry {
 #[derive(Debug, Clone)]
 pub struct UserRecord {
     pub client_username: String,
+    /// Database this entry is limited to; `None` allows any.
+    pub database: Option<String>,
 
     pub client_password: SecretString,
     pub server_username: String,
     pub server_password: SecretString,
+    /// `server_username` came from the file rather than defaulting to the
+    /// client's: backends for this user log in as that role, from pools of
+    /// their own, instead of with the shard's credentials.
+    pub server_username_set: bool,
 
     pub pool_size: Option<u32>,
     pub pooler_mode: Option<PoolerMode>,
     pub statement_timeout: Option<Duration>,
+    /// Overrides `[server] idle_in_transaction_timeout`; zero turns it off
+    /// for this user.
+    pub idle_in_transaction_timeout: Option<Duration>,
     pub admin: bool,
+    /// Shared by every connection of this username; `None` is unlimited.
+    pub rate_limit: Option<RateLimit>,
 }
 
 // -----------------------------------------------------------------------------
@@ -248,6 +327,15 @@ fn validate(u: &UsersFileEntry) -> Result<(), UsersError> {
     if u.password.is_empty() {
         return Err(UsersError::InvalidField("password".into()));
     }
+    if u.database.as_deref().is_some_and(|db| db.trim().is_empty()) {
+        return Err(UsersError::InvalidField("database".into()));
+    }
+    if u.queries_per_second == Some(0) {
+        return Err(UsersError::InvalidField("queries_per_second".into()));
+    }
+    if u.burst == Some(0) || (u.burst.is_some() && u.queries_per_second.is_none()) {
+        return Err(UsersError::InvalidField("burst".into()));
+    }
     Ok(())
 }
 
@@ -321,9 +409,15 @@ pub enum UsersError {
     #[error("duplicate [[users]] entry for user '{username}'")]
     DuplicateUser { username: String },
 
+    #[error("server_username '{role}' is mapped with different server_passwords")]
+    ConflictingServerPassword { role: String },
+
     #[error("unknown user '{username}'")]
     UnknownUser { username: String },
 
+    #[error("user '{username}' may not connect to database '{database}'")]
+    DatabaseNotAllowed { username: String, database: String },
+
     #[error("invalid or missing field '{0}'")]
     InvalidField(String),
 
@@ -364,6 +458,7 @@ mod tests {
             pool_size = 64
             pooler_mode = "transaction"
             statement_timeout = 30_000
+            idle_in_transaction_timeout = 5_000
 
             [[users]]
             username = "bob"
@@ -372,24 +467,30 @@ mod tests {
             server_password = "server-secret"
             pooler_mode = "session"
             statement_timeout = 10_000
+            idle_in_transaction_timeout = 0
             admin = true
         "#;
 
         let tmp = write_tmp(toml);
         let users = UsersConfig::from_file_async(tmp.path()).await.unwrap();
 
-        let rec = users.authenticate("alice", "hunter2").unwrap();
+        let rec = users.authenticate("alice", "hunter2", "app").unwrap();
         assert_eq!(rec.server_username, "alice");
         assert_eq!(rec.pool_size, Some(64));
         assert_eq!(rec.pooler_mode, Some(PoolerMode::Transaction));
         assert_eq!(rec.statement_timeout, Some(Duration::from_millis(30_000)));
+        assert_eq!(
+            rec.idle_in_transaction_timeout,
+            Some(Duration::from_millis(5_000))
+        );
         assert!(!rec.admin);
 
-        let rec = users.authenticate("bob", "opensesame").unwrap();
+        let rec = users.authenticate("bob", "opensesame", "app").unwrap();
         assert_eq!(rec.server_username, "pgapp");
         assert_eq!(rec.server_password.expose_secret(), "server-secret");
         assert_eq!(rec.pooler_mode, Some(PoolerMode::Session));
         assert_eq!(rec.statement_timeout, Some(Duration::from_millis(10_000)));
+        assert_eq!(rec.idle_in_transaction_timeout, Some(Duration::ZERO));
         assert!(rec.admin);
     }
 
@@ -405,7 +506,7 @@ mod tests {
         let tmp = write_tmp(toml);
         let users = UsersConfig::from_file_async(tmp.path()).await.unwrap();
 
-        let rec = users.authenticate("legacy", "password").unwrap();
+        let rec = users.authenticate("legacy", "password", "app").unwrap();
         assert_eq!(rec.server_username, "legacy_backend");
     }
 
@@ -420,15 +521,163 @@ mod tests {
         let tmp = write_tmp(toml);
         let users = UsersConfig::from_file_async(tmp.path()).await.unwrap();
 
-        let err = users.authenticate("alice", "nope").unwrap_err();
+        let err = users.authenticate("alice", "nope", "app").unwrap_err();
         assert!(matches!(err, UsersError::BadPassword));
 
-        let err = users.authenticate("steeve", "nope").unwrap_err();
+        let err = users.authenticate("steeve", "nope", "app").unwrap_err();
         match err {
             UsersError::UnknownUser { username } => assert_eq!(username, "steeve"),
             _ => panic!("expected UnknownUser"),
         }
     }
+
+    #[tokio::test]
+    async fn database_scoped_entries() {
+        let toml = r#"
+            [[users]]
+            username = "alice"
+            password = "analytics-pw"
+            database = "analytics"
+            pool_size = 4
+
+            [[users]]
+            username = "alice"
+            password = "anywhere-pw"
+
+            [[users]]
+            username = "carol"
+            password = "ledger-pw"
+            database = "ledger"
+        "#;
+
+        let tmp = write_tmp(toml);
+        let users = UsersConfig::from_file_async(tmp.path()).await.unwrap();
+
+        // Exact (user, database) match wins over the wildcard entry.
+        let rec = users
+            .authenticate("alice", "analytics-pw", "analytics")
+            .unwrap();
+        assert_eq!(rec.database.as_deref(), Some("analytics"));
+        assert_eq!(rec.pool_size, Some(4));
+        let err = users
+            .authenticate("alice", "anywhere-pw", "analytics")
+            .unwrap_err();
+        assert!(matches!(err, UsersError::BadPassword));
+
+        // No database configured: matches any database.
+        let rec = users.authenticate("alice", "anywhere-pw", "other").unwrap();
+        assert_eq!(rec.database, None);
+
+        // Bound to another database only.
+        let err = users
+            .authenticate("carol", "ledger-pw", "other")
+            .unwrap_err();
+        match err {
+            UsersError::DatabaseNotAllowed { username, database } => {
+                assert_eq!(username, "carol");
+                assert_eq!(database, "other");
+            }
+            _ => panic!("expected DatabaseNotAllowed"),
+        }
+    }
+
+    #[tokio::test]
+    async fn duplicate_user_database_pair_is_rejected() {
+        let toml = r#"
+            [[users]]
+            username = "alice"
+            password = "a"
+            database = "app"
+
+            [[users]]
+            username = "alice"
+            password = "b"
+            database = "app"
+        "#;
+
+        let tmp = write_tmp(toml);
+        let err = UsersConfig::from_file_async(tmp.path()).await.unwrap_err();
+        assert!(matches!(err, UsersError::DuplicateUser { .. }));
+    }
+
+    #[test]
+    fn one_role_with_two_server_passwords_is_rejected() {
+        let toml = r#"
+            [[users]]
+            username = "alice"
+            password = "a"
+            server_username = "app"
+            server_password = "one"
+
+            [[users]]
+            username = "bob"
+            password = "b"
+            server_username = "app"
+            server_password = "two"
+        "#;
+
+        match UsersConfig::parse(toml).unwrap_err() {
+            UsersError::ConflictingServerPassword { role } => assert_eq!(role, "app"),
+            other => panic!("expected ConflictingServerPassword, got {other:?}"),
+        }
+
+        let agreeing = toml.replace("\"two\"", "\"one\"");
+        assert!(UsersConfig::parse(&agreeing).is_ok());
+    }
+
+    #[test]
+    fn rate_limit_burst_defaults_to_the_rate() {
+        let toml = r#"
+            [[users]]
+            username = "alice"
+            password = "a"
+            queries_per_second = 50
+
+            [[users]]
+            username = "bob"
+            password = "b"
+            queries_per_second = 5
+            burst = 20
+
+            [[users]]
+            username = "carol"
+            password = "c"
+        "#;
+
+        let users = UsersConfig::parse(toml).unwrap();
+        let limit = |name: &str| {
+            users
+                .authenticate(name, &name[..1], "app")
+                .unwrap()
+                .rate_limit
+        };
+        assert_eq!(
+            limit("alice"),
+            Some(RateLimit {
+                queries_per_second: 50,
+                burst: 50
+            })
+        );
+        assert_eq!(
+            limit("bob"),
+            Some(RateLimit {
+                queries_per_second: 5,
+                burst: 20
+            })
+        );
+        assert_eq!(limit("carol"), None);
+
+        let burst_only = "[[users]]\nusername = \"a\"\npassword = \"a\"\nburst = 5\n";
+        assert!(matches!(
+            UsersConfig::parse(burst_only),
+            Err(UsersError::InvalidField(field)) if field == "burst"
+        ));
+        let zero_rate = "[[users]]\nusername = \"a\"\npassword = \"a\"\nqueries_per_second = 0\n";
+        assert!(matches!(
+            UsersConfig::parse(zero_rate),
+            Err(UsersError::InvalidField(field)) if field == "queries_per_second"
+        ));
+    }
 }
 
 // -----------------------------------------------------------------------------
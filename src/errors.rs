@@ -42,6 +42,63 @@ impl ErrorResponse {
         Self::new(Severity::Error, "XX000", message)
     }
 
+    pub fn syntax_error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "42601", message)
+    }
+
+    pub fn insufficient_privilege(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "42501", message)
+    }
+
+    pub fn invalid_cursor_name(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "34000", message)
+    }
+
+    pub fn undefined_object(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "42704", message)
+    }
+
+    pub fn feature_not_supported(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "0A000", message)
+    }
+
+    pub fn too_many_connections(message: impl Into<String>) -> Self {
+        Self::new(Severity::Fatal, "53300", message)
+    }
+
+    pub fn configuration_limit_exceeded(message: impl Into<String>) -> Self {
+        Self::new(Severity::Fatal, "53400", message)
+    }
+
+    pub fn program_limit_exceeded(message: impl Into<String>) -> Self {
+        Self::new(Severity::Fatal, "54000", message)
+    }
+
+    /// The backend connection broke mid-use (I/O error on its socket).
+    pub fn connection_failure(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "08006", message)
+    }
+
+    /// A backend was opened but couldn't be set up for use.
+    pub fn unable_to_establish_connection(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "08001", message)
+    }
+
+    /// The backend connection is already gone (closed by the server).
+    pub fn connection_does_not_exist(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "08003", message)
+    }
+
+    /// The server can't take the work right now; clients may retry.
+    pub fn cannot_connect_now(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, "57P03", message)
+    }
+
+    /// The client left a transaction open longer than it may.
+    pub fn idle_in_transaction_session_timeout(message: impl Into<String>) -> Self {
+        Self::new(Severity::Fatal, "25P03", message)
+    }
+
     pub fn protocol_violation(message: impl Into<String>) -> Self {
         Self::new(Severity::Fatal, "08P01", message)
     }
@@ -55,6 +112,11 @@ impl ErrorResponse {
 // ----- ErrorResponse: Builder ------------------------------------------------
 
 impl ErrorResponse {
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
     pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
         self.detail = Some(detail.into());
         self
@@ -132,14 +194,25 @@ impl ErrorResponse {
 impl ErrorResponse {
     /// Build the backend 'E' frame. Returns a complete wire buffer.
     pub fn to_bytes(&self) -> Bytes {
+        self.encode(b'E')
+    }
+
+    /// The same fields as a NoticeResponse ('N'), for a `Notice` (or
+    /// lower) severity the client shows without failing anything.
+    pub fn to_notice_bytes(&self) -> Bytes {
+        self.encode(b'N')
+    }
+
+    fn encode(&self, tag: u8) -> Bytes {
         let mut buf = BytesMut::with_capacity(256);
 
-        buf.put_u8(b'E'); // message type
+        buf.put_u8(tag); // message type
         buf.put_u32(0); // length placeholder
 
+        // pgcrab doesn't localize, so the nonlocalized 'V' that clients
+        // classify errors by always matches 'S'.
         put_field(&mut buf, b'S', self.severity.as_str());
-        // Optional: also include nonlocalized severity ('V') if you want
-        // put_field(&mut buf, b'V', self.severity.as_str());
+        put_field(&mut buf, b'V', self.severity.as_str());
 
         put_field(&mut buf, b'C', self.code);
         put_field(&mut buf, b'M', &self.message);
@@ -280,6 +353,26 @@ mod tests {
         assert!(b.len() > 12);
     }
 
+    #[test]
+    fn severity_is_sent_localized_and_not() {
+        for severity in [Severity::Error, Severity::Fatal, Severity::Panic] {
+            let b = ErrorResponse::new(severity, "XX000", "boom").to_bytes();
+            let len = u32::from_be_bytes(b[1..5].try_into().unwrap()) as usize;
+            assert_eq!(len, b.len() - 1);
+
+            let fields: Vec<(u8, &str)> = b[5..b.len() - 1]
+                .split(|&byte| byte == 0)
+                .filter(|field| !field.is_empty())
+                .map(|field| (field[0], std::str::from_utf8(&field[1..]).unwrap()))
+                .collect();
+            let field = |tag| fields.iter().find(|(t, _)| *t == tag).map(|(_, v)| *v);
+
+            assert_eq!(field(b'S'), Some(severity.as_str()));
+            assert_eq!(field(b'V'), field(b'S'));
+        }
+        assert_eq!(Severity::Panic.as_str(), "PANIC");
+    }
+
     #[test]
     fn includes_optional_fields() {
         let e = ErrorResponse::new(Severity::Fatal, "08P01", "bad protocol")
@@ -295,6 +388,29 @@ mod tests {
         let b = e.to_bytes();
         assert!(b.windows(7).any(|w| w == b"SFATAL\0")); // crude sanity
     }
+
+    #[test]
+    fn constructors_emit_their_sqlstate() {
+        let cases = [
+            (ErrorResponse::internal_error("x"), "XX000"),
+            (ErrorResponse::connection_failure("x"), "08006"),
+            (ErrorResponse::connection_does_not_exist("x"), "08003"),
+            (ErrorResponse::feature_not_supported("x"), "0A000"),
+            (ErrorResponse::cannot_connect_now("x"), "57P03"),
+            (
+                ErrorResponse::idle_in_transaction_session_timeout("x"),
+                "25P03",
+            ),
+        ];
+        for (error, code) in cases {
+            let field = format!("C{code}\0");
+            let b = error.to_bytes();
+            assert!(
+                b.windows(field.len()).any(|w| w == field.as_bytes()),
+                "{code}"
+            );
+        }
+    }
 }
 
 // -----------------------------------------------------------------------------
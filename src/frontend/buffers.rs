@@ -1,3 +1,4 @@
+use crate::analytics;
 use crate::frontend::sequence_tracker::SequenceTracker;
 use crate::frontend::transport::FrontendTransport;
 use crate::shared_types::AuthStage;
@@ -9,6 +10,14 @@ use bytes::{Bytes, BytesMut};
 
 const SCRATCH_CAPACITY_HINT: usize = 4096;
 
+/// A backend message arriving behind queued output may take the outbox this
+/// many times past `max_outbox_bytes` before it waits for the client to
+/// drain it.
+const OUTBOX_OVERRUN_FACTOR: usize = 4;
+
+/// Tag byte plus a length field that counts its own 4 bytes.
+const MIN_TAGGED_FRAME_LEN: usize = 5;
+
 // -----------------------------------------------------------------------------
 // ----- FrontendBuffers -------------------------------------------------------
 
@@ -17,14 +26,41 @@ pub(crate) struct FrontendBuffers {
     inbox: BytesMut,
     inbox_tracker: SequenceTracker,
     outbox: BytesMut,
+    max_message_size: usize,
+    max_outbox_bytes: usize,
+}
+
+/// A client frame refused on its declared length alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BadFrameLength {
+    /// Over `max_message_size`.
+    TooLarge { declared: usize, limit: usize },
+    /// Shorter than its own header, so it can never be complete.
+    TooShort { declared: usize },
+}
+
+/// A backend message too large to queue for the client, even with backend
+/// reads paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct OutboxOverrun {
+    pub(crate) declared: usize,
+    pub(crate) limit: usize,
 }
 
 impl FrontendBuffers {
+    #[cfg(test)]
     pub(crate) fn new() -> Self {
+        let defaults = crate::config::server::ServerConfig::default();
+        Self::with_limits(defaults.max_message_size, defaults.max_outbox_bytes)
+    }
+
+    pub(crate) fn with_limits(max_message_size: usize, max_outbox_bytes: usize) -> Self {
         Self {
             inbox: BytesMut::with_capacity(SCRATCH_CAPACITY_HINT),
             inbox_tracker: SequenceTracker::new(),
             outbox: BytesMut::with_capacity(SCRATCH_CAPACITY_HINT),
+            max_message_size,
+            max_outbox_bytes,
         }
     }
 
@@ -33,10 +69,17 @@ impl FrontendBuffers {
         transport: &mut FrontendTransport,
     ) -> std::io::Result<usize> {
         self.inbox.reserve(SCRATCH_CAPACITY_HINT);
-        transport.read_buf(&mut self.inbox).await
+        let n = transport.read_buf(&mut self.inbox).await?;
+        analytics::add_client_bytes_in(n);
+        Ok(n)
     }
 
-    pub(crate) fn track_new_inbox_frames(&mut self, stage: AuthStage) {
+    /// Checks each frame's declared length as soon as its header arrives, so
+    /// an oversized or malformed message is refused before it is buffered.
+    pub(crate) fn track_new_inbox_frames(
+        &mut self,
+        stage: AuthStage,
+    ) -> Result<(), BadFrameLength> {
         loop {
             let cursor = self.inbox_tracker.len();
 
@@ -45,16 +88,34 @@ impl FrontendBuffers {
                 break;
             }
 
+            if let Some(declared) = declared_len(stage, frame_slice) {
+                if declared < MIN_TAGGED_FRAME_LEN {
+                    return Err(BadFrameLength::TooShort { declared });
+                }
+                if declared > self.max_message_size {
+                    return Err(BadFrameLength::TooLarge {
+                        declared,
+                        limit: self.max_message_size,
+                    });
+                }
+            }
+
             let Some(result) = peek_frontend(stage, frame_slice) else {
                 break;
             };
 
             self.inbox_tracker.push(result.message_type, result.len);
         }
+
+        Ok(())
     }
 
-    pub(crate) fn pull_next_sequence(&mut self, stage: AuthStage) -> Option<BytesMut> {
-        let Some(bytes_to_take) = self.inbox_tracker.take_until_flush(stage) else {
+    pub(crate) fn pull_next_sequence(
+        &mut self,
+        stage: AuthStage,
+        copy_in: bool,
+    ) -> Option<BytesMut> {
+        let Some(bytes_to_take) = self.inbox_tracker.take_until_flush(stage, copy_in) else {
             return None;
         };
 
@@ -67,15 +128,215 @@ impl FrontendBuffers {
         self.outbox.extend_from_slice(response);
     }
 
+    /// At `max_outbox_bytes`: stop relaying backend frames until the client
+    /// drains the outbox.
+    pub(crate) fn outbox_full(&self) -> bool {
+        self.outbox.len() >= self.max_outbox_bytes
+    }
+
+    /// Checks the backend frame at the front of `backend_buffer` as soon as
+    /// its header arrives, so one too large to queue is refused before it
+    /// is buffered. With nothing queued any size goes through: the message
+    /// is the only thing the client is waiting on.
+    pub(crate) fn check_backend_frame(&self, backend_buffer: &[u8]) -> Result<(), OutboxOverrun> {
+        if self.outbox.is_empty() {
+            return Ok(());
+        }
+        let Some(declared) = declared_len(AuthStage::Ready, backend_buffer) else {
+            return Ok(());
+        };
+
+        let limit = self.max_outbox_bytes.saturating_mul(OUTBOX_OVERRUN_FACTOR);
+        if self.outbox.len().saturating_add(declared) > limit {
+            return Err(OutboxOverrun { declared, limit });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn outbox(&self) -> &[u8] {
+        &self.outbox
+    }
+
+    #[cfg(test)]
+    pub(crate) fn push_inbox(&mut self, bytes: &[u8]) {
+        self.inbox.extend_from_slice(bytes);
+    }
+
+    /// Returns the number of bytes written.
     pub(crate) async fn flush_to(
         &mut self,
         transport: &mut FrontendTransport,
-    ) -> std::io::Result<()> {
-        if !self.outbox.is_empty() {
+    ) -> std::io::Result<usize> {
+        let n = self.outbox.len();
+        if n > 0 {
+            analytics::add_client_bytes_out(n);
             transport.write_all_buf(&mut self.outbox).await?;
         }
 
-        Ok(())
+        Ok(n)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+/// Total frame length from a tagged header; startup frames are capped by
+/// their own observers.
+fn declared_len(stage: AuthStage, bytes: &[u8]) -> Option<usize> {
+    if stage == AuthStage::Startup || bytes.len() < 5 {
+        return None;
+    }
+
+    let len = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+    Some(len.saturating_add(1))
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_header_claiming_a_gigabyte() {
+        let mut buffers = FrontendBuffers::new();
+        let claimed = 1024 * 1024 * 1024u32;
+        let mut header = vec![b'Q'];
+        header.extend_from_slice(&claimed.to_be_bytes());
+        header.extend_from_slice(b"SELECT");
+        buffers.push_inbox(&header);
+
+        let err = buffers
+            .track_new_inbox_frames(AuthStage::Ready)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            BadFrameLength::TooLarge {
+                declared: 1 + claimed as usize,
+                limit: 64 * 1024 * 1024,
+            }
+        );
+        assert!(
+            buffers
+                .pull_next_sequence(AuthStage::Ready, false)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn accepts_frames_up_to_the_limit() {
+        let mut buffers = FrontendBuffers::with_limits(14, 1024);
+        buffers.push_inbox(&[
+            b'Q', 0, 0, 0, 13, b'S', b'E', b'L', b'E', b'C', b'T', b' ', b'1', 0,
+        ]);
+
+        buffers.track_new_inbox_frames(AuthStage::Ready).unwrap();
+        assert_eq!(
+            buffers
+                .pull_next_sequence(AuthStage::Ready, false)
+                .map(|s| s.len()),
+            Some(14)
+        );
+
+        let mut small = FrontendBuffers::with_limits(13, 1024);
+        small.push_inbox(&[b'Q', 0, 0, 0, 13]);
+        assert!(small.track_new_inbox_frames(AuthStage::Ready).is_err());
+    }
+
+    #[test]
+    fn rejects_lengths_shorter_than_the_header() {
+        for (payload_len, stage) in [
+            (0u32, AuthStage::Ready),
+            (3, AuthStage::Ready),
+            (3, AuthStage::Authenticating),
+        ] {
+            let mut buffers = FrontendBuffers::new();
+            let mut frame = vec![b'Q'];
+            frame.extend_from_slice(&payload_len.to_be_bytes());
+            frame.extend_from_slice(b"SELECT 1\0");
+            buffers.push_inbox(&frame);
+
+            assert_eq!(
+                buffers.track_new_inbox_frames(stage),
+                Err(BadFrameLength::TooShort {
+                    declared: 1 + payload_len as usize
+                })
+            );
+            assert!(buffers.pull_next_sequence(stage, false).is_none());
+        }
+    }
+
+    #[test]
+    fn near_max_lengths_are_too_large_without_overflowing() {
+        for payload_len in [u32::MAX - 1, u32::MAX] {
+            let mut buffers = FrontendBuffers::new();
+            let mut frame = vec![b'P'];
+            frame.extend_from_slice(&payload_len.to_be_bytes());
+            buffers.push_inbox(&frame);
+
+            assert!(matches!(
+                buffers.track_new_inbox_frames(AuthStage::Ready),
+                Err(BadFrameLength::TooLarge { declared, .. }) if declared >= payload_len as usize
+            ));
+            assert!(
+                buffers
+                    .pull_next_sequence(AuthStage::Ready, false)
+                    .is_none()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn stalled_client_bounds_the_outbox_until_it_overruns() {
+        use std::time::Duration;
+        use tokio::net::{TcpListener, TcpStream};
+        use tokio::time::timeout;
+
+        const LIMIT: usize = 64 * 1024;
+        const CHUNK: usize = 16 * 1024;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Connected but never read from.
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut transport = FrontendTransport::new(stream);
+
+        let mut buffers = FrontendBuffers::with_limits(1024, LIMIT);
+        let row = Bytes::from(vec![b'D'; CHUNK]);
+        let mut stalled = false;
+        for _ in 0..10_000 {
+            if buffers.outbox_full() {
+                let flush = timeout(Duration::from_millis(100), buffers.flush_to(&mut transport));
+                if flush.await.is_err() {
+                    stalled = true;
+                    break;
+                }
+            }
+            buffers.queue_response(&row);
+            assert!(buffers.outbox().len() < LIMIT + CHUNK);
+        }
+        assert!(stalled, "the client's socket never filled up");
+        assert!(buffers.outbox().len() < LIMIT + CHUNK);
+
+        // Reads stay paused; a message that fits is still accepted.
+        let mut header = vec![b'D'];
+        header.extend_from_slice(&(LIMIT as u32).to_be_bytes());
+        assert!(buffers.check_backend_frame(&header).is_ok());
+
+        let huge = (OUTBOX_OVERRUN_FACTOR * LIMIT) as u32;
+        let mut header = vec![b'D'];
+        header.extend_from_slice(&huge.to_be_bytes());
+        let overrun = buffers.check_backend_frame(&header).unwrap_err();
+        assert_eq!(overrun.declared, 1 + huge as usize);
+        assert_eq!(overrun.limit, OUTBOX_OVERRUN_FACTOR * LIMIT);
+
+        // Once the client has read everything, the same message may come.
+        let empty = FrontendBuffers::with_limits(1024, LIMIT);
+        assert!(empty.check_backend_frame(&header).is_ok());
     }
 }
 
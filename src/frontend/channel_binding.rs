@@ -0,0 +1,297 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use thiserror::Error;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+pub const SCRAM_SHA_256_PLUS: &str = "SCRAM-SHA-256-PLUS";
+
+/// The only binding type we offer (RFC 5929), same as Postgres.
+pub const TLS_SERVER_END_POINT: &str = "tls-server-end-point";
+
+// -----------------------------------------------------------------------------
+// ----- Mechanisms ------------------------------------------------------------
+
+/// Postgres' own order: channel binding first, for clients that can use it.
+pub const DEFAULT_SASL_MECHANISMS: &[&str] = &[SCRAM_SHA_256_PLUS, SCRAM_SHA_256];
+
+/// The mechanism named `name`, if we implement it.
+pub fn sasl_mechanism(name: &str) -> Option<&'static str> {
+    [SCRAM_SHA_256_PLUS, SCRAM_SHA_256]
+        .into_iter()
+        .find(|mechanism| *mechanism == name)
+}
+
+/// Mechanisms for AuthenticationSASL: `preferred` (`[server]
+/// sasl_mechanisms`), in its order. `-PLUS` is only offered when the client
+/// is on TLS and we have binding data for the cert.
+pub fn sasl_mechanisms(preferred: &[&'static str], end_point: Option<&[u8]>) -> Vec<&'static str> {
+    preferred
+        .iter()
+        .copied()
+        .filter(|mechanism| *mechanism != SCRAM_SHA_256_PLUS || end_point.is_some())
+        .collect()
+}
+
+// -----------------------------------------------------------------------------
+// ----- Gs2Header -------------------------------------------------------------
+
+/// The client's channel binding choice, first field of the gs2 header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CbindFlag {
+    /// `n`: the client doesn't support channel binding.
+    Unsupported,
+    /// `y`: the client supports it but thinks we don't.
+    ServerUnsupported,
+    /// `p=tls-server-end-point`.
+    Bound,
+}
+
+/// gs2 header at the front of client-first-message (RFC 5802 section 7),
+/// e.g. `p=tls-server-end-point,,`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gs2Header<'a> {
+    raw: &'a str,
+    flag: CbindFlag,
+}
+
+impl<'a> Gs2Header<'a> {
+    /// Splits client-first-message into its gs2 header and the bare message.
+    pub fn parse(client_first: &'a str) -> Result<(Self, &'a str), ChannelBindingError> {
+        let (flag, rest) = client_first
+            .split_once(',')
+            .ok_or(ChannelBindingError::MalformedHeader)?;
+
+        let flag = match flag {
+            "n" => CbindFlag::Unsupported,
+            "y" => CbindFlag::ServerUnsupported,
+            _ => match flag.strip_prefix("p=") {
+                Some(TLS_SERVER_END_POINT) => CbindFlag::Bound,
+                Some(name) => {
+                    return Err(ChannelBindingError::UnsupportedType(name.to_string()));
+                }
+                None => return Err(ChannelBindingError::MalformedHeader),
+            },
+        };
+
+        let (authzid, bare) = rest
+            .split_once(',')
+            .ok_or(ChannelBindingError::MalformedHeader)?;
+        if !authzid.is_empty() {
+            return Err(ChannelBindingError::AuthzidUnsupported);
+        }
+
+        let raw = &client_first[..client_first.len() - bare.len()];
+        Ok((Self { raw, flag }, bare))
+    }
+
+    pub fn flag(&self) -> CbindFlag {
+        self.flag
+    }
+
+    /// Checks the flag against the mechanism the client picked. A `y` over
+    /// TLS means the `-PLUS` offer was stripped in transit.
+    pub fn check_mechanism(
+        &self,
+        mechanism: &str,
+        end_point: Option<&[u8]>,
+    ) -> Result<(), ChannelBindingError> {
+        match (mechanism, self.flag) {
+            (SCRAM_SHA_256_PLUS, CbindFlag::Bound) if end_point.is_some() => Ok(()),
+            (SCRAM_SHA_256_PLUS, _) => Err(ChannelBindingError::BindingRequired),
+            (_, CbindFlag::Bound) => Err(ChannelBindingError::BindingUnexpected),
+            (_, CbindFlag::ServerUnsupported) if end_point.is_some() => {
+                Err(ChannelBindingError::Downgrade)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Validates the `c=` attribute of client-final-message: base64 of this
+    /// header, followed by our binding data when the client bound.
+    pub fn verify(
+        &self,
+        channel_binding: &str,
+        end_point: Option<&[u8]>,
+    ) -> Result<(), ChannelBindingError> {
+        let mut expected = self.raw.as_bytes().to_vec();
+        if self.flag == CbindFlag::Bound {
+            expected.extend_from_slice(end_point.ok_or(ChannelBindingError::BindingRequired)?);
+        }
+
+        if STANDARD.encode(expected) != channel_binding {
+            return Err(ChannelBindingError::Mismatch);
+        }
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ChannelBindingError {
+    #[error("malformed SCRAM message: invalid gs2 header")]
+    MalformedHeader,
+
+    #[error("unsupported SCRAM channel-binding type \"{0}\"")]
+    UnsupportedType(String),
+
+    #[error("client uses authorization identity, but it is not supported")]
+    AuthzidUnsupported,
+
+    #[error("channel binding is required for SCRAM-SHA-256-PLUS")]
+    BindingRequired,
+
+    #[error("channel binding not expected for SCRAM-SHA-256")]
+    BindingUnexpected,
+
+    #[error("client supports channel binding but thinks the server does not")]
+    Downgrade,
+
+    #[error("SCRAM channel binding check failed")]
+    Mismatch,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const END_POINT: &[u8] = &[0xab; 32];
+
+    #[test]
+    fn plus_is_only_offered_with_binding_data() {
+        assert_eq!(
+            sasl_mechanisms(DEFAULT_SASL_MECHANISMS, Some(END_POINT)),
+            [SCRAM_SHA_256_PLUS, SCRAM_SHA_256]
+        );
+        assert_eq!(
+            sasl_mechanisms(DEFAULT_SASL_MECHANISMS, None),
+            [SCRAM_SHA_256]
+        );
+
+        // A configured order is kept, minus what the connection can't do.
+        let preferred = [SCRAM_SHA_256, SCRAM_SHA_256_PLUS];
+        assert_eq!(
+            sasl_mechanisms(&preferred, Some(END_POINT)),
+            [SCRAM_SHA_256, SCRAM_SHA_256_PLUS]
+        );
+        assert_eq!(sasl_mechanisms(&preferred, None), [SCRAM_SHA_256]);
+        assert!(sasl_mechanisms(&[SCRAM_SHA_256_PLUS], None).is_empty());
+
+        assert_eq!(sasl_mechanism("SCRAM-SHA-256"), Some(SCRAM_SHA_256));
+        assert_eq!(sasl_mechanism("SCRAM-SHA-1"), None);
+    }
+
+    #[test]
+    fn parses_gs2_headers() {
+        let (header, bare) = Gs2Header::parse("p=tls-server-end-point,,n=,r=abc").unwrap();
+        assert_eq!(header.flag(), CbindFlag::Bound);
+        assert_eq!(header.raw, "p=tls-server-end-point,,");
+        assert_eq!(bare, "n=,r=abc");
+
+        let (header, bare) = Gs2Header::parse("n,,n=,r=abc").unwrap();
+        assert_eq!(header.flag(), CbindFlag::Unsupported);
+        assert_eq!(bare, "n=,r=abc");
+
+        assert_eq!(
+            Gs2Header::parse("p=tls-unique,,n=,r=abc"),
+            Err(ChannelBindingError::UnsupportedType("tls-unique".into()))
+        );
+        assert_eq!(
+            Gs2Header::parse("n,a=admin,n=,r=abc"),
+            Err(ChannelBindingError::AuthzidUnsupported)
+        );
+        assert_eq!(
+            Gs2Header::parse("x,,n=,r=abc"),
+            Err(ChannelBindingError::MalformedHeader)
+        );
+        assert_eq!(
+            Gs2Header::parse("n"),
+            Err(ChannelBindingError::MalformedHeader)
+        );
+    }
+
+    #[test]
+    fn flag_must_match_the_mechanism() {
+        let (bound, _) = Gs2Header::parse("p=tls-server-end-point,,n=,r=abc").unwrap();
+        let (unbound, _) = Gs2Header::parse("n,,n=,r=abc").unwrap();
+        let (thinks_unsupported, _) = Gs2Header::parse("y,,n=,r=abc").unwrap();
+
+        assert!(
+            bound
+                .check_mechanism(SCRAM_SHA_256_PLUS, Some(END_POINT))
+                .is_ok()
+        );
+        assert!(
+            unbound
+                .check_mechanism(SCRAM_SHA_256, Some(END_POINT))
+                .is_ok()
+        );
+        assert!(unbound.check_mechanism(SCRAM_SHA_256, None).is_ok());
+        assert!(
+            thinks_unsupported
+                .check_mechanism(SCRAM_SHA_256, None)
+                .is_ok()
+        );
+
+        assert_eq!(
+            unbound.check_mechanism(SCRAM_SHA_256_PLUS, Some(END_POINT)),
+            Err(ChannelBindingError::BindingRequired)
+        );
+        assert_eq!(
+            bound.check_mechanism(SCRAM_SHA_256_PLUS, None),
+            Err(ChannelBindingError::BindingRequired)
+        );
+        assert_eq!(
+            bound.check_mechanism(SCRAM_SHA_256, Some(END_POINT)),
+            Err(ChannelBindingError::BindingUnexpected)
+        );
+        assert_eq!(
+            thinks_unsupported.check_mechanism(SCRAM_SHA_256, Some(END_POINT)),
+            Err(ChannelBindingError::Downgrade)
+        );
+    }
+
+    #[test]
+    fn verifies_the_client_final_binding() {
+        let (bound, _) = Gs2Header::parse("p=tls-server-end-point,,n=,r=abc").unwrap();
+        let expected =
+            STANDARD.encode([b"p=tls-server-end-point,,".as_slice(), END_POINT].concat());
+        assert!(bound.verify(&expected, Some(END_POINT)).is_ok());
+
+        let (unbound, _) = Gs2Header::parse("n,,n=,r=abc").unwrap();
+        assert!(unbound.verify("biws", None).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_mismatched_binding() {
+        let (bound, _) = Gs2Header::parse("p=tls-server-end-point,,n=,r=abc").unwrap();
+
+        // Binding data from a different certificate, e.g. a MITM proxy.
+        let other_cert =
+            STANDARD.encode([b"p=tls-server-end-point,,".as_slice(), &[0xcd; 32]].concat());
+        assert_eq!(
+            bound.verify(&other_cert, Some(END_POINT)),
+            Err(ChannelBindingError::Mismatch)
+        );
+
+        // The header without binding data, as a downgraded client would send.
+        assert_eq!(
+            bound.verify("biws", Some(END_POINT)),
+            Err(ChannelBindingError::Mismatch)
+        );
+        assert_eq!(
+            bound.verify("not base64!", Some(END_POINT)),
+            Err(ChannelBindingError::Mismatch)
+        );
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout;
+
+use crate::ErrorResponse;
+use crate::analytics;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// How long a rejected client gets to read the error before we hang up.
+const REJECT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+// -----------------------------------------------------------------------------
+// ----- ClientLimiter ---------------------------------------------------------
+
+/// Caps concurrent client connections at `[server] max_clients`.
+#[derive(Debug, Clone)]
+pub struct ClientLimiter {
+    permits: Option<Arc<Semaphore>>,
+}
+
+/// Held for the lifetime of one client connection.
+#[derive(Debug)]
+pub struct ClientSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl ClientLimiter {
+    /// `None` leaves the number of clients unbounded.
+    pub fn new(max_clients: Option<usize>) -> Self {
+        analytics::set_max_clients(max_clients);
+        Self {
+            permits: max_clients.map(|max| Arc::new(Semaphore::new(max))),
+        }
+    }
+
+    pub fn try_acquire(&self) -> Option<ClientSlot> {
+        let permit = match &self.permits {
+            Some(permits) => Some(permits.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+
+        analytics::client_connected();
+        Some(ClientSlot { _permit: permit })
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        analytics::client_disconnected();
+    }
+}
+
+/// Sends a FATAL 53300, with `[server] retry_after_hint` as its HINT, and
+/// closes. The write side is shut down first and the startup packet drained,
+/// so the close doesn't reset the error away.
+pub async fn reject_too_many_clients<S>(mut stream: S, retry_after_hint: Option<String>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let error = too_many_clients(retry_after_hint);
+    if stream.write_all(&error.to_bytes()).await.is_err() {
+        return;
+    }
+    let _ = stream.shutdown().await;
+
+    let mut scratch = [0u8; 1024];
+    let _ = timeout(REJECT_DRAIN_TIMEOUT, async {
+        while matches!(stream.read(&mut scratch).await, Ok(n) if n > 0) {}
+    })
+    .await;
+}
+
+fn too_many_clients(retry_after_hint: Option<String>) -> ErrorResponse {
+    let error = ErrorResponse::too_many_connections("sorry, too many clients already");
+    match retry_after_hint {
+        Some(hint) => error.with_hint(hint),
+        None => error,
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn limit_is_released_with_the_slot() {
+        let limiter = ClientLimiter::new(Some(1));
+        let slot = limiter.try_acquire().expect("first client fits");
+        assert!(limiter.try_acquire().is_none());
+
+        drop(slot);
+        assert!(limiter.try_acquire().is_some());
+    }
+
+    #[test]
+    fn unbounded_without_max_clients() {
+        let limiter = ClientLimiter::new(None);
+        let slots: Vec<_> = (0..64).filter_map(|_| limiter.try_acquire()).collect();
+        assert_eq!(slots.len(), 64);
+    }
+
+    #[tokio::test]
+    async fn overflow_client_reads_too_many_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let limiter = ClientLimiter::new(Some(1));
+
+        let server = tokio::spawn(async move {
+            let mut held = Vec::new();
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                match limiter.try_acquire() {
+                    Some(slot) => held.push((slot, stream)),
+                    None => reject_too_many_clients(stream, None).await,
+                }
+            }
+            held
+        });
+
+        let _first = TcpStream::connect(addr).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        // Startup packet: protocol 3.0, user=crab.
+        let startup = [
+            0, 0, 0, 19, 0, 3, 0, 0, b'u', b's', b'e', b'r', 0, b'c', b'r', b'a', b'b', 0, 0,
+        ];
+        second.write_all(&startup).await.unwrap();
+
+        let mut reply = Vec::new();
+        second.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply.first(), Some(&b'E'));
+        let text = String::from_utf8_lossy(&reply);
+        assert!(text.contains("FATAL"));
+        assert!(text.contains("53300"));
+
+        drop(second);
+        assert_eq!(server.await.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn retry_hint_is_sent_only_when_configured() {
+        let error = too_many_clients(None);
+        assert_eq!(error.hint, None);
+        assert!(!error.to_bytes().windows(2).any(|w| w == b"\0H"));
+
+        let error = too_many_clients(Some("retry in 1s".to_string()));
+        assert_eq!(error.code, "53300");
+        assert!(error.to_bytes().windows(13).any(|w| w == b"\0Hretry in 1s"));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
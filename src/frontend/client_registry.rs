@@ -0,0 +1,140 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+use crate::frontend::context::FrontendContext;
+
+// -----------------------------------------------------------------------------
+// ----- Registry --------------------------------------------------------------
+
+/// Every live client connection, keyed by its correlation id.
+static REGISTRY: Mutex<BTreeMap<u64, ClientInfo>> = Mutex::new(BTreeMap::new());
+
+/// What `SHOW PGCRAB CLIENTS` reports about one connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub peer: Option<SocketAddr>,
+    pub username: Option<String>,
+    pub database: Option<String>,
+    pub pool: Option<String>,
+    pub connected_at: SystemTime,
+    pub last_activity: SystemTime,
+}
+
+/// Active connections, oldest first.
+pub fn clients_snapshot() -> Vec<ClientInfo> {
+    let mut clients: Vec<_> = REGISTRY.lock().values().cloned().collect();
+    clients.sort_by_key(|client| (client.connected_at, client.id));
+    clients
+}
+
+// -----------------------------------------------------------------------------
+// ----- ClientRegistration ----------------------------------------------------
+
+/// Keeps a connection listed while alive. Removal happens on drop, so a
+/// connection task that errors out or panics never leaves a stale entry.
+#[derive(Debug)]
+pub(crate) struct ClientRegistration {
+    id: u64,
+}
+
+impl ClientRegistration {
+    pub(crate) fn register(id: u64, peer: Option<SocketAddr>) -> Self {
+        let now = SystemTime::now();
+        REGISTRY.lock().insert(
+            id,
+            ClientInfo {
+                id,
+                peer,
+                username: None,
+                database: None,
+                pool: None,
+                connected_at: now,
+                last_activity: now,
+            },
+        );
+        Self { id }
+    }
+
+    /// Refreshes the entry from the connection's state and marks it active.
+    pub(crate) fn update(&self, context: &FrontendContext) {
+        let mut registry = REGISTRY.lock();
+        let Some(info) = registry.get_mut(&self.id) else {
+            return;
+        };
+
+        if info.username != context.username {
+            info.username.clone_from(&context.username);
+        }
+        if info.database != context.database {
+            info.database.clone_from(&context.database);
+        }
+        if info.pool != context.current_pool {
+            info.pool.clone_from(&context.current_pool);
+        }
+        info.last_activity = SystemTime::now();
+    }
+}
+
+impl Drop for ClientRegistration {
+    fn drop(&mut self) {
+        REGISTRY.lock().remove(&self.id);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(id: u64) -> Option<ClientInfo> {
+        clients_snapshot()
+            .into_iter()
+            .find(|client| client.id == id)
+    }
+
+    #[test]
+    fn entries_follow_the_registration_guard() {
+        let (first_id, second_id) = (rand::random(), rand::random());
+        let first = ClientRegistration::register(first_id, Some("127.0.0.1:4000".parse().unwrap()));
+        let second = ClientRegistration::register(second_id, None);
+
+        let mut context = FrontendContext::new();
+        context.username = Some("alice".to_string());
+        context.database = Some("app".to_string());
+        context.current_pool = Some("shard_0".to_string());
+        first.update(&context);
+
+        let info = listed(first_id).expect("first client listed");
+        assert_eq!(info.username.as_deref(), Some("alice"));
+        assert_eq!(info.database.as_deref(), Some("app"));
+        assert_eq!(info.pool.as_deref(), Some("shard_0"));
+        assert!(info.last_activity >= info.connected_at);
+        assert!(listed(second_id).is_some());
+
+        drop(second);
+        assert!(listed(second_id).is_none());
+        assert!(listed(first_id).is_some());
+        drop(first);
+    }
+
+    #[test]
+    fn panicking_task_still_deregisters() {
+        let id = rand::random();
+        let result = std::panic::catch_unwind(|| {
+            let _registration = ClientRegistration::register(id, None);
+            assert!(listed(id).is_some());
+            panic!("connection task blew up");
+        });
+
+        assert!(result.is_err());
+        assert!(listed(id).is_none());
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -1,19 +1,41 @@
 use bytes::{Bytes, BytesMut};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::TcpStream;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpStream, UnixStream};
 use tokio::select;
+use tokio::time::{sleep, timeout};
+use tracing::{Instrument, warn};
 
+use crate::Config;
 use crate::ErrorResponse;
-use crate::frontend::buffers::FrontendBuffers;
-use crate::frontend::context::FrontendContext;
+use crate::analytics;
+use crate::config::users::PoolerMode;
+use crate::frontend::buffers::{BadFrameLength, FrontendBuffers};
+use crate::frontend::client_registry::ClientRegistration;
+use crate::frontend::context::{self, FrontendContext, PendingClose, ProtocolDesync};
 use crate::frontend::handlers;
 use crate::frontend::proxy_responses as responses;
-use crate::frontend::transport::FrontendTransport;
+use crate::frontend::transport::{ClientStream, FrontendTransport};
 use crate::gateway::GatewayPools;
+use crate::logging;
+use crate::parser::StatementType;
 use crate::shared_types::AuthStage;
 use crate::shared_types::ReadyStatus;
 use crate::tls;
-use crate::wire::utils::peek_backend;
+use crate::wire::observers::command_complete::CommandCompleteFrameObserver;
+use crate::wire::types::MessageType;
+use crate::wire::utils::try_peek_backend;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// How long the FATAL for an outbox overrun may wait on a slow client.
+const OVERRUN_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a client gets to read what is queued before a backend message
+/// too large to queue behind it.
+const OVERRUN_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
 
 // -----------------------------------------------------------------------------
 // ----- FrontendConnection ----------------------------------------------------
@@ -21,80 +43,96 @@ use crate::wire::utils::peek_backend;
 /// Drives the client connection through the Startup -> Authenticating -> Ready
 /// stages, delegating protocol handling to stage-specific handlers.
 pub struct FrontendConnection {
+    /// Random correlation id carried by every log line of this connection.
+    id: u64,
+    peer: Option<SocketAddr>,
+    registration: ClientRegistration,
     context: FrontendContext,
     buffers: FrontendBuffers,
     transport: FrontendTransport,
     tls_acceptor: Option<tokio_rustls::TlsAcceptor>,
+    /// The acceptor's channel binding data, handed to the context once the
+    /// client is on TLS.
+    tls_end_point: Option<Vec<u8>>,
     pools: Arc<GatewayPools>,
-    backend_tracker: BackendFrameTracker,
 }
 
-#[derive(Debug, Default)]
-struct BackendFrameTracker {
-    pending: Option<(u8, usize)>,
-}
+// -----------------------------------------------------------------------------
+// ----- FrontendConnection: Static --------------------------------------------
 
-impl BackendFrameTracker {
-    fn next_frame(&mut self, buf: &[u8]) -> Option<(u8, usize)> {
-        if let Some(pending) = self.pending {
-            let total = 1 + pending.1;
-            if buf.len() >= total {
-                self.pending = None;
-                return Some(pending);
-            }
-            return None;
-        }
+impl FrontendConnection {
+    pub fn new(stream: TcpStream, pools: Arc<GatewayPools>) -> Self {
+        let peer = stream.peer_addr().ok();
+        Self::with_stream(stream, peer, tls::acceptor_with_end_point(), pools)
+    }
 
-        if let Some(frame) = peek_backend(buf) {
-            return Some(frame);
-        }
+    /// A client on the Unix socket. As with Postgres, TLS isn't offered there.
+    pub fn from_unix(stream: UnixStream, pools: Arc<GatewayPools>) -> Self {
+        Self::with_stream(stream, None, None, pools)
+    }
 
-        if buf.len() < 5 {
-            return None;
-        }
+    fn with_stream(
+        stream: impl ClientStream,
+        peer: Option<SocketAddr>,
+        tls: Option<(tokio_rustls::TlsAcceptor, Option<Vec<u8>>)>,
+        pools: Arc<GatewayPools>,
+    ) -> Self {
+        let config = Config::handle();
+        let mut context = FrontendContext::new();
+        context.strict_parse = config.strict_parse;
+        context.validate_text_params = config.server.validate_text_params;
+        context.max_decode_failures = config.server.max_decode_failures;
+        context.query_log = config.query_log.clone();
+        context.routing = config.routing.clone();
+        context.startup = config.startup.clone();
+        context.policy = config.policy.clone();
+        context.connect_notice = config.server.connect_notice.clone();
+        context.client_auth = config.server.client_auth;
+        context.sasl_mechanisms = config.server.sasl_mechanisms.clone();
+        context.idle_in_transaction_timeout = config.server.idle_in_transaction_timeout;
 
-        let tag = buf[0];
-        let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
-        if len < 4 {
-            return None;
-        }
+        let (tls_acceptor, tls_end_point) = match tls {
+            Some((acceptor, end_point)) => (Some(acceptor), end_point),
+            None => (None, None),
+        };
+        let id = rand::random();
 
-        let total = 1 + len;
-        if buf.len() < total {
-            self.pending = Some((tag, len));
+        Self {
+            id,
+            peer,
+            registration: ClientRegistration::register(id, peer),
+            context,
+            buffers: FrontendBuffers::with_limits(
+                config.server.max_message_size,
+                config.server.max_outbox_bytes,
+            ),
+            transport: FrontendTransport::new(stream),
+            tls_acceptor,
+            tls_end_point,
+            pools,
         }
-
-        None
-    }
-
-    fn reset(&mut self) {
-        self.pending = None;
     }
 }
 
 // -----------------------------------------------------------------------------
-// ----- FrontendConnection: Static --------------------------------------------
+// ----- FrontendConnection: Public --------------------------------------------
 
 impl FrontendConnection {
-    pub fn new(stream: TcpStream, pools: Arc<GatewayPools>) -> Self {
-        Self {
-            context: FrontendContext::new(),
-            buffers: FrontendBuffers::new(),
-            transport: FrontendTransport::new(stream),
-            tls_acceptor: tls::acceptor(),
-            pools,
-            backend_tracker: BackendFrameTracker::default(),
-        }
+    pub async fn serve(self) -> std::io::Result<()> {
+        let span = logging::connection_span(self.id, self.peer);
+        self.serve_loop().instrument(span).await
     }
 }
 
 // -----------------------------------------------------------------------------
-// ----- FrontendConnection: Public --------------------------------------------
+// ----- FrontendConnection: Private -------------------------------------------
 
 impl FrontendConnection {
-    pub async fn serve(mut self) -> std::io::Result<()> {
+    async fn serve_loop(mut self) -> std::io::Result<()> {
         loop {
             if self.context.gateway_session.is_some() {
+                // Restarted on every pass, so any traffic resets it.
+                let idle_limit = self.context.idle_in_transaction_limit();
                 select! {
                     read_res = async {
                         self.buffers.read_from(&mut self.transport).await
@@ -108,6 +146,10 @@ impl FrontendConnection {
                             break;
                         }
                     }
+                    _ = sleep(idle_limit.unwrap_or_default()), if idle_limit.is_some() => {
+                        self.close_idle_transaction().await?;
+                        break;
+                    }
                 }
             } else {
                 let read_res = self.buffers.read_from(&mut self.transport).await;
@@ -119,12 +161,7 @@ impl FrontendConnection {
 
         Ok(())
     }
-}
-
-// -----------------------------------------------------------------------------
-// ----- FrontendConnection: Private -------------------------------------------
 
-impl FrontendConnection {
     async fn process_sequence(&mut self, seq_or_msg: BytesMut) {
         match self.context.stage {
             AuthStage::Startup => handlers::startup::handle_startup(
@@ -169,12 +206,29 @@ impl FrontendConnection {
         if n == 0 {
             return Ok(false);
         }
+        self.context.traffic.client_bytes_in += n as u64;
 
         // read -> track -> process -> flush
-        self.buffers.track_new_inbox_frames(self.context.stage);
+        if let Err(bad_length) = self.buffers.track_new_inbox_frames(self.context.stage) {
+            let error = match bad_length {
+                BadFrameLength::TooLarge { declared, limit } => {
+                    ErrorResponse::program_limit_exceeded(format!(
+                        "message of {declared} bytes exceeds max_message_size of {limit} bytes"
+                    ))
+                }
+                BadFrameLength::TooShort { declared } => ErrorResponse::protocol_violation(
+                    format!("invalid message length {}", declared - 1),
+                ),
+            };
+            self.buffers.queue_response(&error.to_bytes());
+            self.flush().await?;
+            return Ok(false);
+        }
 
-        while let Some(sequence) = self.buffers.pull_next_sequence(self.context.stage) {
-            let had_session = self.context.gateway_session.is_some();
+        while let Some(sequence) = self
+            .buffers
+            .pull_next_sequence(self.context.stage, self.context.copy_in)
+        {
             self.process_sequence(sequence).await;
 
             if self.context.should_close() {
@@ -184,13 +238,10 @@ impl FrontendConnection {
             if self.context.wants_tls_upgrade() {
                 break;
             }
-
-            if had_session && self.context.gateway_session.is_none() {
-                self.backend_tracker.reset();
-            }
         }
 
-        self.buffers.flush_to(&mut self.transport).await?;
+        self.registration.update(&self.context);
+        self.flush().await?;
 
         if self.context.should_close() {
             return Ok(false);
@@ -198,7 +249,10 @@ impl FrontendConnection {
 
         if self.context.take_tls_upgrade() {
             if let Some(acceptor) = self.tls_acceptor.as_ref() {
+                let handshake = Instant::now();
                 self.transport.upgrade_to_tls(acceptor).await?;
+                self.context.setup.record_tls(handshake.elapsed());
+                self.context.tls_end_point = self.tls_end_point.take();
             }
         }
 
@@ -212,26 +266,63 @@ impl FrontendConnection {
         let n = match read_res {
             Ok(n) => n,
             Err(err) => {
-                self.backend_error(format!("backend read failed: {err}"));
-                self.buffers.flush_to(&mut self.transport).await?;
+                self.backend_error(ErrorResponse::connection_failure(format!(
+                    "backend read failed: {err}"
+                )));
+                self.flush().await?;
                 return Ok(true);
             }
         };
 
         if n == 0 {
-            self.backend_error("backend closed connection".to_string());
-            self.buffers.flush_to(&mut self.transport).await?;
+            self.backend_error(ErrorResponse::connection_does_not_exist(
+                "backend closed connection",
+            ));
+            self.flush().await?;
             return Ok(true);
         }
+        self.context.traffic.backend_bytes_in += n as u64;
 
-        let (pending_parses, pending_syncs, virtual_portals, gateway_session, current_pool) = {
+        let (
+            pending_parses,
+            pending_closes,
+            pending_describes,
+            pending_syncs,
+            virtual_portals,
+            pending_replies,
+            skip_until_sync,
+            failed_batch,
+            pending_denials,
+            copy_in,
+            virtual_statements,
+            gateway_session,
+            current_pool,
+            ready_status,
+            session_state,
+            pooler_mode,
+            query_log,
+            username,
+        ) = {
             let context = &mut self.context;
             (
                 &mut context.pending_parses,
+                &mut context.pending_closes,
+                &mut context.pending_describes,
                 &mut context.pending_syncs,
                 &mut context.virtual_portals,
+                &mut context.pending_replies,
+                &mut context.skip_until_sync,
+                &mut context.failed_batch,
+                &mut context.pending_denials,
+                &mut context.copy_in,
+                &mut context.virtual_statements,
                 &mut context.gateway_session,
                 &mut context.current_pool,
+                &mut context.ready_status,
+                &mut context.session_state,
+                context.pooler_mode,
+                &context.query_log,
+                context.username.as_deref(),
             )
         };
 
@@ -239,47 +330,143 @@ impl FrontendConnection {
             return Ok(true);
         };
 
+        let pool = Arc::clone(session.pool());
         let backend = session.backend();
         let mut release_session = false;
+        let mut unknown_tag = None;
+        let mut desync = None;
+        let mut flushed = 0;
         loop {
-            let (tag, total_len, frame) = {
+            // Backpressure: leave the rest in the backend buffer, and the
+            // socket unread, until a slow client catches up.
+            if self.buffers.outbox_full() {
+                flushed += self.buffers.flush_to(&mut self.transport).await?;
+            }
+
+            let (message_type, total_len, frame) = {
                 let buffer = backend.buffer();
-                let Some((tag, len)) = self.backend_tracker.next_frame(buffer) else {
-                    break;
+                let (message_type, len) = match try_peek_backend(buffer) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err(unknown) => {
+                        unknown_tag = Some(unknown);
+                        break;
+                    }
                 };
                 let total_len = 1 + len;
                 let frame = Bytes::copy_from_slice(&buffer[..total_len]);
-                (tag, total_len, frame)
+                (message_type, total_len, frame)
             };
 
             backend.consume(total_len);
 
+            let accounted = match message_type {
+                MessageType::CommandComplete
+                | MessageType::EmptyQueryResponse
+                | MessageType::PortalSuspended => context::check_execute_reply(pending_replies),
+                MessageType::ReadyForQuery => context::account_ready(pending_syncs),
+                _ => Ok(()),
+            };
+            if let Err(err) = accounted {
+                desync = Some(err);
+                break;
+            }
+
             let mut forward = true;
-            match tag {
-                b'1' => {
-                    if let Some(pending) = pending_parses.pop_front() {
-                        if let (Some(signature), Some(name)) =
-                            (pending.signature, pending.backend_statement_name)
-                        {
-                            backend.prepared_insert(signature, name);
-                        }
-                        if pending.suppress_response {
-                            forward = false;
-                        }
+            match message_type {
+                MessageType::ParseComplete => {
+                    if let Some(reply) = pending_parses
+                        .front_mut()
+                        .and_then(|pending| pending.replay.take())
+                    {
+                        self.buffers.queue_response(&reply);
                     }
+                    forward = context::complete_parse(pending_parses, backend);
                 }
-                b'E' => {
-                    pending_parses.clear();
+                MessageType::ParameterDescription
+                | MessageType::RowDescription
+                | MessageType::NoData => {
+                    if let Some(described) =
+                        context::collect_describe(pending_describes, message_type, &frame)
+                        && let Some(key) = described.key
+                    {
+                        pool.cache_describe(key, described.reply.freeze(), described.generation);
+                    }
+                }
+                MessageType::CloseComplete => match context::complete_close(pending_closes) {
+                    PendingClose::Client => {}
+                    PendingClose::Evicted => forward = false,
+                    PendingClose::Deallocate => {
+                        forward = false;
+                        self.buffers
+                            .queue_response(&responses::command_complete("DEALLOCATE"));
+                    }
+                },
+                MessageType::CopyInResponse => {
+                    context::enter_copy_in(pending_replies, pending_syncs);
+                    *copy_in = true;
+                }
+                MessageType::CommandComplete | MessageType::EmptyQueryResponse => {
+                    if let Ok(observer) = CommandCompleteFrameObserver::new(&frame)
+                        && let Some(rows) = observer.affected_rows()
+                    {
+                        let statement_type = StatementType::from_command_tag(observer.command());
+                        analytics::add_affected_rows(statement_type, rows);
+                    }
+                    *copy_in = false;
+                    context::complete_pending_execute(pending_replies, virtual_portals, false);
+                }
+                MessageType::PortalSuspended => {
+                    context::complete_pending_execute(pending_replies, virtual_portals, true);
+                }
+                MessageType::ErrorResponse => {
+                    *copy_in = false;
+                    context::fail_pending_parses(pending_parses, virtual_statements);
+                    pending_closes.clear();
+                    pending_describes.clear();
+                    context::fail_pending_replies(pending_replies);
                     virtual_portals.clear();
+                    // With no Query or Sync sent yet, the backend discards
+                    // what the client sends until its Sync.
+                    *failed_batch = *pending_syncs == 0;
+                    if let Some(denial) = context::take_denial(pending_denials, &frame) {
+                        self.buffers.queue_response(&denial.to_bytes());
+                        forward = false;
+                    }
                 }
-                b'Z' => {
-                    if *pending_syncs > 0 {
-                        *pending_syncs -= 1;
+                MessageType::ReadyForQuery => {
+                    if let Some(status) = frame.get(5).copied().and_then(ReadyStatus::from_byte) {
+                        analytics::record_ready_transition(*ready_status, status);
+                        *ready_status = status;
+                    }
+                    let settled =
+                        context::settle_on_ready(pending_replies, virtual_portals, *ready_status);
+                    if let Some(error) = settled.injected {
+                        self.buffers.queue_response(&error.to_bytes());
+                    }
+                    if let Some(sample) = settled.sample {
+                        sample.finish(query_log, username);
+                    }
+                    if let Some(setting) = settled.setting {
+                        session_state.apply(setting);
+                    }
+                    if let Some((latency, failed)) = settled.completed {
+                        pool.record_query(latency, failed);
                     }
-                    if *pending_syncs == 0 {
+                    // An open transaction or a suspended portal lives on this
+                    // backend; keep it until the client is done with them.
+                    // Session mode keeps it for the whole connection.
+                    if pooler_mode == PoolerMode::Transaction
+                        && *pending_syncs == 0
+                        && !ready_status.in_transaction()
+                        && !virtual_portals.values().any(|p| p.suspended)
+                    {
                         release_session = true;
                     }
                 }
+                // Relayed too; the backend's values decide what the next
+                // checkout's session replay can skip.
+                MessageType::ParameterStatus => backend.note_parameter_status(&frame),
                 _ => {}
             }
 
@@ -288,31 +475,606 @@ impl FrontendConnection {
             }
         }
 
+        if let Some(desync) = desync {
+            self.recover_from_desync(desync);
+            self.flush().await?;
+            return Ok(true);
+        }
+
+        // What is queued may be this same read's earlier frames, e.g. the
+        // RowDescription: backpressure, not a refusal, until the client has
+        // had time to read them.
+        let mut overrun = self.buffers.check_backend_frame(backend.buffer()).err();
+        if overrun.is_some()
+            && let Ok(drained) = timeout(
+                OVERRUN_DRAIN_TIMEOUT,
+                self.buffers.flush_to(&mut self.transport),
+            )
+            .await
+        {
+            flushed += drained?;
+            overrun = self.buffers.check_backend_frame(backend.buffer()).err();
+        }
+        if overrun.is_some() {
+            session.discard();
+        }
+
         if release_session {
             *gateway_session = None;
             *current_pool = None;
             pending_parses.clear();
+            pending_closes.clear();
+            pending_describes.clear();
             *pending_syncs = 0;
+            pending_replies.clear();
+            *skip_until_sync = None;
+            *failed_batch = false;
+            pending_denials.clear();
+            *copy_in = false;
             virtual_portals.clear();
-            self.backend_tracker.reset();
+            self.context.untracked_setting = false;
+            self.registration.update(&self.context);
+        }
+        self.context.traffic.client_bytes_out += flushed as u64;
+
+        if let Some(overrun) = overrun {
+            let error = ErrorResponse::configuration_limit_exceeded(format!(
+                "backend message of {} bytes exceeds the client outbox limit of {} bytes",
+                overrun.declared, overrun.limit
+            ));
+            self.buffers.queue_response(&error.to_bytes());
+            self.context.gateway_session = None;
+            self.context.current_pool = None;
+            // Best effort: the client may be why the outbox is full.
+            let _ = timeout(OVERRUN_FLUSH_TIMEOUT, self.flush()).await;
+            return Ok(false);
+        }
+
+        if let Some(unknown) = unknown_tag {
+            self.backend_error(ErrorResponse::connection_failure(unknown.to_string()));
         }
 
-        self.buffers.flush_to(&mut self.transport).await?;
+        self.flush().await?;
 
         Ok(true)
     }
 
-    fn backend_error(&mut self, message: String) {
-        let error = ErrorResponse::internal_error(message);
+    async fn flush(&mut self) -> std::io::Result<()> {
+        let n = self.buffers.flush_to(&mut self.transport).await?;
+        self.context.traffic.client_bytes_out += n as u64;
+        Ok(())
+    }
+
+    /// Whatever the backend sends next can't be matched to the client's
+    /// frames, so it is closed rather than pooled. A client still waiting
+    /// gets the error and one ReadyForQuery per Query or Sync it sent.
+    fn recover_from_desync(&mut self, desync: ProtocolDesync) {
+        warn!(%desync, "backend protocol desync, closing the backend");
+        let owed = self.context.pending_syncs;
+        self.context.drop_backend(true);
+        if owed > 0 {
+            let error =
+                ErrorResponse::protocol_violation(format!("backend protocol desync: {desync}"));
+            self.buffers.queue_response(&error.to_bytes());
+            for _ in 0..owed {
+                self.buffers
+                    .queue_response(&responses::ready_matching(&self.context));
+            }
+        }
+    }
+
+    /// Like Postgres' own timeout, ends the connection: closing the backend
+    /// rolls the transaction back and frees it for other clients.
+    async fn close_idle_transaction(&mut self) -> std::io::Result<()> {
+        warn!("client idle in transaction past idle_in_transaction_timeout");
+        let error = ErrorResponse::idle_in_transaction_session_timeout(
+            "terminating connection due to idle-in-transaction timeout",
+        );
+        self.buffers.queue_response(&error.to_bytes());
+        self.context.drop_backend(true);
+        self.registration.update(&self.context);
+        self.flush().await
+    }
+
+    fn backend_error(&mut self, error: ErrorResponse) {
+        self.context.drop_backend(false);
         self.buffers.queue_response(&error.to_bytes());
         self.buffers
-            .queue_response(&responses::ready_with_status(ReadyStatus::Idle));
-        self.context.gateway_session = None;
-        self.context.current_pool = None;
-        self.context.pending_parses.clear();
-        self.context.pending_syncs = 0;
-        self.context.virtual_portals.clear();
-        self.backend_tracker.reset();
+            .queue_response(&responses::ready_matching(&self.context));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::shards::ShardRecord;
+    use crate::wire::builders;
+    use crate::wire::observers::describe::DescribeTarget;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, duplex};
+    use tokio::net::TcpListener;
+
+    const SYNC: [u8; 5] = [b'S', 0, 0, 0, 4];
+
+    /// ParameterDescription for one int4, then NoData.
+    const DESCRIPTION: [u8; 16] = [b't', 0, 0, 0, 10, 0, 1, 0, 0, 0, 23, b'n', 0, 0, 0, 4];
+
+    /// What a `scripted_backend` received.
+    #[derive(Debug, Default)]
+    struct Received {
+        connects: AtomicUsize,
+        statement_describes: AtomicUsize,
+        queries: parking_lot::Mutex<Vec<String>>,
+        /// Type of every frontend frame after startup, in order.
+        tags: parking_lot::Mutex<Vec<u8>>,
+    }
+
+    /// Postgres as far as the relay can tell: every frame gets the reply
+    /// its type calls for, and transactions open, fail and end. SQL that
+    /// starts with `FAIL`, or is a policy denial, doesn't parse; after a
+    /// failed Parse, extended frames are skipped until the Sync. A Parse of
+    /// `LOSE READY` makes it drop the next Sync's ReadyForQuery. A Query of
+    /// `BIG ROW` returns one `BIG_ROW_BYTES` value, all in one write.
+    async fn scripted_backend() -> (u16, Arc<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Received::default());
+        let seen = Arc::clone(&received);
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let seen = Arc::clone(&seen);
+                seen.connects.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let startup_len = stream.read_u32().await.unwrap() as usize;
+                    let mut startup = vec![0u8; startup_len - 4];
+                    stream.read_exact(&mut startup).await.unwrap();
+                    stream
+                        .write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I'])
+                        .await
+                        .unwrap();
+
+                    let mut status = b'I';
+                    let mut skipping = false;
+                    let mut lose_ready = false;
+                    loop {
+                        let Ok(tag) = stream.read_u8().await else {
+                            return;
+                        };
+                        let len = stream.read_u32().await.unwrap() as usize;
+                        let mut body = vec![0u8; len - 4];
+                        stream.read_exact(&mut body).await.unwrap();
+                        seen.tags.lock().push(tag);
+                        if skipping && !matches!(tag, b'S' | b'X') {
+                            continue;
+                        }
+                        let reply: Vec<u8> = match tag {
+                            b'P' => {
+                                let sql = cstr_at(&body, 1 + cstr_at(&body, 0).len());
+                                lose_ready |= sql == "LOSE READY";
+                                match scripted_error(&sql, status) {
+                                    Some(error) => {
+                                        skipping = true;
+                                        status = failed(status);
+                                        error
+                                    }
+                                    None => vec![b'1', 0, 0, 0, 4],
+                                }
+                            }
+                            b'B' => vec![b'2', 0, 0, 0, 4],
+                            b'C' => vec![b'3', 0, 0, 0, 4],
+                            b'D' if body[0] == b'S' => {
+                                seen.statement_describes.fetch_add(1, Ordering::SeqCst);
+                                DESCRIPTION.to_vec()
+                            }
+                            b'D' => vec![b'n', 0, 0, 0, 4],
+                            b'E' => command_complete("SELECT 0"),
+                            b'S' if std::mem::take(&mut lose_ready) => Vec::new(),
+                            b'S' => {
+                                skipping = false;
+                                vec![b'Z', 0, 0, 0, 5, status]
+                            }
+                            b'Q' => {
+                                let sql = cstr_at(&body, 0);
+                                seen.queries.lock().push(sql.clone());
+                                let mut reply = match scripted_error(&sql, status) {
+                                    Some(error) => {
+                                        status = failed(status);
+                                        error
+                                    }
+                                    None if sql == "BIG ROW" => big_row(),
+                                    None if sql == "BEGIN" => {
+                                        status = b'T';
+                                        command_complete("BEGIN")
+                                    }
+                                    None if sql == "COMMIT" || sql == "ROLLBACK" => {
+                                        let tag = if status == b'E' { "ROLLBACK" } else { &sql };
+                                        status = b'I';
+                                        command_complete(tag)
+                                    }
+                                    None => command_complete("OK"),
+                                };
+                                reply.extend_from_slice(&[b'Z', 0, 0, 0, 5, status]);
+                                reply
+                            }
+                            b'X' => return,
+                            _ => Vec::new(),
+                        };
+                        stream.write_all(&reply).await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, received)
+    }
+
+    fn cstr_at(body: &[u8], start: usize) -> String {
+        let end = start + body[start..].iter().position(|&b| b == 0).unwrap();
+        String::from_utf8_lossy(&body[start..end]).into_owned()
+    }
+
+    /// What Postgres would answer `sql` with as an error, if anything.
+    fn scripted_error(sql: &str, status: u8) -> Option<Vec<u8>> {
+        let error = if status == b'E' && sql != "COMMIT" && sql != "ROLLBACK" {
+            ErrorResponse::new(
+                crate::errors::Severity::Error,
+                "25P02",
+                "current transaction is aborted, commands ignored until end of transaction block",
+            )
+        } else if sql.starts_with("FAIL") || sql.starts_with(context::DENIAL_MARKER) {
+            ErrorResponse::syntax_error(format!("syntax error at or near \"{sql}\""))
+        } else {
+            return None;
+        };
+        Some(error.to_bytes().to_vec())
+    }
+
+    fn failed(status: u8) -> u8 {
+        if status == b'I' { b'I' } else { b'E' }
+    }
+
+    fn command_complete(tag: &str) -> Vec<u8> {
+        let mut frame = vec![b'C'];
+        frame.extend_from_slice(&(4 + tag.len() as u32 + 1).to_be_bytes());
+        frame.extend_from_slice(tag.as_bytes());
+        frame.push(0);
+        frame
+    }
+
+    const BIG_ROW_BYTES: usize = 64 * 1024;
+
+    /// RowDescription for one text column, then a DataRow with
+    /// `BIG_ROW_BYTES` in it.
+    fn big_row() -> Vec<u8> {
+        let mut reply = vec![b'T'];
+        reply.extend_from_slice(&(4 + 2 + 22u32).to_be_bytes());
+        reply.extend_from_slice(&1u16.to_be_bytes());
+        reply.extend_from_slice(b"big\0");
+        reply.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 25, 255, 255, 255, 255, 255, 255]);
+        reply.extend_from_slice(&0u16.to_be_bytes());
+
+        reply.push(b'D');
+        reply.extend_from_slice(&(4 + 2 + 4 + BIG_ROW_BYTES as u32).to_be_bytes());
+        reply.extend_from_slice(&1u16.to_be_bytes());
+        reply.extend_from_slice(&(BIG_ROW_BYTES as u32).to_be_bytes());
+        reply.resize(reply.len() + BIG_ROW_BYTES, b'x');
+
+        reply.extend_from_slice(&command_complete("SELECT 1"));
+        reply
+    }
+
+    fn query_frame(sql: &str) -> Vec<u8> {
+        let mut frame = vec![b'Q'];
+        frame.extend_from_slice(&(4 + sql.len() as u32 + 1).to_be_bytes());
+        frame.extend_from_slice(sql.as_bytes());
+        frame.push(0);
+        frame
+    }
+
+    /// A logged-in transaction-mode client on one single-backend shard,
+    /// served on its own task; the test talks to it through the returned
+    /// end of the pipe.
+    fn serve_client(shard: ShardRecord) -> DuplexStream {
+        serve_client_with(shard, |_| {})
+    }
+
+    /// `serve_client`, with the context adjusted before it's served.
+    fn serve_client_with(
+        shard: ShardRecord,
+        configure: impl FnOnce(&mut FrontendContext),
+    ) -> DuplexStream {
+        serve_client_on(shard, configure, FrontendBuffers::new())
+    }
+
+    /// `serve_client_with`, relaying through `buffers`.
+    fn serve_client_on(
+        shard: ShardRecord,
+        configure: impl FnOnce(&mut FrontendContext),
+        buffers: FrontendBuffers,
+    ) -> DuplexStream {
+        let (client, server) = duplex(64 * 1024);
+        let mut context = FrontendContext::new();
+        context.stage = AuthStage::Ready;
+        context.username = Some("user".to_string());
+        configure(&mut context);
+        let id = rand::random();
+        let connection = FrontendConnection {
+            id,
+            peer: None,
+            registration: ClientRegistration::register(id, None),
+            context,
+            buffers,
+            transport: FrontendTransport::new(server),
+            tls_acceptor: None,
+            tls_end_point: None,
+            pools: Arc::new(GatewayPools::new(vec![shard])),
+        };
+        tokio::spawn(connection.serve());
+        client
+    }
+
+    fn shard(port: u16) -> ShardRecord {
+        ShardRecord {
+            shard_name: "fake".to_string(),
+            host: "127.0.0.1".to_string(),
+            port,
+            user: "user".to_string(),
+            min_connections: 1,
+            max_connections: 1,
+            server_reset_query: String::new(),
+            ..ShardRecord::default()
+        }
+    }
+
+    /// Sends `request`, then reads replies up to and including the next
+    /// ReadyForQuery; returns them as (type, body) pairs.
+    async fn round_trip(client: &mut DuplexStream, request: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        client.write_all(request).await.unwrap();
+        let mut frames = Vec::new();
+        loop {
+            let tag = client.read_u8().await.unwrap();
+            let len = client.read_u32().await.unwrap() as usize;
+            let mut body = vec![0u8; len - 4];
+            client.read_exact(&mut body).await.unwrap();
+            frames.push((tag, body));
+            if tag == b'Z' {
+                return frames;
+            }
+        }
+    }
+
+    fn tags(frames: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        frames.iter().map(|(tag, _)| *tag).collect()
+    }
+
+    fn error_code(body: &[u8]) -> String {
+        body.split(|&b| b == 0)
+            .find(|field| field.first() == Some(&b'C'))
+            .map(|field| String::from_utf8_lossy(&field[1..]).into_owned())
+            .unwrap_or_default()
+    }
+
+    fn describe(name: &str) -> Vec<u8> {
+        let mut request = BytesMut::new();
+        builders::build_describe(&mut request, DescribeTarget::Statement, name);
+        request.extend_from_slice(&SYNC);
+        request.to_vec()
+    }
+
+    #[tokio::test]
+    async fn repeated_describes_are_answered_from_the_cache() {
+        let (port, received) = scripted_backend().await;
+        let mut client = serve_client(ShardRecord {
+            describe_cache_size: Some(8),
+            ..shard(port)
+        });
+
+        let mut request = BytesMut::new();
+        builders::build_parse(&mut request, "s", "SELECT $1::int", &[]);
+        request.extend_from_slice(&describe("s"));
+        let frames = round_trip(&mut client, &request).await;
+        assert_eq!(tags(&frames), b"1tnZ");
+
+        // Same reply, in the same place, without a backend Describe.
+        let frames = round_trip(&mut client, &describe("s")).await;
+        assert_eq!(tags(&frames), b"tnZ");
+        assert_eq!(frames[0].1, DESCRIPTION[5..11]);
+        assert_eq!(received.statement_describes.load(Ordering::SeqCst), 1);
+
+        // Another search_path may resolve the query to other tables.
+        round_trip(&mut client, &query_frame("SET search_path TO tenant")).await;
+        let frames = round_trip(&mut client, &describe("s")).await;
+        assert_eq!(tags(&frames), b"tnZ");
+        assert_eq!(received.statement_describes.load(Ordering::SeqCst), 2);
+        round_trip(&mut client, &describe("s")).await;
+        assert_eq!(received.statement_describes.load(Ordering::SeqCst), 2);
+
+        // DDL empties the cache.
+        round_trip(
+            &mut client,
+            &query_frame("ALTER TABLE users ADD COLUMN c int"),
+        )
+        .await;
+        round_trip(&mut client, &describe("s")).await;
+        assert_eq!(received.statement_describes.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn describes_go_to_the_backend_without_a_cache() {
+        let (port, received) = scripted_backend().await;
+        let mut client = serve_client(shard(port));
+
+        let mut request = BytesMut::new();
+        builders::build_parse(&mut request, "s", "SELECT $1::int", &[]);
+        request.extend_from_slice(&describe("s"));
+        round_trip(&mut client, &request).await;
+        let frames = round_trip(&mut client, &describe("s")).await;
+        assert_eq!(tags(&frames), b"tnZ");
+        assert_eq!(received.statement_describes.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failing_on_connect_is_its_own_checkout_error() {
+        let (port, _) = scripted_backend().await;
+        let mut client = serve_client(ShardRecord {
+            on_connect: vec!["FAIL to set up".to_string()],
+            ..shard(port)
+        });
+
+        let frames = round_trip(&mut client, &query_frame("SELECT 1")).await;
+        assert_eq!(tags(&frames), b"EZ");
+        assert_eq!(error_code(&frames[0].1), "08001");
+        let message = String::from_utf8_lossy(&frames[0].1).into_owned();
+        assert!(
+            message.contains("backend on_connect failed: syntax error"),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn policy_denials_fail_the_transaction_they_are_in() {
+        let (port, received) = scripted_backend().await;
+        let mut client = serve_client_with(shard(port), |context| {
+            context.policy.deny_statements = vec!["DropStmt".to_string()];
+        });
+
+        round_trip(&mut client, &query_frame("BEGIN")).await;
+        let frames = round_trip(&mut client, &query_frame("DROP TABLE users")).await;
+        assert_eq!(tags(&frames), b"EZ");
+        assert_eq!(error_code(&frames[0].1), "42501");
+        assert_eq!(frames[1].1, [b'E']);
+
+        // Postgres won't commit what came before the denied statement.
+        let frames = round_trip(&mut client, &query_frame("COMMIT")).await;
+        assert_eq!(frames[0].1, b"ROLLBACK\0");
+        assert_eq!(frames[1].1, [b'I']);
+
+        let queries = received.queries.lock().clone();
+        assert!(!queries.iter().any(|query| query.contains("DROP")));
+    }
+
+    #[tokio::test]
+    async fn policy_denials_come_after_earlier_replies() {
+        let (port, _) = scripted_backend().await;
+        let mut client = serve_client_with(shard(port), |context| {
+            context.policy.deny_statements = vec!["DropStmt".to_string()];
+        });
+
+        let mut request = BytesMut::new();
+        builders::build_parse(&mut request, "", "SELECT 1", &[]);
+        builders::build_bind(&mut request, "", "", &[], &[], &[]);
+        builders::build_execute(&mut request, "", 0);
+        builders::build_parse(&mut request, "", "DROP TABLE users", &[]);
+        builders::build_bind(&mut request, "", "", &[], &[], &[]);
+        builders::build_execute(&mut request, "", 0);
+        request.extend_from_slice(&SYNC);
+        let frames = round_trip(&mut client, &request).await;
+        assert_eq!(tags(&frames), b"12CEZ");
+        assert_eq!(error_code(&frames[3].1), "42501");
+
+        // The batch after it runs as usual.
+        let frames = round_trip(&mut client, &query_frame("SELECT 1")).await;
+        assert_eq!(tags(&frames), b"CZ");
+    }
+
+    #[tokio::test]
+    async fn backend_is_kept_until_the_transaction_ends() {
+        let (port, received) = scripted_backend().await;
+        let mut client = serve_client(ShardRecord {
+            server_reset_query: "DISCARD ALL".to_string(),
+            ..shard(port)
+        });
+
+        // A release would reset the backend before its next checkout.
+        for sql in ["BEGIN", "SELECT 1", "COMMIT"] {
+            round_trip(&mut client, &query_frame(sql)).await;
+        }
+        assert_eq!(
+            received.queries.lock()[..3],
+            ["BEGIN", "SELECT 1", "COMMIT"]
+        );
+
+        // Outside a transaction, each query gets a reset backend.
+        round_trip(&mut client, &query_frame("SELECT 2")).await;
+        assert_eq!(received.queries.lock()[3..5], ["DISCARD ALL", "SELECT 2"]);
+    }
+
+    #[tokio::test]
+    async fn large_row_behind_its_row_description_waits_for_the_client() {
+        let (port, _) = scripted_backend().await;
+        // The row is 16 times the overrun limit of 4 × 1 KiB.
+        let buffers = FrontendBuffers::with_limits(1024 * 1024, 1024);
+        let mut client = serve_client_on(shard(port), |_| {}, buffers);
+
+        let frames = round_trip(&mut client, &query_frame("BIG ROW")).await;
+        assert_eq!(tags(&frames), b"TDCZ");
+        assert_eq!(frames[1].1.len(), 2 + 4 + BIG_ROW_BYTES);
+    }
+
+    /// Extended frames up to an Execute of the unnamed portal.
+    fn run_unnamed(request: &mut BytesMut, sql: &str) {
+        builders::build_parse(request, "", sql, &[]);
+        builders::build_bind(request, "", "", &[], &[], &[]);
+        builders::build_execute(request, "", 0);
+    }
+
+    #[tokio::test]
+    async fn failed_parse_skips_the_rest_of_the_batch_until_sync() {
+        let (port, received) = scripted_backend().await;
+        let mut client = serve_client(shard(port));
+
+        // No Sync yet: the error comes back on the Flush alone.
+        let mut request = BytesMut::new();
+        run_unnamed(&mut request, "FAIL here");
+        request.extend_from_slice(&[b'H', 0, 0, 0, 4]);
+        client.write_all(&request).await.unwrap();
+        assert_eq!(client.read_u8().await.unwrap(), b'E');
+        let len = client.read_u32().await.unwrap() as usize;
+        let mut body = vec![0u8; len - 4];
+        client.read_exact(&mut body).await.unwrap();
+        assert_eq!(error_code(&body), "42601");
+
+        // Postgres would discard these; the relay doesn't send them.
+        let mut request = BytesMut::new();
+        builders::build_bind(&mut request, "", "", &[], &[], &[]);
+        builders::build_execute(&mut request, "", 0);
+        request.extend_from_slice(&SYNC);
+        let frames = round_trip(&mut client, &request).await;
+        assert_eq!(tags(&frames), b"Z");
+        assert_eq!(*received.tags.lock(), b"PBEHS");
+
+        let mut request = BytesMut::new();
+        run_unnamed(&mut request, "SELECT 1");
+        request.extend_from_slice(&SYNC);
+        let frames = round_trip(&mut client, &request).await;
+        assert_eq!(tags(&frames), b"12CZ");
+    }
+
+    #[tokio::test]
+    async fn desynced_backend_is_closed_and_owed_syncs_answered() {
+        let (port, received) = scripted_backend().await;
+        let mut client = serve_client(shard(port));
+
+        // The first Sync's ReadyForQuery never comes, so the second batch's
+        // CommandComplete can't be matched.
+        let mut request = BytesMut::new();
+        run_unnamed(&mut request, "LOSE READY");
+        request.extend_from_slice(&SYNC);
+        run_unnamed(&mut request, "SELECT 1");
+        request.extend_from_slice(&SYNC);
+        let frames = round_trip(&mut client, &request).await;
+        assert_eq!(tags(&frames), b"12C12EZ");
+        assert_eq!(error_code(&frames[5].1), "08P01");
+        let frames = round_trip(&mut client, &[]).await;
+        assert_eq!(tags(&frames), b"Z");
+
+        // Not pooled: the next query gets a new backend.
+        let frames = round_trip(&mut client, &query_frame("SELECT 1")).await;
+        assert_eq!(tags(&frames), b"CZ");
+        assert_eq!(received.connects.load(Ordering::SeqCst), 2);
     }
 }
 
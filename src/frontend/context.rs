@@ -1,19 +1,52 @@
-use secrecy::ExposeSecret;
+use bytes::{Bytes, BytesMut};
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::config::users::UsersConfig;
-use crate::gateway::GatewaySession;
-use crate::shared_types::{AuthStage, BackendIdentity, StatementSignature};
+use crate::ErrorResponse;
+use crate::analytics::ByteCounters;
+use crate::backend::BackendConnection;
+use crate::config::policy::PolicyConfig;
+use crate::config::query_log::QueryLogConfig;
+use crate::config::routing::RoutingConfig;
+use crate::config::server::{ClientAuth, ServerConfig};
+use crate::config::startup::StartupConfig;
+use crate::config::users::{PoolerMode, UserRecord, UsersConfig};
+use crate::frontend::channel_binding::DEFAULT_SASL_MECHANISMS;
+use crate::frontend::query_log::QuerySample;
+use crate::frontend::scram::ScramExchange;
+use crate::frontend::session_state::{SessionState, SettingChange};
+use crate::frontend::setup_timings::SetupTimings;
+use crate::gateway::{DescribeKey, GatewaySession, RateLimiter};
+use crate::shared_types::{AuthStage, BackendIdentity, ReadyStatus, StatementSignature};
+use crate::wire::types::MessageType;
 
 // -----------------------------------------------------------------------------
 // ----- FrontendContext -------------------------------------------------------
 
 #[derive(Debug)]
 pub(crate) struct PendingParse {
+    /// Client statement this Parse creates, with its generation; `None` for
+    /// the proxy's own Parses.
+    pub(crate) client_statement: Option<(String, u64)>,
     pub(crate) signature: Option<StatementSignature>,
     pub(crate) backend_statement_name: Option<String>,
     pub(crate) suppress_response: bool,
+    /// Relayed to the client in place of the ParseComplete: a cached
+    /// Describe reply, kept in order behind the backend's earlier replies.
+    pub(crate) replay: Option<Bytes>,
+}
+
+/// A statement Describe sent to a shard with `describe_cache_size`; its
+/// reply collects here until the RowDescription or NoData ends it.
+#[derive(Debug)]
+pub(crate) struct PendingDescribe {
+    /// `None` when the reply can't be cached for this client.
+    pub(crate) key: Option<DescribeKey>,
+    /// The cache's generation when the Describe went out.
+    pub(crate) generation: u64,
+    pub(crate) reply: BytesMut,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +61,94 @@ pub(crate) struct VirtualStatement {
 #[derive(Debug, Clone)]
 pub(crate) struct PortalBinding {
     pub(crate) backend_portal_name: String,
+    /// The backend statement it was bound from; closing that statement
+    /// would close the portal too.
+    pub(crate) backend_statement_name: String,
+    /// Backend answered the last Execute with PortalSuspended; the portal
+    /// stays open until the transaction ends so the client can resume it.
+    pub(crate) suspended: bool,
+    /// Binary flags from the Bind's result format codes, as sent: empty
+    /// means all text, a single entry covers every column, otherwise one
+    /// entry per column. Read through `is_binary_column`.
+    pub(crate) result_formats: Vec<bool>,
+}
+
+impl PortalBinding {
+    /// Whether result column `column` comes back in binary, for rewriting
+    /// its `DataRow` values.
+    pub(crate) fn is_binary_column(&self, column: usize) -> bool {
+        match self.result_formats.as_slice() {
+            [] => false,
+            [all] => *all,
+            formats => formats.get(column).copied().unwrap_or(false),
+        }
+    }
+}
+
+/// What a backend CloseComplete answers; one per Close sent, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PendingClose {
+    /// The client's own Close: relayed as is.
+    Client,
+    /// The proxy evicting a prepare; the client never asked, so it's dropped.
+    Evicted,
+    /// A simple-protocol `DEALLOCATE` of a client statement, sent as a
+    /// Close: the client gets the CommandComplete it expects instead.
+    Deallocate,
+}
+
+/// Frontend frame awaiting its backend response, in send order.
+#[derive(Debug)]
+pub(crate) enum PendingReply {
+    /// Execute on this client portal; answered by CommandComplete,
+    /// EmptyQueryResponse or PortalSuspended.
+    Execute(String),
+    /// Query or Sync; answered by ReadyForQuery. `query` is set for a
+    /// Query, whose statements complete ahead of it; `injected` is an error
+    /// raised by the proxy itself, delivered just ahead of that
+    /// ReadyForQuery; `sample` times a Query picked for the query log;
+    /// `setting` is a session setting the Query changes, kept unless the
+    /// backend rejects it; `sent` and `failed` feed the shard's query stats.
+    /// `sent` is `None` for a Sync or Query the proxy made up and for a
+    /// COPY FROM STDIN, whose time is the client's: neither is timed.
+    Ready {
+        query: bool,
+        injected: Option<Box<ErrorResponse>>,
+        sample: Option<Box<QuerySample>>,
+        setting: Option<SettingChange>,
+        sent: Option<Instant>,
+        failed: bool,
+    },
+}
+
+/// Backend replies that no longer line up with the frames the client sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProtocolDesync {
+    /// ReadyForQuery with no Query or Sync waiting for one.
+    ExtraReady,
+    /// An Execute completed past a Sync that never got its ReadyForQuery.
+    MissingReady,
+}
+
+impl fmt::Display for ProtocolDesync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolDesync::ExtraReady => write!(f, "ReadyForQuery without a pending Sync"),
+            ProtocolDesync::MissingReady => write!(f, "missing ReadyForQuery for a Sync"),
+        }
+    }
+}
+
+/// What a ReadyForQuery settles; see `settle_on_ready`.
+#[derive(Debug, Default)]
+pub(crate) struct SettledReady {
+    pub(crate) injected: Option<ErrorResponse>,
+    pub(crate) sample: Option<Box<QuerySample>>,
+    pub(crate) setting: Option<SettingChange>,
+    /// From the Query or Sync going out to its ReadyForQuery, and whether the
+    /// backend raised an error in between; `None` for a stray ReadyForQuery
+    /// or an untimed one.
+    pub(crate) completed: Option<(Duration, bool)>,
 }
 
 #[derive(Debug)]
@@ -38,12 +159,77 @@ pub(crate) struct FrontendContext {
     pub(crate) gateway_session: Option<GatewaySession>,
     pub(crate) current_pool: Option<String>,
     pub(crate) stage: AuthStage,
+    pub(crate) ready_status: ReadyStatus,
     pub(crate) is_admin: bool,
+    /// Backend role from the user's `server_username`, if set; checkouts
+    /// come from that role's pools.
+    pub(crate) server_role: Option<String>,
+    /// Shard named by the client's `pgcrab.shard` startup option; every
+    /// checkout comes from it instead of going through `routing`.
+    pub(crate) pinned_shard: Option<String>,
+    /// Session mode pins one backend for the whole connection; transaction
+    /// mode returns it after each transaction and replays `session_state`
+    /// on the next one.
+    pub(crate) pooler_mode: PoolerMode,
+    pub(crate) session_state: SessionState,
+    pub(crate) strict_parse: bool,
+    /// `[server] validate_text_params`.
+    pub(crate) validate_text_params: bool,
+    /// Client messages in a row that failed to decode; see
+    /// `count_decode_failures` for what resets it.
+    pub(crate) decode_failures: u32,
+    /// `[server] max_decode_failures`.
+    pub(crate) max_decode_failures: u32,
+    pub(crate) query_log: QueryLogConfig,
+    pub(crate) routing: RoutingConfig,
+    pub(crate) startup: StartupConfig,
+    pub(crate) policy: PolicyConfig,
+    /// `[server] connect_notice`, before its placeholders are filled in.
+    pub(crate) connect_notice: Option<String>,
+    /// `[server] client_auth`.
+    pub(crate) client_auth: ClientAuth,
+    /// `[server] sasl_mechanisms`.
+    pub(crate) sasl_mechanisms: Vec<&'static str>,
+    /// Channel binding data for the certificate the client's TLS session
+    /// got; `None` in plaintext.
+    pub(crate) tls_end_point: Option<Vec<u8>>,
+    /// The SCRAM exchange between its two client messages.
+    pub(crate) scram: Option<ScramExchange>,
+    /// `[server] idle_in_transaction_timeout`, or the user's override.
+    pub(crate) idle_in_transaction_timeout: Option<Duration>,
+    /// The user's shared query budget; each Query and Execute takes a token.
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+    /// A token was taken for the lone Query being sent before its checkout;
+    /// `handle_query_frame` uses it rather than taking another.
+    pub(crate) rate_token_taken: bool,
     pub(crate) virtual_statements: HashMap<String, VirtualStatement>,
     pub(crate) virtual_portals: HashMap<String, PortalBinding>,
+    pub(crate) pending_replies: VecDeque<PendingReply>,
+    /// Set when the proxy rejects an extended-protocol frame: like Postgres,
+    /// discard everything up to the next Sync, which reports this error.
+    pub(crate) skip_until_sync: Option<ErrorResponse>,
+    /// Set when the backend errored before the client sent the batch's Sync:
+    /// Postgres discards everything up to that Sync, so the proxy drops it
+    /// too instead of waiting on replies that will never come.
+    pub(crate) failed_batch: bool,
+    /// Statements `[policy]` refused, sent on as `DENIAL_MARKER` SQL; each
+    /// is keyed by the number after its marker. See `take_denial`.
+    pub(crate) pending_denials: VecDeque<(u64, ErrorResponse)>,
+    pub(crate) next_denial: u64,
+    /// Backend is in COPY IN: client frames stream through as they arrive and
+    /// Syncs are ignored by Postgres until CopyDone or CopyFail.
+    pub(crate) copy_in: bool,
     pub(crate) in_flight_prepares: HashMap<StatementSignature, String>,
     pub(crate) pending_parses: VecDeque<PendingParse>,
+    pub(crate) pending_closes: VecDeque<PendingClose>,
+    pub(crate) pending_describes: VecDeque<PendingDescribe>,
+    /// A `SET` or `set_config` ran that `session_state` doesn't follow, so
+    /// the backend's `search_path` is unknown: described statements skip
+    /// the describe cache until the backend goes back to the pool.
+    pub(crate) untracked_setting: bool,
     pub(crate) pending_syncs: usize,
+    pub(crate) traffic: ByteCounters,
+    pub(crate) setup: SetupTimings,
     close_after_flush: bool,
     upgrade_to_tls: bool,
 }
@@ -57,12 +243,44 @@ impl FrontendContext {
             gateway_session: None,
             current_pool: None,
             stage: AuthStage::Startup,
+            ready_status: ReadyStatus::Idle,
             is_admin: false,
+            server_role: None,
+            pinned_shard: None,
+            pooler_mode: PoolerMode::Transaction,
+            session_state: SessionState::default(),
+            strict_parse: false,
+            validate_text_params: true,
+            decode_failures: 0,
+            max_decode_failures: ServerConfig::default().max_decode_failures,
+            query_log: QueryLogConfig::default(),
+            routing: RoutingConfig::default(),
+            policy: PolicyConfig::default(),
+            startup: StartupConfig::default(),
+            connect_notice: None,
+            client_auth: ClientAuth::default(),
+            sasl_mechanisms: DEFAULT_SASL_MECHANISMS.to_vec(),
+            tls_end_point: None,
+            scram: None,
+            idle_in_transaction_timeout: None,
+            rate_limiter: None,
+            rate_token_taken: false,
             virtual_statements: HashMap::new(),
             virtual_portals: HashMap::new(),
+            pending_replies: VecDeque::new(),
+            skip_until_sync: None,
+            failed_batch: false,
+            pending_denials: VecDeque::new(),
+            next_denial: 0,
+            copy_in: false,
             in_flight_prepares: HashMap::new(),
             pending_parses: VecDeque::new(),
+            pending_closes: VecDeque::new(),
+            pending_describes: VecDeque::new(),
+            untracked_setting: false,
             pending_syncs: 0,
+            traffic: ByteCounters::default(),
+            setup: SetupTimings::new(),
             close_after_flush: false,
             upgrade_to_tls: false,
         }
@@ -88,31 +306,360 @@ impl FrontendContext {
         std::mem::take(&mut self.upgrade_to_tls)
     }
 
+    /// How long the client may leave its transaction idle from now: set only
+    /// while the backend has answered everything and reports a transaction
+    /// open (or failed).
+    pub(crate) fn idle_in_transaction_limit(&self) -> Option<Duration> {
+        let idle = self.gateway_session.is_some()
+            && self.pending_syncs == 0
+            && self.pending_replies.is_empty()
+            && !self.copy_in;
+        if !idle || !self.ready_status.in_transaction() {
+            return None;
+        }
+        self.idle_in_transaction_timeout
+    }
+
+    /// Lets go of the backend and forgets everything it still owed the
+    /// client. A `discard`ed backend is closed instead of pooled.
+    pub(crate) fn drop_backend(&mut self, discard: bool) {
+        if discard && let Some(session) = self.gateway_session.as_mut() {
+            session.discard();
+        }
+        self.ready_status = ReadyStatus::Idle;
+        self.gateway_session = None;
+        self.current_pool = None;
+        self.pending_parses.clear();
+        self.pending_closes.clear();
+        self.pending_describes.clear();
+        self.untracked_setting = false;
+        self.pending_syncs = 0;
+        self.pending_replies.clear();
+        self.skip_until_sync = None;
+        self.failed_batch = false;
+        self.pending_denials.clear();
+        self.copy_in = false;
+        self.virtual_portals.clear();
+    }
+
     pub(crate) async fn authenticate(&mut self, supplied_password: &str) -> Result<(), String> {
         let Some(username) = self.username.as_ref() else {
             return Err("no username".to_string());
         };
+        let database = self.database.as_deref().unwrap_or(username);
+
+        let user = UsersConfig::handle()
+            .authenticate(username, supplied_password, database)
+            .map_err(|_| "authentication failed".to_string())?;
+        self.accept_user(user);
+
+        Ok(())
+    }
+
+    /// The `[[users]]` entry this client logs in as, if there is one.
+    pub(crate) fn user_entry(&self) -> Option<UserRecord> {
+        let username = self.username.as_ref()?;
+        let database = self.database.as_deref().unwrap_or(username);
+        UsersConfig::handle().lookup(username, database).ok()
+    }
+
+    /// Takes on the settings of the entry the client proved it knows the
+    /// password of.
+    pub(crate) fn accept_user(&mut self, user: UserRecord) {
+        self.is_admin = user.admin;
+        self.pooler_mode = user.pooler_mode.unwrap_or(PoolerMode::Transaction);
+        self.rate_limiter = user
+            .rate_limit
+            .map(|limit| RateLimiter::for_user(&user.client_username, limit));
+        self.server_role = user.server_username_set.then_some(user.server_username);
+        if let Some(timeout) = user.idle_in_transaction_timeout {
+            self.idle_in_transaction_timeout = (!timeout.is_zero()).then_some(timeout);
+        }
+
+        // TODO: Remove when gateway sessions are used, this would lead to dead code otherwise.
+        self.gateway_session = None;
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Portal Tracking -------------------------------------------------------
+
+/// Backend finished (or suspended) the oldest outstanding Execute.
+pub(crate) fn complete_pending_execute(
+    pending_replies: &mut VecDeque<PendingReply>,
+    virtual_portals: &mut HashMap<String, PortalBinding>,
+    suspended: bool,
+) {
+    if !matches!(pending_replies.front(), Some(PendingReply::Execute(_))) {
+        return;
+    }
+
+    let Some(PendingReply::Execute(portal)) = pending_replies.pop_front() else {
+        return;
+    };
 
-        let users = UsersConfig::snapshot();
+    if let Some(binding) = virtual_portals.get_mut(&portal) {
+        binding.suspended = suspended;
+    }
+}
+
+/// Backend raised an error: it skips the remaining Executes up to the next
+/// Sync, and any error the proxy meant to raise later in that batch is moot.
+pub(crate) fn fail_pending_replies(pending_replies: &mut VecDeque<PendingReply>) {
+    while matches!(pending_replies.front(), Some(PendingReply::Execute(_))) {
+        pending_replies.pop_front();
+    }
+
+    if let Some(PendingReply::Ready {
+        injected,
+        setting,
+        failed,
+        ..
+    }) = pending_replies.front_mut()
+    {
+        *injected = None;
+        *setting = None;
+        *failed = true;
+    }
+}
+
+/// Start of the SQL sent in place of a refused statement. It is no
+/// statement, so the backend answers it with a syntax error naming it.
+pub(crate) const DENIAL_MARKER: &str = "pgcrab_policy_denial_";
+
+/// The policy error to send in place of a backend ErrorResponse that is
+/// the syntax error for a `DENIAL_MARKER`: SQLSTATE `42601`, with the
+/// marker quoted as the token it failed at. Any other error that happens
+/// to mention a marker is the client's own. Denials before it never reached
+/// the backend's parser (it was skipping to a Sync), so they're dropped.
+pub(crate) fn take_denial(
+    pending_denials: &mut VecDeque<(u64, ErrorResponse)>,
+    frame: &[u8],
+) -> Option<ErrorResponse> {
+    let field = |tag: u8| {
+        frame
+            .get(5..)?
+            .split(|&b| b == 0)
+            .find(|field| field.first() == Some(&tag))
+            .and_then(|field| std::str::from_utf8(&field[1..]).ok())
+    };
+    if field(b'C')? != "42601" {
+        return None;
+    }
+    let (_, rest) = field(b'M')?.split_once(&format!("\"{DENIAL_MARKER}"))?;
+    let (digits, _) = rest.split_once('"')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let id: u64 = digits.parse().ok()?;
+
+    while let Some(&(pending, _)) = pending_denials.front() {
+        if pending > id {
+            return None;
+        }
+        let (pending, error) = pending_denials.pop_front()?;
+        if pending == id {
+            return Some(error);
+        }
+    }
+    None
+}
+
+/// Backend answered the oldest Parse with ParseComplete: records its
+/// prepare on the backend. `false` when the reply answers a Parse the client
+/// didn't send, and mustn't reach it.
+pub(crate) fn complete_parse(
+    pending_parses: &mut VecDeque<PendingParse>,
+    backend: &mut BackendConnection,
+) -> bool {
+    let Some(pending) = pending_parses.pop_front() else {
+        return true;
+    };
+    if let (Some(signature), Some(name)) = (pending.signature, pending.backend_statement_name) {
+        backend.prepared_insert(signature, name);
+    }
+    !pending.suppress_response
+}
 
-        let maybe_user = users.iter().find(|u| u.client_username == *username);
+/// Collects the backend's reply to the oldest pending statement Describe;
+/// returns it once complete, for the shard's describe cache.
+pub(crate) fn collect_describe(
+    pending_describes: &mut VecDeque<PendingDescribe>,
+    message_type: MessageType,
+    frame: &[u8],
+) -> Option<PendingDescribe> {
+    match message_type {
+        MessageType::ParameterDescription => {
+            pending_describes
+                .front_mut()?
+                .reply
+                .extend_from_slice(frame);
+            None
+        }
+        MessageType::RowDescription | MessageType::NoData => {
+            // Portal Describes and Queries send these without a
+            // ParameterDescription ahead of them.
+            if pending_describes.front()?.reply.is_empty() {
+                return None;
+            }
+            let mut pending = pending_describes.pop_front()?;
+            pending.reply.extend_from_slice(frame);
+            Some(pending)
+        }
+        _ => None,
+    }
+}
 
-        let Some(user) = maybe_user else {
-            return Err("authentication failed".to_string());
+/// Backend answered the oldest Close with CloseComplete; which one it was.
+/// One nothing was waiting for is relayed as the client's.
+pub(crate) fn complete_close(pending_closes: &mut VecDeque<PendingClose>) -> PendingClose {
+    pending_closes.pop_front().unwrap_or(PendingClose::Client)
+}
+
+/// Backend raised an error ahead of these Parses' ParseComplete: whether
+/// they failed or were skipped, their statements don't exist, so later Binds
+/// to them go through as sent and fail on the backend as they would on
+/// Postgres.
+pub(crate) fn fail_pending_parses(
+    pending_parses: &mut VecDeque<PendingParse>,
+    virtual_statements: &mut HashMap<String, VirtualStatement>,
+) {
+    for pending in pending_parses.drain(..) {
+        let Some((name, generation)) = pending.client_statement else {
+            continue;
         };
+        if let Some(statement) = virtual_statements.get_mut(&name)
+            && statement.generation == generation
+        {
+            statement.closed = true;
+        }
+    }
+}
+
+/// Backend answered the COPY at the head of the queue with CopyInResponse.
+/// Postgres ignores every Sync received in COPY IN, so any the client already
+/// sent behind that command will never get a ReadyForQuery.
+pub(crate) fn enter_copy_in(
+    pending_replies: &mut VecDeque<PendingReply>,
+    pending_syncs: &mut usize,
+) {
+    let Some(mut head) = pending_replies.pop_front() else {
+        return;
+    };
+    // Until CopyDone, the backend waits on the client.
+    if let PendingReply::Ready { sent, .. } = &mut head {
+        *sent = None;
+    }
 
-        let config_password = user.client_password.expose_secret();
+    let queued = pending_replies.len();
+    pending_replies.retain(|pending| !matches!(pending, PendingReply::Ready { .. }));
+    *pending_syncs = pending_syncs.saturating_sub(queued - pending_replies.len());
+    pending_replies.push_front(head);
+}
+
+/// Counts a backend ReadyForQuery against the client's pending Query and
+/// Sync frames.
+pub(crate) fn account_ready(pending_syncs: &mut usize) -> Result<(), ProtocolDesync> {
+    *pending_syncs = pending_syncs
+        .checked_sub(1)
+        .ok_or(ProtocolDesync::ExtraReady)?;
+    Ok(())
+}
+
+/// Checks an Execute reply (CommandComplete, EmptyQueryResponse or
+/// PortalSuspended) against the queue: with a Sync still at the head and an
+/// Execute behind it, the ReadyForQuery for that Sync never came.
+pub(crate) fn check_execute_reply(
+    pending_replies: &VecDeque<PendingReply>,
+) -> Result<(), ProtocolDesync> {
+    let Some(PendingReply::Ready { query: false, .. }) = pending_replies.front() else {
+        return Ok(());
+    };
+    if pending_replies
+        .iter()
+        .any(|pending| matches!(pending, PendingReply::Execute(_)))
+    {
+        return Err(ProtocolDesync::MissingReady);
+    }
+    Ok(())
+}
 
-        if config_password != supplied_password {
-            return Err("authentication failed".to_string());
+/// ReadyForQuery answers the Query or Sync at the head of the queue; returns
+/// the proxy error to send ahead of it, if any, the query log sample it
+/// completes, the session setting it confirms and how it went. Portals survive only if
+/// suspended inside an open transaction, or if a later pipelined Execute
+/// still targets them.
+pub(crate) fn settle_on_ready(
+    pending_replies: &mut VecDeque<PendingReply>,
+    virtual_portals: &mut HashMap<String, PortalBinding>,
+    status: ReadyStatus,
+) -> SettledReady {
+    let settled = match pending_replies.front_mut() {
+        Some(PendingReply::Ready {
+            injected,
+            sample,
+            setting,
+            sent,
+            failed,
+            ..
+        }) => {
+            let settled = SettledReady {
+                injected: injected.take().map(|error| *error),
+                sample: sample.take(),
+                setting: setting.take(),
+                completed: sent.map(|sent| (sent.elapsed(), *failed)),
+            };
+            pending_replies.pop_front();
+            settled
         }
+        _ => SettledReady::default(),
+    };
 
-        self.is_admin = user.admin;
+    let in_transaction = status.in_transaction();
+    virtual_portals.retain(|portal, binding| {
+        (in_transaction && binding.suspended)
+            || pending_replies
+                .iter()
+                .any(|pending| matches!(pending, PendingReply::Execute(name) if name == portal))
+    });
 
-        // TODO: Remove when gateway sessions are used, this would lead to dead code otherwise.
-        self.gateway_session = None;
+    settled
+}
 
-        Ok(())
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denials() -> VecDeque<(u64, ErrorResponse)> {
+        VecDeque::from([(
+            3,
+            ErrorResponse::insufficient_privilege("DropStmt is not allowed"),
+        )])
+    }
+
+    #[test]
+    fn denial_is_only_the_syntax_error_at_its_marker() {
+        let mut pending = denials();
+        let own_error = ErrorResponse::new(
+            crate::errors::Severity::Error,
+            "42703",
+            format!("column \"{DENIAL_MARKER}3\" does not exist"),
+        );
+        assert!(take_denial(&mut pending, &own_error.to_bytes()).is_none());
+        let unquoted = ErrorResponse::syntax_error(format!("near {DENIAL_MARKER}3"));
+        assert!(take_denial(&mut pending, &unquoted.to_bytes()).is_none());
+        let longer = ErrorResponse::syntax_error(format!("at or near \"{DENIAL_MARKER}3x\""));
+        assert!(take_denial(&mut pending, &longer.to_bytes()).is_none());
+        assert_eq!(pending.len(), 1);
+
+        let marker =
+            ErrorResponse::syntax_error(format!("syntax error at or near \"{DENIAL_MARKER}3\""));
+        let denial = take_denial(&mut pending, &marker.to_bytes()).expect("denial");
+        assert_eq!(denial.code, "42501");
+        assert!(pending.is_empty());
     }
 }
 
@@ -1,12 +1,25 @@
 use bytes::BytesMut;
+use secrecy::ExposeSecret;
 
 use crate::ErrorResponse;
+use crate::backend::server_params;
+use crate::config::server::ClientAuth;
+use crate::errors::Severity;
 use crate::frontend::buffers::FrontendBuffers;
 use crate::frontend::context::FrontendContext;
 use crate::frontend::proxy_responses as responses;
+use crate::frontend::scram::{ScramError, ScramExchange};
 use crate::shared_types::AuthStage;
 use crate::shared_types::ReadyStatus;
 use crate::wire::observers::password_message::PasswordMessageFrameObserver;
+use crate::wire::observers::sasl_initial_response::SASLInitialResponseFrameObserver;
+use crate::wire::observers::sasl_response::SASLResponseFrameObserver;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+const SASL_CONTINUE: i32 = 11;
+const SASL_FINAL: i32 = 12;
 
 // -----------------------------------------------------------------------------
 // ----- Authenticating Handler -----------------------------------------------
@@ -16,6 +29,11 @@ pub(crate) async fn handle_authenticating(
     buffers: &mut FrontendBuffers,
     message: BytesMut,
 ) {
+    if context.client_auth == ClientAuth::ScramSha256 {
+        handle_sasl(context, buffers, &message);
+        return;
+    }
+
     let Ok(frame) = PasswordMessageFrameObserver::new(&message) else {
         let error = ErrorResponse::protocol_violation("cannot parse password");
         buffers.queue_response(&error.to_bytes());
@@ -25,19 +43,9 @@ pub(crate) async fn handle_authenticating(
     match context.authenticate(frame.password()).await {
         Ok(_) => {
             context.stage = AuthStage::Ready;
+            context.setup.record_auth();
 
-            // AuthenticationOk
-            buffers.queue_response(&responses::auth_ok());
-
-            // ParameterStatus (keep it minimal but sane)
-            buffers.queue_response(&responses::param_status("server_encoding", "UTF8"));
-            buffers.queue_response(&responses::param_status("client_encoding", "UTF8"));
-
-            // BackendKeyData
-            buffers.queue_response(&responses::backend_key_data(context.backend_identity));
-
-            // ReadyForQuery (idle)
-            buffers.queue_response(&responses::ready_with_status(ReadyStatus::Idle));
+            queue_startup_response(context, buffers);
         }
         Err(e) => {
             let error = ErrorResponse::internal_error(&e);
@@ -46,5 +54,246 @@ pub(crate) async fn handle_authenticating(
     }
 }
 
+/// SCRAM takes two round trips: the SASLInitialResponse gets
+/// AuthenticationSASLContinue, and the SASLResponse, if its proof holds,
+/// AuthenticationSASLFinal and then what a cleartext login gets. Any
+/// failure ends the connection, as in Postgres.
+fn handle_sasl(context: &mut FrontendContext, buffers: &mut FrontendBuffers, message: &[u8]) {
+    let Some(mut exchange) = context.scram.take() else {
+        match start_sasl(context, message) {
+            Ok((exchange, server_first)) => {
+                context.scram = Some(exchange);
+                buffers.queue_response(&responses::auth_sasl_data(SASL_CONTINUE, &server_first));
+            }
+            Err(message) => refuse(context, buffers, ErrorResponse::protocol_violation(message)),
+        }
+        return;
+    };
+
+    let Ok(frame) = SASLResponseFrameObserver::new(message) else {
+        let error = ErrorResponse::protocol_violation("malformed SASLResponse");
+        refuse(context, buffers, error);
+        return;
+    };
+
+    match (exchange.client_final(frame.data()), context.user_entry()) {
+        (Ok(server_final), Some(user)) => {
+            buffers.queue_response(&responses::auth_sasl_data(SASL_FINAL, &server_final));
+            context.accept_user(user);
+            context.stage = AuthStage::Ready;
+            context.setup.record_auth();
+
+            queue_startup_response(context, buffers);
+        }
+        (Ok(_) | Err(ScramError::InvalidProof), _) => {
+            let user = context.username.as_deref().unwrap_or_default();
+            let error = ErrorResponse::invalid_password(format!(
+                "password authentication failed for user \"{user}\""
+            ));
+            refuse(context, buffers, error);
+        }
+        (Err(e), _) => refuse(
+            context,
+            buffers,
+            ErrorResponse::protocol_violation(e.to_string()),
+        ),
+    }
+}
+
+/// An unknown user gets an exchange too; it fails only at the proof.
+fn start_sasl(
+    context: &FrontendContext,
+    message: &[u8],
+) -> Result<(ScramExchange, Vec<u8>), String> {
+    let frame = SASLInitialResponseFrameObserver::new(message)
+        .map_err(|_| "malformed SASLInitialResponse".to_string())?;
+
+    let user = context.user_entry();
+    let password = user
+        .as_ref()
+        .map(|user| user.client_password.expose_secret());
+    let mut exchange = ScramExchange::new(
+        password,
+        context.tls_end_point.clone(),
+        &context.sasl_mechanisms,
+    );
+    let server_first = exchange
+        .client_first(
+            frame.mechanism(),
+            frame.initial_response().unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok((exchange, server_first))
+}
+
+fn refuse(context: &mut FrontendContext, buffers: &mut FrontendBuffers, error: ErrorResponse) {
+    buffers.queue_response(&error.to_bytes());
+    context.request_close();
+}
+
+/// AuthenticationOk through the first ReadyForQuery.
+fn queue_startup_response(context: &FrontendContext, buffers: &mut FrontendBuffers) {
+    buffers.queue_response(&responses::auth_ok());
+
+    // What drivers expect to know about the server, as the backends report it.
+    let observed = server_params::snapshot();
+    for (name, value) in context.startup.parameter_status(&observed) {
+        buffers.queue_response(&responses::param_status(&name, &value));
+    }
+
+    buffers.queue_response(&responses::backend_key_data(context.backend_identity));
+    if let Some(template) = &context.connect_notice {
+        let notice =
+            ErrorResponse::new(Severity::Notice, "00000", connect_notice(template, context));
+        buffers.queue_response(&notice.to_notice_bytes());
+    }
+    buffers.queue_response(&responses::ready_with_status(ReadyStatus::Idle));
+}
+
+/// `[server] connect_notice` with its placeholders filled in, in one pass:
+/// a `{user}` inside the user name stays as typed. `{database}` is the
+/// database the client asked for; the shard its queries run on is picked
+/// per checkout, after the notice went out.
+fn connect_notice(template: &str, context: &FrontendContext) -> String {
+    let user = context.username.as_deref().unwrap_or_default();
+    let database = context.database.as_deref().unwrap_or(user);
+    let mut notice = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        notice.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = [
+            ("{version}", env!("CARGO_PKG_VERSION")),
+            ("{user}", user),
+            ("{database}", database),
+        ]
+        .into_iter()
+        .find(|(placeholder, _)| rest.starts_with(placeholder));
+        match value {
+            Some((placeholder, value)) => {
+                notice.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                notice.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    notice.push_str(rest);
+    notice
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn startup_response_reports_what_strict_drivers_expect() {
+        let context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+        queue_startup_response(&context, &mut buffers);
+
+        let outbox = buffers.outbox();
+        let contains = |frame: &[u8]| outbox.windows(frame.len()).any(|window| window == frame);
+        assert!(contains(&responses::param_status(
+            "standard_conforming_strings",
+            "on"
+        )));
+        assert!(contains(&responses::param_status(
+            "client_encoding",
+            "UTF8"
+        )));
+        assert!(contains(b"server_version\0"));
+        assert!(outbox.ends_with(&responses::ready_with_status(ReadyStatus::Idle)));
+    }
+
+    #[test]
+    fn configured_server_version_reaches_the_client() {
+        let mut context = FrontendContext::new();
+        context
+            .startup
+            .parameter_status
+            .insert("server_version".to_string(), "14.9-pgcrab".to_string());
+        let mut buffers = FrontendBuffers::new();
+        queue_startup_response(&context, &mut buffers);
+
+        let outbox = buffers.outbox();
+        let overridden = responses::param_status("server_version", "14.9-pgcrab");
+        assert!(contains(outbox, &overridden));
+        let reported = outbox
+            .windows(b"server_version\0".len())
+            .filter(|window| *window == b"server_version\0")
+            .count();
+        assert_eq!(reported, 1);
+    }
+
+    #[test]
+    fn connect_notice_comes_before_ready_for_query() {
+        let mut context = FrontendContext::new();
+        context.username = Some("alice".to_string());
+        context.database = Some("shard_1".to_string());
+        // A random BackendKeyData could hold an `N` byte.
+        context.backend_identity = crate::shared_types::BackendIdentity {
+            process_id: 1,
+            secret_key: 2,
+        };
+        let mut buffers = FrontendBuffers::new();
+        queue_startup_response(&context, &mut buffers);
+        assert!(!buffers.outbox().contains(&b'N'));
+
+        context.connect_notice = Some("connected via pgcrab {version} to {database}".to_string());
+        let mut buffers = FrontendBuffers::new();
+        queue_startup_response(&context, &mut buffers);
+
+        // Walk the frames: the notice is whole, and ReadyForQuery is last.
+        let outbox = buffers.outbox();
+        let mut frames = Vec::new();
+        let mut rest = outbox;
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[1..5].try_into().unwrap()) as usize;
+            frames.push(&rest[..1 + len]);
+            rest = &rest[1 + len..];
+        }
+        let notice = frames.iter().find(|frame| frame[0] == b'N').unwrap();
+        let ready = frames.last().unwrap();
+        assert_eq!(ready[0], b'Z');
+
+        let message = format!(
+            "Mconnected via pgcrab {} to shard_1\0",
+            env!("CARGO_PKG_VERSION")
+        );
+        assert!(contains(notice, b"SNOTICE\0"));
+        assert!(contains(notice, b"C00000\0"));
+        assert!(contains(notice, message.as_bytes()));
+        assert!(notice.ends_with(b"\0\0"));
+    }
+
+    #[test]
+    fn connect_notice_fills_placeholders_once() {
+        let mut context = FrontendContext::new();
+        context.username = Some("{database}".to_string());
+        assert_eq!(
+            connect_notice("{user} on {database}, {other} {", &context),
+            "{database} on {database}, {other} {"
+        );
+
+        context.database = Some("app".to_string());
+        assert_eq!(
+            connect_notice("{user}@{database}", &context),
+            "{database}@app"
+        );
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
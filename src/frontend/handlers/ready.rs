@@ -1,22 +1,35 @@
-use bytes::{BufMut, BytesMut};
-use memchr::memchr;
-use std::collections::HashMap;
+use bytes::BytesMut;
+use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tracing::debug;
+use std::time::Instant;
+use tracing::{Span, debug};
 
 use crate::ErrorResponse;
 use crate::admin;
+use crate::analytics;
+use crate::config::startup::SHARD_PIN_PARAM;
+use crate::config::users::PoolerMode;
+use crate::errors::Severity;
 use crate::frontend::buffers::FrontendBuffers;
-use crate::frontend::context::{FrontendContext, PendingParse, PortalBinding, VirtualStatement};
+use crate::frontend::context::{
+    DENIAL_MARKER, FrontendContext, PendingClose, PendingDescribe, PendingParse, PendingReply,
+    PortalBinding, VirtualStatement,
+};
 use crate::frontend::proxy_responses as responses;
+use crate::frontend::query_log::QuerySample;
+use crate::frontend::session_state::SettingChange;
+use crate::gateway::GatewayError;
 use crate::gateway::GatewayPools;
 use crate::gateway::GatewaySession;
-use crate::parser;
+use crate::gateway::RoutingDecision;
+use crate::parser::{self, ParseError, ParsedQuery, StatementType};
 use crate::shared_types::AuthStage;
-use crate::shared_types::ReadyStatus;
 use crate::shared_types::StatementSignature;
-use crate::wire::observers::bind::BindFrameObserver;
+use crate::wire::builders;
+use crate::wire::observers::bind::{BindFrameObserver, NewBindObserverError};
 use crate::wire::observers::close::{CloseFrameObserver, CloseTarget};
+use crate::wire::observers::copy_fail::CopyFailFrameObserver;
 use crate::wire::observers::describe::{DescribeFrameObserver, DescribeTarget};
 use crate::wire::observers::execute::ExecuteFrameObserver;
 use crate::wire::observers::parse::ParseFrameObserver;
@@ -24,37 +37,145 @@ use crate::wire::observers::query::QueryFrameObserver;
 use crate::wire::types::MessageType;
 use crate::wire::utils::peek_frontend;
 
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Statements that can't change what describing a statement returns; any
+/// other kind, DDL above all, empties the shard's describe cache.
+const DESCRIBE_SAFE_STATEMENTS: [&str; 19] = [
+    "SelectStmt",
+    "InsertStmt",
+    "UpdateStmt",
+    "DeleteStmt",
+    "MergeStmt",
+    "TransactionStmt",
+    "VariableSetStmt",
+    "VariableShowStmt",
+    "ExplainStmt",
+    "CopyStmt",
+    "PrepareStmt",
+    "ExecuteStmt",
+    "DeallocateStmt",
+    "ListenStmt",
+    "UnlistenStmt",
+    "NotifyStmt",
+    "LockStmt",
+    "FetchStmt",
+    "ClosePortalStmt",
+];
+
 // -----------------------------------------------------------------------------
 // ----- Ready Handler ---------------------------------------------------------
 
+/// Forwards one client sequence to a backend session.
+///
+/// A sequence ends at Sync, Flush, Query, Terminate or a COPY terminator.
+/// Sync closes the extended-protocol batch: the backend answers it with
+/// ReadyForQuery, and the session goes back to the pool once every Sync is
+/// answered. Flush only asks the backend to push out what it has so far; it
+/// gets no ReadyForQuery, so the session stays checked out and its replies
+/// are relayed (and flushed to the client) as they arrive, ahead of any
+/// later Sync.
 pub(crate) async fn handle_ready(
     context: &mut FrontendContext,
     buffers: &mut FrontendBuffers,
     sequence: BytesMut,
     pools: &GatewayPools,
 ) {
-    if context.is_admin && try_handle_admin_sequence(context, buffers, &sequence, pools).await {
+    // Too much garbage in a row: close before it costs a checkout.
+    if count_decode_failures(context, &sequence) {
+        let error = ErrorResponse::protocol_violation(format!(
+            "{} malformed messages in a row",
+            context.decode_failures
+        ));
+        buffers.queue_response(&error.to_bytes());
+        context.drop_backend(true);
+        context.request_close();
+        return;
+    }
+
+    if try_handle_admin_sequence(context, buffers, &sequence, pools).await {
+        return;
+    }
+
+    // Nothing for a backend to run: answer like Postgres would rather than
+    // check one out. A held session still gets it, to keep replies in order.
+    if context.gateway_session.is_none() && is_empty_query(&sequence) {
+        buffers.queue_response(&responses::empty_query_response());
+        buffers.queue_response(&responses::ready_matching(context));
+        return;
+    }
+
+    // Same for a lone Query that doesn't parse. In a longer sequence, or
+    // behind a held session, it's refused in order: the backend rejects a
+    // bad Query itself, and a bad Parse skips to the next Sync.
+    if context.strict_parse
+        && context.gateway_session.is_none()
+        && let Some(Err(err)) = lone_query(&sequence).map(parser::parse)
+    {
+        buffers.queue_response(&syntax_error(&err).to_bytes());
+        buffers.queue_response(&responses::ready_matching(context));
+        return;
+    }
+
+    // And a lone Query `[policy]` refuses. Anywhere else the backend must
+    // fail in its place; see `refuse_on_backend`.
+    if context.gateway_session.is_none()
+        && let Some(error) = lone_query(&sequence).and_then(|query| policy_denial(context, query))
+    {
+        buffers.queue_response(&error.to_bytes());
+        buffers.queue_response(&responses::ready_matching(context));
+        return;
+    }
+
+    // A lone Query over the rate limit needs no backend either.
+    let rate_token_taken = context.gateway_session.is_none() && lone_query(&sequence).is_some();
+    if rate_token_taken && let Some(error) = rate_limit_error(context) {
+        buffers.queue_response(&error.to_bytes());
+        buffers.queue_response(&responses::ready_matching(context));
         return;
     }
 
     if context.gateway_session.is_none() {
         context.current_pool = None;
-        let Some(pool) = pools.random_pool() else {
-            let err = ErrorResponse::internal_error("no backend shards available");
-            buffers.queue_response(&err.to_bytes());
-            buffers.queue_response(&responses::ready_with_status(ReadyStatus::Idle));
-            return;
+        let read_only = is_standalone_read(context, &sequence);
+        let decision = match route(context, pools, read_only) {
+            Ok(decision) => decision,
+            Err(err) => {
+                buffers.queue_response(&err.to_bytes());
+                buffers.queue_response(&responses::ready_matching(context));
+                return;
+            }
         };
 
+        if RoutingDecision::trace_enabled(&context.routing) {
+            let parsed = leading_query(&sequence).and_then(|query| parser::parse(query).ok());
+            decision.trace(parsed.as_ref(), context.ready_status.in_transaction());
+        }
+
+        let pool = decision.pool;
+
         match GatewaySession::from_pool(&pool).await {
-            Ok(session) => {
+            Ok(mut session) => {
+                // The pool reset the backend; bring back this client's SETs.
+                if let Err(err) = context.session_state.replay_into(&mut session).await {
+                    let error = ErrorResponse::internal_error(err);
+                    buffers.queue_response(&error.to_bytes());
+                    buffers.queue_response(&responses::ready_matching(context));
+                    return;
+                }
+                let backend_pid = session.backend().process_id();
+                if let Some(pid) = backend_pid {
+                    Span::current().record("backend_pid", pid);
+                }
+                debug!(shard = pool.name(), backend_pid, "acquired backend session");
+                context.setup.report(session.opened_timings());
                 context.gateway_session = Some(session);
                 context.current_pool = Some(pool.name().to_string());
             }
             Err(err) => {
-                let error = ErrorResponse::internal_error(err);
-                buffers.queue_response(&error.to_bytes());
-                buffers.queue_response(&responses::ready_with_status(ReadyStatus::Idle));
+                buffers.queue_response(&checkout_error(err).to_bytes());
+                buffers.queue_response(&responses::ready_matching(context));
                 return;
             }
         }
@@ -64,20 +185,20 @@ pub(crate) async fn handle_ready(
         return;
     };
 
+    context.rate_token_taken = rate_token_taken;
     let sequence = prepare_sequence(context, &mut session, buffers, sequence);
 
     if let Err(err) = session.backend().send(&sequence).await {
-        let error = ErrorResponse::internal_error(format!("backend write failed: {err}"));
+        // The backend and any transaction on it are gone.
+        context.gateway_session = Some(session);
+        context.drop_backend(true);
+        let error = ErrorResponse::connection_failure(format!("backend write failed: {err}"));
         buffers.queue_response(&error.to_bytes());
-        buffers.queue_response(&responses::ready_with_status(ReadyStatus::Idle));
-        context.gateway_session = None;
-        context.current_pool = None;
-        context.pending_parses.clear();
-        context.pending_syncs = 0;
-        context.virtual_portals.clear();
+        buffers.queue_response(&responses::ready_matching(context));
         return;
     }
 
+    context.traffic.backend_bytes_out += sequence.len() as u64;
     context.gateway_session = Some(session);
 }
 
@@ -107,15 +228,267 @@ async fn try_handle_admin_sequence(
         return false;
     };
 
+    if !context.is_admin {
+        buffers.queue_response(&admin_denied().to_bytes());
+        buffers.queue_response(&responses::ready_matching(context));
+        return true;
+    }
+
     for response in admin::command_responses(command, context, pools).await {
         buffers.queue_response(&response);
     }
 
-    buffers.queue_response(&responses::ready_with_status(ReadyStatus::Idle));
+    buffers.queue_response(&responses::ready_matching(context));
 
     true
 }
 
+fn admin_denied() -> ErrorResponse {
+    ErrorResponse::insufficient_privilege(
+        "permission denied: PgCrab admin commands require an admin user",
+    )
+}
+
+/// Counts the sequence's messages into `decode_failures`: one that fails
+/// to decode adds one, one that decodes starts the count over. Those with
+/// nothing to decode, like Sync, Flush and Terminate, leave it be. `true`
+/// once it reaches `max_decode_failures`.
+fn count_decode_failures(context: &mut FrontendContext, sequence: &[u8]) -> bool {
+    let mut cursor = 0;
+    while cursor < sequence.len() {
+        let Some(peek) = peek_frontend(AuthStage::Ready, &sequence[cursor..]) else {
+            break;
+        };
+        let Some(frame) = cursor
+            .checked_add(peek.len)
+            .filter(|&end| end > cursor)
+            .and_then(|end| sequence.get(cursor..end))
+        else {
+            break;
+        };
+        cursor += peek.len;
+
+        let decoded = match peek.message_type {
+            MessageType::Query => QueryFrameObserver::new(frame).is_ok(),
+            MessageType::Parse => ParseFrameObserver::new(frame).is_ok(),
+            MessageType::Bind => bind_observer(context, frame).is_ok(),
+            MessageType::Describe => DescribeFrameObserver::new(frame).is_ok(),
+            MessageType::Execute => ExecuteFrameObserver::new(frame).is_ok(),
+            MessageType::Close => CloseFrameObserver::new(frame).is_ok(),
+            MessageType::CopyFail => CopyFailFrameObserver::new(frame).is_ok(),
+            _ => continue,
+        };
+        if decoded {
+            context.decode_failures = 0;
+            continue;
+        }
+        context.decode_failures += 1;
+        if context.decode_failures >= context.max_decode_failures {
+            return true;
+        }
+    }
+    false
+}
+
+/// The sequence is a lone Query whose text is empty or only whitespace.
+fn is_empty_query(sequence: &[u8]) -> bool {
+    lone_query(sequence).is_some_and(|query| query.trim().is_empty())
+}
+
+/// The SQL of a sequence that is a single Query frame.
+fn lone_query(sequence: &[u8]) -> Option<&str> {
+    let peek = peek_frontend(AuthStage::Ready, sequence)?;
+    if peek.message_type != MessageType::Query || peek.len != sequence.len() {
+        return None;
+    }
+
+    QueryFrameObserver::new(sequence)
+        .ok()
+        .map(|observer| observer.query())
+}
+
+/// `--strict-parse`'s answer to SQL that does not parse: a 42601.
+fn syntax_error(err: &ParseError) -> ErrorResponse {
+    let error = ErrorResponse::syntax_error(err.message());
+    match err.position() {
+        Some(position) => error.with_position(position),
+        None => error,
+    }
+}
+
+/// Why `[policy]` refuses `query`: a statement type in `deny_statements`
+/// as a 42501, or for a transaction-mode client a `session_only` statement
+/// or function as a 0A000. SQL that doesn't parse is left for the backend
+/// to reject.
+fn policy_denial(context: &FrontendContext, query: &str) -> Option<ErrorResponse> {
+    let policy = &context.policy;
+    let checks_session_only =
+        context.pooler_mode == PoolerMode::Transaction && !policy.session_only.is_empty();
+    if policy.deny_statements.is_empty() && !checks_session_only {
+        return None;
+    }
+
+    let parsed = parser::parse(query).ok()?;
+    if let Some(statement_type) = policy.denied(&parsed.statement_types) {
+        return Some(ErrorResponse::insufficient_privilege(format!(
+            "permission denied: {statement_type} is not allowed by pgcrab policy"
+        )));
+    }
+    if checks_session_only && let Some(name) = policy.session_only(&parsed) {
+        let error = ErrorResponse::feature_not_supported(format!(
+            "{name} is not supported in transaction pooling mode"
+        ))
+        .with_detail("Its effect would be lost once the backend goes back to the pool.")
+        .with_hint("connect as a user with pooler_mode = \"session\"");
+        return Some(error);
+    }
+
+    None
+}
+
+/// SQL to send in place of a refused statement. The backend can't parse
+/// it, so it fails the statement's batch, and any transaction it's in, the
+/// way the statement failing would have, with its replies still in order;
+/// the relay swaps the syntax error for `error` (`context::take_denial`).
+fn refuse_on_backend(context: &mut FrontendContext, error: ErrorResponse) -> String {
+    context.next_denial = context.next_denial.wrapping_add(1);
+    context
+        .pending_denials
+        .push_back((context.next_denial, error));
+    format!("{DENIAL_MARKER}{}", context.next_denial)
+}
+
+/// The client's side of a failed checkout. None of these end the client's
+/// connection, so errors that are FATAL at login are sent as ERROR here.
+fn checkout_error(err: GatewayError) -> ErrorResponse {
+    match err {
+        GatewayError::Connect(message) => ErrorResponse::connection_failure(message),
+        GatewayError::BackendAuth(message) => {
+            ErrorResponse::invalid_password(message).with_severity(Severity::Error)
+        }
+        GatewayError::OnConnect(message) => ErrorResponse::unable_to_establish_connection(message),
+        GatewayError::Timeout(message) | GatewayError::Paused(message) => {
+            ErrorResponse::cannot_connect_now(message)
+        }
+        GatewayError::PoolExhausted(message) => {
+            ErrorResponse::too_many_connections(message).with_severity(Severity::Error)
+        }
+        GatewayError::Protocol(message) => {
+            ErrorResponse::protocol_violation(message).with_severity(Severity::Error)
+        }
+    }
+}
+
+/// Where the next checkout comes from: the client's pinned shard if it
+/// named one at startup, else wherever `[routing]` sends it. A pin is never
+/// quietly rerouted; a shard that can't take it is an error.
+fn route(
+    context: &FrontendContext,
+    pools: &GatewayPools,
+    read_only: bool,
+) -> Result<RoutingDecision, ErrorResponse> {
+    let server_role = context.server_role.as_deref();
+    let Some(shard) = context.pinned_shard.as_deref() else {
+        return RoutingDecision::route(pools, &context.routing, server_role, read_only).ok_or_else(
+            || {
+                ErrorResponse::cannot_connect_now("no backend shards available")
+                    .with_hint("check the [[shards]] in the config; paused shards take no work")
+            },
+        );
+    };
+
+    let Some(decision) = RoutingDecision::pinned(pools, shard, server_role) else {
+        return Err(ErrorResponse::undefined_object(format!(
+            "shard \"{shard}\" from {SHARD_PIN_PARAM} does not exist"
+        ))
+        .with_hint("name one of the [[shards]] in the config"));
+    };
+    if decision.pool.is_paused() {
+        return Err(ErrorResponse::cannot_connect_now(format!(
+            "shard \"{shard}\" is paused"
+        )));
+    }
+    if !decision.pool.is_healthy() {
+        return Err(
+            ErrorResponse::cannot_connect_now(format!("shard \"{shard}\" is unreachable"))
+                .with_hint(format!(
+                    "reconnect without {SHARD_PIN_PARAM} to use another shard"
+                )),
+        );
+    }
+    Ok(decision)
+}
+
+/// With read/write splitting on, whether the sequence may run on a read
+/// replica: every statement it carries, including the one behind a Bind to a
+/// prepared statement, is a lone `SELECT` that writes and locks nothing (see
+/// `ParsedQuery::read_only`), sent outside a transaction on a
+/// transaction-mode connection. Anything unknown stays on the primary.
+fn is_standalone_read(context: &FrontendContext, sequence: &[u8]) -> bool {
+    if !context.routing.splits_reads()
+        || context.pooler_mode != PoolerMode::Transaction
+        || context.ready_status.in_transaction()
+    {
+        return false;
+    }
+
+    let mut saw_statement = false;
+    let mut parsed_here: SmallVec<[&str; 4]> = SmallVec::new();
+    let mut cursor = 0;
+    while cursor < sequence.len() {
+        let Some(peek) = peek_frontend(AuthStage::Ready, &sequence[cursor..]) else {
+            return false;
+        };
+        let Some(end) = cursor.checked_add(peek.len).filter(|&end| end > cursor) else {
+            return false;
+        };
+        let Some(frame) = sequence.get(cursor..end) else {
+            return false;
+        };
+        cursor = end;
+
+        let query = match peek.message_type {
+            MessageType::Query => QueryFrameObserver::new(frame).ok().map(|obs| obs.query()),
+            MessageType::Parse => ParseFrameObserver::new(frame).ok().map(|obs| {
+                parsed_here.push(obs.statement());
+                obs.query()
+            }),
+            MessageType::Bind => {
+                let Ok(observer) = bind_observer(context, frame) else {
+                    return false;
+                };
+                if parsed_here.contains(&observer.statement()) {
+                    continue;
+                }
+                context
+                    .virtual_statements
+                    .get(observer.statement())
+                    .map(|statement| &*statement.query)
+            }
+            MessageType::Describe
+            | MessageType::Execute
+            | MessageType::Close
+            | MessageType::Sync
+            | MessageType::Flush => continue,
+            _ => return false,
+        };
+
+        let Some(query) = query else {
+            return false;
+        };
+        // The parser only classifies a Query's first statement.
+        if query.trim().trim_end_matches(';').contains(';') {
+            return false;
+        }
+        match parser::parse(query) {
+            Ok(parsed) if parsed.read_only => saw_statement = true,
+            _ => return false,
+        }
+    }
+
+    saw_statement
+}
+
 fn prepare_sequence(
     context: &mut FrontendContext,
     session: &mut GatewaySession,
@@ -145,11 +518,15 @@ fn prepare_sequence(
         }
 
         let frame = &sequence[cursor..end];
+        let skipping = context.skip_until_sync.is_some() || context.failed_batch;
+        if skipping && peek.message_type != MessageType::Sync {
+            cursor = end;
+            continue;
+        }
+
         match peek.message_type {
             MessageType::Query => {
-                handle_query_frame(context, session, frame);
-                context.pending_syncs = context.pending_syncs.saturating_add(1);
-                output.extend_from_slice(frame);
+                handle_query_frame(context, session, frame, &mut output);
             }
             MessageType::Parse => {
                 handle_parse_frame(
@@ -186,10 +563,20 @@ fn prepare_sequence(
                 handle_close_frame(context, session, frame, &mut output);
             }
             MessageType::Sync => {
-                context.pending_syncs = context.pending_syncs.saturating_add(1);
-                context.virtual_portals.clear();
+                handle_sync_frame(context, frame, &mut output);
+            }
+            MessageType::Flush => {
+                // Nothing pending is settled by a Flush; the backend just
+                // sends what it owes without waiting for a Sync.
                 output.extend_from_slice(frame);
             }
+            MessageType::CopyDone => {
+                context.copy_in = false;
+                output.extend_from_slice(frame);
+            }
+            MessageType::CopyFail => {
+                handle_copy_fail_frame(context, frame, &mut output);
+            }
             _ => {
                 output.extend_from_slice(frame);
             }
@@ -201,18 +588,99 @@ fn prepare_sequence(
     output
 }
 
-fn handle_query_frame(context: &mut FrontendContext, session: &mut GatewaySession, frame: &[u8]) {
-    match QueryFrameObserver::new(frame) {
+/// Queues the ReadyForQuery this Query is owed, with its query log sample
+/// and, in transaction mode outside a transaction, the session setting it
+/// changes. A Query over the user's rate limit is not forwarded: behind a
+/// held backend a Sync takes its place, and the error goes out ahead of
+/// that ReadyForQuery.
+/// One `[policy]` refuses goes out as `refuse_on_backend` SQL.
+/// `DEALLOCATE` of a Parse'd statement goes out as a Close; see
+/// `handle_deallocate`.
+fn handle_query_frame(
+    context: &mut FrontendContext,
+    session: &mut GatewaySession,
+    frame: &[u8],
+    output: &mut BytesMut,
+) {
+    let error = if std::mem::take(&mut context.rate_token_taken) {
+        None
+    } else {
+        rate_limit_error(context)
+    };
+    if let Some(error) = error {
+        context.pending_replies.push_back(PendingReply::Ready {
+            query: false,
+            injected: Some(Box::new(error)),
+            sample: None,
+            setting: None,
+            sent: None,
+            failed: false,
+        });
+        context.pending_syncs = context.pending_syncs.saturating_add(1);
+        builders::build_sync(output);
+        return;
+    }
+
+    if let Ok(observer) = QueryFrameObserver::new(frame)
+        && let Some(error) = policy_denial(context, observer.query())
+    {
+        let marker = refuse_on_backend(context, error);
+        context.pending_replies.push_back(PendingReply::Ready {
+            query: true,
+            injected: None,
+            sample: None,
+            setting: None,
+            sent: None,
+            failed: false,
+        });
+        context.pending_syncs = context.pending_syncs.saturating_add(1);
+        builders::build_query(output, &marker);
+        return;
+    }
+
+    if let Ok(observer) = QueryFrameObserver::new(frame)
+        && let Some(name) = deallocate_target(observer.query())
+        && context
+            .virtual_statements
+            .get(&name)
+            .is_some_and(|statement| !statement.closed)
+    {
+        handle_deallocate(context, session, &name, observer.query(), output);
+        return;
+    }
+
+    let (sample, setting) = match QueryFrameObserver::new(frame) {
         Ok(observer) => {
-            parse_and_log(observer.query(), "Query");
+            let parsed = parse_and_log(observer.query(), "Query");
             if is_reset_query(observer.query()) {
                 session.backend().prepared_reset();
                 context.virtual_statements.clear();
                 context.virtual_portals.clear();
             }
+            let setting = (context.pooler_mode == PoolerMode::Transaction
+                && !context.ready_status.in_transaction())
+            .then(|| SettingChange::parse(observer.query()))
+            .flatten();
+            note_describe_effects(context, session, parsed.as_ref(), setting.is_some());
+            let sample = QuerySample::start(&context.query_log, observer.query());
+            (sample, setting)
         }
-        Err(err) => debug!(error = %err, "failed to decode Query frame"),
-    }
+        Err(err) => {
+            debug!(error = %err, "failed to decode Query frame");
+            (None, None)
+        }
+    };
+
+    context.pending_replies.push_back(PendingReply::Ready {
+        query: true,
+        injected: None,
+        sample,
+        setting,
+        sent: Some(Instant::now()),
+        failed: false,
+    });
+    context.pending_syncs = context.pending_syncs.saturating_add(1);
+    output.extend_from_slice(frame);
 }
 
 fn handle_parse_frame(
@@ -228,16 +696,52 @@ fn handle_parse_frame(
         Err(err) => {
             debug!(error = %err, "failed to decode Parse frame");
             context.pending_parses.push_back(PendingParse {
+                client_statement: None,
                 signature: None,
                 backend_statement_name: None,
                 suppress_response: false,
+                replay: None,
             });
             output.extend_from_slice(frame);
             return;
         }
     };
 
-    parse_and_log(observer.query(), "Parse");
+    // Admin commands only run as simple Queries; Postgres wouldn't know them.
+    if admin::parse_admin_command(observer.query()).is_some() {
+        let error = if context.is_admin {
+            ErrorResponse::feature_not_supported(
+                "PgCrab admin commands must be sent as simple queries",
+            )
+        } else {
+            admin_denied()
+        };
+        context.skip_until_sync = Some(error);
+        return;
+    }
+
+    if context.strict_parse
+        && let Err(err) = parser::parse(observer.query())
+    {
+        context.skip_until_sync = Some(syntax_error(&err));
+        return;
+    }
+
+    if let Some(error) = policy_denial(context, observer.query()) {
+        let marker = refuse_on_backend(context, error);
+        builders::build_parse(output, "", &marker, &[]);
+        context.pending_parses.push_back(PendingParse {
+            client_statement: None,
+            signature: None,
+            backend_statement_name: None,
+            suppress_response: false,
+            replay: None,
+        });
+        return;
+    }
+
+    let parsed = parse_and_log(observer.query(), "Parse");
+    note_describe_effects(context, session, parsed.as_ref(), false);
 
     let statement = observer.statement();
     let mut param_type_oids = Vec::with_capacity(observer.param_type_count());
@@ -284,21 +788,75 @@ fn handle_parse_frame(
         },
     );
 
-    let backend_statement_name = session.backend().allocate_statement_name();
-    build_parse_frame_into(
+    let backend = session.backend();
+    if backend.share_prepared() {
+        backend.prepared_retain(signature);
+        let prepared = in_flight_prepares.contains_key(&signature)
+            || backend.prepared_lookup(&signature).is_some();
+        if prepared {
+            // Parsing the unnamed statement, which pgcrab never binds, puts
+            // the client's ParseComplete in order with the backend's replies.
+            builders::build_parse(output, "", "", &[]);
+            context.pending_parses.push_back(PendingParse {
+                client_statement: Some((statement.to_string(), generation)),
+                signature: None,
+                backend_statement_name: None,
+                suppress_response: false,
+                replay: None,
+            });
+            return;
+        }
+    }
+
+    let backend_statement_name =
+        allocate_statement_name(context, session, output, in_flight_prepares);
+    builders::build_parse(
         output,
         &backend_statement_name,
         query.as_ref(),
         param_type_oids.as_ref(),
     );
     context.pending_parses.push_back(PendingParse {
+        client_statement: Some((statement.to_string(), generation)),
         signature: Some(signature),
         backend_statement_name: Some(backend_statement_name.clone()),
         suppress_response: false,
+        replay: None,
     });
     in_flight_prepares.insert(signature, backend_statement_name);
 }
 
+/// Makes room under the shard's `max_prepared_statements` first, closing
+/// the least recently used prepares on the backend. Prepares this batch is
+/// still parsing, or that a portal was bound from, are left alone: a Close
+/// would take the portal with it, failing the Execute behind it.
+fn allocate_statement_name(
+    context: &mut FrontendContext,
+    session: &mut GatewaySession,
+    output: &mut BytesMut,
+    in_flight_prepares: &HashMap<StatementSignature, String>,
+) -> String {
+    let pinned: HashSet<&str> = in_flight_prepares
+        .values()
+        .chain(
+            context
+                .virtual_portals
+                .values()
+                .map(|portal| &portal.backend_statement_name),
+        )
+        .map(String::as_str)
+        .collect();
+    let evicted = session
+        .backend()
+        .prepared_evict(in_flight_prepares.len(), |name| pinned.contains(name));
+    for evicted in evicted {
+        debug!(statement = evicted, "evicting least recently used prepare");
+        builders::build_close(output, CloseTarget::Statement, &evicted);
+        context.pending_closes.push_back(PendingClose::Evicted);
+    }
+    session.backend().allocate_statement_name()
+}
+
 struct PrepareOutcome {
     backend_statement_name: String,
 }
@@ -325,17 +883,20 @@ fn ensure_prepared(
         };
     }
 
-    let backend_statement_name = session.backend().allocate_statement_name();
-    build_parse_frame_into(
+    let backend_statement_name =
+        allocate_statement_name(context, session, output, in_flight_prepares);
+    builders::build_parse(
         output,
         &backend_statement_name,
         query.as_ref(),
         param_type_oids.as_ref(),
     );
     context.pending_parses.push_back(PendingParse {
+        client_statement: None,
         signature: Some(signature),
         backend_statement_name: Some(backend_statement_name.clone()),
         suppress_response,
+        replay: None,
     });
     in_flight_prepares.insert(signature, backend_statement_name.clone());
     PrepareOutcome {
@@ -343,6 +904,19 @@ fn ensure_prepared(
     }
 }
 
+/// Text params only get checked for UTF-8 with `[server] validate_text_params`;
+/// the relay itself never reads them as text.
+fn bind_observer<'a>(
+    context: &FrontendContext,
+    frame: &'a [u8],
+) -> Result<BindFrameObserver<'a>, NewBindObserverError> {
+    if context.validate_text_params {
+        BindFrameObserver::new(frame)
+    } else {
+        BindFrameObserver::new_unvalidated(frame)
+    }
+}
+
 fn handle_bind_frame(
     context: &mut FrontendContext,
     session: &mut GatewaySession,
@@ -350,7 +924,7 @@ fn handle_bind_frame(
     output: &mut BytesMut,
     in_flight_prepares: &mut HashMap<StatementSignature, String>,
 ) {
-    let observer = match BindFrameObserver::new(frame) {
+    let observer = match bind_observer(context, frame) {
         Ok(observer) => observer,
         Err(err) => {
             debug!(error = %err, "failed to decode Bind frame");
@@ -389,20 +963,29 @@ fn handle_bind_frame(
     );
 
     let backend_portal_name = session.backend().allocate_portal_name();
-    if !rewrite_bind_frame_into(
+    let param_formats: SmallVec<[i16; 8]> = (0..observer.param_count())
+        .map(|idx| i16::from(observer.param_is_binary(idx)))
+        .collect();
+    let params: SmallVec<[Option<&[u8]>; 8]> = observer.params_raw().collect();
+    let result_formats: SmallVec<[i16; 8]> = (0..observer.result_format_count())
+        .map(|idx| i16::from(observer.result_is_binary(idx)))
+        .collect();
+    builders::build_bind(
         output,
-        frame,
         &backend_portal_name,
         &prepared.backend_statement_name,
-    ) {
-        output.extend_from_slice(frame);
-        return;
-    }
+        &param_formats,
+        &params,
+        &result_formats,
+    );
 
     context.virtual_portals.insert(
         portal.to_string(),
         PortalBinding {
             backend_portal_name: backend_portal_name.clone(),
+            backend_statement_name: prepared.backend_statement_name,
+            suspended: false,
+            result_formats: result_formats.iter().map(|format| *format == 1).collect(),
         },
     );
 }
@@ -445,6 +1028,26 @@ fn handle_describe_frame(
             }
 
             let signature = virtual_statement.signature;
+            let pool = Arc::clone(session.pool());
+            let key = pool.describe_key(signature, context.session_state.search_path());
+            // Session mode doesn't track the client's settings, so its
+            // search_path is unknown.
+            let usable =
+                context.pooler_mode == PoolerMode::Transaction && !context.untracked_setting;
+            if usable && let Some(reply) = key.as_ref().and_then(|key| pool.cached_describe(key)) {
+                // Parsing the unnamed statement, which pgcrab never binds,
+                // holds the cached reply's place among the backend's.
+                builders::build_parse(output, "", "", &[]);
+                context.pending_parses.push_back(PendingParse {
+                    client_statement: None,
+                    signature: None,
+                    backend_statement_name: None,
+                    suppress_response: true,
+                    replay: Some(reply),
+                });
+                return;
+            }
+
             let query = Arc::clone(&virtual_statement.query);
             let param_type_oids = Arc::clone(&virtual_statement.param_type_oids);
             let prepared = ensure_prepared(
@@ -458,21 +1061,31 @@ fn handle_describe_frame(
                 true,
             );
 
-            build_describe_frame_into(
+            builders::build_describe(
                 output,
                 DescribeTarget::Statement,
                 &prepared.backend_statement_name,
             );
+            // Tracked even when unusable, to keep replies lined up.
+            if let Some(key) = key {
+                context.pending_describes.push_back(PendingDescribe {
+                    key: usable.then_some(key),
+                    generation: pool.describe_generation(),
+                    reply: BytesMut::new(),
+                });
+            }
         }
         DescribeTarget::Portal => {
             let name = observer.name();
             let Some(binding) = context.virtual_portals.get(name) else {
                 debug!(portal = name, "Describe references unknown portal");
-                output.extend_from_slice(frame);
+                context.skip_until_sync = Some(ErrorResponse::invalid_cursor_name(format!(
+                    "portal \"{name}\" does not exist"
+                )));
                 return;
             };
 
-            build_describe_frame_into(output, DescribeTarget::Portal, &binding.backend_portal_name);
+            builders::build_describe(output, DescribeTarget::Portal, &binding.backend_portal_name);
         }
     }
 }
@@ -487,14 +1100,95 @@ fn handle_execute_frame(context: &mut FrontendContext, frame: &[u8], output: &mu
         }
     };
 
+    // Like any other failed Execute, the rest of the batch is skipped.
+    if let Some(error) = rate_limit_error(context) {
+        context.skip_until_sync = Some(error);
+        return;
+    }
+
     let portal = observer.portal();
+    context
+        .pending_replies
+        .push_back(PendingReply::Execute(portal.to_string()));
+
     let Some(binding) = context.virtual_portals.get(portal) else {
         debug!(portal, "Execute references unknown portal");
         output.extend_from_slice(frame);
         return;
     };
 
-    build_execute_frame_into(output, &binding.backend_portal_name, observer.max_rows());
+    if binding.suspended {
+        debug!(portal, "resuming suspended portal");
+    }
+    debug!(
+        portal,
+        first_column_binary = binding.is_binary_column(0),
+        "executing portal"
+    );
+
+    builders::build_execute(output, &binding.backend_portal_name, observer.max_rows());
+}
+
+/// Takes a token from the user's limiter; the error when none is left.
+fn rate_limit_error(context: &FrontendContext) -> Option<ErrorResponse> {
+    let limiter = context.rate_limiter.as_ref()?;
+    if limiter.try_acquire() {
+        return None;
+    }
+
+    let limit = limiter.limit();
+    let username = context.username.as_deref().unwrap_or_default();
+    let error = ErrorResponse::configuration_limit_exceeded(format!(
+        "query rate limit exceeded for user \"{username}\""
+    ))
+    .with_severity(Severity::Error)
+    .with_detail(format!(
+        "The limit is {} queries per second with a burst of {}.",
+        limit.queries_per_second, limit.burst
+    ));
+    Some(error)
+}
+
+fn handle_sync_frame(context: &mut FrontendContext, frame: &[u8], output: &mut BytesMut) {
+    if context.copy_in {
+        // Postgres ignores Sync during COPY IN; nothing will answer it.
+        output.extend_from_slice(frame);
+        return;
+    }
+
+    // The backend's own error already went out; this Sync ends its batch.
+    context.failed_batch = false;
+    let injected = context.skip_until_sync.take().map(Box::new);
+    // After a proxy error the backend only sees the Sync: nothing to time.
+    let sent = injected.is_none().then(Instant::now);
+    context.pending_replies.push_back(PendingReply::Ready {
+        query: false,
+        injected,
+        sample: None,
+        setting: None,
+        sent,
+        failed: false,
+    });
+    context.pending_syncs = context.pending_syncs.saturating_add(1);
+    // Portals are pruned on the matching ReadyForQuery, once the backend
+    // has said whether they were suspended and whether the transaction ended.
+    output.extend_from_slice(frame);
+}
+
+/// The client gave up on its COPY FROM STDIN. The backend rolls the COPY
+/// back and answers with an ErrorResponse carrying the client's message,
+/// which settles the Query (or, in the extended protocol, fails the batch
+/// up to the client's next Sync) like any other error.
+fn handle_copy_fail_frame(context: &mut FrontendContext, frame: &[u8], output: &mut BytesMut) {
+    match CopyFailFrameObserver::new(frame) {
+        Ok(observer) => debug!(reason = observer.message(), "client aborted COPY"),
+        Err(err) => {
+            debug!(error = %err, "failed to decode CopyFail frame");
+        }
+    }
+
+    context.copy_in = false;
+    output.extend_from_slice(frame);
 }
 
 fn handle_close_frame(
@@ -503,6 +1197,9 @@ fn handle_close_frame(
     frame: &[u8],
     output: &mut BytesMut,
 ) {
+    // Whatever it's rewritten to, one Close reaches the backend.
+    context.pending_closes.push_back(PendingClose::Client);
+
     let observer = match CloseFrameObserver::new(frame) {
         Ok(observer) => observer,
         Err(err) => {
@@ -514,29 +1211,15 @@ fn handle_close_frame(
 
     match observer.target() {
         CloseTarget::Statement => {
-            let name = observer.name();
-            let signature = context.virtual_statements.get_mut(name).map(|statement| {
-                statement.closed = true;
-                statement.signature
-            });
-            if let Some(signature) = signature {
-                let backend_name = session
-                    .backend()
-                    .prepared_lookup(&signature)
-                    .map(str::to_string);
-                if let Some(backend_name) = backend_name {
-                    session.backend().prepared_remove_name(&backend_name);
-                    build_close_frame_into(output, CloseTarget::Statement, &backend_name);
-                    return;
-                }
+            if !close_statement(context, session, observer.name(), output) {
+                output.extend_from_slice(frame);
             }
-            output.extend_from_slice(frame);
         }
         CloseTarget::Portal => {
             let name = observer.name();
             let removed = context.virtual_portals.remove(name);
             if let Some(binding) = removed {
-                build_close_frame_into(output, CloseTarget::Portal, &binding.backend_portal_name);
+                builders::build_close(output, CloseTarget::Portal, &binding.backend_portal_name);
                 return;
             }
             output.extend_from_slice(frame);
@@ -544,104 +1227,154 @@ fn handle_close_frame(
     }
 }
 
-fn build_parse_frame_into(
+/// Marks the client statement closed and writes the Close for its backend
+/// prepare; `false`, writing nothing, when no prepare is mapped to it.
+fn close_statement(
+    context: &mut FrontendContext,
+    session: &mut GatewaySession,
+    name: &str,
     output: &mut BytesMut,
-    statement: &str,
+) -> bool {
+    let Some(signature) = context.virtual_statements.get_mut(name).map(|statement| {
+        statement.closed = true;
+        statement.signature
+    }) else {
+        return false;
+    };
+
+    let backend = session.backend();
+    let Some(backend_name) = backend.prepared_lookup(&signature).map(str::to_string) else {
+        return false;
+    };
+
+    if backend.share_prepared() && !backend.prepared_release(&signature) {
+        // Other clients still use it; closing the unnamed statement answers
+        // with an in-order CloseComplete.
+        builders::build_close(output, CloseTarget::Statement, "");
+        return true;
+    }
+    backend.prepared_remove_name(&backend_name);
+    builders::build_close(output, CloseTarget::Statement, &backend_name);
+    true
+}
+
+/// `DEALLOCATE <name>` of a statement the client prepared with Parse: the
+/// backend only knows it by pgcrab's name, so the Query becomes a Close and
+/// a Sync, and the relay answers with the `DEALLOCATE` the client expects.
+fn handle_deallocate(
+    context: &mut FrontendContext,
+    session: &mut GatewaySession,
+    name: &str,
     query: &str,
-    param_type_oids: &[i32],
+    output: &mut BytesMut,
 ) {
-    let body_len = statement.len() + 1 + query.len() + 1 + 2 + 4 * param_type_oids.len();
-    output.reserve(1 + 4 + body_len);
-    output.put_u8(b'P');
-    output.put_u32((4 + body_len) as u32);
-    output.extend_from_slice(statement.as_bytes());
-    output.put_u8(0);
-    output.extend_from_slice(query.as_bytes());
-    output.put_u8(0);
-    output.put_i16(param_type_oids.len() as i16);
-    for &oid in param_type_oids {
-        output.put_i32(oid);
+    parse_and_log(query, "Query");
+    if !close_statement(context, session, name, output) {
+        // Never prepared on this backend; closing the unnamed statement
+        // still gets a CloseComplete to answer with.
+        builders::build_close(output, CloseTarget::Statement, "");
     }
+    context.pending_closes.push_back(PendingClose::Deallocate);
+
+    builders::build_sync(output);
+    context.pending_replies.push_back(PendingReply::Ready {
+        query: true,
+        injected: None,
+        sample: QuerySample::start(&context.query_log, query),
+        setting: None,
+        sent: Some(Instant::now()),
+        failed: false,
+    });
+    context.pending_syncs = context.pending_syncs.saturating_add(1);
 }
 
-fn rewrite_bind_frame_into(
-    output: &mut BytesMut,
-    frame: &[u8],
-    portal: &str,
-    statement: &str,
-) -> bool {
-    if frame.len() < 5 || frame[0] != b'B' {
-        return false;
+/// The statement a lone `DEALLOCATE [PREPARE] <name>` frees, folded like
+/// any SQL identifier; `None` for anything else, `DEALLOCATE ALL` included.
+fn deallocate_target(query: &str) -> Option<String> {
+    let statement = query.trim().trim_end_matches(';').trim_end();
+    let (keyword, rest) = statement.split_once(char::is_whitespace)?;
+    if !keyword.eq_ignore_ascii_case("DEALLOCATE") {
+        return None;
     }
 
-    let mut pos = 5;
-    let Some(portal_rel) = memchr(0, &frame[pos..]) else {
-        return false;
-    };
-    pos += portal_rel + 1;
-    let Some(statement_rel) = memchr(0, &frame[pos..]) else {
-        return false;
-    };
-    let tail_start = pos + statement_rel + 1;
-    if tail_start > frame.len() {
-        return false;
+    let mut rest = rest.trim_start();
+    if let Some((word, after)) = rest.split_once(char::is_whitespace)
+        && word.eq_ignore_ascii_case("PREPARE")
+    {
+        rest = after.trim_start();
     }
 
-    let tail = &frame[tail_start..];
-    let body_len = portal.len() + 1 + statement.len() + 1 + tail.len();
-    output.reserve(1 + 4 + body_len);
-    output.put_u8(b'B');
-    output.put_u32((4 + body_len) as u32);
-    output.extend_from_slice(portal.as_bytes());
-    output.put_u8(0);
-    output.extend_from_slice(statement.as_bytes());
-    output.put_u8(0);
-    output.extend_from_slice(tail);
-    true
+    if let Some(quoted) = rest.strip_prefix('"') {
+        let inner = quoted.strip_suffix('"')?;
+        if inner.is_empty() || inner.replace("\"\"", "").contains('"') {
+            return None;
+        }
+        return Some(inner.replace("\"\"", "\""));
+    }
+
+    let is_identifier = rest
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+    if rest.is_empty() || !is_identifier || rest.eq_ignore_ascii_case("ALL") {
+        return None;
+    }
+    Some(rest.to_lowercase())
 }
 
-fn build_describe_frame_into(output: &mut BytesMut, target: DescribeTarget, name: &str) {
-    let body_len = 1 + name.len() + 1;
-    output.reserve(1 + 4 + body_len);
-    output.put_u8(b'D');
-    output.put_u32((4 + body_len) as u32);
-    let target_byte = match target {
-        DescribeTarget::Portal => b'P',
-        DescribeTarget::Statement => b'S',
-    };
-    output.put_u8(target_byte);
-    output.extend_from_slice(name.as_bytes());
-    output.put_u8(0);
-}
-
-fn build_execute_frame_into(output: &mut BytesMut, portal: &str, max_rows: i32) {
-    let body_len = portal.len() + 1 + 4;
-    output.reserve(1 + 4 + body_len);
-    output.put_u8(b'E');
-    output.put_u32((4 + body_len) as u32);
-    output.extend_from_slice(portal.as_bytes());
-    output.put_u8(0);
-    output.put_i32(max_rows);
-}
-
-fn build_close_frame_into(output: &mut BytesMut, target: CloseTarget, name: &str) {
-    let body_len = 1 + name.len() + 1;
-    output.reserve(1 + 4 + body_len);
-    output.put_u8(b'C');
-    output.put_u32((4 + body_len) as u32);
-    let target_byte = match target {
-        CloseTarget::Portal => b'P',
-        CloseTarget::Statement => b'S',
-    };
-    output.put_u8(target_byte);
-    output.extend_from_slice(name.as_bytes());
-    output.put_u8(0);
+/// SQL text of the first Query or Parse frame in a sequence, if any.
+fn leading_query(sequence: &[u8]) -> Option<&str> {
+    let peek = peek_frontend(AuthStage::Ready, sequence)?;
+    let frame = sequence.get(..peek.len)?;
+    match peek.message_type {
+        MessageType::Query => QueryFrameObserver::new(frame).ok().map(|obs| obs.query()),
+        MessageType::Parse => ParseFrameObserver::new(frame).ok().map(|obs| obs.query()),
+        _ => None,
+    }
 }
 
-fn parse_and_log(query: &str, message_type: &'static str) {
+/// Also counts the statement by type; SQL that doesn't parse is `Other`.
+fn parse_and_log(query: &str, message_type: &'static str) -> Option<ParsedQuery> {
     match parser::parse(query) {
-        Ok(parsed) => debug!(message_type, ?parsed.ast, "parsed SQL"),
-        Err(err) => debug!(message_type, error = %err, "failed to parse SQL"),
+        Ok(parsed) => {
+            analytics::inc_query(parsed.statement_type);
+            debug!(message_type, ast = ?parsed.ast(), "parsed SQL");
+            Some(parsed)
+        }
+        Err(err) => {
+            analytics::inc_query(StatementType::Other);
+            debug!(message_type, error = %err, "failed to parse SQL");
+            None
+        }
+    }
+}
+
+/// Keeps the shard's describe cache honest about a Query or Parse going
+/// out: anything that may change a description, DDL above all, empties
+/// it, and a setting `session_state` won't follow (`tracked` is false)
+/// keeps this client off it until the backend goes back.
+fn note_describe_effects(
+    context: &mut FrontendContext,
+    session: &mut GatewaySession,
+    parsed: Option<&ParsedQuery>,
+    tracked: bool,
+) {
+    let Some(parsed) = parsed else {
+        return;
+    };
+    let types = &parsed.statement_types;
+    if types
+        .iter()
+        .any(|kind| !DESCRIBE_SAFE_STATEMENTS.contains(&kind.as_str()))
+    {
+        session.pool().forget_describes();
+    }
+    let sets = types.iter().any(|kind| kind == "VariableSetStmt")
+        || parsed
+            .functions
+            .iter()
+            .any(|name| name.rsplit('.').next() == Some("set_config"));
+    if sets && !tracked {
+        context.untracked_setting = true;
     }
 }
 
@@ -656,5 +1389,1636 @@ fn is_reset_query(query: &str) -> bool {
         || trimmed.eq_ignore_ascii_case("RESET ALL")
 }
 
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::routing::RoutingConfig;
+    use crate::config::shards::ShardRecord;
+    use crate::config::users::RateLimit;
+    use crate::frontend::context;
+    use crate::gateway::RateLimiter;
+    use crate::shared_types::ReadyStatus;
+    use bytes::BufMut;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+    use tokio::task::JoinHandle;
+
+    const SYNC: [u8; 5] = [b'S', 0, 0, 0, 4];
+    const FLUSH: [u8; 5] = [b'H', 0, 0, 0, 4];
+
+    /// Minimal Postgres stand-in: completes startup without auth, then
+    /// returns every byte it receives up to and including the first Sync.
+    async fn fake_backend() -> (GatewayPools, JoinHandle<Vec<u8>>) {
+        fake_backend_until(&SYNC).await
+    }
+
+    /// Like `fake_backend`, but stops reading after `terminator`.
+    async fn fake_backend_until(terminator: &'static [u8]) -> (GatewayPools, JoinHandle<Vec<u8>>) {
+        fake_backend_sharing(terminator, false).await
+    }
+
+    /// Like `fake_backend_until`, with the shard's shared prepares toggled.
+    async fn fake_backend_sharing(
+        terminator: &'static [u8],
+        shared_prepared_statements: bool,
+    ) -> (GatewayPools, JoinHandle<Vec<u8>>) {
+        fake_backend_for(terminator, |port| {
+            fake_shard("fake", port, shared_prepared_statements)
+        })
+        .await
+    }
+
+    /// Like `fake_backend_until`, for the shard `shard` builds on the port.
+    async fn fake_backend_for(
+        terminator: &'static [u8],
+        shard: impl FnOnce(u16) -> ShardRecord,
+    ) -> (GatewayPools, JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let startup_len = stream.read_u32().await.unwrap() as usize;
+            let mut startup = vec![0u8; startup_len - 4];
+            stream.read_exact(&mut startup).await.unwrap();
+            stream
+                .write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I'])
+                .await
+                .unwrap();
+
+            let mut received = Vec::new();
+            while !received.ends_with(terminator) {
+                let mut chunk = [0u8; 1024];
+                let n = stream.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "pgcrab closed before the terminator");
+                received.extend_from_slice(&chunk[..n]);
+            }
+            received
+        });
+
+        (GatewayPools::new(vec![shard(port)]), received)
+    }
+
+    /// One single-connection shard named `fake` on `port`.
+    fn fake_pools(port: u16, shared_prepared_statements: bool) -> GatewayPools {
+        GatewayPools::new(vec![fake_shard("fake", port, shared_prepared_statements)])
+    }
+
+    fn fake_shard(name: &str, port: u16, shared_prepared_statements: bool) -> ShardRecord {
+        ShardRecord {
+            shard_name: name.to_string(),
+            host: "127.0.0.1".to_string(),
+            port,
+            user: "user".to_string(),
+            min_connections: 1,
+            max_connections: 1,
+            server_reset_query: String::new(),
+            shared_prepared_statements,
+            ..ShardRecord::default()
+        }
+    }
+
+    /// Backend that reports the text of every Query it receives, and only
+    /// answers the session replay, which pgcrab waits on. It reports a
+    /// `client_encoding` of `UTF8` at startup.
+    async fn replaying_backend() -> (GatewayPools, UnboundedReceiver<String>) {
+        let (port, received) = replaying_listener().await;
+        (fake_pools(port, false), received)
+    }
+
+    /// The listener behind `replaying_backend`, on its own port.
+    async fn replaying_listener() -> (u16, UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (queries, received) = unbounded_channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let startup_len = stream.read_u32().await.unwrap() as usize;
+            let mut startup = vec![0u8; startup_len - 4];
+            stream.read_exact(&mut startup).await.unwrap();
+            let mut reply = vec![b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'S', 0, 0, 0, 25];
+            reply.extend_from_slice(b"client_encoding\0UTF8\0");
+            reply.extend_from_slice(&[b'Z', 0, 0, 0, 5, b'I']);
+            stream.write_all(&reply).await.unwrap();
+
+            while let Ok(tag) = stream.read_u8().await {
+                let len = stream.read_u32().await.unwrap() as usize;
+                let mut body = vec![0u8; len - 4];
+                stream.read_exact(&mut body).await.unwrap();
+                if tag != b'Q' {
+                    continue;
+                }
+                let query = String::from_utf8(body[..body.len() - 1].to_vec()).unwrap();
+                let replay = query.ends_with(';');
+                queries.send(query).unwrap();
+                if replay {
+                    let mut reply = vec![b'C', 0, 0, 0, 8];
+                    reply.extend_from_slice(b"SET\0");
+                    reply.extend_from_slice(&[b'Z', 0, 0, 0, 5, b'I']);
+                    stream.write_all(&reply).await.unwrap();
+                }
+            }
+        });
+
+        (port, received)
+    }
+
+    /// Shards `main` and `replica`, split by `[routing]`, with the Queries
+    /// each receives.
+    async fn split_pools() -> (
+        GatewayPools,
+        UnboundedReceiver<String>,
+        UnboundedReceiver<String>,
+    ) {
+        let (main_port, main) = replaying_listener().await;
+        let (replica_port, replica) = replaying_listener().await;
+        let pools = GatewayPools::new(vec![
+            fake_shard("main", main_port, false),
+            fake_shard("replica", replica_port, false),
+        ]);
+        (pools, main, replica)
+    }
+
+    fn split_context() -> FrontendContext {
+        let mut context = FrontendContext::new();
+        context.routing = RoutingConfig {
+            primary: Some("main".to_string()),
+            read_replicas: vec!["replica".to_string()],
+            trace: false,
+        };
+        context
+    }
+
+    /// Stands in for the relay: the backend answered the head Query with
+    /// ReadyForQuery (or an error first) and the session goes back to the
+    /// pool before the next checkout.
+    async fn backend_settles(context: &mut FrontendContext, pools: &GatewayPools, failed: bool) {
+        if failed {
+            context::fail_pending_replies(&mut context.pending_replies);
+        }
+        let settled = context::settle_on_ready(
+            &mut context.pending_replies,
+            &mut context.virtual_portals,
+            ReadyStatus::Idle,
+        );
+        if let Some(setting) = settled.setting {
+            context.session_state.apply(setting);
+        }
+        context.pending_syncs = 0;
+        context.gateway_session = None;
+
+        for _ in 0..100 {
+            if pools.snapshot().await.iter().all(|stats| stats.idle == 1) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("backend never went back to the pool");
+    }
+
+    fn context_with_portal(portal: &str, backend_portal: &str) -> FrontendContext {
+        let mut context = FrontendContext::new();
+        context.virtual_portals.insert(
+            portal.to_string(),
+            PortalBinding {
+                backend_portal_name: backend_portal.to_string(),
+                backend_statement_name: String::new(),
+                suspended: false,
+                result_formats: Vec::new(),
+            },
+        );
+        context
+    }
+
+    fn execute_frame(portal: &str, max_rows: i32) -> BytesMut {
+        let mut frame = BytesMut::new();
+        builders::build_execute(&mut frame, portal, max_rows);
+        frame
+    }
+
+    fn query_frame(sql: &str) -> BytesMut {
+        let mut frame = BytesMut::new();
+        frame.put_u8(b'Q');
+        frame.put_u32((4 + sql.len() + 1) as u32);
+        frame.extend_from_slice(sql.as_bytes());
+        frame.put_u8(0);
+        frame
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+
+    async fn run_query(strict_parse: bool, sql: &str) -> Vec<u8> {
+        run_query_as(false, strict_parse, sql).await
+    }
+
+    async fn run_query_as(is_admin: bool, strict_parse: bool, sql: &str) -> Vec<u8> {
+        run_query_with(
+            |context| {
+                context.is_admin = is_admin;
+                context.strict_parse = strict_parse;
+            },
+            sql,
+        )
+        .await
+    }
+
+    /// A lone Query from a client with no backend held and no shards to
+    /// check one out from; returns what it was answered.
+    async fn run_query_with(configure: impl FnOnce(&mut FrontendContext), sql: &str) -> Vec<u8> {
+        let mut context = FrontendContext::new();
+        configure(&mut context);
+        let mut buffers = FrontendBuffers::new();
+        let pools = GatewayPools::new(Vec::new());
+        handle_ready(&mut context, &mut buffers, query_frame(sql), &pools).await;
+        buffers.outbox().to_vec()
+    }
+
+    fn backend_reply(context: &mut FrontendContext, suspended: bool) {
+        context::complete_pending_execute(
+            &mut context.pending_replies,
+            &mut context.virtual_portals,
+            suspended,
+        );
+    }
+
+    fn backend_ready(context: &mut FrontendContext, status: ReadyStatus) -> Option<ErrorResponse> {
+        context::settle_on_ready(
+            &mut context.pending_replies,
+            &mut context.virtual_portals,
+            status,
+        )
+        .injected
+    }
+
+    #[test]
+    fn syncs_sent_during_copy_in_expect_no_ready() {
+        let mut context = context_with_portal("", "");
+        let mut output = BytesMut::new();
+
+        // Bind/Execute/Sync for COPY ... FROM STDIN, pipelined as one write.
+        handle_execute_frame(&mut context, &execute_frame("", 0), &mut output);
+        handle_sync_frame(&mut context, &SYNC, &mut output);
+        assert_eq!(context.pending_syncs, 1);
+
+        // CopyInResponse: Postgres ignores that Sync.
+        context::enter_copy_in(&mut context.pending_replies, &mut context.pending_syncs);
+        context.copy_in = true;
+        assert_eq!(context.pending_syncs, 0);
+        assert_eq!(context.pending_replies.len(), 1);
+
+        let mut sequence = BytesMut::new();
+        sequence.extend_from_slice(&[b'd', 0, 0, 0, 6, b'1', b'\n']);
+        sequence.extend_from_slice(&SYNC);
+        sequence.extend_from_slice(&[b'c', 0, 0, 0, 4]);
+        sequence.extend_from_slice(&SYNC);
+        let mut cursor = 0;
+        while cursor < sequence.len() {
+            let peek = peek_frontend(AuthStage::Ready, &sequence[cursor..]).unwrap();
+            let frame = &sequence[cursor..cursor + peek.len];
+            match peek.message_type {
+                MessageType::Sync => handle_sync_frame(&mut context, frame, &mut output),
+                MessageType::CopyDone => context.copy_in = false,
+                _ => {}
+            }
+            cursor += peek.len;
+        }
+
+        // Only the Sync after CopyDone is answered.
+        assert!(!context.copy_in);
+        assert_eq!(context.pending_syncs, 1);
+
+        backend_reply(&mut context, false);
+        assert!(backend_ready(&mut context, ReadyStatus::Idle).is_none());
+        assert!(context.pending_replies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn copy_fail_ends_copy_in_and_settles_on_the_backend_error() {
+        const COPY_FAIL: [u8; 10] = [b'f', 0, 0, 0, 9, b'o', b'o', b'p', b's', 0];
+
+        let (pools, received) = fake_backend_until(&COPY_FAIL).await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let copy = query_frame("COPY users FROM STDIN");
+        handle_ready(&mut context, &mut buffers, copy.clone(), &pools).await;
+
+        // CopyInResponse.
+        context::enter_copy_in(&mut context.pending_replies, &mut context.pending_syncs);
+        context.copy_in = true;
+
+        let sequence = [&[b'd', 0, 0, 0, 6, b'1', b'\n'][..], &COPY_FAIL].concat();
+        handle_ready(
+            &mut context,
+            &mut buffers,
+            sequence.as_slice().into(),
+            &pools,
+        )
+        .await;
+
+        // The CopyFail reaches the backend, right behind the data.
+        let received = received.await.unwrap();
+        assert_eq!(received, [&copy[..], &sequence].concat());
+        assert!(!context.copy_in);
+        assert!(context.gateway_session.is_some());
+
+        // ErrorResponse then ReadyForQuery: the COPY's Query settles, and
+        // nothing is left to skip before the client's next query.
+        backend_error(&mut context);
+        assert!(!context.failed_batch);
+        context::account_ready(&mut context.pending_syncs).unwrap();
+        let settled = context::settle_on_ready(
+            &mut context.pending_replies,
+            &mut context.virtual_portals,
+            ReadyStatus::Idle,
+        );
+        // The client's think time in COPY isn't the backend's latency.
+        assert!(settled.completed.is_none());
+        assert!(context.pending_replies.is_empty());
+        assert!(context.skip_until_sync.is_none());
+        assert_eq!(context.pending_syncs, 0);
+        assert!(buffers.outbox().is_empty());
+    }
+
+    #[tokio::test]
+    async fn admin_commands_require_admin_user() {
+        let outbox = run_query_as(false, false, "SHOW PGCRAB POOLS").await;
+        assert_eq!(outbox.first(), Some(&b'E'));
+        assert!(contains(&outbox, b"C42501\0"));
+        assert!(outbox.ends_with(&[b'Z', 0, 0, 0, 5, b'I']));
+
+        let outbox = run_query_as(true, false, "SHOW PGCRAB POOLS").await;
+        assert_eq!(outbox.first(), Some(&b'T'));
+        assert!(contains(&outbox, b"SELECT 0\0"));
+        assert!(outbox.ends_with(&[b'Z', 0, 0, 0, 5, b'I']));
+
+        let outbox = run_query_as(false, false, "CLEAR PGCRAB PARSE CACHE").await;
+        assert_eq!(outbox.first(), Some(&b'E'));
+        assert!(contains(&outbox, b"C42501\0"));
+    }
+
+    #[tokio::test]
+    async fn admin_commands_are_not_prepared_on_the_backend() {
+        for (is_admin, code) in [(false, "42501"), (true, "0A000")] {
+            let (pools, received) = fake_backend().await;
+            let mut context = FrontendContext::new();
+            context.is_admin = is_admin;
+            let mut buffers = FrontendBuffers::new();
+
+            let mut sequence = BytesMut::new();
+            builders::build_parse(&mut sequence, "", "SHOW PGCRAB POOLS", &[]);
+            builders::build_bind(&mut sequence, "", "", &[], &[], &[]);
+            sequence.extend_from_slice(&execute_frame("", 0));
+            sequence.extend_from_slice(&SYNC);
+            handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+
+            assert_eq!(received.await.unwrap(), SYNC);
+            let error = backend_ready(&mut context, ReadyStatus::Idle).expect("admin error");
+            assert_eq!(error.code, code);
+            assert!(context.pending_parses.is_empty());
+        }
+    }
+
+    #[test]
+    fn statements_are_counted_by_type() {
+        let before = analytics::query_counts_snapshot();
+        for (sql, message_type) in [
+            ("SELECT 1", "Query"),
+            ("SELECT * FROM users WHERE id = $1", "Parse"),
+            ("INSERT INTO users (id) VALUES (1)", "Query"),
+            ("UPDATE users SET name = 'x'", "Query"),
+            ("UPDATE users SET name = $1 WHERE id = $2", "Parse"),
+            ("DELETE FROM users", "Query"),
+            ("BEGIN", "Query"),
+            ("SELEC 1", "Query"),
+        ] {
+            parse_and_log(sql, message_type);
+        }
+        let after = analytics::query_counts_snapshot();
+
+        // Counters are global, so other tests may add to them concurrently.
+        assert!(after.select >= before.select + 2);
+        assert!(after.insert > before.insert);
+        assert!(after.update >= before.update + 2);
+        assert!(after.delete > before.delete);
+        assert!(after.other >= before.other + 2);
+    }
+
+    #[tokio::test]
+    async fn set_persists_across_transactions_in_transaction_mode() {
+        let (pools, mut queries) = replaying_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+        assert_eq!(context.pooler_mode, PoolerMode::Transaction);
+
+        let set = query_frame("SET statement_timeout = '5s'");
+        handle_ready(&mut context, &mut buffers, set, &pools).await;
+        assert_eq!(
+            queries.recv().await.unwrap(),
+            "SET statement_timeout = '5s'"
+        );
+        backend_settles(&mut context, &pools, false).await;
+
+        // Each transaction gets a fresh checkout, and the SET goes first.
+        for _ in 0..2 {
+            let begin = query_frame("BEGIN");
+            handle_ready(&mut context, &mut buffers, begin, &pools).await;
+            assert_eq!(
+                queries.recv().await.unwrap(),
+                "SET statement_timeout TO '5s';"
+            );
+            assert_eq!(queries.recv().await.unwrap(), "BEGIN");
+            backend_settles(&mut context, &pools, false).await;
+        }
+
+        // A rejected SET is not replayed; RESET drops the captured one.
+        let bad = query_frame("SET statement_timeout = 'soon'");
+        handle_ready(&mut context, &mut buffers, bad, &pools).await;
+        queries.recv().await.unwrap();
+        queries.recv().await.unwrap();
+        backend_settles(&mut context, &pools, true).await;
+        assert_eq!(
+            context.session_state.replay_query(|_| None),
+            "SET statement_timeout TO '5s';"
+        );
+
+        let reset = query_frame("RESET statement_timeout");
+        handle_ready(&mut context, &mut buffers, reset, &pools).await;
+        queries.recv().await.unwrap();
+        assert_eq!(queries.recv().await.unwrap(), "RESET statement_timeout");
+        backend_settles(&mut context, &pools, false).await;
+        assert!(context.session_state.is_empty());
+        assert!(buffers.outbox().is_empty());
+    }
+
+    #[tokio::test]
+    async fn startup_params_the_backend_reports_skip_the_replay() {
+        let (pools, mut queries) = replaying_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        context
+            .session_state
+            .set_startup_param("client_encoding", "UTF8");
+        handle_ready(&mut context, &mut buffers, query_frame("BEGIN"), &pools).await;
+        assert_eq!(queries.recv().await.unwrap(), "BEGIN");
+        backend_settles(&mut context, &pools, false).await;
+
+        context
+            .session_state
+            .set_startup_param("client_encoding", "LATIN1");
+        handle_ready(&mut context, &mut buffers, query_frame("BEGIN"), &pools).await;
+        assert_eq!(
+            queries.recv().await.unwrap(),
+            "SELECT pg_catalog.set_config(E'client_encoding', E'LATIN1', false);"
+        );
+        assert_eq!(queries.recv().await.unwrap(), "BEGIN");
+    }
+
+    #[tokio::test]
+    async fn session_mode_leaves_set_to_the_pinned_backend() {
+        let (pools, mut queries) = replaying_backend().await;
+        let mut context = FrontendContext::new();
+        context.pooler_mode = PoolerMode::Session;
+        let mut buffers = FrontendBuffers::new();
+
+        let set = query_frame("SET statement_timeout = '5s'");
+        handle_ready(&mut context, &mut buffers, set, &pools).await;
+        assert_eq!(
+            queries.recv().await.unwrap(),
+            "SET statement_timeout = '5s'"
+        );
+        assert!(matches!(
+            context.pending_replies.front(),
+            Some(PendingReply::Ready { setting: None, .. })
+        ));
+    }
+
+    #[test]
+    fn extra_ready_for_query_is_a_desync() {
+        let mut context = FrontendContext::new();
+        let mut output = BytesMut::new();
+        handle_sync_frame(&mut context, &SYNC, &mut output);
+
+        assert_eq!(context::account_ready(&mut context.pending_syncs), Ok(()));
+        backend_ready(&mut context, ReadyStatus::Idle);
+        assert_eq!(
+            context::account_ready(&mut context.pending_syncs),
+            Err(context::ProtocolDesync::ExtraReady)
+        );
+        assert_eq!(context.pending_syncs, 0);
+    }
+
+    #[test]
+    fn missing_ready_for_query_is_a_desync() {
+        let mut context = context_with_portal("first", "pgcrab_p_1");
+        let mut output = BytesMut::new();
+        for portal in ["first", "second"] {
+            handle_execute_frame(&mut context, &execute_frame(portal, 0), &mut output);
+            handle_sync_frame(&mut context, &SYNC, &mut output);
+        }
+        assert_eq!(context.pending_syncs, 2);
+
+        // CommandComplete for the first Execute, then no ReadyForQuery.
+        assert_eq!(
+            context::check_execute_reply(&context.pending_replies),
+            Ok(())
+        );
+        context::complete_pending_execute(
+            &mut context.pending_replies,
+            &mut context.virtual_portals,
+            false,
+        );
+
+        // The second Execute's CommandComplete lands on the first Sync.
+        assert_eq!(
+            context::check_execute_reply(&context.pending_replies),
+            Err(context::ProtocolDesync::MissingReady)
+        );
+    }
+
+    #[test]
+    fn query_results_ahead_of_its_ready_are_not_a_desync() {
+        let mut context = context_with_portal("cursor", "pgcrab_p_7");
+        let mut output = BytesMut::new();
+        // A Query with two statements, pipelined ahead of an Execute.
+        context.pending_replies.push_back(PendingReply::Ready {
+            query: true,
+            injected: None,
+            sample: None,
+            setting: None,
+            sent: Some(Instant::now()),
+            failed: false,
+        });
+        context.pending_syncs += 1;
+        handle_execute_frame(&mut context, &execute_frame("cursor", 0), &mut output);
+        handle_sync_frame(&mut context, &SYNC, &mut output);
+
+        for _ in 0..2 {
+            assert_eq!(
+                context::check_execute_reply(&context.pending_replies),
+                Ok(())
+            );
+        }
+        assert_eq!(context::account_ready(&mut context.pending_syncs), Ok(()));
+        backend_ready(&mut context, ReadyStatus::Idle);
+        assert_eq!(
+            context::check_execute_reply(&context.pending_replies),
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn desynced_backend_is_closed_not_pooled() {
+        let (pools, mut queries) = replaying_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let select = query_frame("SELECT 1");
+        handle_ready(&mut context, &mut buffers, select, &pools).await;
+        assert_eq!(queries.recv().await.unwrap(), "SELECT 1");
+        assert!(context.gateway_session.is_some());
+
+        context.drop_backend(true);
+        assert!(context.gateway_session.is_none());
+        assert_eq!(context.pending_syncs, 0);
+        assert!(context.pending_replies.is_empty());
+
+        for _ in 0..100 {
+            let stats = &pools.snapshot().await[0];
+            if stats.closed == 1 {
+                assert_eq!(stats.idle, 0);
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("desynced backend was never closed");
+    }
+
+    #[tokio::test]
+    async fn standalone_select_goes_to_a_read_replica() {
+        let (pools, _main, mut replica) = split_pools().await;
+        let mut context = split_context();
+        let mut buffers = FrontendBuffers::new();
+
+        let select = query_frame("SELECT * FROM users");
+        handle_ready(&mut context, &mut buffers, select, &pools).await;
+        assert_eq!(replica.recv().await.unwrap(), "SELECT * FROM users");
+        assert_eq!(context.current_pool.as_deref(), Some("replica"));
+    }
+
+    #[tokio::test]
+    async fn insert_goes_to_the_primary() {
+        let (pools, mut main, _replica) = split_pools().await;
+        let mut context = split_context();
+        let mut buffers = FrontendBuffers::new();
+
+        let insert = query_frame("INSERT INTO users VALUES (1)");
+        handle_ready(&mut context, &mut buffers, insert, &pools).await;
+        assert_eq!(main.recv().await.unwrap(), "INSERT INTO users VALUES (1)");
+        assert_eq!(context.current_pool.as_deref(), Some("main"));
+    }
+
+    #[tokio::test]
+    async fn select_inside_a_transaction_stays_on_the_primary() {
+        let (pools, mut main, _replica) = split_pools().await;
+        let mut context = split_context();
+        let mut buffers = FrontendBuffers::new();
+
+        let begin = query_frame("BEGIN");
+        handle_ready(&mut context, &mut buffers, begin, &pools).await;
+        assert_eq!(main.recv().await.unwrap(), "BEGIN");
+
+        // ReadyForQuery 'T': the transaction keeps the session.
+        context::settle_on_ready(
+            &mut context.pending_replies,
+            &mut context.virtual_portals,
+            ReadyStatus::InTransaction,
+        );
+        context.pending_syncs = 0;
+        context.ready_status = ReadyStatus::InTransaction;
+
+        let select = query_frame("SELECT * FROM users");
+        handle_ready(&mut context, &mut buffers, select, &pools).await;
+        assert_eq!(main.recv().await.unwrap(), "SELECT * FROM users");
+        assert_eq!(context.current_pool.as_deref(), Some("main"));
+
+        // Even without a held session, an open transaction isn't a read.
+        let sequence = query_frame("SELECT 1");
+        assert!(!is_standalone_read(&context, &sequence));
+    }
+
+    #[tokio::test]
+    async fn pinned_shard_overrides_routing() {
+        let (pools, mut main, _replica) = split_pools().await;
+        let mut context = split_context();
+        context.pinned_shard = Some("main".to_string());
+        let mut buffers = FrontendBuffers::new();
+
+        // A standalone read would go to the replica, but the pin wins.
+        let select = query_frame("SELECT * FROM users");
+        handle_ready(&mut context, &mut buffers, select, &pools).await;
+        assert_eq!(main.recv().await.unwrap(), "SELECT * FROM users");
+        assert_eq!(context.current_pool.as_deref(), Some("main"));
+        context.drop_backend(true);
+
+        // A paused shard isn't swapped for another one.
+        pools.get("main").unwrap().pause();
+        handle_ready(&mut context, &mut buffers, query_frame("SELECT 1"), &pools).await;
+        let outbox = buffers.outbox().to_vec();
+        assert!(contains(&outbox, b"57P03"));
+        assert!(contains(&outbox, b"shard \"main\" is paused"));
+        assert!(context.gateway_session.is_none());
+    }
+
+    #[tokio::test]
+    async fn pinning_an_unknown_shard_is_an_error() {
+        let (pools, _main, _replica) = split_pools().await;
+        let mut context = split_context();
+        context.pinned_shard = Some("gamma".to_string());
+        let mut buffers = FrontendBuffers::new();
+
+        handle_ready(&mut context, &mut buffers, query_frame("SELECT 1"), &pools).await;
+        let outbox = buffers.outbox().to_vec();
+        assert!(contains(&outbox, b"42704"));
+        assert!(contains(
+            &outbox,
+            b"shard \"gamma\" from pgcrab.shard does not exist"
+        ));
+        assert!(context.current_pool.is_none());
+    }
+
+    /// Backend that answers every startup with a FATAL `code`, or, without
+    /// one, never answers at all.
+    async fn refusing_backend(code: Option<&'static str>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let Some(code) = code else {
+                    held.push(stream);
+                    continue;
+                };
+                let len = stream.read_u32().await.unwrap() as usize;
+                let mut startup = vec![0u8; len - 4];
+                stream.read_exact(&mut startup).await.unwrap();
+
+                let fields = format!("SFATAL\0C{code}\0Mpassword authentication failed\0\0");
+                let mut reply = vec![b'E'];
+                reply.extend_from_slice(&(4 + fields.len() as u32).to_be_bytes());
+                reply.extend_from_slice(fields.as_bytes());
+                stream.write_all(&reply).await.unwrap();
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn checkout_failures_map_to_their_sqlstate() {
+        let run = |record: ShardRecord| async move {
+            let pools = GatewayPools::new(vec![record]);
+            let mut context = FrontendContext::new();
+            let mut buffers = FrontendBuffers::new();
+            handle_ready(&mut context, &mut buffers, query_frame("SELECT 1"), &pools).await;
+            assert!(context.gateway_session.is_none());
+            buffers.outbox().to_vec()
+        };
+
+        // The shard's credentials are wrong: the backend's 28P01, as an ERROR.
+        let port = refusing_backend(Some("28P01")).await;
+        let outbox = run(fake_shard("fake", port, false)).await;
+        assert!(contains(&outbox, b"VERROR\0C28P01\0"));
+        assert!(contains(&outbox, b"password authentication failed"));
+
+        // Any other refusal is a connection failure.
+        let port = refusing_backend(Some("3D000")).await;
+        let outbox = run(fake_shard("fake", port, false)).await;
+        assert!(contains(&outbox, b"C08006\0"));
+
+        // A backend that never finishes startup: try again later.
+        let port = refusing_backend(None).await;
+        let outbox = run(ShardRecord {
+            handshake_timeout: Duration::from_millis(100),
+            ..fake_shard("fake", port, false)
+        })
+        .await;
+        assert!(contains(&outbox, b"C57P03\0"));
+        assert!(contains(&outbox, b"timed out during backend startup"));
+        assert!(outbox.ends_with(&responses::ready_with_status(ReadyStatus::Idle)));
+
+        // Paused between routing and checkout: try again later, too.
+        let paused = GatewayError::Paused("shard fake is paused".to_string());
+        assert_eq!(checkout_error(paused).code, "57P03");
+    }
+
+    #[test]
+    fn only_lone_selects_are_standalone_reads() {
+        let mut context = split_context();
+        let is_read =
+            |context: &FrontendContext, sql: &str| is_standalone_read(context, &query_frame(sql));
+
+        assert!(is_read(&context, "SELECT 1"));
+        assert!(!is_read(&context, "SELECT 1; DELETE FROM users"));
+        assert!(!is_read(&context, "UPDATE users SET name = 'crab'"));
+        assert!(!is_read(
+            &context,
+            "SELECT * FROM jobs FOR UPDATE SKIP LOCKED"
+        ));
+        assert!(!is_read(&context, "SELECT * INTO backup FROM users"));
+
+        // Parse/Bind/Execute/Sync of a SELECT, and a later Bind to it.
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "users_by_id", "SELECT * FROM users", &[]);
+        builders::build_bind(&mut sequence, "", "users_by_id", &[], &[], &[]);
+        sequence.extend_from_slice(&execute_frame("", 0));
+        sequence.extend_from_slice(&SYNC);
+        assert!(is_standalone_read(&context, &sequence));
+
+        let mut bind_only = BytesMut::new();
+        builders::build_bind(&mut bind_only, "", "users_by_id", &[], &[], &[]);
+        bind_only.extend_from_slice(&SYNC);
+        assert!(!is_standalone_read(&context, &bind_only));
+        context.virtual_statements.insert(
+            "users_by_id".to_string(),
+            VirtualStatement {
+                generation: 1,
+                query: Arc::from("SELECT * FROM users"),
+                param_type_oids: Arc::from(Vec::new()),
+                signature: StatementSignature::new("SELECT * FROM users", &[]),
+                closed: false,
+            },
+        );
+        assert!(is_standalone_read(&context, &bind_only));
+
+        context.pooler_mode = PoolerMode::Session;
+        assert!(!is_read(&context, "SELECT 1"));
+        assert!(!is_read(&FrontendContext::new(), "SELECT 1"));
+    }
+
+    #[tokio::test]
+    async fn queries_over_the_rate_limit_are_not_forwarded() {
+        let (pools, received) = fake_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+        context.rate_limiter = Some(Arc::new(RateLimiter::new(RateLimit {
+            queries_per_second: 1,
+            burst: 1,
+        })));
+
+        handle_ready(&mut context, &mut buffers, query_frame("SELECT 1"), &pools).await;
+        handle_ready(&mut context, &mut buffers, query_frame("SELECT 2"), &pools).await;
+
+        // The second Query is swapped for a Sync, whose ReadyForQuery carries
+        // the error.
+        assert_eq!(
+            received.await.unwrap(),
+            [query_frame("SELECT 1").as_ref(), &SYNC].concat()
+        );
+        // Only the client's Query counts towards the shard's latency.
+        assert!(matches!(
+            context.pending_replies[0],
+            PendingReply::Ready { sent: Some(_), .. }
+        ));
+        assert!(matches!(
+            context.pending_replies[1],
+            PendingReply::Ready { sent: None, .. }
+        ));
+        assert!(backend_ready(&mut context, ReadyStatus::Idle).is_none());
+        let error = backend_ready(&mut context, ReadyStatus::Idle).expect("rate limit error");
+        assert_eq!(error.code, "53400");
+        assert!(matches!(error.severity, Severity::Error));
+
+        // An Execute over the limit fails the batch up to its Sync.
+        let mut output = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("", 0), &mut output);
+        assert!(output.is_empty());
+        assert!(context.pending_replies.is_empty());
+        let skipped = context.skip_until_sync.as_ref().expect("rate limit error");
+        assert_eq!(skipped.code, "53400");
+    }
+
+    #[tokio::test]
+    async fn queries_over_the_rate_limit_need_no_backend() {
+        let limiter = Arc::new(RateLimiter::new(RateLimit {
+            queries_per_second: 1,
+            burst: 1,
+        }));
+        assert!(limiter.try_acquire());
+
+        // With no shards, a checkout would fail with a 57P03 instead.
+        let outbox =
+            run_query_with(|context| context.rate_limiter = Some(limiter), "SELECT 1").await;
+        assert_eq!(outbox.first(), Some(&b'E'));
+        assert!(contains(&outbox, b"C53400\0"));
+        assert!(outbox.ends_with(&[b'Z', 0, 0, 0, 5, b'I']));
+    }
+
+    #[tokio::test]
+    async fn empty_query_is_answered_without_a_backend() {
+        let expected = [b'I', 0, 0, 0, 4, b'Z', 0, 0, 0, 5, b'I'];
+        assert_eq!(run_query(false, "").await, expected);
+        assert_eq!(run_query(true, "  \n\t ").await, expected);
+    }
+
+    #[tokio::test]
+    async fn empty_query_goes_to_a_held_session() {
+        const EMPTY_QUERY: [u8; 6] = [b'Q', 0, 0, 0, 5, 0];
+        let (pools, received) = fake_backend_until(&EMPTY_QUERY).await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+        let pool = pools.get("fake").unwrap();
+        context.gateway_session = Some(GatewaySession::from_pool(&pool).await.unwrap());
+
+        handle_ready(&mut context, &mut buffers, query_frame(""), &pools).await;
+
+        // The backend answers with EmptyQueryResponse and ReadyForQuery.
+        assert_eq!(received.await.unwrap(), EMPTY_QUERY);
+        assert!(buffers.outbox().is_empty());
+        assert_eq!(context.pending_syncs, 1);
+    }
+
+    #[tokio::test]
+    async fn strict_parse_rejects_invalid_sql() {
+        let outbox = run_query(true, "SELEC 1").await;
+
+        assert_eq!(outbox.first(), Some(&b'E'));
+        assert!(contains(&outbox, b"C42601\0"));
+        assert!(contains(&outbox, b"P1\0"));
+        assert!(outbox.ends_with(&[b'Z', 0, 0, 0, 5, b'I']));
+    }
+
+    #[tokio::test]
+    async fn strict_parse_skips_a_bad_parse_to_the_sync() {
+        let (pools, received) = fake_backend().await;
+        let mut context = FrontendContext::new();
+        context.strict_parse = true;
+        let mut buffers = FrontendBuffers::new();
+
+        // No Sync yet: nothing may answer the batch before it arrives.
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "", "SELEC 1", &[]);
+        builders::build_bind(&mut sequence, "", "", &[], &[], &[]);
+        sequence.extend_from_slice(&execute_frame("", 0));
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+        assert!(buffers.outbox().is_empty());
+
+        handle_ready(
+            &mut context,
+            &mut buffers,
+            BytesMut::from(&SYNC[..]),
+            &pools,
+        )
+        .await;
+        assert_eq!(received.await.unwrap(), SYNC);
+        assert!(buffers.outbox().is_empty());
+        let error = backend_ready(&mut context, ReadyStatus::Idle).expect("syntax error");
+        assert_eq!(error.code, "42601");
+    }
+
+    #[tokio::test]
+    async fn strict_parse_forwards_valid_sql() {
+        let outbox = run_query(true, "SELECT 1").await;
+
+        assert!(!contains(&outbox, b"C42601\0"));
+        assert!(contains(&outbox, b"no backend shards available"));
+        assert!(contains(&outbox, b"C57P03\0"));
+        assert!(contains(&outbox, b"SERROR\0"));
+    }
+
+    #[tokio::test]
+    async fn policy_denies_listed_statement_types() {
+        let run = |sql| {
+            run_query_with(
+                |context| context.policy.deny_statements = vec!["DropStmt".to_string()],
+                sql,
+            )
+        };
+
+        let outbox = run("DROP TABLE users").await;
+        assert_eq!(outbox.first(), Some(&b'E'));
+        assert!(contains(&outbox, b"C42501\0"));
+        assert!(contains(&outbox, b"DropStmt"));
+        assert!(outbox.ends_with(&[b'Z', 0, 0, 0, 5, b'I']));
+
+        // Behind another statement in the same Query, too.
+        let outbox = run("SELECT 1; DROP TABLE users").await;
+        assert!(contains(&outbox, b"C42501\0"));
+
+        let outbox = run("SELECT 1").await;
+        assert!(!contains(&outbox, b"C42501\0"));
+        assert!(contains(&outbox, b"no backend shards available"));
+    }
+
+    #[tokio::test]
+    async fn synthesized_replies_keep_the_transaction_status() {
+        let pools = GatewayPools::new(Vec::new());
+        for status in [ReadyStatus::InTransaction, ReadyStatus::FailedTransaction] {
+            let ready = responses::ready_with_status(status);
+            let run = |is_admin: bool, sql: &'static str| {
+                let pools = &pools;
+                async move {
+                    let mut context = FrontendContext::new();
+                    context.ready_status = status;
+                    context.is_admin = is_admin;
+                    let mut buffers = FrontendBuffers::new();
+                    handle_ready(&mut context, &mut buffers, query_frame(sql), pools).await;
+                    buffers.outbox().to_vec()
+                }
+            };
+
+            // The proxy's own errors never reach the backend's transaction.
+            let outbox = run(false, "SHOW PGCRAB POOLS").await;
+            assert!(contains(&outbox, b"C42501\0"));
+            assert!(outbox.ends_with(&ready), "{status:?}");
+
+            let outbox = run(true, "SHOW PGCRAB POOLS").await;
+            assert!(outbox.ends_with(&ready), "{status:?}");
+        }
+
+        // With the backend gone, so is its transaction.
+        let mut context = FrontendContext::new();
+        context.ready_status = ReadyStatus::InTransaction;
+        context.drop_backend(true);
+        assert_eq!(
+            responses::ready_matching(&context),
+            responses::ready_with_status(ReadyStatus::Idle)
+        );
+    }
+
+    #[tokio::test]
+    async fn session_only_statements_need_session_mode() {
+        let run = |pooler_mode, sql| {
+            run_query_with(
+                move |context| {
+                    context.pooler_mode = pooler_mode;
+                    context.policy.session_only = vec!["VariableSetStmt".to_string()];
+                },
+                sql,
+            )
+        };
+
+        let outbox = run(PoolerMode::Transaction, "SET x = y").await;
+        assert_eq!(outbox.first(), Some(&b'E'));
+        assert!(contains(&outbox, b"C0A000\0"));
+        assert!(contains(&outbox, b"pooler_mode"));
+        assert!(outbox.ends_with(&[b'Z', 0, 0, 0, 5, b'I']));
+
+        let outbox = run(PoolerMode::Session, "SET x = y").await;
+        assert!(!contains(&outbox, b"C0A000\0"));
+        assert!(contains(&outbox, b"no backend shards available"));
+    }
+
+    #[tokio::test]
+    async fn lenient_parse_forwards_invalid_sql() {
+        let outbox = run_query(false, "SELEC 1").await;
+
+        assert!(!contains(&outbox, b"C42601\0"));
+        assert!(contains(&outbox, b"no backend shards available"));
+    }
+
+    #[tokio::test]
+    async fn describe_unknown_portal_reports_invalid_cursor_at_sync() {
+        let (pools, received) = fake_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut sequence = BytesMut::new();
+        builders::build_describe(&mut sequence, DescribeTarget::Portal, "missing");
+        builders::build_execute(&mut sequence, "missing", 0);
+        sequence.extend_from_slice(&SYNC);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+
+        // Nothing naming the unknown portal reaches the backend, only the Sync.
+        assert_eq!(received.await.unwrap(), SYNC);
+        assert!(buffers.outbox().is_empty());
+        assert!(context.gateway_session.is_some());
+
+        let error = backend_ready(&mut context, ReadyStatus::Idle).expect("cursor error");
+        assert_eq!(error.code, "34000");
+        assert_eq!(error.message, "portal \"missing\" does not exist");
+        assert!(context.skip_until_sync.is_none());
+        assert!(context.pending_replies.is_empty());
+    }
+
+    #[tokio::test]
+    async fn bind_records_the_portal_result_formats() {
+        let (pools, received) = fake_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut sequence = BytesMut::new();
+        builders::build_parse(
+            &mut sequence,
+            "users",
+            "SELECT id, avatar, name FROM users",
+            &[],
+        );
+        builders::build_bind(&mut sequence, "cursor", "users", &[], &[], &[0, 1, 0]);
+        builders::build_bind(&mut sequence, "all_binary", "users", &[], &[], &[1]);
+        sequence.extend_from_slice(&SYNC);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+        received.await.unwrap();
+
+        let portals = &context.virtual_portals;
+        assert_eq!(portals["cursor"].result_formats, [false, true, false]);
+        assert_eq!(portals["all_binary"].result_formats, [true]);
+
+        let binary = |portal: &str| {
+            (0..3)
+                .map(|column| portals[portal].is_binary_column(column))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(binary("cursor"), [false, true, false]);
+        assert_eq!(binary("all_binary"), [true, true, true]);
+    }
+
+    #[tokio::test]
+    async fn flush_without_sync_keeps_the_session_for_its_replies() {
+        let (pools, received) = fake_backend_until(&FLUSH).await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "stmt", "SELECT $1::int", &[]);
+        builders::build_describe(&mut sequence, DescribeTarget::Statement, "stmt");
+        sequence.extend_from_slice(&FLUSH);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+
+        // Parse and Describe reach the backend, followed by the Flush itself.
+        let received = received.await.unwrap();
+        assert_eq!(received.first(), Some(&b'P'));
+        assert!(received.ends_with(&FLUSH));
+        assert!(!contains(&received, &SYNC));
+
+        // No ReadyForQuery is owed, but the session stays to relay
+        // ParseComplete and ParameterDescription as they come back.
+        assert!(context.gateway_session.is_some());
+        assert_eq!(context.pending_syncs, 0);
+        assert!(context.pending_replies.is_empty());
+        assert_eq!(context.pending_parses.len(), 1);
+        assert!(buffers.outbox().is_empty());
+    }
+
+    #[tokio::test]
+    async fn malformed_messages_in_a_row_close_the_connection() {
+        // A Parse whose statement name never ends.
+        const MALFORMED: [u8; 7] = [b'P', 0, 0, 0, 6, b'x', b'y'];
+
+        let (pools, received) = fake_backend_until(&FLUSH).await;
+        let mut context = FrontendContext::new();
+        context.max_decode_failures = 3;
+        let mut buffers = FrontendBuffers::new();
+
+        // A good message starts the count over; a Flush doesn't.
+        let mut sequence = BytesMut::from(&[&MALFORMED[..], &MALFORMED].concat()[..]);
+        builders::build_parse(&mut sequence, "", "SELECT 1", &[]);
+        sequence.extend_from_slice(&MALFORMED);
+        sequence.extend_from_slice(&FLUSH);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+        received.await.unwrap();
+        assert_eq!(context.decode_failures, 1);
+        assert!(!context.should_close());
+
+        let sequence = [&MALFORMED[..], &SYNC, &MALFORMED, &SYNC].concat();
+        handle_ready(
+            &mut context,
+            &mut buffers,
+            sequence.as_slice().into(),
+            &pools,
+        )
+        .await;
+        assert!(context.should_close());
+        assert!(context.gateway_session.is_none());
+        assert!(context.pending_parses.is_empty());
+        assert_eq!(context.pending_syncs, 0);
+
+        let outbox = buffers.outbox();
+        assert!(contains(outbox, b"SFATAL\0"));
+        assert!(contains(outbox, b"C08P01\0"));
+        assert!(contains(outbox, b"3 malformed messages in a row"));
+        assert!(!contains(outbox, &[b'Z', 0, 0, 0, 5]));
+    }
+
+    #[tokio::test]
+    async fn malformed_messages_close_the_connection_before_a_checkout() {
+        const MALFORMED: [u8; 7] = [b'P', 0, 0, 0, 6, b'x', b'y'];
+
+        let mut context = FrontendContext::new();
+        context.max_decode_failures = 2;
+        let mut buffers = FrontendBuffers::new();
+        let pools = GatewayPools::new(Vec::new());
+        let sequence = [&MALFORMED[..], &MALFORMED, &SYNC].concat();
+        handle_ready(
+            &mut context,
+            &mut buffers,
+            sequence.as_slice().into(),
+            &pools,
+        )
+        .await;
+
+        assert!(context.should_close());
+        let outbox = buffers.outbox();
+        assert!(contains(outbox, b"C08P01\0"));
+        assert!(!contains(outbox, b"no backend shards available"));
+    }
+
+    /// Stands in for the relay receiving the backend's ErrorResponse.
+    fn backend_error(context: &mut FrontendContext) {
+        context::fail_pending_parses(&mut context.pending_parses, &mut context.virtual_statements);
+        context.pending_closes.clear();
+        context::fail_pending_replies(&mut context.pending_replies);
+        context.virtual_portals.clear();
+        context.failed_batch = context.pending_syncs == 0;
+    }
+
+    #[tokio::test]
+    async fn failed_parse_discards_the_batch_until_sync() {
+        let (pools, received) = fake_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut first = BytesMut::new();
+        builders::build_parse(&mut first, "stmt", "SELEC 1", &[]);
+        builders::build_bind(&mut first, "", "stmt", &[], &[], &[]);
+        builders::build_execute(&mut first, "", 0);
+        first.extend_from_slice(&FLUSH);
+        handle_ready(&mut context, &mut buffers, first.clone(), &pools).await;
+        assert_eq!(context.pending_parses.len(), 1);
+        assert_eq!(context.pending_replies.len(), 1);
+
+        // The Parse fails: Postgres skips the Bind and Execute behind it,
+        // then everything the client sends until its Sync.
+        backend_error(&mut context);
+        assert!(context.failed_batch);
+        assert!(context.pending_parses.is_empty());
+        assert!(context.pending_replies.is_empty());
+        assert!(context.virtual_statements["stmt"].closed);
+
+        let mut second = BytesMut::new();
+        builders::build_bind(&mut second, "", "stmt", &[], &[], &[]);
+        builders::build_execute(&mut second, "", 0);
+        second.extend_from_slice(&SYNC);
+        handle_ready(&mut context, &mut buffers, second, &pools).await;
+
+        // Only the Sync went out, and its ReadyForQuery is all that's owed.
+        let received = received.await.unwrap();
+        assert!(received.ends_with(&[&FLUSH[..], &SYNC[..]].concat()));
+        assert!(!context.failed_batch);
+        assert!(context.pending_parses.is_empty());
+        assert!(context.virtual_portals.is_empty());
+        assert_eq!(context.pending_syncs, 1);
+
+        assert_eq!(context::account_ready(&mut context.pending_syncs), Ok(()));
+        assert!(backend_ready(&mut context, ReadyStatus::Idle).is_none());
+        assert!(context.pending_replies.is_empty());
+        assert!(buffers.outbox().is_empty());
+
+        // The statement never existed; a Bind to it goes to the backend as
+        // sent, to fail there.
+        let mut output = BytesMut::new();
+        let mut session = context.gateway_session.take().unwrap();
+        let mut bind = BytesMut::new();
+        builders::build_bind(&mut bind, "", "stmt", &[], &[], &[]);
+        handle_bind_frame(
+            &mut context,
+            &mut session,
+            &bind,
+            &mut output,
+            &mut HashMap::new(),
+        );
+        assert_eq!(&output[..], &bind[..]);
+    }
+
+    #[test]
+    fn deallocate_targets_one_statement() {
+        assert_eq!(deallocate_target("DEALLOCATE stmt"), Some("stmt".into()));
+        assert_eq!(
+            deallocate_target("  deallocate prepare My_Stmt ; "),
+            Some("my_stmt".into())
+        );
+        assert_eq!(
+            deallocate_target("DEALLOCATE \"My \"\"Stmt\"\"\";"),
+            Some("My \"Stmt\"".into())
+        );
+        assert_eq!(deallocate_target("DEALLOCATE ALL"), None);
+        assert_eq!(deallocate_target("DEALLOCATE PREPARE ALL"), None);
+        assert_eq!(deallocate_target("DEALLOCATE a; SELECT 1"), None);
+        assert_eq!(deallocate_target("DEALLOCATE"), None);
+        assert_eq!(deallocate_target("SELECT 1"), None);
+    }
+
+    #[tokio::test]
+    async fn deallocate_closes_the_backend_prepare() {
+        let (pools, received) = fake_backend().await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut prepare = BytesMut::new();
+        builders::build_parse(&mut prepare, "stmt", "SELECT 1", &[]);
+        prepare.extend_from_slice(&FLUSH);
+        handle_ready(&mut context, &mut buffers, prepare, &pools).await;
+
+        // The relay records the prepare on its ParseComplete.
+        let parsed = context.pending_parses.pop_front().unwrap();
+        let backend_name = parsed.backend_statement_name.unwrap();
+        let mut session = context.gateway_session.take().unwrap();
+        session
+            .backend()
+            .prepared_insert(parsed.signature.unwrap(), backend_name.clone());
+        context.gateway_session = Some(session);
+
+        let deallocate = query_frame("DEALLOCATE stmt");
+        handle_ready(&mut context, &mut buffers, deallocate.clone(), &pools).await;
+
+        // A Close of pgcrab's name and a Sync go out instead of the Query.
+        let received = received.await.unwrap();
+        let mut close = BytesMut::new();
+        builders::build_close(&mut close, CloseTarget::Statement, &backend_name);
+        assert!(received.ends_with(&[&close[..], &SYNC[..]].concat()));
+        assert!(!contains(&received, &deallocate));
+        assert_eq!(context.pending_closes, [PendingClose::Deallocate]);
+        assert_eq!(context.pending_syncs, 1);
+        assert!(context.virtual_statements["stmt"].closed);
+
+        // The statement is gone: a Bind to it goes to the backend as sent.
+        let mut session = context.gateway_session.take().unwrap();
+        assert!(
+            session
+                .backend()
+                .prepared_lookup(&parsed.signature.unwrap())
+                .is_none()
+        );
+        let mut output = BytesMut::new();
+        let mut bind = BytesMut::new();
+        builders::build_bind(&mut bind, "", "stmt", &[], &[], &[]);
+        handle_bind_frame(
+            &mut context,
+            &mut session,
+            &bind,
+            &mut output,
+            &mut HashMap::new(),
+        );
+        assert_eq!(&output[..], &bind[..]);
+    }
+
+    #[tokio::test]
+    async fn sessions_on_one_backend_share_a_prepare() {
+        let (pools, received) = fake_backend_sharing(&FLUSH, true).await;
+        let mut first = FrontendContext::new();
+        let mut second = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "a", "SELECT 1", &[]);
+        sequence.extend_from_slice(&SYNC);
+        handle_ready(&mut first, &mut buffers, sequence, &pools).await;
+
+        // ParseComplete for the first client's prepare.
+        let mut session = first.gateway_session.take().expect("session");
+        let parse = first.pending_parses.pop_front().expect("pending parse");
+        session.backend().prepared_insert(
+            parse.signature.expect("signature"),
+            parse.backend_statement_name.expect("backend name"),
+        );
+
+        // The second client lands on the same backend and parses the same SQL,
+        // then closes it while the first client still relies on it.
+        second.gateway_session = Some(session);
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "b", "SELECT 1", &[]);
+        builders::build_close(&mut sequence, CloseTarget::Statement, "b");
+        sequence.extend_from_slice(&SYNC);
+        handle_ready(&mut second, &mut buffers, sequence, &pools).await;
+
+        // The last reference going away closes it on the backend.
+        first.gateway_session = second.gateway_session.take();
+        let mut sequence = BytesMut::new();
+        builders::build_close(&mut sequence, CloseTarget::Statement, "a");
+        sequence.extend_from_slice(&FLUSH);
+        handle_ready(&mut first, &mut buffers, sequence, &pools).await;
+
+        let received = received.await.unwrap();
+        let prepares = received
+            .windows(b"SELECT 1".len())
+            .filter(|window| *window == b"SELECT 1")
+            .count();
+        assert_eq!(prepares, 1);
+
+        let mut placeholders = BytesMut::new();
+        builders::build_parse(&mut placeholders, "", "", &[]);
+        builders::build_close(&mut placeholders, CloseTarget::Statement, "");
+        assert!(contains(&received, &placeholders));
+
+        let mut last_close = BytesMut::new();
+        builders::build_close(&mut last_close, CloseTarget::Statement, "ps_0_0");
+        assert!(received.ends_with(&[last_close.as_ref(), &FLUSH].concat()));
+        assert!(buffers.outbox().is_empty());
+    }
+
+    #[tokio::test]
+    async fn prepares_past_the_cap_close_the_least_recently_used() {
+        let (pools, received) = fake_backend_for(&FLUSH, |port| ShardRecord {
+            max_prepared_statements: Some(2),
+            ..fake_shard("fake", port, false)
+        })
+        .await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "a", "SELECT 1", &[]);
+        builders::build_parse(&mut sequence, "b", "SELECT 2", &[]);
+        sequence.extend_from_slice(&SYNC);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+
+        // ParseComplete for both prepares.
+        let session = context.gateway_session.as_mut().expect("session");
+        while let Some(parse) = context.pending_parses.pop_front() {
+            session.backend().prepared_insert(
+                parse.signature.expect("signature"),
+                parse.backend_statement_name.expect("backend name"),
+            );
+        }
+
+        // Binding `a` makes `b` the least recently used, so preparing a
+        // third statement closes `b`.
+        let mut sequence = BytesMut::new();
+        builders::build_bind(&mut sequence, "", "a", &[], &[], &[]);
+        builders::build_parse(&mut sequence, "c", "SELECT 3", &[]);
+        sequence.extend_from_slice(&FLUSH);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+
+        let received = received.await.unwrap();
+        let mut eviction = BytesMut::new();
+        builders::build_close(&mut eviction, CloseTarget::Statement, "ps_0_1");
+        builders::build_parse(&mut eviction, "ps_0_2", "SELECT 3", &[]);
+        assert!(contains(&received, &eviction));
+
+        let mut kept = BytesMut::new();
+        builders::build_close(&mut kept, CloseTarget::Statement, "ps_0_0");
+        assert!(!contains(&received, &kept));
+
+        // The client never sent that Close, so its CloseComplete is dropped.
+        assert_eq!(context.pending_closes, [PendingClose::Evicted]);
+    }
+
+    #[tokio::test]
+    async fn prepares_bound_in_the_batch_are_not_evicted() {
+        let (pools, received) = fake_backend_for(&FLUSH, |port| ShardRecord {
+            max_prepared_statements: Some(1),
+            ..fake_shard("fake", port, false)
+        })
+        .await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "a", "SELECT 1", &[]);
+        sequence.extend_from_slice(&SYNC);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+        let session = context.gateway_session.as_mut().expect("session");
+        let parse = context.pending_parses.pop_front().expect("parse");
+        session.backend().prepared_insert(
+            parse.signature.expect("signature"),
+            parse.backend_statement_name.expect("backend name"),
+        );
+
+        // `a` has a portal by the time `b` needs room: closing `a` would
+        // close the portal and fail its Execute.
+        let mut sequence = BytesMut::new();
+        builders::build_bind(&mut sequence, "", "a", &[], &[], &[]);
+        builders::build_parse(&mut sequence, "b", "SELECT 2", &[]);
+        builders::build_execute(&mut sequence, "", 0);
+        sequence.extend_from_slice(&FLUSH);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+
+        let received = received.await.unwrap();
+        let mut close = BytesMut::new();
+        builders::build_close(&mut close, CloseTarget::Statement, "ps_0_0");
+        assert!(!contains(&received, &close));
+        assert!(context.pending_closes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn client_sees_one_close_complete_per_close_it_sent() {
+        let (pools, received) = fake_backend_for(&FLUSH, |port| ShardRecord {
+            max_prepared_statements: Some(1),
+            ..fake_shard("fake", port, false)
+        })
+        .await;
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "a", "SELECT 1", &[]);
+        sequence.extend_from_slice(&SYNC);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+        let session = context.gateway_session.as_mut().expect("session");
+        let parse = context.pending_parses.pop_front().expect("parse");
+        session.backend().prepared_insert(
+            parse.signature.expect("signature"),
+            parse.backend_statement_name.expect("backend name"),
+        );
+
+        // Preparing `b` evicts `a`'s prepare; then the client closes both.
+        let mut sequence = BytesMut::new();
+        builders::build_parse(&mut sequence, "b", "SELECT 2", &[]);
+        builders::build_close(&mut sequence, CloseTarget::Statement, "a");
+        builders::build_close(&mut sequence, CloseTarget::Statement, "b");
+        sequence.extend_from_slice(&FLUSH);
+        handle_ready(&mut context, &mut buffers, sequence, &pools).await;
+
+        let received = received.await.unwrap();
+        let mut cursor = 0;
+        let mut closes_sent = 0;
+        while cursor < received.len() {
+            let peek = peek_frontend(AuthStage::Ready, &received[cursor..]).unwrap();
+            closes_sent += usize::from(peek.message_type == MessageType::Close);
+            cursor += peek.len;
+        }
+        assert_eq!(closes_sent, 3);
+
+        // One CloseComplete back per Close sent; only the client's get out.
+        let session = context.gateway_session.as_mut().expect("session");
+        let parse = context.pending_parses.pop_front().expect("parse");
+        session.backend().prepared_insert(
+            parse.signature.expect("signature"),
+            parse.backend_statement_name.expect("backend name"),
+        );
+        let relayed = (0..closes_sent)
+            .filter(|_| {
+                context::complete_close(&mut context.pending_closes) == PendingClose::Client
+            })
+            .count();
+        assert_eq!(relayed, 2);
+        assert!(context.pending_closes.is_empty());
+    }
+
+    #[test]
+    fn backend_error_supersedes_injected_error() {
+        let mut context = FrontendContext::new();
+        context
+            .pending_replies
+            .push_back(PendingReply::Execute("earlier".to_string()));
+        let error = ErrorResponse::invalid_cursor_name("portal \"missing\" does not exist");
+        context.pending_replies.push_back(PendingReply::Ready {
+            query: false,
+            injected: Some(Box::new(error)),
+            sample: None,
+            setting: None,
+            sent: Some(Instant::now()),
+            failed: false,
+        });
+
+        context::fail_pending_replies(&mut context.pending_replies);
+        assert!(backend_ready(&mut context, ReadyStatus::Idle).is_none());
+        assert!(context.pending_replies.is_empty());
+    }
+
+    #[test]
+    fn execute_max_rows_round_trips_to_backend_portal() {
+        let mut context = context_with_portal("cursor", "pgcrab_p_7");
+        let mut output = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("cursor", 1), &mut output);
+
+        let observer = ExecuteFrameObserver::new(&output).expect("rewritten execute");
+        assert_eq!(observer.portal(), "pgcrab_p_7");
+        assert_eq!(observer.max_rows(), 1);
+    }
+
+    #[test]
+    fn suspended_portal_resumes_on_same_backend_portal() {
+        let mut context = context_with_portal("cursor", "pgcrab_p_7");
+
+        let mut first = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("cursor", 1), &mut first);
+        backend_reply(&mut context, true);
+        assert!(context.virtual_portals["cursor"].suspended);
+
+        let mut second = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("cursor", 1), &mut second);
+        assert_eq!(first, second);
+
+        backend_reply(&mut context, false);
+        assert!(!context.virtual_portals["cursor"].suspended);
+        assert!(context.pending_replies.is_empty());
+    }
+
+    #[test]
+    fn suspended_portal_survives_sync_inside_transaction() {
+        let mut context = context_with_portal("cursor", "pgcrab_p_7");
+        context.virtual_portals.insert(
+            "done".to_string(),
+            PortalBinding {
+                backend_portal_name: "pgcrab_p_8".to_string(),
+                backend_statement_name: String::new(),
+                suspended: false,
+                result_formats: Vec::new(),
+            },
+        );
+
+        let mut output = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("cursor", 1), &mut output);
+        handle_sync_frame(&mut context, &[b'S', 0, 0, 0, 4], &mut output);
+        backend_reply(&mut context, true);
+        backend_ready(&mut context, ReadyStatus::InTransaction);
+
+        assert!(context.virtual_portals.contains_key("cursor"));
+        assert!(!context.virtual_portals.contains_key("done"));
+        assert!(context.pending_replies.is_empty());
+
+        let mut resumed = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("cursor", 0), &mut resumed);
+        let observer = ExecuteFrameObserver::new(&resumed).expect("resumed execute");
+        assert_eq!(observer.portal(), "pgcrab_p_7");
+    }
+
+    #[test]
+    fn idle_ready_drops_suspended_portals() {
+        let mut context = context_with_portal("cursor", "pgcrab_p_7");
+
+        let mut output = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("cursor", 1), &mut output);
+        handle_sync_frame(&mut context, &[b'S', 0, 0, 0, 4], &mut output);
+        backend_reply(&mut context, true);
+        backend_ready(&mut context, ReadyStatus::Idle);
+
+        assert!(context.virtual_portals.is_empty());
+    }
+
+    #[test]
+    fn query_command_complete_does_not_consume_execute() {
+        let mut context = context_with_portal("cursor", "pgcrab_p_7");
+        context.pending_replies.push_back(PendingReply::Ready {
+            query: true,
+            injected: None,
+            sample: None,
+            setting: None,
+            sent: Some(Instant::now()),
+            failed: false,
+        });
+
+        let mut output = BytesMut::new();
+        handle_execute_frame(&mut context, &execute_frame("cursor", 1), &mut output);
+
+        // CommandComplete from the simple Query, then its ReadyForQuery.
+        backend_reply(&mut context, false);
+        backend_ready(&mut context, ReadyStatus::InTransaction);
+
+        backend_reply(&mut context, true);
+        assert!(context.virtual_portals["cursor"].suspended);
+    }
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
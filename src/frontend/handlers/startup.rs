@@ -1,7 +1,12 @@
 use bytes::BytesMut;
+use tracing::{Span, debug};
 
 use crate::ErrorResponse;
+use crate::config::server::ClientAuth;
+use crate::config::startup::{SHARD_PIN_PARAM, is_protocol_param};
+use crate::errors::Severity;
 use crate::frontend::buffers::FrontendBuffers;
+use crate::frontend::channel_binding::sasl_mechanisms;
 use crate::frontend::context::FrontendContext;
 use crate::frontend::proxy_responses as responses;
 use crate::shared_types::AuthStage;
@@ -10,6 +15,14 @@ use crate::wire::observers::startup::{NewStartupObserverError, StartupFrameObser
 use crate::wire::types::MessageType;
 use crate::wire::utils::peek_frontend;
 
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Newest protocol minor the proxy speaks (3.0). It's also the oldest, so
+/// there's no `min_protocol`/`max_protocol` setting: the only range it
+/// could pick is 3.0 to 3.0. Add one along with support for a 3.x past 3.0.
+const SUPPORTED_MINOR_VERSION: i32 = 0;
+
 // -----------------------------------------------------------------------------
 // ----- Startup Handler -------------------------------------------------------
 
@@ -55,9 +68,7 @@ pub(crate) fn handle_startup(
             let startup_frame = match StartupFrameObserver::new(&message) {
                 Ok(frame) => frame,
                 Err(NewStartupObserverError::UnexpectedVersion(version)) => {
-                    let err =
-                        ErrorResponse::protocol_violation("unsupported startup protocol version")
-                            .with_detail(format!("version: {}", version));
+                    let err = unsupported_protocol(version);
                     buffers.queue_response(&err.to_bytes());
                     context.request_close();
                     return;
@@ -77,16 +88,52 @@ pub(crate) fn handle_startup(
                 return;
             };
 
+            if let Some(mode) = startup_frame.param("replication")
+                && !is_false(mode)
+            {
+                let err = ErrorResponse::feature_not_supported(
+                    "replication connections are not supported by pgcrab",
+                )
+                .with_severity(Severity::Fatal)
+                .with_detail(format!("replication: {mode}"))
+                .with_hint("connect directly to the Postgres server for replication");
+                buffers.queue_response(&err.to_bytes());
+                context.request_close();
+                return;
+            }
+
             let database = startup_frame
                 .param("database")
                 .filter(|v| !v.is_empty())
                 .unwrap_or(username);
 
+            let span = Span::current();
+            span.record("user", username);
+            span.record("database", database);
+
             context.username = Some(username.to_string());
             context.database = Some(database.to_string());
             context.stage = AuthStage::Authenticating;
+            context.pinned_shard = pinned_shard(&startup_frame);
+            forward_startup_params(context, &startup_frame);
 
-            buffers.queue_response(&responses::auth_cleartext());
+            // We recognise no protocol options, so every `_pq_.*` is unsupported.
+            let unsupported: Vec<&str> = startup_frame.protocol_options().collect();
+            if startup_frame.minor_version() > SUPPORTED_MINOR_VERSION || !unsupported.is_empty() {
+                buffers.queue_response(&responses::negotiate_protocol_version(
+                    SUPPORTED_MINOR_VERSION,
+                    &unsupported,
+                ));
+            }
+
+            let request = match context.client_auth {
+                ClientAuth::Password => responses::auth_cleartext(),
+                ClientAuth::ScramSha256 => responses::auth_sasl(&sasl_mechanisms(
+                    &context.sasl_mechanisms,
+                    context.tls_end_point.as_deref(),
+                )),
+            };
+            buffers.queue_response(&request);
         }
 
         _ => {
@@ -98,5 +145,287 @@ pub(crate) fn handle_startup(
     }
 }
 
+/// Like Postgres, refuses a protocol major other than 3 outright: there is
+/// nothing to negotiate down to.
+fn unsupported_protocol(version: i32) -> ErrorResponse {
+    let (major, minor) = (version >> 16, version & 0xffff);
+    let err = ErrorResponse::feature_not_supported(format!(
+        "unsupported frontend protocol {major}.{minor}: \
+         server supports 3.0 to 3.{SUPPORTED_MINOR_VERSION}"
+    ))
+    .with_severity(Severity::Fatal);
+
+    if major < 3 {
+        err.with_hint("protocol 2.0 is gone since PostgreSQL 14; upgrade the client driver")
+    } else {
+        err
+    }
+}
+
+/// Parameters on the `[startup]` allowlist become session defaults, set on
+/// every backend the client checks out. The rest are dropped.
+fn forward_startup_params(context: &mut FrontendContext, startup: &StartupFrameObserver) {
+    for (name, value) in startup.params() {
+        if is_protocol_param(name) {
+            continue;
+        }
+        if !context.startup.allows(name) {
+            debug!(
+                param = name,
+                "dropping startup parameter not in allowed_params"
+            );
+            continue;
+        }
+        context.session_state.set_startup_param(name, value);
+    }
+}
+
+/// `-c pgcrab.shard=name` in `options` or a `pgcrab.shard` startup key;
+/// the `options` setting wins, like a later SET would.
+fn pinned_shard(startup: &StartupFrameObserver) -> Option<String> {
+    let from_options = startup
+        .option_settings()
+        .into_iter()
+        .rev()
+        .find(|(name, _)| name == SHARD_PIN_PARAM)
+        .map(|(_, shard)| shard);
+    from_options
+        .or_else(|| startup.param(SHARD_PIN_PARAM).map(str::to_string))
+        .filter(|shard| !shard.is_empty())
+}
+
+/// Postgres' boolean spellings for `replication=false`, the one value that
+/// still means an ordinary connection.
+fn is_false(value: &str) -> bool {
+    ["false", "off", "no", "0"]
+        .iter()
+        .any(|spelling| value.eq_ignore_ascii_case(spelling))
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BufMut;
+
+    use crate::frontend::channel_binding::{SCRAM_SHA_256, SCRAM_SHA_256_PLUS};
+
+    fn gssenc_request() -> BytesMut {
+        let mut frame = BytesMut::new();
+        frame.put_u32(8);
+        frame.put_i32(80877104);
+        frame
+    }
+
+    fn startup_message(user: &str) -> BytesMut {
+        versioned_startup_message(196608, &[("user", user)])
+    }
+
+    fn versioned_startup_message(version: i32, params: &[(&str, &str)]) -> BytesMut {
+        let mut body = BytesMut::new();
+        body.put_i32(version);
+        for (key, value) in params {
+            body.extend_from_slice(key.as_bytes());
+            body.put_u8(0);
+            body.extend_from_slice(value.as_bytes());
+            body.put_u8(0);
+        }
+        body.put_u8(0);
+
+        let mut frame = BytesMut::new();
+        frame.put_u32((4 + body.len()) as u32);
+        frame.extend_from_slice(&body);
+        frame
+    }
+
+    #[test]
+    fn gssenc_request_is_declined_and_startup_continues() {
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        handle_startup(&mut context, &mut buffers, gssenc_request(), true);
+        assert_eq!(buffers.outbox(), b"N");
+        assert_eq!(context.stage, AuthStage::Startup);
+        assert!(!context.should_close());
+        assert!(!context.wants_tls_upgrade());
+
+        handle_startup(&mut context, &mut buffers, startup_message("alice"), true);
+        assert_eq!(context.stage, AuthStage::Authenticating);
+        assert_eq!(context.username.as_deref(), Some("alice"));
+        assert_eq!(&buffers.outbox()[1..], &responses::auth_cleartext()[..]);
+    }
+
+    #[test]
+    fn scram_offers_plus_only_on_tls() {
+        let mut context = FrontendContext::new();
+        context.client_auth = ClientAuth::ScramSha256;
+        let mut buffers = FrontendBuffers::new();
+        handle_startup(&mut context, &mut buffers, startup_message("alice"), false);
+        assert_eq!(
+            buffers.outbox(),
+            &responses::auth_sasl(&[SCRAM_SHA_256])[..]
+        );
+
+        let mut context = FrontendContext::new();
+        context.client_auth = ClientAuth::ScramSha256;
+        context.tls_end_point = Some(vec![0xab; 32]);
+        let mut buffers = FrontendBuffers::new();
+        handle_startup(&mut context, &mut buffers, startup_message("alice"), false);
+        assert_eq!(
+            buffers.outbox(),
+            &responses::auth_sasl(&[SCRAM_SHA_256_PLUS, SCRAM_SHA_256])[..]
+        );
+    }
+
+    #[test]
+    fn replication_startup_is_rejected() {
+        for mode in ["database", "true", "on"] {
+            let mut context = FrontendContext::new();
+            let mut buffers = FrontendBuffers::new();
+            let startup =
+                versioned_startup_message(196608, &[("user", "alice"), ("replication", mode)]);
+
+            handle_startup(&mut context, &mut buffers, startup, false);
+            let outbox = buffers.outbox();
+            assert_eq!(outbox.first(), Some(&b'E'));
+            assert!(outbox.windows(7).any(|w| w == b"SFATAL\0"));
+            assert!(outbox.windows(7).any(|w| w == b"C0A000\0"));
+            assert!(context.should_close());
+            assert_eq!(context.stage, AuthStage::Startup);
+        }
+
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+        let startup =
+            versioned_startup_message(196608, &[("user", "alice"), ("replication", "false")]);
+        handle_startup(&mut context, &mut buffers, startup, false);
+        assert_eq!(context.stage, AuthStage::Authenticating);
+    }
+
+    #[test]
+    fn only_allowed_startup_params_reach_the_backend() {
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+        let startup = versioned_startup_message(
+            196608,
+            &[
+                ("user", "alice"),
+                ("database", "app"),
+                ("application_name", "psql"),
+                ("work_mem", "1GB"),
+                ("TimeZone", "UTC"),
+            ],
+        );
+
+        handle_startup(&mut context, &mut buffers, startup, false);
+        assert_eq!(context.stage, AuthStage::Authenticating);
+        assert_eq!(
+            context.session_state.replay_query(|_| None),
+            "SELECT pg_catalog.set_config(E'application_name', E'psql', false), \
+             pg_catalog.set_config(E'timezone', E'UTC', false);"
+        );
+
+        let mut context = FrontendContext::new();
+        context.startup.allowed_params = vec!["work_mem".to_string()];
+        let startup = versioned_startup_message(
+            196608,
+            &[
+                ("user", "alice"),
+                ("application_name", "psql"),
+                ("work_mem", "1GB"),
+            ],
+        );
+        handle_startup(&mut context, &mut buffers, startup, false);
+        assert_eq!(
+            context.session_state.replay_query(|_| None),
+            "SELECT pg_catalog.set_config(E'work_mem', E'1GB', false);"
+        );
+    }
+
+    #[test]
+    fn shard_pin_comes_from_options_or_its_own_key() {
+        let pin = |params: &[(&str, &str)]| {
+            let mut context = FrontendContext::new();
+            let mut buffers = FrontendBuffers::new();
+            let startup = versioned_startup_message(196608, params);
+            handle_startup(&mut context, &mut buffers, startup, false);
+            assert_eq!(context.session_state.replay_query(|_| None), "");
+            context.pinned_shard
+        };
+
+        assert_eq!(pin(&[("user", "alice")]), None);
+        assert_eq!(
+            pin(&[("user", "alice"), ("options", "-c pgcrab.shard=alpha")]),
+            Some("alpha".to_string())
+        );
+        assert_eq!(
+            pin(&[("user", "alice"), ("pgcrab.shard", "beta")]),
+            Some("beta".to_string())
+        );
+        assert_eq!(
+            pin(&[
+                ("user", "alice"),
+                ("pgcrab.shard", "beta"),
+                ("options", "--pgcrab.shard=alpha"),
+            ]),
+            Some("alpha".to_string())
+        );
+        assert_eq!(pin(&[("user", "alice"), ("pgcrab.shard", "")]), None);
+    }
+
+    #[test]
+    fn protocol_2_startup_is_refused() {
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+
+        // Fixed-size 2.0 startup packet: the version, then 292 bytes of
+        // padded fields. It frames as one packet rather than stalling.
+        let mut packet = BytesMut::new();
+        packet.put_u32(296);
+        packet.put_i32(2 << 16);
+        packet.extend_from_slice(b"alice");
+        packet.resize(296, 0);
+        buffers.push_inbox(&packet);
+        buffers.track_new_inbox_frames(AuthStage::Startup).unwrap();
+        let message = buffers
+            .pull_next_sequence(AuthStage::Startup, false)
+            .expect("a whole startup packet");
+        assert_eq!(message.len(), 296);
+
+        handle_startup(&mut context, &mut buffers, message, false);
+        let outbox = buffers.outbox();
+        assert_eq!(outbox.first(), Some(&b'E'));
+        assert!(outbox.windows(7).any(|w| w == b"SFATAL\0"));
+        assert!(outbox.windows(7).any(|w| w == b"C0A000\0"));
+        let message = b"unsupported frontend protocol 2.0: server supports 3.0 to 3.0";
+        assert!(outbox.windows(message.len()).any(|w| w == message));
+        assert!(context.should_close());
+        assert_eq!(context.stage, AuthStage::Startup);
+    }
+
+    #[test]
+    fn newer_minor_version_is_negotiated_down() {
+        let mut context = FrontendContext::new();
+        let mut buffers = FrontendBuffers::new();
+        let startup =
+            versioned_startup_message(196608 + 1, &[("user", "alice"), ("_pq_.bogus", "on")]);
+
+        handle_startup(&mut context, &mut buffers, startup, false);
+        assert!(!context.should_close());
+        assert_eq!(context.stage, AuthStage::Authenticating);
+
+        let mut expected = BytesMut::new();
+        expected.put_u8(b'v');
+        expected.put_u32(4 + 4 + 4 + "_pq_.bogus\0".len() as u32);
+        expected.put_i32(0);
+        expected.put_i32(1);
+        expected.extend_from_slice(b"_pq_.bogus\0");
+        expected.extend_from_slice(&responses::auth_cleartext());
+        assert_eq!(buffers.outbox(), &expected[..]);
+    }
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
@@ -1,3 +1,6 @@
+pub mod channel_binding;
+pub mod client_limit;
+pub mod client_registry;
 pub mod connection;
 pub mod sequence_tracker;
 
@@ -5,6 +8,12 @@ pub(crate) mod buffers;
 pub(crate) mod context;
 pub(crate) mod handlers;
 pub(crate) mod proxy_responses;
+pub(crate) mod query_log;
+pub(crate) mod scram;
+pub(crate) mod session_state;
+pub(crate) mod setup_timings;
 pub(crate) mod transport;
 
+pub use client_limit::{ClientLimiter, ClientSlot, reject_too_many_clients};
+pub use client_registry::{ClientInfo, clients_snapshot};
 pub use connection::FrontendConnection;
@@ -3,6 +3,7 @@
 
 use bytes::{BufMut, Bytes, BytesMut};
 
+use crate::frontend::context::FrontendContext;
 use crate::shared_types::{BackendIdentity, ReadyStatus};
 
 // -----------------------------------------------------------------------------
@@ -28,6 +29,33 @@ pub(crate) fn auth_cleartext() -> Bytes {
     b.freeze()
 }
 
+/// AuthenticationSASL: each mechanism NUL-terminated, then one more NUL to
+/// end the list.
+pub(crate) fn auth_sasl(mechanisms: &[&str]) -> Bytes {
+    let list_len: usize = mechanisms.iter().map(|m| m.len() + 1).sum::<usize>() + 1;
+    let payload_len = 4 + 4 + list_len;
+    let mut b = BytesMut::with_capacity(1 + payload_len);
+    b.put_u8(b'R');
+    b.put_u32(payload_len as u32);
+    b.put_i32(10);
+    for mechanism in mechanisms {
+        b.extend_from_slice(mechanism.as_bytes());
+        b.put_u8(0);
+    }
+    b.put_u8(0);
+    b.freeze()
+}
+
+/// AuthenticationSASLContinue (`11`) or AuthenticationSASLFinal (`12`).
+pub(crate) fn auth_sasl_data(code: i32, data: &[u8]) -> Bytes {
+    let mut b = BytesMut::with_capacity(1 + 4 + 4 + data.len());
+    b.put_u8(b'R');
+    b.put_u32((4 + 4 + data.len()) as u32);
+    b.put_i32(code);
+    b.extend_from_slice(data);
+    b.freeze()
+}
+
 pub(crate) fn auth_ok() -> Bytes {
     let mut b = BytesMut::with_capacity(1 + 4 + 4);
     b.put_u8(b'R');
@@ -50,6 +78,10 @@ pub(crate) fn param_status(name: &str, value: &str) -> Bytes {
     b.freeze()
 }
 
+pub(crate) fn empty_query_response() -> Bytes {
+    Bytes::from_static(&[b'I', 0, 0, 0, 4])
+}
+
 pub(crate) fn ready_with_status(status: ReadyStatus) -> Bytes {
     let mut b = BytesMut::with_capacity(1 + 4 + 1);
     b.put_u8(b'Z');
@@ -58,6 +90,23 @@ pub(crate) fn ready_with_status(status: ReadyStatus) -> Bytes {
     b.freeze()
 }
 
+/// ReadyForQuery for a reply the proxy made up: the transaction state the
+/// backend last reported, which an error from the proxy itself leaves as it
+/// was. Without a backend there's no transaction, so it's `I`.
+pub(crate) fn ready_matching(context: &FrontendContext) -> Bytes {
+    ready_with_status(context.ready_status)
+}
+
+pub(crate) fn command_complete(tag: &str) -> Bytes {
+    let payload_len = 4 + tag.len() + 1;
+    let mut b = BytesMut::with_capacity(1 + payload_len);
+    b.put_u8(b'C');
+    b.put_u32(payload_len as u32);
+    b.extend_from_slice(tag.as_bytes());
+    b.put_u8(0);
+    b.freeze()
+}
+
 pub(crate) fn parse_complete() -> Bytes {
     let mut b = BytesMut::with_capacity(1 + 4);
     b.put_u8(b'1');
@@ -65,6 +114,23 @@ pub(crate) fn parse_complete() -> Bytes {
     b.freeze()
 }
 
+/// Tells a client asking for a newer 3.x minor which minor we speak and which
+/// of its `_pq_.*` options were not recognised.
+pub(crate) fn negotiate_protocol_version(newest_minor: i32, unsupported: &[&str]) -> Bytes {
+    let options_len: usize = unsupported.iter().map(|o| o.len() + 1).sum();
+    let payload_len = 4 + 4 + 4 + options_len;
+    let mut b = BytesMut::with_capacity(1 + payload_len);
+    b.put_u8(b'v');
+    b.put_u32(payload_len as u32);
+    b.put_i32(newest_minor);
+    b.put_i32(unsupported.len() as i32);
+    for option in unsupported {
+        b.extend_from_slice(option.as_bytes());
+        b.put_u8(0);
+    }
+    b.freeze()
+}
+
 pub(crate) fn backend_key_data(identity: BackendIdentity) -> Bytes {
     let mut b = BytesMut::with_capacity(1 + 4 + 8);
     b.put_u8(b'K');
@@ -74,5 +140,35 @@ pub(crate) fn backend_key_data(identity: BackendIdentity) -> Bytes {
     b.freeze()
 }
 
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::channel_binding::{
+        DEFAULT_SASL_MECHANISMS, SCRAM_SHA_256, SCRAM_SHA_256_PLUS, sasl_mechanisms,
+    };
+
+    #[test]
+    fn auth_sasl_lists_the_offered_mechanisms() {
+        let frame = auth_sasl(&sasl_mechanisms(DEFAULT_SASL_MECHANISMS, Some(&[0xab; 32])));
+        assert_eq!(frame[0], b'R');
+        assert_eq!(
+            u32::from_be_bytes(frame[1..5].try_into().unwrap()) as usize,
+            frame.len() - 1
+        );
+        assert_eq!(&frame[5..9], &10i32.to_be_bytes());
+        assert_eq!(&frame[9..], b"SCRAM-SHA-256-PLUS\0SCRAM-SHA-256\0\0");
+
+        // Without TLS binding data, no -PLUS.
+        let frame = auth_sasl(&sasl_mechanisms(DEFAULT_SASL_MECHANISMS, None));
+        assert_eq!(&frame[9..], b"SCRAM-SHA-256\0\0");
+        let list = String::from_utf8_lossy(&frame[9..]);
+        assert!(list.contains(SCRAM_SHA_256));
+        assert!(!list.contains(SCRAM_SHA_256_PLUS));
+    }
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
@@ -0,0 +1,153 @@
+use std::time::Instant;
+use tracing::info;
+
+use crate::config::query_log::QueryLogConfig;
+use crate::parser::{self, StatementType};
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Longest statement text written to the log, in bytes.
+const MAX_STATEMENT_LEN: usize = 1024;
+
+/// Logged in place of SQL that can't be normalized, so literals never leak.
+const UNPARSEABLE_STATEMENT: &str = "<unparseable>";
+
+// -----------------------------------------------------------------------------
+// ----- QuerySample -----------------------------------------------------------
+
+/// A query picked for the query log, timed until its ReadyForQuery.
+#[derive(Debug)]
+pub(crate) struct QuerySample {
+    started: Instant,
+    statement: String,
+    statement_type: StatementType,
+}
+
+impl QuerySample {
+    /// `None` unless the log is on and this query won the sampling roll.
+    pub(crate) fn start(config: &QueryLogConfig, query: &str) -> Option<Box<Self>> {
+        if !config.should_sample() {
+            return None;
+        }
+
+        let statement_type = parser::parse(query)
+            .map(|parsed| parsed.statement_type)
+            .unwrap_or(StatementType::Other);
+
+        let statement = if config.log_parameters {
+            query.to_string()
+        } else {
+            parser::normalize(query).unwrap_or_else(|| UNPARSEABLE_STATEMENT.to_string())
+        };
+
+        Some(Box::new(Self {
+            started: Instant::now(),
+            statement: truncate(statement),
+            statement_type,
+        }))
+    }
+
+    /// Logs the query if it ran for at least `min_duration`; returns whether
+    /// it did.
+    pub(crate) fn finish(&self, config: &QueryLogConfig, username: Option<&str>) -> bool {
+        let elapsed = self.started.elapsed();
+        if elapsed < config.min_duration {
+            return false;
+        }
+
+        info!(
+            username = username.unwrap_or(""),
+            statement_type = ?self.statement_type,
+            duration_ms = elapsed.as_secs_f64() * 1000.0,
+            statement = %self.statement,
+            "query"
+        );
+        true
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+fn truncate(mut statement: String) -> String {
+    if statement.len() <= MAX_STATEMENT_LEN {
+        return statement;
+    }
+
+    let mut end = MAX_STATEMENT_LEN;
+    while !statement.is_char_boundary(end) {
+        end -= 1;
+    }
+    statement.truncate(end);
+    statement.push_str("...");
+    statement
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn enabled(min_duration: Duration) -> QueryLogConfig {
+        QueryLogConfig {
+            enabled: true,
+            min_duration,
+            ..QueryLogConfig::default()
+        }
+    }
+
+    #[test]
+    fn fast_query_is_not_logged() {
+        let config = enabled(Duration::from_secs(60));
+        let sample = QuerySample::start(&config, "SELECT 1").expect("sampled");
+        assert!(!sample.finish(&config, Some("pgcrab")));
+    }
+
+    #[test]
+    fn slow_query_is_logged() {
+        let config = enabled(Duration::from_millis(20));
+        let mut sample = QuerySample::start(&config, "SELECT 1").expect("sampled");
+        sample.started -= Duration::from_millis(50);
+        assert!(sample.finish(&config, Some("pgcrab")));
+    }
+
+    #[test]
+    fn literals_are_redacted_by_default() {
+        let sql = "SELECT * FROM users WHERE email = 'crab@example.com' AND id = 42";
+        let config = enabled(Duration::ZERO);
+        let sample = QuerySample::start(&config, sql).expect("sampled");
+        assert_eq!(sample.statement_type, StatementType::Select);
+        assert!(!sample.statement.contains("crab@example.com"));
+        assert!(!sample.statement.contains("42"));
+        assert!(sample.statement.contains("$1"));
+        assert!(sample.statement.contains("$2"));
+
+        let config = QueryLogConfig {
+            log_parameters: true,
+            ..config
+        };
+        let sample = QuerySample::start(&config, sql).expect("sampled");
+        assert_eq!(sample.statement, sql);
+    }
+
+    #[test]
+    fn disabled_log_samples_nothing() {
+        let config = QueryLogConfig::default();
+        assert!(QuerySample::start(&config, "SELECT 1").is_none());
+    }
+
+    #[test]
+    fn long_statements_are_truncated() {
+        let sql = format!("SELECT {}", "x, ".repeat(1000));
+        let statement = truncate(sql);
+        assert_eq!(statement.len(), MAX_STATEMENT_LEN + 3);
+        assert!(statement.ends_with("..."));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,416 @@
+use std::fmt;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::frontend::channel_binding::{ChannelBindingError, Gs2Header, sasl_mechanisms};
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Postgres' default `scram_iterations`.
+const ITERATIONS: u32 = 4096;
+const SALT_LEN: usize = 16;
+/// Raw bytes of our half of the nonce, as Postgres sends.
+const NONCE_LEN: usize = 18;
+
+// -----------------------------------------------------------------------------
+// ----- ScramExchange ---------------------------------------------------------
+
+/// The server side of one SCRAM-SHA-256 (RFC 5802, RFC 7677) exchange,
+/// with `tls-server-end-point` channel binding when the client is on TLS.
+/// The password is the user's plaintext one from `[[users]]`, salted
+/// afresh for each exchange.
+pub(crate) struct ScramExchange {
+    salted_password: [u8; 32],
+    salt: [u8; SALT_LEN],
+    /// No such user: the exchange runs to the end and then fails, so the
+    /// client can't tell a bad user from a bad password.
+    mock: bool,
+    end_point: Option<Vec<u8>>,
+    /// What AuthenticationSASL offered this connection.
+    mechanisms: Vec<&'static str>,
+    state: State,
+}
+
+impl fmt::Debug for ScramExchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScramExchange")
+            .field("mock", &self.mock)
+            .field("bound", &self.end_point.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+enum State {
+    Initial,
+    Continued {
+        client_first: String,
+        server_first: String,
+        nonce: String,
+    },
+    Done,
+}
+
+// -----------------------------------------------------------------------------
+// ----- ScramExchange: Static -------------------------------------------------
+
+impl ScramExchange {
+    /// `password: None` for an unknown user. `end_point` is the binding
+    /// data of the certificate this connection's TLS presented; `preferred`
+    /// is `[server] sasl_mechanisms`.
+    pub(crate) fn new(
+        password: Option<&str>,
+        end_point: Option<Vec<u8>>,
+        preferred: &[&'static str],
+    ) -> Self {
+        let salt = rand::random::<[u8; SALT_LEN]>();
+        let (salted_password, mock) = match password {
+            Some(password) => (hi(&normalize(password), &salt, ITERATIONS), false),
+            None => (rand::random(), true),
+        };
+        Self {
+            salted_password,
+            salt,
+            mock,
+            mechanisms: sasl_mechanisms(preferred, end_point.as_deref()),
+            end_point,
+            state: State::Initial,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- ScramExchange: Public -------------------------------------------------
+
+impl ScramExchange {
+    /// Takes the SASLInitialResponse and returns server-first-message, for
+    /// AuthenticationSASLContinue.
+    pub(crate) fn client_first(
+        &mut self,
+        mechanism: &str,
+        message: &[u8],
+    ) -> Result<Vec<u8>, ScramError> {
+        if !matches!(self.state, State::Initial) {
+            return Err(ScramError::OutOfOrder);
+        }
+        if !self.mechanisms.contains(&mechanism) {
+            return Err(ScramError::UnsupportedMechanism(mechanism.to_string()));
+        }
+
+        let client_first = str::from_utf8(message).map_err(|_| ScramError::Malformed)?;
+        let (header, bare) = Gs2Header::parse(client_first)?;
+        header.check_mechanism(mechanism, self.end_point.as_deref())?;
+
+        // The user name is the startup message's; Postgres ignores `n=` too.
+        let mut attributes = bare.split(',');
+        if !attributes.next().is_some_and(|user| user.starts_with("n=")) {
+            return Err(ScramError::Malformed);
+        }
+        let client_nonce = attributes
+            .next()
+            .and_then(|nonce| nonce.strip_prefix("r="))
+            .filter(|nonce| is_printable(nonce))
+            .ok_or(ScramError::Malformed)?;
+
+        let nonce = format!(
+            "{client_nonce}{}",
+            STANDARD.encode(rand::random::<[u8; NONCE_LEN]>())
+        );
+        let server_first = format!("r={nonce},s={},i={ITERATIONS}", STANDARD.encode(self.salt));
+        self.state = State::Continued {
+            client_first: client_first.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+        };
+        Ok(server_first.into_bytes())
+    }
+
+    /// Takes the SASLResponse and, if the proof checks out, returns
+    /// server-final-message, for AuthenticationSASLFinal.
+    pub(crate) fn client_final(&mut self, message: &[u8]) -> Result<Vec<u8>, ScramError> {
+        let State::Continued {
+            client_first,
+            server_first,
+            nonce,
+        } = std::mem::replace(&mut self.state, State::Done)
+        else {
+            return Err(ScramError::OutOfOrder);
+        };
+
+        let client_final = str::from_utf8(message).map_err(|_| ScramError::Malformed)?;
+        let (without_proof, proof) = client_final
+            .rsplit_once(",p=")
+            .ok_or(ScramError::Malformed)?;
+        let mut attributes = without_proof.split(',');
+        let channel_binding = attributes
+            .next()
+            .and_then(|c| c.strip_prefix("c="))
+            .ok_or(ScramError::Malformed)?;
+        let final_nonce = attributes
+            .next()
+            .and_then(|r| r.strip_prefix("r="))
+            .ok_or(ScramError::Malformed)?;
+
+        let (header, client_first_bare) = Gs2Header::parse(&client_first)?;
+        header.verify(channel_binding, self.end_point.as_deref())?;
+        if final_nonce != nonce {
+            return Err(ScramError::NonceMismatch);
+        }
+        let proof: [u8; 32] = STANDARD
+            .decode(proof)
+            .ok()
+            .and_then(|proof| proof.try_into().ok())
+            .ok_or(ScramError::Malformed)?;
+
+        let auth_message = format!("{client_first_bare},{server_first},{without_proof}");
+        let client_key = hmac(&self.salted_password, b"Client Key");
+        let stored_key: [u8; 32] = Sha256::digest(client_key).into();
+        let signature = hmac(&stored_key, auth_message.as_bytes());
+        let mut recovered = proof;
+        for (byte, mask) in recovered.iter_mut().zip(signature) {
+            *byte ^= mask;
+        }
+        if self.mock || <[u8; 32]>::from(Sha256::digest(recovered)) != stored_key {
+            return Err(ScramError::InvalidProof);
+        }
+
+        let server_key = hmac(&self.salted_password, b"Server Key");
+        let server_signature = hmac(&server_key, auth_message.as_bytes());
+        Ok(format!("v={}", STANDARD.encode(server_signature)).into_bytes())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+/// `Hi()` from RFC 5802: PBKDF2 with HMAC-SHA-256 and one output block.
+fn hi(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut block = [0u8; 32];
+    let mut previous = hmac(password, &[salt, &1u32.to_be_bytes()].concat());
+    block.copy_from_slice(&previous);
+    for _ in 1..iterations {
+        previous = hmac(password, &previous);
+        for (byte, next) in block.iter_mut().zip(previous) {
+            *byte ^= next;
+        }
+    }
+    block
+}
+
+/// SASLprep, as Postgres applies it; a password it rejects is used as is.
+fn normalize(password: &str) -> Vec<u8> {
+    match stringprep::saslprep(password) {
+        Ok(prepared) => prepared.into_owned().into_bytes(),
+        Err(_) => password.as_bytes().to_vec(),
+    }
+}
+
+/// RFC 5802 `printable`: visible ASCII other than `,`.
+fn is_printable(nonce: &str) -> bool {
+    !nonce.is_empty()
+        && nonce
+            .bytes()
+            .all(|b| (0x21..=0x7e).contains(&b) && b != b',')
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub(crate) enum ScramError {
+    #[error("malformed SCRAM message")]
+    Malformed,
+
+    #[error("SASL authentication mechanism \"{0}\" is not supported")]
+    UnsupportedMechanism(String),
+
+    #[error("SCRAM message arrived out of order")]
+    OutOfOrder,
+
+    #[error(transparent)]
+    ChannelBinding(#[from] ChannelBindingError),
+
+    #[error("SCRAM nonce does not match")]
+    NonceMismatch,
+
+    #[error("SCRAM proof does not match")]
+    InvalidProof,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use postgres_protocol::authentication::sasl::{ChannelBinding, ScramSha256};
+
+    use super::*;
+    use crate::frontend::channel_binding::{
+        DEFAULT_SASL_MECHANISMS, SCRAM_SHA_256, SCRAM_SHA_256_PLUS,
+    };
+
+    const END_POINT: &[u8] = &[0xab; 32];
+
+    /// Runs `client` against a server expecting `password`; `Ok` carries
+    /// the client's check of our server-final-message.
+    fn exchange(
+        server: &mut ScramExchange,
+        mechanism: &str,
+        mut client: ScramSha256,
+    ) -> Result<std::io::Result<()>, ScramError> {
+        let server_first = server.client_first(mechanism, client.message())?;
+        client
+            .update(&server_first)
+            .expect("client takes server-first");
+        let server_final = server.client_final(client.message())?;
+        Ok(client.finish(&server_final))
+    }
+
+    #[test]
+    fn the_right_password_authenticates_both_ways() {
+        let mut server = ScramExchange::new(Some("s3cret"), None, DEFAULT_SASL_MECHANISMS);
+        assert_eq!(server.mechanisms, [SCRAM_SHA_256]);
+        let client = ScramSha256::new(b"s3cret", ChannelBinding::unsupported());
+        assert!(
+            exchange(&mut server, SCRAM_SHA_256, client)
+                .unwrap()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn a_wrong_password_or_unknown_user_is_refused() {
+        let mut server = ScramExchange::new(Some("s3cret"), None, DEFAULT_SASL_MECHANISMS);
+        let client = ScramSha256::new(b"guess", ChannelBinding::unsupported());
+        assert_eq!(
+            exchange(&mut server, SCRAM_SHA_256, client).unwrap_err(),
+            ScramError::InvalidProof
+        );
+
+        let mut server = ScramExchange::new(None, None, DEFAULT_SASL_MECHANISMS);
+        let client = ScramSha256::new(b"s3cret", ChannelBinding::unsupported());
+        assert_eq!(
+            exchange(&mut server, SCRAM_SHA_256, client).unwrap_err(),
+            ScramError::InvalidProof
+        );
+    }
+
+    #[test]
+    fn tls_clients_bind_to_our_certificate() {
+        let mut server = ScramExchange::new(
+            Some("s3cret"),
+            Some(END_POINT.to_vec()),
+            DEFAULT_SASL_MECHANISMS,
+        );
+        assert_eq!(server.mechanisms, [SCRAM_SHA_256_PLUS, SCRAM_SHA_256]);
+        let client = ScramSha256::new(
+            b"s3cret",
+            ChannelBinding::tls_server_end_point(END_POINT.to_vec()),
+        );
+        assert!(
+            exchange(&mut server, SCRAM_SHA_256_PLUS, client)
+                .unwrap()
+                .is_ok()
+        );
+
+        // A proxy in the middle presents a different certificate.
+        let mut server = ScramExchange::new(
+            Some("s3cret"),
+            Some(END_POINT.to_vec()),
+            DEFAULT_SASL_MECHANISMS,
+        );
+        let client = ScramSha256::new(
+            b"s3cret",
+            ChannelBinding::tls_server_end_point(vec![0xcd; 32]),
+        );
+        assert_eq!(
+            exchange(&mut server, SCRAM_SHA_256_PLUS, client).unwrap_err(),
+            ScramError::ChannelBinding(ChannelBindingError::Mismatch)
+        );
+    }
+
+    #[test]
+    fn only_configured_mechanisms_are_accepted() {
+        let mut server =
+            ScramExchange::new(Some("s3cret"), Some(END_POINT.to_vec()), &[SCRAM_SHA_256]);
+        assert_eq!(server.mechanisms, [SCRAM_SHA_256]);
+        let client = ScramSha256::new(
+            b"s3cret",
+            ChannelBinding::tls_server_end_point(END_POINT.to_vec()),
+        );
+        assert_eq!(
+            exchange(&mut server, SCRAM_SHA_256_PLUS, client).unwrap_err(),
+            ScramError::UnsupportedMechanism(SCRAM_SHA_256_PLUS.to_string())
+        );
+    }
+
+    #[test]
+    fn plus_needs_tls_and_messages_come_in_order() {
+        let mut server = ScramExchange::new(Some("s3cret"), None, DEFAULT_SASL_MECHANISMS);
+        let client = ScramSha256::new(b"s3cret", ChannelBinding::unrequested());
+        assert_eq!(
+            server.client_first(SCRAM_SHA_256_PLUS, client.message()),
+            Err(ScramError::UnsupportedMechanism(
+                SCRAM_SHA_256_PLUS.to_string()
+            ))
+        );
+        assert_eq!(
+            server.client_final(b"c=biws,r=abc,p=AAAA"),
+            Err(ScramError::OutOfOrder)
+        );
+
+        // Client-first needs a user name, even an empty one, then a nonce.
+        let mut server = ScramExchange::new(Some("s3cret"), None, DEFAULT_SASL_MECHANISMS);
+        assert_eq!(
+            server.client_first(SCRAM_SHA_256, b"n,,n=,r="),
+            Err(ScramError::Malformed)
+        );
+        assert_eq!(
+            server.client_first(SCRAM_SHA_256, b"n,,r=abc"),
+            Err(ScramError::Malformed)
+        );
+    }
+
+    #[test]
+    fn hi_matches_rfc_7677() {
+        // The SCRAM-SHA-256 example: user "user", password "pencil".
+        let salt = STANDARD.decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let mut server = ScramExchange {
+            salted_password: hi(b"pencil", &salt, ITERATIONS),
+            salt: salt.try_into().unwrap(),
+            mock: false,
+            end_point: None,
+            mechanisms: vec![SCRAM_SHA_256],
+            state: State::Continued {
+                client_first: "n,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_string(),
+                server_first: "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                               s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"
+                    .to_string(),
+                nonce: "rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0".to_string(),
+            },
+        };
+        let server_final = server
+            .client_final(
+                b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                  p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+            )
+            .unwrap();
+        assert_eq!(
+            server_final,
+            b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="
+        );
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -51,8 +51,13 @@ impl SequenceTracker {
         self.frames.push_back(FrameSummary { len, message_type });
     }
 
-    pub fn take_until_flush(&mut self, stage: AuthStage) -> Option<usize> {
-        let boundary = self.find_flush_boundary(stage)?;
+    /// During COPY IN every complete frame is taken at once: CopyData has no
+    /// statement boundaries and the backend waits on it.
+    pub fn take_until_flush(&mut self, stage: AuthStage, copy_in: bool) -> Option<usize> {
+        let boundary = match stage {
+            AuthStage::Ready if copy_in => self.find_flush_boundary_copy_in()?,
+            _ => self.find_flush_boundary(stage)?,
+        };
         self.frames.drain(0..boundary.frames_to_flush);
         Some(boundary.bytes_to_flush)
     }
@@ -108,6 +113,8 @@ impl SequenceTracker {
                 MessageType::Flush => true,
                 MessageType::Terminate => true,
                 MessageType::Query => true,
+                MessageType::CopyDone => true,
+                MessageType::CopyFail => true,
                 _ => false,
             };
             let is_too_large =
@@ -121,6 +128,56 @@ impl SequenceTracker {
         }
         None
     }
+
+    fn find_flush_boundary_copy_in(&self) -> Option<FlushBoundary> {
+        if self.frames.is_empty() {
+            return None;
+        }
+
+        Some(FlushBoundary {
+            frames_to_flush: self.frames.len(),
+            bytes_to_flush: self.len(),
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_data_waits_for_a_boundary_outside_copy_in() {
+        let mut tracker = SequenceTracker::new();
+        tracker.push(MessageType::CopyData, 20);
+        tracker.push(MessageType::CopyData, 20);
+
+        assert_eq!(tracker.take_until_flush(AuthStage::Ready, false), None);
+    }
+
+    #[test]
+    fn copy_in_takes_every_complete_frame() {
+        let mut tracker = SequenceTracker::new();
+        tracker.push(MessageType::CopyData, 20);
+        tracker.push(MessageType::CopyData, 30);
+
+        assert_eq!(tracker.take_until_flush(AuthStage::Ready, true), Some(50));
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.take_until_flush(AuthStage::Ready, true), None);
+    }
+
+    #[test]
+    fn copy_done_and_copy_fail_end_a_sequence() {
+        let mut tracker = SequenceTracker::new();
+        tracker.push(MessageType::CopyData, 20);
+        tracker.push(MessageType::CopyDone, 5);
+        tracker.push(MessageType::CopyFail, 6);
+
+        assert_eq!(tracker.take_until_flush(AuthStage::Ready, false), Some(25));
+        assert_eq!(tracker.take_until_flush(AuthStage::Ready, false), Some(6));
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+
+use crate::gateway::GatewaySession;
+
+// -----------------------------------------------------------------------------
+// ----- SettingChange ---------------------------------------------------------
+
+/// A session-level `SET`/`RESET` sent as a simple Query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SettingChange {
+    /// `SET name TO value`; `value` is kept as the client wrote it.
+    Set { name: String, value: String },
+    /// `RESET name` or `SET name TO DEFAULT`.
+    Reset(String),
+    /// `RESET ALL` or `DISCARD ALL`.
+    ResetAll,
+}
+
+impl SettingChange {
+    /// `None` for anything else, including `SET LOCAL` and `SET TRANSACTION`
+    /// (scoped to the transaction) and `SET ROLE`/`SET SESSION
+    /// AUTHORIZATION`, which are not replayed on other backends. So is a
+    /// statement with a comment in it: kept in the value, a `--` would
+    /// comment out the rest of the replay.
+    pub(crate) fn parse(query: &str) -> Option<Self> {
+        let statement = query.trim().trim_end_matches(';').trim_end();
+        if statement.contains(';') || statement.contains("--") || statement.contains("/*") {
+            return None;
+        }
+
+        let (command, rest) = split_word(statement);
+        if command.eq_ignore_ascii_case("RESET") {
+            return match rest {
+                name if name.eq_ignore_ascii_case("ALL") => Some(Self::ResetAll),
+                name if is_setting_name(name) => Some(Self::Reset(name.to_ascii_lowercase())),
+                _ => None,
+            };
+        }
+        if command.eq_ignore_ascii_case("DISCARD") {
+            return rest.eq_ignore_ascii_case("ALL").then_some(Self::ResetAll);
+        }
+        if !command.eq_ignore_ascii_case("SET") {
+            return None;
+        }
+
+        let (mut word, mut rest) = split_word(rest);
+        if word.eq_ignore_ascii_case("SESSION") {
+            (word, rest) = split_word(rest);
+        }
+        for scoped in [
+            "LOCAL",
+            "TRANSACTION",
+            "ROLE",
+            "AUTHORIZATION",
+            "CHARACTERISTICS",
+        ] {
+            if word.eq_ignore_ascii_case(scoped) {
+                return None;
+            }
+        }
+
+        if word.eq_ignore_ascii_case("TIME") {
+            let (zone, value) = split_word(rest);
+            if !zone.eq_ignore_ascii_case("ZONE") {
+                return None;
+            }
+            return Self::setting("timezone".to_string(), value);
+        }
+
+        // `name=value` needs no spaces around the `=`.
+        let (name, value) = match word.split_once('=') {
+            Some((name, value)) => (name, format!("={value} {rest}")),
+            None => (word, rest.to_string()),
+        };
+        if !is_setting_name(name) {
+            return None;
+        }
+        let value = match split_word(&value) {
+            ("", _) => return None,
+            (to, value) if to.eq_ignore_ascii_case("TO") => value,
+            _ => value.strip_prefix('=')?,
+        };
+        Self::setting(name.to_ascii_lowercase(), value)
+    }
+
+    fn setting(name: String, value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        if value.eq_ignore_ascii_case("DEFAULT") {
+            return Some(Self::Reset(name));
+        }
+        Some(Self::Set {
+            name,
+            value: value.to_string(),
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- SessionState ----------------------------------------------------------
+
+/// Session settings a transaction-mode client changed outside a
+/// transaction, and the startup parameters it was allowed to pass. Each
+/// checkout gets a backend reset by the pool, so these are replayed on it
+/// before the client's first query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SessionState {
+    /// Raw values from the startup message. Like on a direct connection,
+    /// they're the session's defaults: `RESET` doesn't drop them.
+    startup_params: BTreeMap<String, String>,
+    settings: BTreeMap<String, String>,
+}
+
+impl SessionState {
+    pub(crate) fn set_startup_param(&mut self, name: &str, value: &str) {
+        self.startup_params
+            .insert(name.to_ascii_lowercase(), value.to_string());
+    }
+
+    /// Applies a change the backend accepted.
+    pub(crate) fn apply(&mut self, change: SettingChange) {
+        match change {
+            SettingChange::Set { name, value } => {
+                self.settings.insert(name, value);
+            }
+            SettingChange::Reset(name) => {
+                self.settings.remove(&name);
+            }
+            SettingChange::ResetAll => self.settings.clear(),
+        }
+    }
+
+    /// The `search_path` the replay leaves the backend with, if the client
+    /// set one; `None` keeps the backend's own.
+    pub(crate) fn search_path(&self) -> Option<&str> {
+        self.settings
+            .get("search_path")
+            .or_else(|| self.startup_params.get("search_path"))
+            .map(String::as_str)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.startup_params.is_empty() && self.settings.is_empty()
+    }
+
+    /// One Query setting everything captured, in name order, or `""` if
+    /// there's nothing to set. Startup values go through `set_config`,
+    /// which reads them the way the startup message does (a `search_path`
+    /// list stays a list), ahead of the `SET`s that override them; one the
+    /// backend already `reported` with the same value is left out.
+    pub(crate) fn replay_query<'a>(&self, reported: impl Fn(&str) -> Option<&'a str>) -> String {
+        let calls = self
+            .startup_params
+            .iter()
+            .filter(|(name, value)| reported(name) != Some(value.as_str()))
+            .map(|(name, value)| {
+                format!(
+                    "pg_catalog.set_config({}, {}, false)",
+                    quote_literal(name),
+                    quote_literal(value)
+                )
+            })
+            .collect::<Vec<_>>();
+        let startup = (!calls.is_empty()).then(|| format!("SELECT {};", calls.join(", ")));
+        let settings = self
+            .settings
+            .iter()
+            .map(|(name, value)| format!("SET {name} TO {value};"));
+        startup
+            .into_iter()
+            .chain(settings)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Runs the captured settings on a freshly checked-out backend. With
+    /// only startup values to set, and the backend reporting them all
+    /// already (e.g. a matching `client_encoding`), that's no round trip.
+    pub(crate) async fn replay_into(&self, session: &mut GatewaySession) -> Result<(), String> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let backend = session.backend();
+        let query = self.replay_query(|name| backend.reported(name));
+        if query.is_empty() {
+            return Ok(());
+        }
+        backend
+            .run_silently(&query, "session replay")
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+/// First whitespace-separated word and the trimmed remainder.
+fn split_word(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    match input.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (input, ""),
+    }
+}
+
+/// An escape string literal, read the same whatever the backend's
+/// `standard_conforming_strings`.
+fn quote_literal(value: &str) -> String {
+    format!("E'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
+}
+
+/// Plain or dotted (`myapp.tenant`) identifier; anything else isn't
+/// replayed verbatim.
+fn is_setting_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'.')
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(name: &str, value: &str) -> Option<SettingChange> {
+        Some(SettingChange::Set {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    #[test]
+    fn parses_session_settings() {
+        assert_eq!(
+            SettingChange::parse("SET statement_timeout = '5s';"),
+            set("statement_timeout", "'5s'")
+        );
+        assert_eq!(
+            SettingChange::parse("set session Search_Path to tenant, public"),
+            set("search_path", "tenant, public")
+        );
+        assert_eq!(
+            SettingChange::parse("SET work_mem='64MB'"),
+            set("work_mem", "'64MB'")
+        );
+        assert_eq!(
+            SettingChange::parse("SET TIME ZONE 'UTC'"),
+            set("timezone", "'UTC'")
+        );
+        assert_eq!(
+            SettingChange::parse("SET myapp.tenant TO DEFAULT"),
+            Some(SettingChange::Reset("myapp.tenant".to_string()))
+        );
+        assert_eq!(
+            SettingChange::parse("reset statement_timeout"),
+            Some(SettingChange::Reset("statement_timeout".to_string()))
+        );
+        assert_eq!(
+            SettingChange::parse("RESET ALL"),
+            Some(SettingChange::ResetAll)
+        );
+        assert_eq!(
+            SettingChange::parse("DISCARD ALL;"),
+            Some(SettingChange::ResetAll)
+        );
+    }
+
+    #[test]
+    fn ignores_scoped_and_unrelated_statements() {
+        for query in [
+            "SET LOCAL statement_timeout = '5s'",
+            "SET TRANSACTION ISOLATION LEVEL SERIALIZABLE",
+            "SET ROLE admin",
+            "SET SESSION AUTHORIZATION admin",
+            "SET statement_timeout = '5s'; SELECT 1",
+            "SET \"odd name\" = 1",
+            "SET statement_timeout",
+            "SELECT 1",
+            "DISCARD PLANS",
+        ] {
+            assert_eq!(SettingChange::parse(query), None, "{query}");
+        }
+    }
+
+    #[test]
+    fn comments_leave_the_setting_untracked() {
+        for query in [
+            "SET search_path TO public -- note",
+            "SET search_path TO public /* note */",
+            "SET statement_timeout = '5s' -- note;",
+        ] {
+            assert_eq!(SettingChange::parse(query), None, "{query}");
+        }
+
+        // Tracked, it would have commented out the settings after it.
+        let mut state = SessionState::default();
+        for query in ["SET search_path TO public -- note", "SET timezone TO 'UTC'"] {
+            if let Some(change) = SettingChange::parse(query) {
+                state.apply(change);
+            }
+        }
+        assert_eq!(state.replay_query(|_| None), "SET timezone TO 'UTC';");
+    }
+
+    #[test]
+    fn applies_changes_in_order() {
+        let mut state = SessionState::default();
+        for query in [
+            "SET statement_timeout = '5s'",
+            "SET search_path TO tenant",
+            "SET statement_timeout = '10s'",
+            "RESET search_path",
+        ] {
+            state.apply(SettingChange::parse(query).unwrap());
+        }
+        assert_eq!(
+            state.replay_query(|_| None),
+            "SET statement_timeout TO '10s';"
+        );
+
+        state.apply(SettingChange::ResetAll);
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn startup_params_replay_first_and_survive_reset_all() {
+        let mut state = SessionState::default();
+        state.set_startup_param("search_path", "\"$user\", public");
+        state.set_startup_param("application_name", "crab's app");
+        state.apply(SettingChange::parse("SET search_path TO tenant").unwrap());
+        assert_eq!(
+            state.replay_query(|_| None),
+            "SELECT pg_catalog.set_config(E'application_name', E'crab''s app', false), \
+             pg_catalog.set_config(E'search_path', E'\"$user\", public', false); \
+             SET search_path TO tenant;"
+        );
+
+        state.apply(SettingChange::ResetAll);
+        assert!(!state.is_empty());
+        assert!(!state.replay_query(|_| None).contains("SET search_path"));
+    }
+
+    #[test]
+    fn startup_params_the_backend_reports_are_not_replayed() {
+        let mut state = SessionState::default();
+        state.set_startup_param("client_encoding", "UTF8");
+        state.set_startup_param("application_name", "psql");
+        let reported = |name: &str| match name {
+            "client_encoding" => Some("UTF8"),
+            "application_name" => Some("pgcrab:app"),
+            _ => None,
+        };
+        assert_eq!(
+            state.replay_query(reported),
+            "SELECT pg_catalog.set_config(E'application_name', E'psql', false);"
+        );
+
+        state.set_startup_param("application_name", "pgcrab:app");
+        assert_eq!(state.replay_query(reported), "");
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
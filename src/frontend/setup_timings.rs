@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::backend::ConnectTimings;
+
+// -----------------------------------------------------------------------------
+// ----- SetupTimings ----------------------------------------------------------
+
+/// Where a client connection's setup time went. The backend is only checked
+/// out for the first query, so that's when the breakdown is logged.
+#[derive(Debug)]
+pub(crate) struct SetupTimings {
+    accepted: Instant,
+    tls: Duration,
+    auth: Option<Duration>,
+    reported: bool,
+}
+
+// -----------------------------------------------------------------------------
+// ----- SetupTimings: Static --------------------------------------------------
+
+impl SetupTimings {
+    pub(crate) fn new() -> Self {
+        Self {
+            accepted: Instant::now(),
+            tls: Duration::ZERO,
+            auth: None,
+            reported: false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- SetupTimings: Public --------------------------------------------------
+
+impl SetupTimings {
+    pub(crate) fn record_tls(&mut self, handshake: Duration) {
+        self.tls = handshake;
+    }
+
+    /// Accept to AuthenticationOk, less the TLS handshake.
+    pub(crate) fn record_auth(&mut self) {
+        self.auth = Some(self.accepted.elapsed().saturating_sub(self.tls));
+    }
+
+    /// Logs the breakdown once, after auth. `backend` is `None` when the
+    /// checkout reused a pooled backend, which took no time to open.
+    pub(crate) fn report(&mut self, backend: Option<ConnectTimings>) {
+        let Some(auth) = self.auth else {
+            return;
+        };
+        if std::mem::replace(&mut self.reported, true) {
+            return;
+        }
+
+        let opened = backend.unwrap_or_default();
+        debug!(
+            tls_ms = ms(self.tls),
+            auth_ms = ms(auth),
+            backend_connect_ms = ms(opened.connect),
+            backend_auth_ms = ms(opened.startup),
+            backend_reused = backend.is_none(),
+            "connection setup"
+        );
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use tracing::field::{Field, Visit};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::{Layer, registry};
+
+    type Fields = Vec<(String, f64)>;
+
+    #[derive(Clone, Default)]
+    struct CapturedEvents(Arc<Mutex<Vec<Fields>>>);
+
+    struct NumericFields(Fields);
+
+    impl Visit for NumericFields {
+        fn record_f64(&mut self, field: &Field, value: f64) {
+            self.0.push((field.name().to_string(), value));
+        }
+
+        fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for CapturedEvents {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            let mut fields = NumericFields(Vec::new());
+            event.record(&mut fields);
+            self.0.lock().push(fields.0);
+        }
+    }
+
+    #[test]
+    fn setup_event_carries_each_phase_once() {
+        let mut timings = SetupTimings::new();
+        timings.record_tls(Duration::from_millis(3));
+        timings.record_auth();
+
+        let captured = CapturedEvents::default();
+        let subscriber = registry().with(captured.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            timings.report(Some(ConnectTimings {
+                connect: Duration::from_millis(5),
+                startup: Duration::from_millis(7),
+            }));
+            timings.report(None);
+        });
+
+        let events = captured.0.lock().clone();
+        assert_eq!(events.len(), 1);
+        let get = |name: &str| {
+            events[0]
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| *value)
+                .unwrap_or_else(|| panic!("missing {name}"))
+        };
+        assert!((get("tls_ms") - 3.0).abs() < 1e-6);
+        assert!(get("auth_ms") >= 0.0);
+        assert!((get("backend_connect_ms") - 5.0).abs() < 1e-6);
+        assert!((get("backend_auth_ms") - 7.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn nothing_is_reported_before_auth() {
+        let captured = CapturedEvents::default();
+        let subscriber = registry().with(captured.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            SetupTimings::new().report(None);
+        });
+        assert!(captured.0.lock().is_empty());
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
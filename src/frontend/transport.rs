@@ -1,21 +1,27 @@
 use bytes::BytesMut;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio_rustls::TlsAcceptor;
 use tokio_rustls::server::TlsStream;
 
+// -----------------------------------------------------------------------------
+// ----- ClientStream ----------------------------------------------------------
+
+/// An accepted client socket, TCP or Unix.
+pub(crate) trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> ClientStream for T {}
+
 // -----------------------------------------------------------------------------
 // ----- FrontendTransport -----------------------------------------------------
 
-#[derive(Debug)]
 pub(crate) enum FrontendTransport {
-    Plain(Option<TcpStream>),
-    Tls(TlsStream<TcpStream>),
+    Plain(Option<Box<dyn ClientStream>>),
+    Tls(Box<TlsStream<Box<dyn ClientStream>>>),
 }
 
 impl FrontendTransport {
-    pub(crate) fn new(stream: TcpStream) -> Self {
-        FrontendTransport::Plain(Some(stream))
+    pub(crate) fn new(stream: impl ClientStream) -> Self {
+        FrontendTransport::Plain(Some(Box::new(stream)))
     }
 
     pub(crate) async fn read_buf(&mut self, buf: &mut BytesMut) -> std::io::Result<usize> {
@@ -50,7 +56,7 @@ impl FrontendTransport {
         })?;
 
         let tls_stream = acceptor.accept(stream).await?;
-        *self = FrontendTransport::Tls(tls_stream);
+        *self = FrontendTransport::Tls(Box::new(tls_stream));
 
         Ok(())
     }
@@ -0,0 +1,126 @@
+use std::num::NonZeroUsize;
+
+use bytes::Bytes;
+use lru::LruCache;
+use parking_lot::Mutex;
+
+use crate::shared_types::StatementSignature;
+
+// -----------------------------------------------------------------------------
+// ----- DescribeKey -----------------------------------------------------------
+
+/// What a statement's description depends on besides the schema: the SQL,
+/// the role it runs as (`"$user"` in a `search_path` resolves to it), and
+/// the session's `search_path`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DescribeKey {
+    pub role: String,
+    pub signature: StatementSignature,
+    pub search_path: Option<String>,
+}
+
+// -----------------------------------------------------------------------------
+// ----- DescribeCache ---------------------------------------------------------
+
+/// Backend replies to statement Describes, ParameterDescription plus
+/// RowDescription or NoData, shared by every pool of one shard. DDL seen
+/// on the shard empties it; see `clear`.
+#[derive(Debug)]
+pub struct DescribeCache {
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug)]
+struct Entries {
+    replies: LruCache<DescribeKey, Bytes>,
+    /// Bumped by `clear`, so a reply requested before it isn't stored after.
+    generation: u64,
+}
+
+// -----------------------------------------------------------------------------
+// ----- DescribeCache: Public -------------------------------------------------
+
+impl DescribeCache {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            inner: Mutex::new(Entries {
+                replies: LruCache::new(capacity),
+                generation: 0,
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &DescribeKey) -> Option<Bytes> {
+        self.inner.lock().replies.get(key).cloned()
+    }
+
+    /// Current generation; pass it back to `put` with the reply.
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().generation
+    }
+
+    /// Keeps `reply` unless the cache was cleared since `generation`.
+    pub fn put(&self, key: DescribeKey, reply: Bytes, generation: u64) {
+        let mut inner = self.inner.lock();
+        if inner.generation == generation {
+            inner.replies.put(key, reply);
+        }
+    }
+
+    /// Drops every entry; returns how many there were.
+    pub fn clear(&self) -> usize {
+        let mut inner = self.inner.lock();
+        inner.generation = inner.generation.wrapping_add(1);
+        let dropped = inner.replies.len();
+        inner.replies.clear();
+        dropped
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(search_path: Option<&str>) -> DescribeKey {
+        DescribeKey {
+            role: "app".to_string(),
+            signature: StatementSignature::new("SELECT * FROM users", &[]),
+            search_path: search_path.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn entries_are_per_search_path() {
+        let cache = DescribeCache::new(NonZeroUsize::new(4).unwrap());
+        let generation = cache.generation();
+        cache.put(key(Some("tenant_a")), Bytes::from_static(b"a"), generation);
+
+        assert_eq!(
+            cache.get(&key(Some("tenant_a"))).as_deref(),
+            Some(&b"a"[..])
+        );
+        assert!(cache.get(&key(Some("tenant_b"))).is_none());
+        assert!(cache.get(&key(None)).is_none());
+    }
+
+    #[test]
+    fn replies_from_before_a_clear_are_dropped() {
+        let cache = DescribeCache::new(NonZeroUsize::new(4).unwrap());
+        let before = cache.generation();
+        cache.put(key(None), Bytes::from_static(b"old"), before);
+        assert_eq!(cache.clear(), 1);
+
+        // Described before the DDL, answered after it.
+        cache.put(key(None), Bytes::from_static(b"stale"), before);
+        assert!(cache.get(&key(None)).is_none());
+
+        cache.put(key(None), Bytes::from_static(b"new"), cache.generation());
+        assert_eq!(cache.get(&key(None)).as_deref(), Some(&b"new"[..]));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,50 @@
+use thiserror::Error;
+
+use crate::backend::BackendStartupError;
+
+// -----------------------------------------------------------------------------
+// ----- GatewayError ----------------------------------------------------------
+
+/// Why a checkout got no backend; each kind reaches the client with its own
+/// SQLSTATE.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GatewayError {
+    /// Opening a backend failed: refused, reset, TLS declined, or turned
+    /// away at startup.
+    #[error("{0}")]
+    Connect(String),
+    /// The backend refused the shard's credentials.
+    #[error("{0}")]
+    BackendAuth(String),
+    /// Connecting, startup or `on_connect` ran past the shard's timeout.
+    #[error("{0}")]
+    Timeout(String),
+    /// A new backend was opened, but one of the shard's `on_connect`
+    /// statements failed on it.
+    #[error("{0}")]
+    OnConnect(String),
+    /// The shard is paused; nothing is handed out until it's resumed.
+    #[error("{0}")]
+    Paused(String),
+    /// No backend freed up within `checkout_timeout`.
+    #[error("{0}")]
+    PoolExhausted(String),
+    /// The backend sent something startup can't follow.
+    #[error("{0}")]
+    Protocol(String),
+}
+
+impl From<BackendStartupError> for GatewayError {
+    fn from(err: BackendStartupError) -> Self {
+        match err {
+            BackendStartupError::Io(message) | BackendStartupError::Refused(message) => {
+                GatewayError::Connect(message)
+            }
+            BackendStartupError::Auth(message) => GatewayError::BackendAuth(message),
+            BackendStartupError::Protocol(message) => GatewayError::Protocol(message),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
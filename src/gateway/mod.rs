@@ -1,7 +1,17 @@
+pub mod describe_cache;
+pub mod error;
 pub mod pool;
+pub mod query_stats;
+pub mod rate_limit;
+pub mod routing;
 pub mod session;
 
-pub use pool::{GatewayPools, PoolStats, PooledConnection, ShardPool};
+pub use describe_cache::{DescribeCache, DescribeKey};
+pub use error::GatewayError;
+pub use pool::{GatewayPools, PoolKey, PoolStats, PooledConnection, ShardPool};
+pub use query_stats::QueryStats;
+pub use rate_limit::RateLimiter;
+pub use routing::{RoutingDecision, RoutingStrategy};
 pub use session::GatewaySession;
 
 // Gateway orchestration module; keep protocol-specific code in frontend/backend.
@@ -1,24 +1,71 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::num::NonZeroUsize;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
 
-use rand::seq::IteratorRandom;
-use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
-use tracing::{info, warn};
+use bytes::Bytes;
+use rand::Rng;
+use rand::seq::IndexedRandom;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore, oneshot};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{Instant, sleep, timeout, timeout_at};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::ClientConfig;
+use tracing::{debug, info, warn};
 
-use crate::backend::BackendConnection;
+use crate::backend::{BackendConnection, ConnectTimings, SilentRunError};
 use crate::config::shards::ShardRecord;
+use crate::config::users::UserRecord;
+use crate::gateway::{DescribeCache, DescribeKey, GatewayError, QueryStats};
+use crate::shared_types::StatementSignature;
+use crate::tls;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Pause between maintenance passes while the shard is healthy.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Ceiling for the pause after repeated failed top-ups.
+const MAX_MAINTENANCE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Warmup logs its progress every this many backend connects.
+const WARMUP_PROGRESS_EVERY: usize = 10;
+
+const TERMINATE: [u8; 5] = [b'X', 0, 0, 0, 4];
 
 // -----------------------------------------------------------------------------
 // ----- GatewayPools ----------------------------------------------------------
 
+/// `(shard name, backend user)`.
+pub type PoolKey = (String, String);
+
 #[derive(Debug)]
 pub struct GatewayPools {
-    pools: HashMap<String, Arc<ShardPool>>,
+    /// Every pool, keyed by shard and the role its backends log in as.
+    pools: HashMap<PoolKey, Arc<ShardPool>>,
+    /// Per shard, the pool using the shard's own `user`/`password`.
+    defaults: HashMap<String, Arc<ShardPool>>,
+    /// Shard weights for random picks; replaced by `reweight` on reload.
+    weights: parking_lot::RwLock<WeightTable>,
+    /// The same weights for just the shards of a candidate list, e.g.
+    /// `[routing] read_replicas`; built on first use, dropped by `reweight`.
+    subsets: parking_lot::RwLock<HashMap<Vec<String>, Arc<WeightTable>>>,
+}
+
+/// Shard weights laid end to end, so a weighted pick is a binary search.
+#[derive(Debug, Default)]
+struct WeightTable {
+    /// `(shard name, weight, running total through this shard)`.
+    cumulative: Vec<(String, u32, u64)>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PoolStats {
     pub name: String,
+    /// Role the pool's backends log in as.
+    pub user: String,
     pub host: String,
     pub port: u16,
     pub min: u32,
@@ -26,26 +73,214 @@ pub struct PoolStats {
     pub idle: usize,
     pub in_use: usize,
     pub available: usize,
+    /// Backend connections opened over the pool's lifetime.
+    pub created: u64,
+    /// Backend connections dropped over the pool's lifetime.
+    pub closed: u64,
+    /// `PAUSE PGCRAB POOL` is in effect: no new checkouts.
+    pub paused: bool,
+    /// Checkouts queued for a backend because the pool is at `max`.
+    pub waiters: usize,
+    /// The last attempt to open a backend, by a checkout or maintenance,
+    /// succeeded.
+    pub healthy: bool,
+    /// Moving average of query latency, from send to ReadyForQuery.
+    pub avg_latency_micros: u64,
+    /// Moving average share of queries the backend answered with an error.
+    pub error_rate: f64,
 }
 
 impl GatewayPools {
     pub fn new(shards: Vec<ShardRecord>) -> Self {
-        let mut pools = HashMap::with_capacity(shards.len());
+        Self::with_users(shards, &[], None)
+    }
+
+    /// Backends log in with `application_name = "<prefix>:<role>"`, `<role>`
+    /// being the user they log in as, so `pg_stat_activity` shows which
+    /// pooler and role own them. Pools are shared by every client mapped to
+    /// the role, so the client's own username isn't part of it.
+    pub fn with_application_name_prefix(shards: Vec<ShardRecord>, prefix: Option<&str>) -> Self {
+        Self::with_users(shards, &[], prefix)
+    }
+
+    /// Like `with_application_name_prefix`, plus one pool per shard for
+    /// every `server_username` set in `users`, logging in with that entry's
+    /// `server_password`. Clients mapped to a role only use its pools.
+    pub fn with_users(
+        shards: Vec<ShardRecord>,
+        users: &[UserRecord],
+        prefix: Option<&str>,
+    ) -> Self {
+        let roles: BTreeMap<&str, &UserRecord> = users
+            .iter()
+            .filter(|user| user.server_username_set)
+            .map(|user| (user.server_username.as_str(), user))
+            .collect();
+        let application_name = |role: &str| prefix.map(|prefix| format!("{prefix}:{role}"));
+
+        let weights = WeightTable::new(shards.iter().map(|s| (s.shard_name.as_str(), s.weight)));
+        let mut pools = HashMap::with_capacity(shards.len() * (1 + roles.len()));
+        let mut defaults = HashMap::with_capacity(shards.len());
         for shard in shards {
-            let name = shard.shard_name.clone();
-            pools.insert(name, Arc::new(ShardPool::new(shard)));
+            let describes = shard
+                .describe_cache_size
+                .and_then(NonZeroUsize::new)
+                .map(|size| Arc::new(DescribeCache::new(size)));
+            for (&role, user) in &roles {
+                if role == shard.user {
+                    continue;
+                }
+                let record = ShardRecord {
+                    user: role.to_string(),
+                    password: user.server_password.clone(),
+                    ..shard.clone()
+                };
+                let pool = ShardPool::new(record, application_name(role), describes.clone());
+                pools.insert((shard.shard_name.clone(), role.to_string()), Arc::new(pool));
+            }
+
+            let key = (shard.shard_name.clone(), shard.user.clone());
+            let pool = Arc::new(ShardPool::new(shard, application_name(&key.1), describes));
+            defaults.insert(key.0.clone(), pool.clone());
+            pools.insert(key, pool);
+        }
+
+        Self {
+            pools,
+            defaults,
+            weights: parking_lot::RwLock::new(weights),
+            subsets: parking_lot::RwLock::default(),
         }
+    }
+
+    /// Takes new shard weights, e.g. after a config reload. Shards without
+    /// a pool are ignored; pools missing from `shards` keep their weight.
+    pub fn reweight(&self, shards: &[ShardRecord]) {
+        let mut weights = self.weights.write();
+        let mut names: Vec<&String> = self.defaults.keys().collect();
+        names.sort();
+        let table = WeightTable::new(names.into_iter().map(|name| {
+            let weight = shards
+                .iter()
+                .find(|shard| &shard.shard_name == name)
+                .map_or_else(|| weights.weight(name), |shard| shard.weight);
+            (name.as_str(), weight)
+        }));
+        *weights = table;
+        self.subsets.write().clear();
+    }
 
-        Self { pools }
+    /// Empties every shard's describe cache, e.g. after a config reload,
+    /// which may follow a migration.
+    pub fn forget_describes(&self) {
+        for pool in self.defaults.values() {
+            pool.forget_describes();
+        }
     }
 
+    /// The shard's pool with its own credentials.
     pub fn get(&self, shard_name: &str) -> Option<Arc<ShardPool>> {
-        self.pools.get(shard_name).cloned()
+        self.defaults.get(shard_name).cloned()
+    }
+
+    /// The shard's pool logging in as `server_username`.
+    pub fn get_as(&self, shard_name: &str, server_username: &str) -> Option<Arc<ShardPool>> {
+        let key = (shard_name.to_string(), server_username.to_string());
+        self.pools.get(&key).cloned()
     }
 
+    /// Every pool on the shard, whatever role it logs in as.
+    pub fn shard_pools(&self, shard_name: &str) -> Vec<Arc<ShardPool>> {
+        self.pools
+            .iter()
+            .filter(|((shard, _), _)| shard == shard_name)
+            .map(|(_, pool)| pool.clone())
+            .collect()
+    }
+
+    /// The shard's pool for `server_username`, or its default pool.
+    pub fn pool_for(
+        &self,
+        shard_name: &str,
+        server_username: Option<&str>,
+    ) -> Option<Arc<ShardPool>> {
+        match server_username {
+            Some(role) => self.get_as(shard_name, role),
+            None => self.get(shard_name),
+        }
+    }
+
+    /// Like `pool_for`, picking one of `shard_names` by shard weight;
+    /// paused pools are skipped.
+    pub fn random_pool_among(
+        &self,
+        shard_names: &[String],
+        server_username: Option<&str>,
+    ) -> Option<Arc<ShardPool>> {
+        self.pick_pool(&self.subset_weights(shard_names), server_username)
+    }
+
+    /// Any pool taking checkouts, by shard weight; paused ones are skipped.
     pub fn random_pool(&self) -> Option<Arc<ShardPool>> {
-        let mut rng = rand::rng();
-        self.pools.values().choose(&mut rng).cloned()
+        self.random_pool_as(None)
+    }
+
+    /// Like `random_pool`, among the pools logging in as `server_username`;
+    /// `None` picks from the shards' own credentials.
+    pub fn random_pool_as(&self, server_username: Option<&str>) -> Option<Arc<ShardPool>> {
+        self.pick_pool(&self.weights.read(), server_username)
+    }
+
+    /// A pool by `weights`, logging in as `server_username`; paused ones
+    /// are skipped.
+    fn pick_pool(
+        &self,
+        weights: &WeightTable,
+        server_username: Option<&str>,
+    ) -> Option<Arc<ShardPool>> {
+        let total = weights.total();
+        if total > 0 {
+            let roll = rand::rng().random_range(0..total);
+            if let Some(pool) = weights
+                .pick(roll)
+                .and_then(|name| self.pool_for(name, server_username))
+                .filter(|pool| !pool.is_paused())
+            {
+                return Some(pool);
+            }
+        }
+
+        // Landed on a paused shard (or one this role has no pool on): draw
+        // again among the ones that can take the checkout.
+        let candidates: Vec<(Arc<ShardPool>, u32)> = weights
+            .cumulative
+            .iter()
+            .filter_map(|(name, weight, _)| {
+                let pool = self.pool_for(name, server_username)?;
+                Some((pool, *weight))
+            })
+            .filter(|(pool, _)| !pool.is_paused())
+            .collect();
+        pick_weighted(candidates)
+    }
+
+    /// The weight table for `shard_names`, built once per list.
+    fn subset_weights(&self, shard_names: &[String]) -> Arc<WeightTable> {
+        if let Some(table) = self.subsets.read().get(shard_names) {
+            return Arc::clone(table);
+        }
+        // Held until the table is stored, so a `reweight` can't slip in
+        // between and leave it stale.
+        let weights = self.weights.read();
+        let table = Arc::new(WeightTable::new(
+            shard_names
+                .iter()
+                .map(|name| (name.as_str(), weights.weight(name))),
+        ));
+        self.subsets
+            .write()
+            .insert(shard_names.to_vec(), Arc::clone(&table));
+        table
     }
 
     pub async fn snapshot(&self) -> Vec<PoolStats> {
@@ -53,15 +288,107 @@ impl GatewayPools {
         for pool in self.pools.values() {
             stats.push(pool.stats().await);
         }
-        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats.sort_by(|a, b| (&a.name, &a.user).cmp(&(&b.name, &b.user)));
         stats
     }
 
-    pub async fn warm_all(&self) {
+    /// Opens every pool's `min_connections`, at most `concurrency` backend
+    /// connects in flight at once across all shards, so a big config doesn't
+    /// stampede the Postgres servers it shares.
+    pub async fn warm_all(&self, concurrency: u32) {
+        let limit = Arc::new(Semaphore::new(concurrency.max(1) as usize));
+        let mut connects = JoinSet::new();
         for pool in self.pools.values() {
-            pool.warm_min().await;
+            let missing = pool.missing_idle().await;
+            if missing == 0 {
+                continue;
+            }
+            info!(
+                "warming shard {}: creating {missing} backend connections",
+                pool.shard.shard_name
+            );
+            for _ in 0..missing {
+                let pool = Arc::clone(pool);
+                let limit = Arc::clone(&limit);
+                connects.spawn(async move {
+                    let _permit = limit.acquire_owned().await;
+                    if let Err(err) = pool.open_new_connection().await {
+                        warn!(
+                            "failed to warm shard {} connection: {err}",
+                            pool.shard.shard_name
+                        );
+                        return false;
+                    }
+                    true
+                });
+            }
+        }
+
+        let total = connects.len();
+        let mut opened = 0;
+        let mut done = 0;
+        while let Some(result) = connects.join_next().await {
+            done += 1;
+            opened += usize::from(result.unwrap_or(false));
+            if done % WARMUP_PROGRESS_EVERY == 0 && done < total {
+                info!("warmup: {done}/{total} backend connects done");
+            }
+        }
+        if total > 0 {
+            info!("warmup: opened {opened}/{total} backend connections");
         }
     }
+
+    /// Starts one maintenance loop per shard; see `ShardPool::maintain`.
+    pub fn spawn_maintenance(&self) -> Vec<JoinHandle<()>> {
+        self.pools
+            .values()
+            .map(ShardPool::spawn_maintenance)
+            .collect()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- WeightTable -----------------------------------------------------------
+
+impl WeightTable {
+    fn new<'a>(weights: impl Iterator<Item = (&'a str, u32)>) -> Self {
+        let mut total = 0u64;
+        let cumulative = weights
+            .map(|(name, weight)| {
+                total += u64::from(weight);
+                (name.to_string(), weight, total)
+            })
+            .collect();
+        Self { cumulative }
+    }
+
+    fn total(&self) -> u64 {
+        self.cumulative.last().map_or(0, |(_, _, total)| *total)
+    }
+
+    /// The shard whose slice of `0..total` holds `roll`.
+    fn pick(&self, roll: u64) -> Option<&str> {
+        let index = self
+            .cumulative
+            .partition_point(|(_, _, through)| *through <= roll);
+        self.cumulative.get(index).map(|(name, _, _)| name.as_str())
+    }
+
+    /// Unknown shards count as weight 1.
+    fn weight(&self, shard_name: &str) -> u32 {
+        self.cumulative
+            .iter()
+            .find(|(name, _, _)| name == shard_name)
+            .map_or(1, |(_, weight, _)| *weight)
+    }
+}
+
+fn pick_weighted(candidates: Vec<(Arc<ShardPool>, u32)>) -> Option<Arc<ShardPool>> {
+    candidates
+        .choose_weighted(&mut rand::rng(), |(_, weight)| *weight)
+        .ok()
+        .map(|(pool, _)| pool.clone())
 }
 
 // -----------------------------------------------------------------------------
@@ -70,22 +397,52 @@ impl GatewayPools {
 #[derive(Debug)]
 pub struct ShardPool {
     shard: ShardRecord,
+    application_name: Option<String>,
+    /// Per the shard's `sslmode`; a bad CA bundle fails every connect.
+    tls: Result<Option<Arc<ClientConfig>>, String>,
     idle: Mutex<VecDeque<IdleConnection>>,
+    /// Checkouts waiting at `max`, oldest first. Only touched with `idle`
+    /// locked, so a returned backend can't slip past a waiter.
+    waiters: parking_lot::Mutex<VecDeque<oneshot::Sender<IdleConnection>>>,
     max: Arc<Semaphore>,
     min: u32,
     max_connections: u32,
+    created: AtomicU64,
+    closed: AtomicU64,
+    paused: AtomicBool,
+    healthy: AtomicBool,
+    query_stats: parking_lot::Mutex<QueryStats>,
+    /// `describe_cache_size`; shared with the shard's other pools.
+    describes: Option<Arc<DescribeCache>>,
 }
 
 impl ShardPool {
-    fn new(shard: ShardRecord) -> Self {
+    fn new(
+        shard: ShardRecord,
+        application_name: Option<String>,
+        describes: Option<Arc<DescribeCache>>,
+    ) -> Self {
         let min = shard.min_connections.max(1);
         let max = shard.max_connections.max(1);
+        let tls = tls::client_config(shard.sslmode, shard.sslrootcert.as_deref());
+        if let Err(err) = &tls {
+            warn!("shard {} tls config: {err}", shard.shard_name);
+        }
         Self {
             shard,
+            application_name,
+            tls,
             idle: Mutex::new(VecDeque::new()),
+            waiters: parking_lot::Mutex::new(VecDeque::new()),
             max: Arc::new(Semaphore::new(max as usize)),
             min,
             max_connections: max,
+            created: AtomicU64::new(0),
+            closed: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            healthy: AtomicBool::new(true),
+            query_stats: parking_lot::Mutex::new(QueryStats::default()),
+            describes,
         }
     }
 
@@ -93,14 +450,91 @@ impl ShardPool {
         &self.shard.shard_name
     }
 
+    /// Stops new checkouts for maintenance; sessions already holding a
+    /// backend keep it until they release it.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+        info!("shard {} paused", self.shard.shard_name);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+        info!("shard {} resumed", self.shard.shard_name);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// False once opening a backend failed, until one opens again.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Folds a completed query into the shard's moving averages.
+    pub fn record_query(&self, latency: Duration, failed: bool) {
+        self.query_stats.lock().record(latency, failed);
+    }
+
+    /// Where this pool's description of a statement is cached, for a
+    /// session with this `search_path`; `None` unless the shard sets
+    /// `describe_cache_size`.
+    pub fn describe_key(
+        &self,
+        signature: StatementSignature,
+        search_path: Option<&str>,
+    ) -> Option<DescribeKey> {
+        self.describes.as_ref()?;
+        Some(DescribeKey {
+            role: self.shard.user.clone(),
+            signature,
+            search_path: search_path.map(str::to_string),
+        })
+    }
+
+    /// A backend's reply to describing the statement, if one was cached.
+    pub fn cached_describe(&self, key: &DescribeKey) -> Option<Bytes> {
+        self.describes.as_ref()?.get(key)
+    }
+
+    /// The describe cache's generation, taken when a Describe goes out.
+    pub fn describe_generation(&self) -> u64 {
+        self.describes
+            .as_ref()
+            .map_or(0, |describes| describes.generation())
+    }
+
+    /// Keeps a backend's reply to a statement Describe for `cached_describe`,
+    /// unless DDL emptied the cache since `generation`.
+    pub fn cache_describe(&self, key: DescribeKey, reply: Bytes, generation: u64) {
+        if let Some(describes) = &self.describes {
+            describes.put(key, reply, generation);
+        }
+    }
+
+    /// Empties the describe cache the shard's pools share, e.g. after DDL.
+    pub fn forget_describes(&self) {
+        if let Some(describes) = &self.describes {
+            let dropped = describes.clear();
+            if dropped > 0 {
+                debug!(
+                    "shard {}: dropped {dropped} cached descriptions",
+                    self.shard.shard_name
+                );
+            }
+        }
+    }
+
     pub async fn stats(&self) -> PoolStats {
         let idle = self.idle.lock().await.len();
+        let query_stats = *self.query_stats.lock();
         let available = self.max.available_permits();
         let max = self.max_connections as usize;
         let in_use = max.saturating_sub(available).saturating_sub(idle);
 
         PoolStats {
             name: self.shard.shard_name.clone(),
+            user: self.shard.user.clone(),
             host: self.shard.host.clone(),
             port: self.shard.port,
             min: self.min,
@@ -108,92 +542,374 @@ impl ShardPool {
             idle,
             in_use,
             available,
+            created: self.created.load(Ordering::Relaxed),
+            closed: self.closed.load(Ordering::Relaxed),
+            paused: self.is_paused(),
+            waiters: self
+                .waiters
+                .lock()
+                .iter()
+                .filter(|waiter| !waiter.is_closed())
+                .count(),
+            healthy: self.healthy.load(Ordering::Relaxed),
+            avg_latency_micros: query_stats.avg_latency_micros(),
+            error_rate: query_stats.error_rate(),
         }
     }
 
-    pub async fn warm_min(&self) {
-        let current = { self.idle.lock().await.len() as u32 };
-        if current >= self.min {
-            return;
-        }
+    /// Idle backends short of `min`.
+    async fn missing_idle(&self) -> u32 {
+        let current = self.idle.lock().await.len() as u32;
+        self.min.saturating_sub(current)
+    }
 
-        let target = self.min - current;
-        info!(
-            "warming shard {}: creating {target} backend connections",
-            self.shard.shard_name
-        );
+    /// One maintenance pass: closes backends idle longer than
+    /// `idle_lifetime` while more than `min` are idle, then opens new ones
+    /// until `min` are idle again or the pool is at `max`.
+    pub async fn maintain(&self) -> Result<(), GatewayError> {
+        self.reap_idle().await;
+        self.top_up_idle().await
+    }
 
-        for _ in 0..target {
-            if let Err(err) = self.open_new_connection().await {
-                warn!(
-                    "failed to warm shard {} connection: {err}",
-                    self.shard.shard_name
-                );
+    /// Runs `maintain` until the pool is dropped, backing off while the
+    /// shard keeps refusing new connections.
+    pub fn spawn_maintenance(self: &Arc<Self>) -> JoinHandle<()> {
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                let pause = {
+                    let Some(pool) = pool.upgrade() else {
+                        return;
+                    };
+                    match pool.maintain().await {
+                        Ok(()) => {
+                            failures = 0;
+                            MAINTENANCE_INTERVAL
+                        }
+                        Err(err) => {
+                            warn!(
+                                "failed to top up shard {} idle connections: {err}",
+                                pool.shard.shard_name
+                            );
+                            let backoff = pool.shard.connect_retry.delay(failures);
+                            failures = failures.saturating_add(1);
+                            backoff.clamp(MAINTENANCE_INTERVAL, MAX_MAINTENANCE_BACKOFF)
+                        }
+                    }
+                };
+                sleep(pause).await;
             }
+        })
+    }
+
+    pub async fn acquire(self: &Arc<Self>) -> Result<PooledConnection, GatewayError> {
+        if self.is_paused() {
+            return Err(GatewayError::Paused(format!(
+                "shard {} is paused",
+                self.shard.shard_name
+            )));
         }
+
+        let permit = match self.checkout().await? {
+            Checkout::Idle(mut idle) if !self.past_server_lifetime(idle.created_at) => {
+                if !self.shard.test_on_checkout || self.ping(&mut idle.conn).await {
+                    return Ok(PooledConnection::new(
+                        self.clone(),
+                        idle.conn,
+                        idle.permit,
+                        idle.created_at,
+                    ));
+                }
+                // Died while idle: a fresh backend takes its slot.
+                self.closed.fetch_add(1, Ordering::Relaxed);
+                idle.permit
+            }
+            Checkout::Idle(idle) => {
+                // Too old to reuse: its replacement takes over the same slot.
+                self.retire(idle.conn).await;
+                idle.permit
+            }
+            Checkout::Slot(permit) => permit,
+        };
+
+        let conn = self.connect_backend().await?;
+        Ok(PooledConnection::just_opened(self.clone(), conn, permit))
     }
 
-    pub async fn acquire(self: &Arc<Self>) -> Result<PooledConnection, String> {
-        if let Some(idle) = self.idle.lock().await.pop_front() {
-            return Ok(PooledConnection::new(self.clone(), idle.conn, idle.permit));
+    /// An idle backend, or a free slot to open one in. At `max`, checkouts
+    /// queue and are served in arrival order for up to `checkout_timeout`.
+    async fn checkout(&self) -> Result<Checkout, GatewayError> {
+        let mut handoff = {
+            let mut idle = self.idle.lock().await;
+            if let Some(idle) = idle.pop_front() {
+                return Ok(Checkout::Idle(Box::new(idle)));
+            }
+            if let Ok(permit) = self.max.clone().try_acquire_owned() {
+                return Ok(Checkout::Slot(permit));
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let mut waiters = self.waiters.lock();
+            waiters.retain(|waiter| !waiter.is_closed());
+            waiters.push_back(sender);
+            receiver
+        };
+
+        // A returned backend is handed over by `push_idle`; a freed slot
+        // comes through the semaphore, which also queues in arrival order.
+        let deadline = Instant::now() + self.shard.checkout_timeout;
+        let granted = timeout_at(deadline, async {
+            tokio::select! {
+                idle = &mut handoff => idle.map(|idle| Checkout::Idle(Box::new(idle))).ok(),
+                permit = self.max.clone().acquire_owned() => permit.map(Checkout::Slot).ok(),
+            }
+        })
+        .await;
+
+        if !matches!(granted, Ok(Some(Checkout::Idle(_)))) {
+            // Handed a backend just as the wait ended: pass it on.
+            handoff.close();
+            if let Ok(idle) = handoff.try_recv() {
+                self.push_idle(idle.conn, idle.permit, idle.created_at)
+                    .await;
+            }
+        }
+
+        match granted {
+            Ok(Some(checkout)) => Ok(checkout),
+            Ok(None) => Err(GatewayError::PoolExhausted(
+                "backend pool closed".to_string(),
+            )),
+            Err(_) => Err(GatewayError::PoolExhausted(format!(
+                "timed out waiting for a backend on shard {}",
+                self.shard.shard_name
+            ))),
         }
+    }
 
+    async fn open_new_connection(&self) -> Result<(), GatewayError> {
         let permit = self
             .max
             .clone()
             .acquire_owned()
             .await
-            .map_err(|_| "backend pool closed".to_string())?;
+            .map_err(|_| GatewayError::PoolExhausted("backend pool closed".to_string()))?;
 
-        let conn = BackendConnection::connect(&self.shard.host, self.shard.port)
-            .await
-            .map_err(|e| format!("failed to connect to backend: {e}"))?;
-        let mut conn = conn;
-        conn.startup(
-            &self.shard.user,
-            &self.shard.shard_name,
-            self.shard.password_exposed(),
-        )
-        .await
-        .map_err(|e| format!("backend startup failed: {e}"))?;
+        let conn = self.connect_backend().await?;
+        self.push_idle(conn, permit, Instant::now()).await;
+        Ok(())
+    }
 
-        Ok(PooledConnection::new(self.clone(), conn, permit))
+    /// Opens a backend, recording whether it worked in `healthy`.
+    async fn connect_backend(&self) -> Result<BackendConnection, GatewayError> {
+        let result = self.open_backend().await;
+        if self.healthy.swap(result.is_ok(), Ordering::Relaxed) != result.is_ok() {
+            match &result {
+                Ok(_) => info!("shard {} is reachable again", self.shard.shard_name),
+                Err(err) => warn!("shard {} marked unhealthy: {err}", self.shard.shard_name),
+            }
+        }
+        result
     }
 
-    async fn open_new_connection(&self) -> Result<(), String> {
-        let permit = self
-            .max
+    /// Connects and runs startup. Failed TCP connects are retried per the
+    /// shard's `connect_retry` policy within its timeout; startup gets
+    /// `handshake_timeout` of its own.
+    async fn open_backend(&self) -> Result<BackendConnection, GatewayError> {
+        let policy = self.shard.connect_retry;
+        let deadline = Instant::now() + policy.timeout;
+        let tls = self
+            .tls
             .clone()
-            .acquire_owned()
+            .map_err(GatewayError::Connect)?
+            .map(TlsConnector::from);
+
+        let started = Instant::now();
+        let mut attempt = 0;
+        let mut conn = loop {
+            let result = timeout_at(
+                deadline,
+                BackendConnection::connect(&self.shard.host, self.shard.port, tls.as_ref()),
+            )
             .await
-            .map_err(|_| "backend pool closed".to_string())?;
+            .map_err(|_| GatewayError::Timeout("timed out connecting to backend".to_string()))?;
+
+            let err = match result {
+                Ok(conn) => break conn,
+                Err(err) => err,
+            };
+
+            let delay = policy.delay(attempt);
+            if attempt >= policy.retries || Instant::now() + delay >= deadline {
+                return Err(GatewayError::Connect(format!(
+                    "failed to connect to backend: {err}"
+                )));
+            }
+
+            attempt += 1;
+            warn!(
+                "connect to shard {} failed ({err}); retry {attempt}/{} in {delay:?}",
+                self.shard.shard_name, policy.retries
+            );
+            sleep(delay).await;
+        };
+        let connected = Instant::now();
+
+        timeout(
+            self.shard.handshake_timeout,
+            conn.startup(
+                &self.shard.user,
+                &self.shard.shard_name,
+                self.shard.password_exposed(),
+                self.application_name.as_deref(),
+                &self.shard.options,
+            ),
+        )
+        .await
+        .map_err(|_| GatewayError::Timeout("timed out during backend startup".to_string()))??;
+        let startup = connected.elapsed();
 
-        let conn = BackendConnection::connect(&self.shard.host, self.shard.port)
+        conn.set_on_connect(self.shard.on_connect.clone());
+        timeout(self.shard.handshake_timeout, conn.run_on_connect())
             .await
-            .map_err(|e| format!("failed to connect to backend: {e}"))?;
-        let mut conn = conn;
-        conn.startup(
-            &self.shard.user,
-            &self.shard.shard_name,
-            self.shard.password_exposed(),
+            .map_err(|_| GatewayError::Timeout("timed out running on_connect".to_string()))?
+            .map_err(|err| match err {
+                SilentRunError::Io(message) => GatewayError::Connect(message),
+                SilentRunError::Failed(message) => GatewayError::OnConnect(message),
+            })?;
+
+        conn.set_connect_timings(ConnectTimings {
+            connect: connected - started,
+            startup,
+        });
+        conn.set_share_prepared(self.shard.shared_prepared_statements);
+        conn.set_max_prepared(self.shard.max_prepared_statements);
+        self.created.fetch_add(1, Ordering::Relaxed);
+        Ok(conn)
+    }
+
+    /// Drops idle backends past `server_lifetime`, and those idle longer
+    /// than `idle_lifetime` while more than `min` are idle.
+    async fn reap_idle(&self) {
+        let lifetime = self.shard.idle_lifetime;
+        let expired = {
+            let mut idle = self.idle.lock().await;
+            let mut expired = Vec::new();
+            let mut index = 0;
+            while index < idle.len() {
+                let entry = &idle[index];
+                let too_old = self.past_server_lifetime(entry.created_at);
+                let surplus = idle.len() > self.min as usize && entry.since.elapsed() >= lifetime;
+                if too_old || surplus {
+                    expired.extend(idle.remove(index));
+                } else {
+                    index += 1;
+                }
+            }
+            expired
+        };
+
+        if expired.is_empty() {
+            return;
+        }
+
+        info!(
+            "closing {} idle backend connections on shard {}",
+            expired.len(),
+            self.shard.shard_name
+        );
+        for idle in expired {
+            self.retire(idle.conn).await;
+        }
+    }
+
+    async fn top_up_idle(&self) -> Result<(), GatewayError> {
+        loop {
+            if self.idle.lock().await.len() >= self.min as usize {
+                return Ok(());
+            }
+
+            // Every permit is out: the pool is at `max` already.
+            let Ok(permit) = self.max.clone().try_acquire_owned() else {
+                return Ok(());
+            };
+
+            let conn = self.connect_backend().await?;
+            self.push_idle(conn, permit, Instant::now()).await;
+        }
+    }
+
+    fn past_server_lifetime(&self, created_at: Instant) -> bool {
+        created_at.elapsed() >= self.shard.server_lifetime
+    }
+
+    /// `test_on_checkout`: whether an idle backend still answers a query.
+    async fn ping(&self, conn: &mut BackendConnection) -> bool {
+        let result = timeout(
+            self.shard.handshake_timeout,
+            conn.run_silently("SELECT 1", "checkout test"),
         )
         .await
-        .map_err(|e| format!("backend startup failed: {e}"))?;
+        .unwrap_or_else(|_| {
+            Err(SilentRunError::Io(
+                "backend checkout test timed out".to_string(),
+            ))
+        });
 
-        self.push_idle(conn, permit).await;
-        Ok(())
+        if let Err(err) = &result {
+            warn!(
+                "replacing idle backend on shard {}: {err}",
+                self.shard.shard_name
+            );
+        }
+        result.is_ok()
     }
 
-    async fn push_idle(&self, mut conn: BackendConnection, permit: OwnedSemaphorePermit) {
-        if let Err(err) = conn.reset_session().await {
+    /// Closes a backend for good, telling the server first.
+    async fn retire(&self, mut conn: BackendConnection) {
+        let _ = conn.send(&TERMINATE).await;
+        self.closed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    async fn reset_and_push_idle(
+        &self,
+        mut conn: BackendConnection,
+        permit: OwnedSemaphorePermit,
+        created_at: Instant,
+    ) {
+        if let Err(err) = conn.reset_session(&self.shard.server_reset_query).await {
             warn!(
                 "dropping backend connection after reset failure on shard {}: {err}",
                 self.shard.shard_name
             );
+            self.closed.fetch_add(1, Ordering::Relaxed);
             return;
         }
+        self.push_idle(conn, permit, created_at).await;
+    }
+
+    async fn push_idle(
+        &self,
+        conn: BackendConnection,
+        permit: OwnedSemaphorePermit,
+        created_at: Instant,
+    ) {
+        let mut entry = IdleConnection {
+            conn,
+            permit,
+            created_at,
+            since: Instant::now(),
+        };
+
         let mut idle = self.idle.lock().await;
-        idle.push_back(IdleConnection { conn, permit });
+        while let Some(waiter) = self.waiters.lock().pop_front() {
+            match waiter.send(entry) {
+                Ok(()) => return,
+                Err(unclaimed) => entry = unclaimed,
+            }
+        }
+        idle.push_back(entry);
     }
 }
 
@@ -205,21 +921,80 @@ pub struct PooledConnection {
     pool: Arc<ShardPool>,
     conn: Option<BackendConnection>,
     permit: Option<OwnedSemaphorePermit>,
+    /// When the backend was opened; past `server_lifetime` it is retired.
+    created_at: Instant,
+    /// Reset ran and nothing touched the backend since; skip it on release.
+    clean: bool,
+    /// Left mid-response; closed on release instead of reset.
+    discarded: bool,
+    /// Opened for this checkout rather than taken from the idle list.
+    opened: bool,
 }
 
 impl PooledConnection {
-    fn new(pool: Arc<ShardPool>, conn: BackendConnection, permit: OwnedSemaphorePermit) -> Self {
+    fn new(
+        pool: Arc<ShardPool>,
+        conn: BackendConnection,
+        permit: OwnedSemaphorePermit,
+        created_at: Instant,
+    ) -> Self {
         Self {
             pool,
             conn: Some(conn),
             permit: Some(permit),
+            created_at,
+            clean: false,
+            discarded: false,
+            opened: false,
         }
     }
 
+    fn just_opened(
+        pool: Arc<ShardPool>,
+        conn: BackendConnection,
+        permit: OwnedSemaphorePermit,
+    ) -> Self {
+        let mut pooled = Self::new(pool, conn, permit, Instant::now());
+        pooled.opened = true;
+        pooled
+    }
+
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    pub fn pool(&self) -> &Arc<ShardPool> {
+        &self.pool
+    }
+
+    /// How long opening the backend took, when this checkout opened it.
+    pub fn opened_timings(&self) -> Option<ConnectTimings> {
+        let conn = self.conn.as_ref().filter(|_| self.opened)?;
+        Some(conn.connect_timings())
+    }
+
     pub fn connection(&mut self) -> &mut BackendConnection {
+        self.clean = false;
+        self.conn
+            .as_mut()
+            .expect("pooled connection missing backend connection")
+    }
+
+    /// Closes the backend on release; for one whose stream can't be resumed.
+    pub fn discard(&mut self) {
+        self.discarded = true;
+    }
+
+    /// Runs the shard's `server_reset_query` now instead of on release.
+    pub async fn reset(&mut self) -> Result<(), String> {
+        let reset_query = self.pool.shard.server_reset_query.as_str();
         self.conn
             .as_mut()
             .expect("pooled connection missing backend connection")
+            .reset_session(reset_query)
+            .await?;
+        self.clean = true;
+        Ok(())
     }
 }
 
@@ -233,16 +1008,894 @@ impl Drop for PooledConnection {
         };
 
         let pool = self.pool.clone();
+        let clean = self.clean;
+        let discarded = self.discarded;
+        let created_at = self.created_at;
         tokio::spawn(async move {
-            pool.push_idle(conn, permit).await;
+            if discarded || pool.past_server_lifetime(created_at) {
+                pool.retire(conn).await;
+            } else if clean {
+                pool.push_idle(conn, permit, created_at).await;
+            } else {
+                pool.reset_and_push_idle(conn, permit, created_at).await;
+            }
         });
     }
 }
 
+#[derive(Debug)]
+enum Checkout {
+    Idle(Box<IdleConnection>),
+    /// Room under `max` for a new backend.
+    Slot(OwnedSemaphorePermit),
+}
+
 #[derive(Debug)]
 struct IdleConnection {
     conn: BackendConnection,
     permit: OwnedSemaphorePermit,
+    created_at: Instant,
+    since: Instant,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::shards::{ConnectRetryPolicy, SslMode};
+    use crate::gateway::GatewaySession;
+    use secrecy::SecretString;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn shard(port: u16, connect_retry: ConnectRetryPolicy) -> ShardRecord {
+        ShardRecord {
+            shard_name: "flaky".to_string(),
+            host: "127.0.0.1".to_string(),
+            port,
+            user: "user".to_string(),
+            min_connections: 1,
+            max_connections: 1,
+            server_reset_query: String::new(),
+            connect_retry,
+            ..ShardRecord::default()
+        }
+    }
+
+    /// A fake Postgres: accepts any number of backend connections,
+    /// completes their startup with a distinct pid each from 1000, answers
+    /// every Query with `SELECT 1` and holds each open until the proxy hangs
+    /// up. The fields change that for one test.
+    #[derive(Debug, Clone, Default)]
+    struct FakeBackend {
+        /// Pause before answering each startup.
+        startup_delay: Duration,
+        /// Hang up on the first connection right after its startup.
+        hang_up_first: bool,
+        /// Most startups seen in progress at once.
+        peak_startups: Arc<AtomicUsize>,
+    }
+
+    impl FakeBackend {
+        async fn spawn(self) -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let in_startup = Arc::new(AtomicUsize::new(0));
+            tokio::spawn(async move {
+                for pid in 1000.. {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let (this, in_startup) = (self.clone(), Arc::clone(&in_startup));
+                    tokio::spawn(async move {
+                        let now = in_startup.fetch_add(1, Ordering::SeqCst) + 1;
+                        this.peak_startups.fetch_max(now, Ordering::SeqCst);
+                        let startup_len = stream.read_u32().await.unwrap() as usize;
+                        let mut startup = vec![0u8; startup_len - 4];
+                        stream.read_exact(&mut startup).await.unwrap();
+                        sleep(this.startup_delay).await;
+                        in_startup.fetch_sub(1, Ordering::SeqCst);
+
+                        let mut reply = vec![b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'K', 0, 0, 0, 12];
+                        reply.extend_from_slice(&i32::to_be_bytes(pid));
+                        reply.extend_from_slice(&[0, 0, 0, 7, b'Z', 0, 0, 0, 5, b'I']);
+                        stream.write_all(&reply).await.unwrap();
+                        if this.hang_up_first && pid == 1000 {
+                            return;
+                        }
+
+                        while let Ok(tag) = stream.read_u8().await {
+                            let len = stream.read_u32().await.unwrap() as usize;
+                            let mut body = vec![0u8; len - 4];
+                            stream.read_exact(&mut body).await.unwrap();
+                            if tag == b'Q' {
+                                let mut reply = vec![b'C', 0, 0, 0, 13];
+                                reply.extend_from_slice(b"SELECT 1\0");
+                                reply.extend_from_slice(&[b'Z', 0, 0, 0, 5, b'I']);
+                                stream.write_all(&reply).await.unwrap();
+                            }
+                        }
+                    });
+                }
+            });
+            port
+        }
+    }
+
+    async fn fake_backend() -> u16 {
+        FakeBackend::default().spawn().await
+    }
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    /// Like `fake_backend`, but only speaks TLS: it expects an SSLRequest,
+    /// answers 'S' and runs startup inside the handshake with the fixture
+    /// certificate for `localhost`.
+    async fn tls_backend() -> u16 {
+        let certs = tls::load_certs(&fixture("server.crt")).unwrap();
+        let key = tls::load_key(&fixture("server.key")).unwrap();
+        let config = tokio_rustls::rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let mut request = [0u8; 8];
+                    stream.read_exact(&mut request).await.unwrap();
+                    assert_eq!(request, [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x2f]);
+                    stream.write_all(b"S").await.unwrap();
+
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let startup_len = stream.read_u32().await.unwrap() as usize;
+                    let mut startup = vec![0u8; startup_len - 4];
+                    stream.read_exact(&mut startup).await.unwrap();
+                    stream
+                        .write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I'])
+                        .await
+                        .unwrap();
+                    let mut rest = Vec::new();
+                    let _ = stream.read_to_end(&mut rest).await;
+                });
+            }
+        });
+        port
+    }
+
+    async fn wait_for_idle(pool: &ShardPool, idle: usize) -> PoolStats {
+        for _ in 0..100 {
+            let stats = pool.stats().await;
+            if stats.idle == idle {
+                return stats;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        panic!("pool never reached {idle} idle: {:?}", pool.stats().await);
+    }
+
+    /// A port with nothing listening, so connects are refused.
+    async fn refused_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_backend_accepts() {
+        let port = refused_port().await;
+
+        // First attempt hits a closed port; the backend comes up shortly after.
+        let backend = tokio::spawn(async move {
+            sleep(Duration::from_millis(30)).await;
+            let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let startup_len = stream.read_u32().await.unwrap() as usize;
+            let mut startup = vec![0u8; startup_len - 4];
+            stream.read_exact(&mut startup).await.unwrap();
+            stream
+                .write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I'])
+                .await
+                .unwrap();
+            stream
+        });
+
+        let policy = ConnectRetryPolicy {
+            retries: 10,
+            backoff: Duration::from_millis(20),
+            timeout: Duration::from_secs(5),
+        };
+        let pools = GatewayPools::new(vec![shard(port, policy)]);
+        let pool = pools.get("flaky").unwrap();
+
+        let session = GatewaySession::from_pool(&pool).await;
+        assert!(session.is_ok(), "{:?}", session.err());
+        drop(backend.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_retry_budget() {
+        let port = refused_port().await;
+        let policy = ConnectRetryPolicy {
+            retries: 1,
+            backoff: Duration::from_millis(1),
+            timeout: Duration::from_secs(5),
+        };
+        let pools = GatewayPools::new(vec![shard(port, policy)]);
+        let pool = pools.get("flaky").unwrap();
+
+        let err = GatewaySession::from_pool(&pool).await.unwrap_err();
+        assert!(matches!(err, GatewayError::Connect(_)));
+        assert!(
+            err.to_string().starts_with("failed to connect to backend"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn require_negotiates_tls_with_the_backend() {
+        let port = tls_backend().await;
+        let record = ShardRecord {
+            sslmode: SslMode::Require,
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+
+        let session = GatewaySession::from_pool(&pool).await;
+        assert!(session.is_ok(), "{:?}", session.err());
+    }
+
+    #[tokio::test]
+    async fn verify_full_checks_the_host_name() {
+        let port = tls_backend().await;
+        let no_retries = ConnectRetryPolicy {
+            retries: 0,
+            ..ConnectRetryPolicy::default()
+        };
+        let verified = |host: &str| ShardRecord {
+            host: host.to_string(),
+            sslmode: SslMode::VerifyFull,
+            sslrootcert: Some(fixture("ca.crt")),
+            ..shard(port, no_retries)
+        };
+
+        let pools = GatewayPools::new(vec![verified("localhost")]);
+        let session = GatewaySession::from_pool(&pools.get("flaky").unwrap()).await;
+        assert!(session.is_ok(), "{:?}", session.err());
+
+        // The certificate names localhost, not 127.0.0.1.
+        let pools = GatewayPools::new(vec![verified("127.0.0.1")]);
+        let err = GatewaySession::from_pool(&pools.get("flaky").unwrap())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("certificate"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn require_fails_when_the_backend_refuses_ssl() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 8];
+            stream.read_exact(&mut request).await.unwrap();
+            stream.write_all(b"N").await.unwrap();
+        });
+
+        let record = ShardRecord {
+            sslmode: SslMode::Require,
+            ..shard(
+                port,
+                ConnectRetryPolicy {
+                    retries: 0,
+                    ..ConnectRetryPolicy::default()
+                },
+            )
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let err = GatewaySession::from_pool(&pools.get("flaky").unwrap())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("does not support SSL"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn silent_backend_times_out_the_handshake() {
+        // Accepts, then never answers the startup message.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                held.push(stream);
+            }
+        });
+
+        let record = ShardRecord {
+            handshake_timeout: Duration::from_millis(100),
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+        assert!(pool.stats().await.healthy);
+
+        let started = Instant::now();
+        let err = pool.acquire().await.unwrap_err();
+        assert_eq!(
+            err,
+            GatewayError::Timeout("timed out during backend startup".to_string())
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(!pool.stats().await.healthy);
+    }
+
+    #[tokio::test]
+    async fn maintenance_refills_idle_to_min() {
+        let port = fake_backend().await;
+        let record = ShardRecord {
+            min_connections: 2,
+            max_connections: 3,
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+        pools.warm_all(1).await;
+        assert_eq!(pool.stats().await.idle, 2);
+
+        // Both idle backends die; the maintenance loop opens replacements.
+        pool.idle.lock().await.clear();
+        assert_eq!(pool.stats().await.idle, 0);
+        let loops = pools.spawn_maintenance();
+
+        let stats = wait_for_idle(&pool, 2).await;
+        assert_eq!(stats.created, 4);
+        assert!(stats.idle + stats.in_use <= stats.max as usize);
+        loops.iter().for_each(JoinHandle::abort);
+    }
+
+    #[tokio::test]
+    async fn warm_all_caps_concurrent_connects() {
+        let backend = FakeBackend {
+            startup_delay: Duration::from_millis(30),
+            ..FakeBackend::default()
+        };
+        let peak = Arc::clone(&backend.peak_startups);
+        let port = backend.spawn().await;
+
+        let shards = (0..3)
+            .map(|i| ShardRecord {
+                shard_name: format!("shard_{i}"),
+                min_connections: 3,
+                max_connections: 3,
+                ..shard(port, ConnectRetryPolicy::default())
+            })
+            .collect();
+        let pools = GatewayPools::new(shards);
+        pools.warm_all(2).await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        for stats in pools.snapshot().await {
+            assert_eq!(stats.idle, 3, "{}", stats.name);
+        }
+
+        // Already warm: nothing more to open.
+        pools.warm_all(2).await;
+        let created: u64 = pools.snapshot().await.iter().map(|s| s.created).sum();
+        assert_eq!(created, 9);
+    }
+
+    #[tokio::test]
+    async fn maintenance_never_exceeds_max() {
+        let port = fake_backend().await;
+        let record = ShardRecord {
+            min_connections: 2,
+            max_connections: 2,
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+
+        // Both permits are checked out, so nothing can be opened.
+        let first = GatewaySession::from_pool(&pool).await.unwrap();
+        let second = GatewaySession::from_pool(&pool).await.unwrap();
+        pool.maintain().await.unwrap();
+        let stats = pool.stats().await;
+        assert_eq!((stats.idle, stats.in_use, stats.created), (0, 2, 2));
+
+        drop((first, second));
+        wait_for_idle(&pool, 2).await;
+    }
+
+    #[tokio::test]
+    async fn maintenance_reaps_expired_idle_above_min() {
+        let port = fake_backend().await;
+        let record = ShardRecord {
+            min_connections: 1,
+            max_connections: 3,
+            idle_lifetime: Duration::ZERO,
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+
+        let sessions = [
+            GatewaySession::from_pool(&pool).await.unwrap(),
+            GatewaySession::from_pool(&pool).await.unwrap(),
+            GatewaySession::from_pool(&pool).await.unwrap(),
+        ];
+        drop(sessions);
+        wait_for_idle(&pool, 3).await;
+
+        pool.maintain().await.unwrap();
+        let stats = pool.stats().await;
+        assert_eq!(stats.idle, 1);
+        assert_eq!((stats.created, stats.closed), (3, 2));
+        assert_eq!(stats.available, 2);
+    }
+
+    #[tokio::test]
+    async fn checkout_captures_the_backend_key_data() {
+        let port = fake_backend().await;
+        let pools = GatewayPools::new(vec![shard(port, ConnectRetryPolicy::default())]);
+        let pool = pools.get("flaky").unwrap();
+
+        let session = GatewaySession::from_pool(&pool).await.unwrap();
+        let identity = session.backend_identity().expect("backend identity");
+        assert!(identity.process_id >= 1000);
+        assert_eq!(identity.secret_key, 7);
+    }
+
+    #[tokio::test]
+    async fn dead_idle_backend_is_replaced_when_tested_on_checkout() {
+        let port = FakeBackend {
+            hang_up_first: true,
+            ..FakeBackend::default()
+        }
+        .spawn()
+        .await;
+        let record = ShardRecord {
+            test_on_checkout: true,
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+
+        // The first backend hangs up as soon as it's pooled.
+        let mut session = GatewaySession::from_pool(&pool).await.unwrap();
+        assert_eq!(session.backend().process_id(), Some(1000));
+        drop(session);
+        wait_for_idle(&pool, 1).await;
+
+        let mut session = GatewaySession::from_pool(&pool).await.unwrap();
+        assert_eq!(session.backend().process_id(), Some(1001));
+        session
+            .backend()
+            .run_silently("SELECT 1", "query")
+            .await
+            .expect("the replacement answers");
+
+        let stats = pool.stats().await;
+        assert_eq!((stats.created, stats.closed), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn expired_backend_is_replaced_on_checkout() {
+        let port = fake_backend().await;
+        let record = ShardRecord {
+            server_lifetime: Duration::from_millis(30),
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+
+        let mut session = GatewaySession::from_pool(&pool).await.unwrap();
+        let first_pid = session.backend().process_id().expect("first pid");
+        drop(session);
+        wait_for_idle(&pool, 1).await;
+
+        // Reused while young.
+        let mut session = GatewaySession::from_pool(&pool).await.unwrap();
+        assert_eq!(session.backend().process_id(), Some(first_pid));
+        drop(session);
+        wait_for_idle(&pool, 1).await;
+
+        sleep(Duration::from_millis(40)).await;
+        let mut session = GatewaySession::from_pool(&pool).await.unwrap();
+        let second_pid = session.backend().process_id().expect("second pid");
+        assert_ne!(first_pid, second_pid);
+
+        let stats = pool.stats().await;
+        assert_eq!((stats.created, stats.closed), (2, 1));
+        assert_eq!(stats.in_use, 1);
+    }
+
+    #[tokio::test]
+    async fn expired_backend_is_retired_on_return() {
+        let port = fake_backend().await;
+        let record = ShardRecord {
+            server_lifetime: Duration::from_millis(10),
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+
+        let session = GatewaySession::from_pool(&pool).await.unwrap();
+        sleep(Duration::from_millis(20)).await;
+        drop(session);
+
+        for _ in 0..100 {
+            if pool.stats().await.closed == 1 {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        let stats = pool.stats().await;
+        assert_eq!((stats.idle, stats.in_use, stats.closed), (0, 0, 1));
+        assert_eq!(stats.available, 1);
+    }
+
+    fn weighted(name: &str, weight: u32) -> ShardRecord {
+        ShardRecord {
+            shard_name: name.to_string(),
+            weight,
+            ..shard(1, ConnectRetryPolicy::default())
+        }
+    }
+
+    /// Share of 10k `random_pool` picks landing on each shard.
+    fn pick_shares(pools: &GatewayPools) -> HashMap<String, f64> {
+        const PICKS: usize = 10_000;
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for _ in 0..PICKS {
+            let pool = pools.random_pool().unwrap();
+            *counts.entry(pool.name().to_string()).or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(name, count)| (name, count as f64 / PICKS as f64))
+            .collect()
+    }
+
+    #[test]
+    fn weight_table_slices_the_range_by_weight() {
+        let table = WeightTable::new([("a", 1), ("b", 2)].into_iter());
+        assert_eq!(table.total(), 3);
+        assert_eq!(table.pick(0), Some("a"));
+        assert_eq!(table.pick(1), Some("b"));
+        assert_eq!(table.pick(2), Some("b"));
+        assert_eq!(table.pick(3), None);
+    }
+
+    #[test]
+    fn random_pool_follows_shard_weights() {
+        let pools = GatewayPools::new(vec![
+            weighted("light", 1),
+            weighted("medium", 2),
+            weighted("heavy", 5),
+        ]);
+
+        // Expected 1/8, 2/8 and 5/8; 10k picks land well within 3 points.
+        let shares = pick_shares(&pools);
+        for (name, expected) in [("light", 0.125), ("medium", 0.25), ("heavy", 0.625)] {
+            let share = shares.get(name).copied().unwrap_or_default();
+            assert!((share - expected).abs() < 0.03, "{name}: {share}");
+        }
+
+        // A paused shard's share goes to the others, in proportion.
+        pools.get("heavy").unwrap().pause();
+        let shares = pick_shares(&pools);
+        assert!(!shares.contains_key("heavy"));
+        assert!((shares["medium"] - 2.0 / 3.0).abs() < 0.03, "{shares:?}");
+
+        // Reload: equal weights pick uniformly.
+        pools.get("heavy").unwrap().resume();
+        pools.reweight(&[
+            weighted("light", 1),
+            weighted("medium", 1),
+            weighted("heavy", 1),
+        ]);
+        let shares = pick_shares(&pools);
+        for name in ["light", "medium", "heavy"] {
+            assert!((shares[name] - 1.0 / 3.0).abs() < 0.03, "{shares:?}");
+        }
+    }
+
+    #[test]
+    fn random_pool_among_follows_the_candidates_weights() {
+        let pools = GatewayPools::new(vec![
+            weighted("primary", 10),
+            weighted("light", 1),
+            weighted("heavy", 3),
+        ]);
+        let replicas = ["light".to_string(), "heavy".to_string()];
+        let shares = |pools: &GatewayPools| {
+            let mut heavy = 0;
+            for _ in 0..10_000 {
+                let pool = pools.random_pool_among(&replicas, None).unwrap();
+                assert_ne!(pool.name(), "primary");
+                heavy += usize::from(pool.name() == "heavy");
+            }
+            heavy as f64 / 10_000.0
+        };
+
+        // Expected 3/4 for heavy, then half once a reload evens them out.
+        assert!((shares(&pools) - 0.75).abs() < 0.03);
+        pools.reweight(&[weighted("light", 1), weighted("heavy", 1)]);
+        assert!((shares(&pools) - 0.5).abs() < 0.03);
+
+        pools.get("heavy").unwrap().pause();
+        assert_eq!(shares(&pools), 0.0);
+    }
+
+    #[tokio::test]
+    async fn paused_pool_blocks_checkouts_until_resumed() {
+        let port = fake_backend().await;
+        let pools = GatewayPools::new(vec![shard(port, ConnectRetryPolicy::default())]);
+        let pool = pools.get("flaky").unwrap();
+
+        let mut in_flight = GatewaySession::from_pool(&pool).await.unwrap();
+        pool.pause();
+        assert!(pool.stats().await.paused);
+        assert!(pools.random_pool().is_none());
+
+        let err = GatewaySession::from_pool(&pool).await.unwrap_err();
+        assert_eq!(
+            err,
+            GatewayError::Paused("shard flaky is paused".to_string())
+        );
+
+        // The session checked out before the pause keeps its backend and
+        // hands it back as usual.
+        let pid = in_flight.backend().process_id().expect("pid");
+        drop(in_flight);
+        let stats = wait_for_idle(&pool, 1).await;
+        assert_eq!(stats.closed, 0);
+
+        pool.resume();
+        assert!(!pool.stats().await.paused);
+        assert!(pools.random_pool().is_some());
+        let mut session = GatewaySession::from_pool(&pool).await.unwrap();
+        assert_eq!(session.backend().process_id(), Some(pid));
+    }
+
+    async fn wait_for_waiters(pool: &ShardPool, waiters: usize) {
+        for _ in 0..100 {
+            if pool.stats().await.waiters == waiters {
+                return;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        panic!(
+            "pool never reached {waiters} waiters: {:?}",
+            pool.stats().await
+        );
+    }
+
+    #[tokio::test]
+    async fn waiting_checkouts_are_served_in_arrival_order() {
+        let port = fake_backend().await;
+        let pools = GatewayPools::new(vec![shard(port, ConnectRetryPolicy::default())]);
+        let pool = pools.get("flaky").unwrap();
+        let held = pool.acquire().await.unwrap();
+
+        let (granted, mut order) = tokio::sync::mpsc::unbounded_channel();
+        for waiter in 0..3 {
+            let queued = pool.clone();
+            let granted = granted.clone();
+            tokio::spawn(async move {
+                let backend = queued.acquire().await.unwrap();
+                granted.send(waiter).unwrap();
+                sleep(Duration::from_millis(10)).await;
+                drop(backend);
+            });
+            wait_for_waiters(&pool, waiter + 1).await;
+        }
+
+        drop(held);
+        let mut served = Vec::new();
+        for _ in 0..3 {
+            let next = tokio::time::timeout(Duration::from_secs(5), order.recv());
+            served.push(next.await.unwrap().unwrap());
+        }
+        assert_eq!(served, [0, 1, 2]);
+
+        let stats = wait_for_idle(&pool, 1).await;
+        assert_eq!(stats.waiters, 0);
+        assert_eq!(stats.created, 1);
+    }
+
+    #[tokio::test]
+    async fn checkout_gives_up_after_checkout_timeout() {
+        let port = fake_backend().await;
+        let record = ShardRecord {
+            checkout_timeout: Duration::from_millis(50),
+            ..shard(port, ConnectRetryPolicy::default())
+        };
+        let pools = GatewayPools::new(vec![record]);
+        let pool = pools.get("flaky").unwrap();
+        let held = pool.acquire().await.unwrap();
+
+        let err = pool.acquire().await.unwrap_err();
+        assert_eq!(
+            err,
+            GatewayError::PoolExhausted(
+                "timed out waiting for a backend on shard flaky".to_string()
+            )
+        );
+        assert_eq!(pool.stats().await.waiters, 0);
+
+        drop(held);
+        wait_for_idle(&pool, 1).await;
+        assert!(pool.acquire().await.is_ok());
+    }
+
+    fn user(client: &str, server: Option<&str>) -> UserRecord {
+        let secret = |value: &str| SecretString::new(value.to_string().into_boxed_str());
+        UserRecord {
+            client_username: client.to_string(),
+            database: None,
+            client_password: secret("client-secret"),
+            server_username: server.unwrap_or(client).to_string(),
+            server_password: secret("server-secret"),
+            server_username_set: server.is_some(),
+            pool_size: None,
+            pooler_mode: None,
+            statement_timeout: None,
+            idle_in_transaction_timeout: None,
+            admin: false,
+            rate_limit: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn server_roles_get_their_own_warm_pools() {
+        let port = fake_backend().await;
+        let users = [
+            user("alice", Some("app_rw")),
+            user("bob", Some("app_ro")),
+            user("carol", Some("app_rw")),
+            user("dave", None),
+        ];
+        let pools = GatewayPools::with_users(
+            vec![shard(port, ConnectRetryPolicy::default())],
+            &users,
+            None,
+        );
+        pools.warm_all(4).await;
+
+        let stats = pools.snapshot().await;
+        let keys: Vec<_> = stats
+            .iter()
+            .map(|stat| (stat.name.as_str(), stat.user.as_str(), stat.idle))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("flaky", "app_ro", 1),
+                ("flaky", "app_rw", 1),
+                ("flaky", "user", 1)
+            ]
+        );
+
+        let rw = pools.get_as("flaky", "app_rw").unwrap();
+        let ro = pools.get_as("flaky", "app_ro").unwrap();
+        let default = pools.get("flaky").unwrap();
+        assert!(!Arc::ptr_eq(&rw, &ro));
+        assert!(!Arc::ptr_eq(&rw, &default));
+        assert_eq!(pools.shard_pools("flaky").len(), 3);
+
+        let picked = pools.random_pool_as(Some("app_rw")).unwrap();
+        assert!(Arc::ptr_eq(&picked, &rw));
+        let picked = pools.random_pool().unwrap();
+        assert!(Arc::ptr_eq(&picked, &default));
+        assert!(pools.random_pool_as(Some("nobody")).is_none());
+    }
+
+    /// Startup parameters the pool sends for `record`, as key/value pairs.
+    async fn startup_params(record: ShardRecord, prefix: Option<&str>) -> Vec<(String, String)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let backend = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let startup_len = stream.read_u32().await.unwrap() as usize;
+            let mut startup = vec![0u8; startup_len - 4];
+            stream.read_exact(&mut startup).await.unwrap();
+            stream
+                .write_all(&[b'R', 0, 0, 0, 8, 0, 0, 0, 0, b'Z', 0, 0, 0, 5, b'I'])
+                .await
+                .unwrap();
+            (startup, stream)
+        });
+
+        let record = ShardRecord { port, ..record };
+        let pools = GatewayPools::with_application_name_prefix(vec![record], prefix);
+        let pool = pools.get("flaky").unwrap();
+        let _session = GatewaySession::from_pool(&pool).await.unwrap();
+
+        let (startup, _stream) = backend.await.unwrap();
+        // Past the protocol version; every field ends in a NUL, and one more
+        // ends the list.
+        let fields: Vec<String> = startup[4..startup.len() - 2]
+            .split(|&b| b == 0)
+            .map(|field| String::from_utf8(field.to_vec()).unwrap())
+            .collect();
+        fields
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect()
+    }
+
+    fn param<'a>(params: &'a [(String, String)], key: &str) -> Option<&'a str> {
+        params
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn backend_startup_carries_application_name() {
+        let record = shard(0, ConnectRetryPolicy::default());
+        let params = startup_params(record, Some("pgcrab")).await;
+        // Named after the role the backend logs in as.
+        assert_eq!(param(&params, "user"), Some("user"));
+        assert_eq!(param(&params, "application_name"), Some("pgcrab:user"));
+    }
+
+    #[tokio::test]
+    async fn backend_startup_carries_shard_options() {
+        let options = BTreeMap::from([
+            ("search_path".to_string(), "tenant, public".to_string()),
+            ("statement_timeout".to_string(), "5s".to_string()),
+            ("application_name".to_string(), "reports".to_string()),
+        ]);
+        let record = ShardRecord {
+            options,
+            ..shard(0, ConnectRetryPolicy::default())
+        };
+        let params = startup_params(record, Some("pgcrab")).await;
+
+        assert_eq!(param(&params, "user"), Some("user"));
+        assert_eq!(param(&params, "database"), Some("flaky"));
+        assert_eq!(param(&params, "search_path"), Some("tenant, public"));
+        assert_eq!(param(&params, "statement_timeout"), Some("5s"));
+        let names: Vec<_> = params
+            .iter()
+            .filter(|(name, _)| name == "application_name")
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert_eq!(param(&params, "application_name"), Some("reports"));
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_with_jitter() {
+        let policy = ConnectRetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(100),
+            timeout: Duration::from_secs(5),
+        };
+        for attempt in 0..3 {
+            let step = Duration::from_millis(100 << attempt);
+            let delay = policy.delay(attempt);
+            assert!(delay <= step && delay >= step / 2, "{attempt}: {delay:?}");
+        }
+    }
 }
 
 // -----------------------------------------------------------------------------
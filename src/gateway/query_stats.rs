@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Weight of each new query in the moving averages: the last few dozen
+/// queries dominate, so a shard that slows down shows it within seconds.
+const SMOOTHING: f64 = 0.1;
+
+// -----------------------------------------------------------------------------
+// ----- QueryStats ------------------------------------------------------------
+
+/// Exponentially weighted moving averages of a shard's query latency and
+/// error rate, one sample per ReadyForQuery.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryStats {
+    latency_micros: f64,
+    error_rate: f64,
+    samples: u64,
+}
+
+// -----------------------------------------------------------------------------
+// ----- QueryStats: Public ----------------------------------------------------
+
+impl QueryStats {
+    /// Folds in one query or batch, from send to ReadyForQuery; `failed`
+    /// when the backend answered it with an ErrorResponse. The first
+    /// sample seeds the averages.
+    pub fn record(&mut self, latency: Duration, failed: bool) {
+        let latency_micros = latency.as_secs_f64() * 1_000_000.0;
+        let error = if failed { 1.0 } else { 0.0 };
+
+        if self.samples == 0 {
+            self.latency_micros = latency_micros;
+            self.error_rate = error;
+        } else {
+            self.latency_micros += SMOOTHING * (latency_micros - self.latency_micros);
+            self.error_rate += SMOOTHING * (error - self.error_rate);
+        }
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    pub fn avg_latency_micros(&self) -> u64 {
+        self.latency_micros.round() as u64
+    }
+
+    /// Between 0 and 1.
+    pub fn error_rate(&self) -> f64 {
+        self.error_rate
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_converge_on_recent_queries() {
+        let mut stats = QueryStats::default();
+        assert_eq!(stats.avg_latency_micros(), 0);
+        assert_eq!(stats.samples(), 0);
+
+        stats.record(Duration::from_micros(1000), false);
+        assert_eq!(stats.avg_latency_micros(), 1000);
+        assert_eq!(stats.error_rate(), 0.0);
+
+        // One step moves a tenth of the way.
+        stats.record(Duration::from_micros(2000), true);
+        assert_eq!(stats.avg_latency_micros(), 1100);
+        assert!((stats.error_rate() - 0.1).abs() < 1e-9);
+
+        // The shard slows down for good: the old latency fades out.
+        for _ in 0..200 {
+            stats.record(Duration::from_micros(5000), false);
+        }
+        assert_eq!(stats.avg_latency_micros(), 5000);
+        assert!(stats.error_rate() < 1e-6);
+
+        // Every other query failing settles around half.
+        for i in 0..200 {
+            stats.record(Duration::from_micros(5000), i % 2 == 0);
+        }
+        assert!((stats.error_rate() - 0.5).abs() < 0.06);
+        assert_eq!(stats.samples(), 402);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
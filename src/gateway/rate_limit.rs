@@ -0,0 +1,150 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use crate::config::users::RateLimit;
+
+// -----------------------------------------------------------------------------
+// ----- Global Registry -------------------------------------------------------
+
+/// One limiter per username, shared by all of that user's connections.
+static LIMITERS: OnceLock<Mutex<HashMap<String, Arc<RateLimiter>>>> = OnceLock::new();
+
+// -----------------------------------------------------------------------------
+// ----- RateLimiter -----------------------------------------------------------
+
+/// Token bucket: a query takes one token, tokens come back at
+/// `queries_per_second` up to `burst`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+// -----------------------------------------------------------------------------
+// ----- RateLimiter: Static ---------------------------------------------------
+
+impl RateLimiter {
+    /// Starts with a full bucket.
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(limit.burst),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// The limiter `username`'s connections share. After a reload changes
+    /// the limit, connections made from then on share a fresh bucket.
+    pub fn for_user(username: &str, limit: RateLimit) -> Arc<RateLimiter> {
+        let mut limiters = LIMITERS.get_or_init(Default::default).lock();
+        if let Some(limiter) = limiters.get(username)
+            && limiter.limit == limit
+        {
+            return Arc::clone(limiter);
+        }
+
+        let limiter = Arc::new(Self::new(limit));
+        limiters.insert(username.to_string(), Arc::clone(&limiter));
+        limiter
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- RateLimiter: Public ---------------------------------------------------
+
+impl RateLimiter {
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Takes a token for one query; `false` when the bucket is empty.
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- RateLimiter: Private --------------------------------------------------
+
+impl RateLimiter {
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let mut bucket = self.bucket.lock();
+        if now > bucket.refilled_at {
+            let refill =
+                (now - bucket.refilled_at).as_secs_f64() * f64::from(self.limit.queries_per_second);
+            bucket.tokens = (bucket.tokens + refill).min(f64::from(self.limit.burst));
+            bucket.refilled_at = now;
+        }
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limit(queries_per_second: u32, burst: u32) -> RateLimit {
+        RateLimit {
+            queries_per_second,
+            burst,
+        }
+    }
+
+    #[test]
+    fn burst_is_rejected_past_its_size_then_refills() {
+        let limiter = RateLimiter::new(limit(2, 3));
+        let start = limiter.bucket.lock().refilled_at;
+
+        assert!((0..3).all(|_| limiter.try_acquire_at(start)));
+        assert!(!limiter.try_acquire_at(start));
+
+        // Half a second at 2/s is one query.
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.try_acquire_at(later));
+        assert!(!limiter.try_acquire_at(later));
+
+        // A long pause refills no more than the burst.
+        let idle = later + Duration::from_secs(60);
+        assert!((0..3).all(|_| limiter.try_acquire_at(idle)));
+        assert!(!limiter.try_acquire_at(idle));
+    }
+
+    #[test]
+    fn connections_of_one_user_share_a_bucket() {
+        let first = RateLimiter::for_user("rate_limit_shared", limit(1, 1));
+        let second = RateLimiter::for_user("rate_limit_shared", limit(1, 1));
+        assert!(Arc::ptr_eq(&first, &second));
+
+        assert!(first.try_acquire());
+        assert!(!second.try_acquire());
+
+        let other = RateLimiter::for_user("rate_limit_other", limit(1, 1));
+        assert!(other.try_acquire());
+
+        let reloaded = RateLimiter::for_user("rate_limit_shared", limit(1, 5));
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+        assert_eq!(reloaded.limit(), limit(1, 5));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
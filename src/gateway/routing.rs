@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use tracing::{Level, debug, enabled};
+
+use crate::config::routing::RoutingConfig;
+use crate::gateway::{GatewayPools, ShardPool};
+use crate::parser::ParsedQuery;
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Tracing target for routing decisions; logged with `[routing] trace` at
+/// debug level.
+pub const ROUTING_TRACE_TARGET: &str = "pgcrab::routing";
+
+// -----------------------------------------------------------------------------
+// ----- RoutingStrategy -------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutingStrategy {
+    Random,
+    Primary,
+    ReadReplica,
+    Pinned,
+}
+
+impl RoutingStrategy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RoutingStrategy::Random => "random",
+            RoutingStrategy::Primary => "primary",
+            RoutingStrategy::ReadReplica => "read_replica",
+            RoutingStrategy::Pinned => "pinned",
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- RoutingDecision -------------------------------------------------------
+
+#[derive(Debug)]
+pub struct RoutingDecision {
+    pub pool: Arc<ShardPool>,
+    pub strategy: RoutingStrategy,
+    pub reason: &'static str,
+}
+
+// -----------------------------------------------------------------------------
+// ----- RoutingDecision: Static -----------------------------------------------
+
+impl RoutingDecision {
+    /// `server_role` restricts the pick to pools logging in as that role.
+    /// With a `[routing]` primary, `read_only` work goes to a read replica
+    /// when one is taking checkouts and everything else to the primary.
+    pub fn route(
+        pools: &GatewayPools,
+        routing: &RoutingConfig,
+        server_role: Option<&str>,
+        read_only: bool,
+    ) -> Option<Self> {
+        let Some(primary) = routing.primary.as_deref() else {
+            let pool = pools.random_pool_as(server_role)?;
+            return Some(Self {
+                pool,
+                strategy: RoutingStrategy::Random,
+                reason: "no routing rules -> uniform random shard",
+            });
+        };
+
+        if read_only
+            && let Some(pool) = pools.random_pool_among(&routing.read_replicas, server_role)
+        {
+            return Some(Self {
+                pool,
+                strategy: RoutingStrategy::ReadReplica,
+                reason: "standalone read -> random read replica",
+            });
+        }
+
+        let pool = pools.pool_for(primary, server_role)?;
+        let reason = if read_only {
+            "no read replica available -> primary"
+        } else {
+            "write or transaction -> primary"
+        };
+        Some(Self {
+            pool,
+            strategy: RoutingStrategy::Primary,
+            reason,
+        })
+    }
+
+    /// The shard a client pinned with `pgcrab.shard`, whatever the routing
+    /// rules say; `None` when it has no pool for `server_role`. The caller
+    /// decides what to do about a paused or unhealthy pool.
+    pub fn pinned(
+        pools: &GatewayPools,
+        shard_name: &str,
+        server_role: Option<&str>,
+    ) -> Option<Self> {
+        let pool = pools.pool_for(shard_name, server_role)?;
+        Some(Self {
+            pool,
+            strategy: RoutingStrategy::Pinned,
+            reason: "pgcrab.shard startup option -> pinned shard",
+        })
+    }
+
+    /// Cheap check so callers can skip parsing when nobody will see the
+    /// trace: `[routing] trace` is on and the target logs at debug.
+    #[inline]
+    pub fn trace_enabled(routing: &RoutingConfig) -> bool {
+        routing.trace && enabled!(target: ROUTING_TRACE_TARGET, Level::DEBUG)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- RoutingDecision: Public -----------------------------------------------
+
+impl RoutingDecision {
+    pub fn trace(&self, statement: Option<&ParsedQuery>, in_transaction: bool) {
+        let statement_type = statement.map(|parsed| parsed.statement_type);
+        let tables = statement
+            .map(|parsed| parsed.tables.as_slice())
+            .unwrap_or(&[]);
+
+        debug!(
+            target: ROUTING_TRACE_TARGET,
+            statement_type = ?statement_type,
+            tables = ?tables,
+            in_transaction,
+            strategy = self.strategy.as_str(),
+            pool = self.pool.name(),
+            reason = self.reason,
+            "routing decision"
+        );
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::shards::ShardRecord;
+    use crate::parser;
+    use parking_lot::Mutex;
+    use secrecy::SecretString;
+    use std::fmt;
+    use tracing::field::{Field, Visit};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::{Layer, registry};
+
+    #[derive(Clone, Default)]
+    struct CapturedFields(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl Visit for CapturedFields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .lock()
+                .push((field.name().to_string(), format!("{value:?}")));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0
+                .lock()
+                .push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for CapturedFields {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            event.record(&mut self.clone());
+        }
+    }
+
+    fn shard(name: &str) -> ShardRecord {
+        ShardRecord {
+            shard_name: name.to_string(),
+            host: "127.0.0.1".to_string(),
+            user: "user".to_string(),
+            password: SecretString::new("secret".to_string().into_boxed_str()),
+            min_connections: 1,
+            max_connections: 1,
+            ..ShardRecord::default()
+        }
+    }
+
+    #[test]
+    fn route_returns_none_without_pools() {
+        let pools = GatewayPools::new(Vec::new());
+        let routing = RoutingConfig::default();
+        assert!(RoutingDecision::route(&pools, &routing, None, false).is_none());
+    }
+
+    #[test]
+    fn traces_select_decision_fields() {
+        let pools = GatewayPools::new(vec![shard("alpha")]);
+        let routing = RoutingConfig::default();
+        let decision = RoutingDecision::route(&pools, &routing, None, false).expect("decision");
+        let parsed = parser::parse("SELECT * FROM users").expect("parse select");
+
+        let captured = CapturedFields::default();
+        let subscriber = registry().with(captured.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            // Debug is on, but routing doesn't ask for the trace.
+            assert!(!RoutingDecision::trace_enabled(&routing));
+            let traced = RoutingConfig {
+                trace: true,
+                ..RoutingConfig::default()
+            };
+            assert!(RoutingDecision::trace_enabled(&traced));
+            decision.trace(Some(&parsed), false);
+        });
+
+        let fields = captured.0.lock().clone();
+        let get = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+
+        assert_eq!(get("statement_type").as_deref(), Some("Some(Select)"));
+        assert_eq!(get("tables").as_deref(), Some("[\"users\"]"));
+        assert_eq!(get("in_transaction").as_deref(), Some("false"));
+        assert_eq!(get("strategy").as_deref(), Some("random"));
+        assert_eq!(get("pool").as_deref(), Some("alpha"));
+        assert!(get("reason").is_some());
+    }
+
+    #[test]
+    fn reads_go_to_replicas_and_the_rest_to_the_primary() {
+        let pools = GatewayPools::new(vec![shard("main"), shard("replica")]);
+        let routing = RoutingConfig {
+            primary: Some("main".to_string()),
+            read_replicas: vec!["replica".to_string()],
+            trace: false,
+        };
+
+        let read = RoutingDecision::route(&pools, &routing, None, true).expect("decision");
+        assert_eq!(read.pool.name(), "replica");
+        assert_eq!(read.strategy, RoutingStrategy::ReadReplica);
+
+        let write = RoutingDecision::route(&pools, &routing, None, false).expect("decision");
+        assert_eq!(write.pool.name(), "main");
+        assert_eq!(write.strategy, RoutingStrategy::Primary);
+
+        // A paused replica leaves reads to the primary.
+        pools.get("replica").unwrap().pause();
+        let read = RoutingDecision::route(&pools, &routing, None, true).expect("decision");
+        assert_eq!(read.pool.name(), "main");
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
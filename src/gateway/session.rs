@@ -1,17 +1,20 @@
 use std::sync::Arc;
 
-use crate::backend::BackendConnection;
-use crate::gateway::{PooledConnection, ShardPool};
+use crate::backend::{BackendConnection, ConnectTimings};
+use crate::gateway::{GatewayError, PooledConnection, ShardPool};
+use crate::shared_types::BackendIdentity;
 
 #[derive(Debug)]
 pub struct GatewaySession {
     backend: PooledConnection,
+    identity: Option<BackendIdentity>,
 }
 
 impl GatewaySession {
-    pub async fn from_pool(pool: &Arc<ShardPool>) -> Result<Self, String> {
-        let backend = pool.acquire().await?;
-        let mut session = Self { backend };
+    pub async fn from_pool(pool: &Arc<ShardPool>) -> Result<Self, GatewayError> {
+        let mut backend = pool.acquire().await?;
+        let identity = backend.connection().identity();
+        let mut session = Self { backend, identity };
         let _ = session.backend.connection().peer_addr();
         Ok(session)
     }
@@ -19,4 +22,30 @@ impl GatewaySession {
     pub fn backend(&mut self) -> &mut BackendConnection {
         self.backend.connection()
     }
+
+    /// The pool the backend came from, and goes back to.
+    pub fn pool(&self) -> &Arc<ShardPool> {
+        self.backend.pool()
+    }
+
+    /// The backend's real pid and secret key, as it sent them at startup.
+    pub fn backend_identity(&self) -> Option<BackendIdentity> {
+        self.identity
+    }
+
+    /// How long opening the backend took; `None` if it came from the pool.
+    pub fn opened_timings(&self) -> Option<ConnectTimings> {
+        self.backend.opened_timings()
+    }
+
+    /// Closes the backend when the session drops instead of pooling it.
+    pub fn discard(&mut self) {
+        self.backend.discard();
+    }
+
+    /// Sends the shard's reset query and waits for ReadyForQuery, so the
+    /// backend returns to the pool without this client's session state.
+    pub async fn reset(&mut self) -> Result<(), String> {
+        self.backend.reset().await
+    }
 }
@@ -5,7 +5,9 @@ pub mod config;
 pub mod errors;
 pub mod frontend;
 pub mod gateway;
+pub mod logging;
 pub mod parser;
+pub mod server;
 pub mod shared_types;
 pub mod tls;
 pub mod wire;
@@ -13,3 +15,4 @@ pub mod wire;
 pub use config::Config;
 pub use errors::ErrorResponse;
 pub use frontend::FrontendConnection;
+pub use server::{Server, ServerBuilder};
@@ -0,0 +1,161 @@
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+
+use tracing::{Span, Subscriber, field};
+use tracing_subscriber::fmt::{self, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+use crate::config::types::{LogFormat, LogLevel};
+
+// -----------------------------------------------------------------------------
+// ----- Globals ---------------------------------------------------------------
+
+type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Filter of the installed global subscriber, swapped on config reload.
+static FILTER: OnceLock<FilterHandle> = OnceLock::new();
+
+// -----------------------------------------------------------------------------
+// ----- Logging: Exported -----------------------------------------------------
+
+/// Installs the global subscriber; a second call is a no-op.
+pub fn init(level: &LogLevel, format: &LogFormat) {
+    let filter = EnvFilter::try_new(level.clone().as_str()).unwrap();
+    let (subscriber, handle) = subscriber(filter, format, std::io::stdout);
+    if subscriber.try_init().is_ok() {
+        let _ = FILTER.set(handle);
+    }
+}
+
+/// Swaps the global subscriber's level in place; a no-op before `init`.
+pub fn set_level(level: &LogLevel) {
+    if let Some(handle) = FILTER.get() {
+        apply_level(handle, level);
+    }
+}
+
+/// Span wrapping one client connection's `serve()`, keyed by a random
+/// correlation id. `user`, `database` and `backend_pid` are recorded as they
+/// become known.
+pub fn connection_span(id: u64, peer: Option<SocketAddr>) -> Span {
+    let span = tracing::info_span!(
+        "conn",
+        id = %format_args!("{id:016x}"),
+        peer = field::Empty,
+        user = field::Empty,
+        database = field::Empty,
+        backend_pid = field::Empty,
+    );
+    if let Some(peer) = peer {
+        span.record("peer", field::display(peer));
+    }
+    span
+}
+
+// -----------------------------------------------------------------------------
+// ----- Logging: Private helpers ----------------------------------------------
+
+fn subscriber<W>(
+    filter: EnvFilter,
+    format: &LogFormat,
+    writer: W,
+) -> (Box<dyn Subscriber + Send + Sync>, FilterHandle)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+    let layer = fmt::layer().with_target(false).with_writer(writer);
+
+    let subscriber: Box<dyn Subscriber + Send + Sync> = match format {
+        LogFormat::Text => Box::new(registry.with(layer)),
+        LogFormat::Json => Box::new(registry.with(layer.json().with_span_list(false))),
+    };
+    (subscriber, handle)
+}
+
+fn apply_level(handle: &FilterHandle, level: &LogLevel) {
+    let filter = EnvFilter::try_new(level.clone().as_str()).unwrap();
+    if let Err(e) = handle.reload(filter) {
+        tracing::error!("failed to apply log level {}: {e}", level.clone().as_str());
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines_carry_connection_fields() {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let filter = EnvFilter::try_new("info").unwrap();
+        let (subscriber, _) = subscriber(filter, &LogFormat::Json, move || writer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = connection_span(0xc0ffee, Some("127.0.0.1:5555".parse().unwrap()));
+            let _entered = span.enter();
+            Span::current().record("user", "pgcrab");
+            Span::current().record("backend_pid", 4242);
+            tracing::info!("hello from a client");
+        });
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let line = output.lines().next().expect("one log line");
+        let json: serde_json::Value = serde_json::from_str(line).expect("valid JSON");
+
+        assert_eq!(json["level"], "INFO");
+        assert!(json["timestamp"].is_string());
+        assert_eq!(json["fields"]["message"], "hello from a client");
+        assert_eq!(json["span"]["name"], "conn");
+        assert_eq!(json["span"]["id"], "0000000000c0ffee");
+        assert_eq!(json["span"]["peer"], "127.0.0.1:5555");
+        assert_eq!(json["span"]["user"], "pgcrab");
+        assert_eq!(json["span"]["backend_pid"], 4242);
+    }
+
+    #[test]
+    fn reloaded_level_updates_the_filter() {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let filter = EnvFilter::try_new(LogLevel::Info.as_str()).unwrap();
+        let (subscriber, handle) = subscriber(filter, &LogFormat::Text, move || writer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("hidden at info");
+            apply_level(&handle, &LogLevel::Debug);
+            tracing::debug!("shown at debug");
+
+            let current = handle.with_current(|filter| filter.to_string()).unwrap();
+            assert_eq!(current, "debug");
+        });
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert!(!output.contains("hidden at info"));
+        assert!(output.contains("shown at debug"));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -4,23 +4,16 @@ use std::{
     net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
 };
-use tokio::net::{TcpListener, TcpSocket};
 use tokio::signal;
-use tracing::{error, info};
-use tracing_subscriber::{EnvFilter, fmt};
-
-use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 use pgcrab::{
-    Config, FrontendConnection, admin, config::shards::ShardsConfig, config::types::LogLevel,
-    gateway::GatewayPools, parser,
+    Config, Server, admin,
+    config::check::ConfigReport,
+    config::types::{LogFormat, LogLevel},
+    logging,
 };
 
-// -----------------------------------------------------------------------------
-// ----- Constants -------------------------------------------------------------
-
-const APP_NAME: &str = "🦀 PgCrab";
-
 // -----------------------------------------------------------------------------
 // ----- Main ------------------------------------------------------------------
 
@@ -32,13 +25,13 @@ async fn main() -> std::io::Result<()> {
             command: Some(Command::Admin(admin_args)),
             ..
         } => {
-            run_admin(admin_args);
+            run_admin(admin_args).await;
             Ok(())
         }
         args => {
             let serve_args = args.into_serve_args();
-            setup(&serve_args).await;
-            run_forever().await
+            let server = setup(serve_args).await?;
+            server.run(shutdown_on_ctrl_c()).await
         }
     }
 }
@@ -46,79 +39,38 @@ async fn main() -> std::io::Result<()> {
 // -----------------------------------------------------------------------------
 // ----- Setup -----------------------------------------------------------------
 
-async fn setup(args: &ServeArgs) {
+async fn setup(args: ServeArgs) -> std::io::Result<Server> {
     must_exist_file(&args.config_file, "--config / pgcrab.toml");
 
-    let listen_addr = SocketAddr::from((args.host, args.port));
-    Config::init(
-        listen_addr,
-        args.log_level.clone(),
-        args.parser_cache_capacity,
-        args.config_file.clone(),
-    )
-    .await;
-
-    parser::init_cache(args.parser_cache_capacity);
+    let server = Server::builder()
+        .default_listen(SocketAddr::from((args.host, args.port)))
+        .config(args.config_file)
+        .log_level(args.log_level)
+        .log_format(args.log_format)
+        .parser_cache_capacity(args.parser_cache_capacity)
+        .strict_parse(args.strict_parse)
+        .reload_on_sighup(true)
+        .build()
+        .await?;
 
     init_tracing();
+    Ok(server)
 }
 
 fn init_tracing() {
-    let config = Config::snapshot();
-    let filter = EnvFilter::try_new(config.log_level.as_str()).unwrap();
-    let _ = fmt().with_env_filter(filter).with_target(false).try_init();
+    let config = Config::handle();
+    logging::init(&config.log_level, &config.log_format);
 }
 
-// -----------------------------------------------------------------------------
-// ----- Run -------------------------------------------------------------------
-
-async fn run_forever() -> std::io::Result<()> {
-    let config = Config::snapshot();
-
-    let pools = Arc::new(GatewayPools::new(ShardsConfig::snapshot()));
-    pools.warm_all().await;
-
-    let socket = if config.listen_addr.is_ipv4() {
-        TcpSocket::new_v4()?
-    } else {
-        TcpSocket::new_v6()?
-    };
-
-    socket.bind(config.listen_addr)?;
-
-    let listener: TcpListener = socket.listen(1024)?;
-
-    info!("{} :: Listening on {}", APP_NAME, config.listen_addr);
-
-    loop {
-        tokio::select! {
-            _ = signal::ctrl_c() => {
-                info!("{} :: Shutting down", APP_NAME);
-                break;
-            }
-
-            accept_res = listener.accept() => {
-                let (stream, peer) = match accept_res {
-                    Ok(v) => v,
-                    Err(e) => { error!("accept error: {e}"); continue; }
-                };
-
-                // You can still set nodelay on the Tokio stream.
-                let _ = stream.set_nodelay(true);
-
-                let pools = pools.clone();
-                tokio::spawn(async move {
-                    let conn = FrontendConnection::new(stream, pools);
-
-                    if let Err(e) = conn.serve().await {
-                        error!("client {peer} error: {e}");
-                    }
-                });
-            }
+fn shutdown_on_ctrl_c() -> CancellationToken {
+    let shutdown = CancellationToken::new();
+    let token = shutdown.clone();
+    tokio::spawn(async move {
+        if signal::ctrl_c().await.is_ok() {
+            token.cancel();
         }
-    }
-
-    Ok(())
+    });
+    shutdown
 }
 
 // -----------------------------------------------------------------------------
@@ -142,6 +94,10 @@ struct Args {
     #[arg(long = "log", default_value = "info")]
     log_level: LogLevel,
 
+    // Not required via CLI or ENV (defaults to text).
+    #[arg(long = "log-format", env = "PGCRAB_LOG_FORMAT", default_value = "text")]
+    log_format: LogFormat,
+
     #[arg(
         long = "parser-cache-capacity",
         env = "PGCRAB_PARSER_CACHE_CAPACITY",
@@ -149,6 +105,10 @@ struct Args {
     )]
     parser_cache_capacity: usize,
 
+    // Reject SQL the parser can't understand instead of forwarding it.
+    #[arg(long = "strict-parse", env = "PGCRAB_STRICT_PARSE")]
+    strict_parse: bool,
+
     // Must exist; no defaults.
     #[arg(long = "config", env = "PGCRAB_CONFIG_FILE")]
     config_file: Option<PathBuf>,
@@ -167,7 +127,29 @@ struct AdminArgs {
 
 #[derive(Subcommand, Debug)]
 enum AdminCommand {
-    Stats,
+    Stats {
+        /// Print the stats as one JSON object instead of `key=value` lines.
+        #[arg(long)]
+        json: bool,
+
+        /// A running pgcrab (`host:port`) to read live stats from, as an
+        /// admin user; without it, only this process's (empty) counters.
+        #[arg(long = "connect", value_parser = parse_host_port)]
+        connect: Option<(String, u16)>,
+
+        #[arg(long = "user", env = "PGCRAB_ADMIN_USER", default_value = "admin")]
+        user: String,
+
+        // Defaults to the user name, as with psql.
+        #[arg(long = "dbname")]
+        dbname: Option<String>,
+    },
+    /// Validate the config file and exit non-zero on any error, without
+    /// starting the server.
+    CheckConfig {
+        #[arg(long = "config", env = "PGCRAB_CONFIG_FILE")]
+        config_file: PathBuf,
+    },
 }
 
 #[derive(Debug)]
@@ -175,7 +157,9 @@ struct ServeArgs {
     host: IpAddr,
     port: u16,
     log_level: LogLevel,
+    log_format: LogFormat,
     parser_cache_capacity: usize,
+    strict_parse: bool,
     config_file: PathBuf,
 }
 
@@ -185,10 +169,12 @@ impl Args {
             host: expect_arg(self.host, "host", "--host / PGCRAB_HOST"),
             port: expect_arg(self.port, "port", "--port / PGCRAB_PORT"),
             log_level: self.log_level,
+            log_format: self.log_format,
             parser_cache_capacity: expect_positive(
                 self.parser_cache_capacity,
                 "parser-cache-capacity",
             ),
+            strict_parse: self.strict_parse,
             config_file: expect_arg(self.config_file, "config", "--config / PGCRAB_CONFIG_FILE"),
         }
     }
@@ -205,6 +191,21 @@ fn expect_positive(value: usize, name: &str) -> usize {
     value
 }
 
+/// `host:port`, with IPv6 hosts in brackets (`[::1]:6432`).
+fn parse_host_port(value: &str) -> Result<(String, u16), String> {
+    let (host, port) = value
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected host:port, got '{value}'"))?;
+    let port = port
+        .parse()
+        .map_err(|_| format!("invalid port in '{value}'"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return Err(format!("missing host in '{value}'"));
+    }
+    Ok((host.to_string(), port))
+}
+
 fn must_exist_file(path: &Path, hint: &str) {
     let md = fs::metadata(path).unwrap_or_else(|_| {
         panic!("required file missing: {} (from {hint})", path.display());
@@ -215,14 +216,78 @@ fn must_exist_file(path: &Path, hint: &str) {
     }
 }
 
-fn run_admin(args: AdminArgs) {
+async fn run_admin(args: AdminArgs) {
     match args.command {
-        AdminCommand::Stats => {
+        AdminCommand::Stats {
+            json,
+            connect: Some((host, port)),
+            user,
+            dbname,
+        } => {
+            let rows = fetch_live(host, port, user, dbname)
+                .await
+                .unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                });
+            if json {
+                println!("{}", admin::format_metrics_json(&rows));
+            } else {
+                println!("{}", admin::format_metrics(&rows));
+            }
+        }
+        AdminCommand::Stats { json, .. } => {
             let stats = admin::parse_cache_stats();
-            println!("{}", admin::format_parse_cache_stats(stats));
+            if json {
+                println!("{}", admin::format_parse_cache_stats_json(stats));
+            } else {
+                println!("{}", admin::format_parse_cache_stats(stats));
+            }
+        }
+        AdminCommand::CheckConfig { config_file } => {
+            let report = ConfigReport::check(&config_file).await;
+            println!("{report}");
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
         }
     }
 }
 
+/// `SHOW PGCRAB ANALYTICS` from a running pgcrab. The password comes from
+/// `PGCRAB_ADMIN_PASSWORD` or the terminal, never the command line, so it
+/// stays out of `ps` and shell history.
+#[cfg(feature = "live-stats")]
+async fn fetch_live(
+    host: String,
+    port: u16,
+    user: String,
+    dbname: Option<String>,
+) -> Result<Vec<(String, String)>, String> {
+    let password = match std::env::var("PGCRAB_ADMIN_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => rpassword::prompt_password(format!("Password for user {user}: "))
+            .map_err(|e| format!("could not read the password: {e}"))?,
+    };
+    let target = admin::LiveTarget {
+        host,
+        port,
+        dbname: dbname.unwrap_or_else(|| user.clone()),
+        user,
+        password,
+    };
+    admin::fetch_analytics(&target).await
+}
+
+#[cfg(not(feature = "live-stats"))]
+async fn fetch_live(
+    _host: String,
+    _port: u16,
+    _user: String,
+    _dbname: Option<String>,
+) -> Result<Vec<(String, String)>, String> {
+    Err("--connect needs pgcrab built with `--features live-stats`".to_string())
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
@@ -1,17 +1,25 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, OnceLock};
 
 use lru::LruCache;
 use parking_lot::RwLock;
 use pg_query::ParseResult;
+use pg_query::protobuf::{Node, SelectStmt, node::Node as NodeEnum};
 use tracing::{debug, warn};
 
 use crate::analytics;
 
 const DEFAULT_CACHE_CAPACITY: usize = 1024;
+/// `DeclareCursorStmt.options` bit for `WITH HOLD`.
+const CURSOR_OPT_HOLD: i32 = 0x0020;
 static CACHE_CAPACITY: OnceLock<NonZeroUsize> = OnceLock::new();
 
+/// `[parser] normalize`: `parse` keys the cache by fingerprint.
+static NORMALIZED_KEYS: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StatementType {
     Select,
@@ -21,24 +29,139 @@ pub enum StatementType {
     Other,
 }
 
+impl StatementType {
+    /// Statement type behind a CommandComplete tag's first word.
+    pub fn from_command_tag(command: &str) -> Self {
+        match command {
+            "SELECT" => StatementType::Select,
+            "INSERT" => StatementType::Insert,
+            "UPDATE" => StatementType::Update,
+            "DELETE" => StatementType::Delete,
+            _ => StatementType::Other,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ParsedQuery {
     pub statement_type: StatementType,
+    /// The first statement is a SELECT that changes nothing: no `FOR
+    /// UPDATE`/`FOR SHARE`, no `SELECT INTO`, no `INSERT`/`UPDATE`/`DELETE`
+    /// in its `WITH`.
+    pub read_only: bool,
     pub tables: Vec<String>,
-    #[allow(dead_code)]
-    pub(crate) ast: Arc<ParseResult>,
+    /// Node name of every statement in the query, e.g. `DropStmt`; the
+    /// rest of `ParsedQuery` only describes the first.
+    pub statement_types: Vec<String>,
+    /// Functions called anywhere in the query, schema-qualified when the
+    /// query qualifies them (`pg_catalog.pg_advisory_lock`).
+    pub functions: Vec<String>,
+    /// Some statement declares a `WITH HOLD` cursor, which outlives its
+    /// transaction.
+    pub holds_cursor: bool,
+    pub(crate) syntax: Syntax,
+}
+
+/// Where `ParsedQuery::ast` comes from.
+#[derive(Debug, Clone)]
+pub(crate) enum Syntax {
+    /// The query's own first statement, cached with it.
+    Tree(Arc<ParseResult>),
+    /// The query's text. Normalized entries are shared by every query with
+    /// the same fingerprint, whose trees differ in literals and aliases, so
+    /// they keep none; see `parse_normalized`.
+    Text(Arc<str>),
+}
+
+/// A column in a SELECT list, as written there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnRef {
+    /// What the column is qualified with: `u` in `u.email`, `app.users` in
+    /// `app.users.email`.
+    pub table: Option<String>,
+    /// The column's name, or `*` for a wildcard; see `is_wildcard`.
+    pub column: String,
+    /// Set by `AS` (or a bare alias) in the query.
+    pub alias: Option<String>,
+}
+
+impl ColumnRef {
+    pub const WILDCARD: &'static str = "*";
+
+    /// `*` or `t.*`: every column of the query's (or `t`'s) tables.
+    pub fn is_wildcard(&self) -> bool {
+        self.column == Self::WILDCARD
+    }
+}
+
+impl ParsedQuery {
+    /// First statement's tree; parsed again from the text for a
+    /// normalized entry.
+    pub(crate) fn ast(&self) -> Option<Cow<'_, ParseResult>> {
+        match &self.syntax {
+            Syntax::Tree(ast) => Some(Cow::Borrowed(ast)),
+            Syntax::Text(query) => pg_query::parse(query)
+                .ok()
+                .map(|ast| Cow::Owned(first_statement_only(ast))),
+        }
+    }
+
+    /// Column references a SELECT returns, in order; for a UNION and the
+    /// like, those of its first arm, which name the result. Entries that
+    /// aren't a plain column (`count(*)`, `lower(email)`, literals) are
+    /// left out, as is anything that isn't a SELECT.
+    pub fn output_columns(&self) -> Vec<ColumnRef> {
+        let Some(ast) = self.ast() else {
+            return Vec::new();
+        };
+        let Some(statement) = ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|raw| raw.stmt.as_deref())
+        else {
+            return Vec::new();
+        };
+        let select: &SelectStmt = match &statement.node {
+            Some(NodeEnum::SelectStmt(select)) => select,
+            _ => return Vec::new(),
+        };
+
+        let mut first_arm = select;
+        while let Some(left) = first_arm.larg.as_deref() {
+            first_arm = left;
+        }
+        first_arm
+            .target_list
+            .iter()
+            .filter_map(output_column)
+            .collect()
+    }
 }
 
 #[derive(Debug)]
 pub struct ParseError {
     message: String,
+    position: Option<u32>,
 }
 
 impl ParseError {
-    fn new(message: impl Into<String>) -> Self {
-        Self {
-            message: message.into(),
-        }
+    fn from_pg_query(query: &str, err: pg_query::Error) -> Self {
+        let message = match err {
+            pg_query::Error::Parse(message) => message,
+            other => other.to_string(),
+        };
+        let position = error_position(query, &message);
+        Self { message, position }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// 1-based character offset into the query, as Postgres reports it.
+    pub fn position(&self) -> Option<u32> {
+        self.position
     }
 }
 
@@ -50,9 +173,38 @@ impl fmt::Display for ParseError {
 
 impl std::error::Error for ParseError {}
 
+/// Parses through the cache, keyed on the exact query text unless
+/// `[parser] normalize` is set; see `parse_normalized`.
 pub fn parse(query: &str) -> Result<ParsedQuery, ParseError> {
-    let cache = parser_cache();
-    let key = query.as_bytes();
+    if NORMALIZED_KEYS.load(Ordering::Relaxed) {
+        return parse_normalized(query);
+    }
+    parser_cache().parse(query)
+}
+
+/// Like `parse`, keyed on the query's fingerprint: queries differing only
+/// in whitespace, comments or literal values share one entry. Fingerprinting
+/// parses the query too, so this saves cache slots rather than parse time.
+/// Only the classification (statement types, tables, functions) is shared;
+/// `ast` parses this query's own text.
+pub fn parse_normalized(query: &str) -> Result<ParsedQuery, ParseError> {
+    parser_cache().parse_normalized(query)
+}
+
+/// Applies `[parser] normalize`; entries cached under the other mode's keys
+/// age out of the LRU.
+pub fn set_normalized_keys(normalize: bool) {
+    NORMALIZED_KEYS.store(normalize, Ordering::Relaxed);
+}
+
+/// `keep_tree: false` caches the text in place of the tree, for entries
+/// other queries share.
+fn parse_keyed(
+    cache: &ParserCache,
+    query: &str,
+    key: &[u8],
+    keep_tree: bool,
+) -> Result<ParsedQuery, ParseError> {
     if let Some(cached) = cache.get(key) {
         analytics::inc_parse_cache_hit();
         debug!(cache = "hit", query_len = query.len(), "parser cache");
@@ -61,24 +213,63 @@ pub fn parse(query: &str) -> Result<ParsedQuery, ParseError> {
 
     analytics::inc_parse_cache_miss();
     debug!(cache = "miss", query_len = query.len(), "parser cache");
-    let ast = pg_query::parse(query)
-        .map_err(|err| ParseError::new(err.to_string()))
-        .map(first_statement_only)?;
+    let ast = pg_query::parse(query).map_err(|err| ParseError::from_pg_query(query, err))?;
+    let statement_types = ast
+        .statement_types()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let functions = ast.functions();
+    let holds_cursor = holds_cursor(&ast);
+    let ast = first_statement_only(ast);
     let statement_type = statement_type_for(&ast);
+    let read_only = is_read_only(&ast);
     let mut tables = ast.tables();
     tables.sort();
 
+    let syntax = if keep_tree {
+        Syntax::Tree(Arc::new(ast))
+    } else {
+        Syntax::Text(Arc::from(query))
+    };
     let parsed = ParsedQuery {
         statement_type,
+        read_only,
         tables,
-        ast: Arc::new(ast),
+        statement_types,
+        functions,
+        holds_cursor,
+        syntax,
     };
 
-    let cached = cache.insert_if_missing(key.to_vec(), Arc::new(parsed));
+    let (cached, evicted) = cache.insert_if_missing(key.to_vec(), Arc::new(parsed));
+    if evicted {
+        analytics::inc_parse_cache_eviction();
+    }
 
     Ok((*cached).clone())
 }
 
+/// `query` with every literal replaced by a `$n` placeholder; `None` if it
+/// doesn't parse.
+pub fn normalize(query: &str) -> Option<String> {
+    pg_query::normalize(query).ok()
+}
+
+/// pg_query drops the cursor position, so recover it from the message text.
+/// Best effort: the first occurrence of the offending token wins.
+fn error_position(query: &str, message: &str) -> Option<u32> {
+    let char_offset = |byte_idx: usize| query[..byte_idx].chars().count() as u32 + 1;
+
+    if message.ends_with("at end of input") {
+        return Some(char_offset(query.len()));
+    }
+
+    let (_, rest) = message.split_once("at or near \"")?;
+    let token = rest.strip_suffix('"')?;
+    query.find(token).map(char_offset)
+}
+
 fn first_statement_only(ast: ParseResult) -> ParseResult {
     if ast.protobuf.stmts.len() <= 1 {
         return ast;
@@ -100,6 +291,77 @@ fn first_statement_only(ast: ParseResult) -> ParseResult {
     ParseResult::new(protobuf, String::new())
 }
 
+fn output_column(target: &Node) -> Option<ColumnRef> {
+    let Some(NodeEnum::ResTarget(target)) = &target.node else {
+        return None;
+    };
+    let Some(NodeEnum::ColumnRef(column_ref)) =
+        target.val.as_deref().and_then(|val| val.node.as_ref())
+    else {
+        return None;
+    };
+
+    let mut names = column_ref
+        .fields
+        .iter()
+        .map(|field| match &field.node {
+            Some(NodeEnum::String(name)) => Some(name.sval.as_str()),
+            Some(NodeEnum::AStar(_)) => Some(ColumnRef::WILDCARD),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let column = names.pop()?.to_string();
+
+    Some(ColumnRef {
+        table: (!names.is_empty()).then(|| names.join(".")),
+        column,
+        alias: (!target.name.is_empty()).then(|| target.name.clone()),
+    })
+}
+
+fn is_read_only(ast: &ParseResult) -> bool {
+    let Some(NodeEnum::SelectStmt(select)) = ast
+        .protobuf
+        .stmts
+        .first()
+        .and_then(|raw| raw.stmt.as_deref())
+        .and_then(|stmt| stmt.node.as_ref())
+    else {
+        return false;
+    };
+    if !select.locking_clause.is_empty() || select.into_clause.is_some() {
+        return false;
+    }
+    let Some(with) = &select.with_clause else {
+        return true;
+    };
+    !with.ctes.iter().any(|cte| {
+        let Some(NodeEnum::CommonTableExpr(cte)) = &cte.node else {
+            return false;
+        };
+        matches!(
+            cte.ctequery
+                .as_deref()
+                .and_then(|query| query.node.as_ref()),
+            Some(
+                NodeEnum::InsertStmt(_)
+                    | NodeEnum::UpdateStmt(_)
+                    | NodeEnum::DeleteStmt(_)
+                    | NodeEnum::MergeStmt(_)
+            )
+        )
+    })
+}
+
+fn holds_cursor(ast: &ParseResult) -> bool {
+    ast.protobuf.stmts.iter().any(|raw| {
+        matches!(
+            raw.stmt.as_deref().and_then(|stmt| stmt.node.as_ref()),
+            Some(NodeEnum::DeclareCursorStmt(declare)) if declare.options & CURSOR_OPT_HOLD != 0
+        )
+    })
+}
+
 fn statement_type_for(ast: &ParseResult) -> StatementType {
     match ast.statement_types().first().copied() {
         Some("SelectStmt") => StatementType::Select,
@@ -122,6 +384,25 @@ impl ParserCache {
         }
     }
 
+    fn parse(&self, query: &str) -> Result<ParsedQuery, ParseError> {
+        parse_keyed(self, query, query.as_bytes(), true)
+    }
+
+    fn parse_normalized(&self, query: &str) -> Result<ParsedQuery, ParseError> {
+        let fingerprint = match pg_query::fingerprint(query) {
+            Ok(fingerprint) => fingerprint.value,
+            // Not cached either way; let the parser report the error.
+            Err(_) => return self.parse(query),
+        };
+
+        // Query text never contains NUL, so this can't collide with an exact key.
+        let mut key = [0u8; 9];
+        key[1..].copy_from_slice(&fingerprint.to_be_bytes());
+        let mut parsed = parse_keyed(self, query, &key, false)?;
+        parsed.syntax = Syntax::Text(Arc::from(query));
+        Ok(parsed)
+    }
+
     fn len(&self) -> usize {
         self.entries.read().len()
     }
@@ -135,19 +416,25 @@ impl ParserCache {
         cache.get(key).cloned()
     }
 
-    fn insert_if_missing(&self, key: Vec<u8>, value: Arc<ParsedQuery>) -> Arc<ParsedQuery> {
+    /// The entry now cached under `key`, and whether making room for it
+    /// pushed out the least recently used one.
+    fn insert_if_missing(&self, key: Vec<u8>, value: Arc<ParsedQuery>) -> (Arc<ParsedQuery>, bool) {
         let mut cache = self.entries.write();
         if let Some(existing) = cache.get(&key) {
-            return existing.clone();
+            return (existing.clone(), false);
         }
 
         let was_full = cache.len() == cache.cap().get();
         cache.put(key, value.clone());
+        (value, was_full)
+    }
 
-        if was_full {
-            analytics::inc_parse_cache_eviction();
-        }
-        value
+    /// Drops every entry; returns how many there were.
+    fn clear(&self) -> usize {
+        let mut cache = self.entries.write();
+        let dropped = cache.len();
+        cache.clear();
+        dropped
     }
 }
 
@@ -165,6 +452,14 @@ pub fn cache_stats() -> CacheStats {
     }
 }
 
+/// Empties the parser cache, counting the dropped entries as manual
+/// evictions. Returns the number dropped.
+pub fn clear_cache() -> usize {
+    let dropped = parser_cache().clear();
+    analytics::add_parse_cache_manual_evictions(dropped);
+    dropped
+}
+
 pub fn init_cache(capacity: usize) {
     let requested = NonZeroUsize::new(capacity)
         .unwrap_or_else(|| NonZeroUsize::new(DEFAULT_CACHE_CAPACITY).expect("default capacity"));
@@ -205,6 +500,37 @@ mod tests {
         assert_eq!(parsed.tables, vec!["users"]);
     }
 
+    #[test]
+    fn only_plain_selects_are_read_only() {
+        for sql in [
+            "SELECT * FROM users WHERE id = 1",
+            "WITH recent AS (SELECT * FROM users) SELECT * FROM recent",
+        ] {
+            assert!(parse(sql).unwrap().read_only, "{sql}");
+        }
+        for sql in [
+            "SELECT * FROM users WHERE id = 1 FOR UPDATE",
+            "SELECT * FROM jobs FOR SHARE SKIP LOCKED",
+            "SELECT * INTO backup FROM users",
+            "WITH gone AS (DELETE FROM users RETURNING id) SELECT id FROM gone",
+            "INSERT INTO users (id) VALUES (1)",
+        ] {
+            let parsed = parse(sql).unwrap();
+            assert!(!parsed.read_only, "{sql}");
+        }
+    }
+
+    #[test]
+    fn with_hold_cursors_are_flagged() {
+        assert!(
+            parse("DECLARE c CURSOR WITH HOLD FOR SELECT 1")
+                .unwrap()
+                .holds_cursor
+        );
+        assert!(!parse("DECLARE c CURSOR FOR SELECT 1").unwrap().holds_cursor);
+        assert!(!parse("SELECT 1").unwrap().holds_cursor);
+    }
+
     #[test]
     fn parse_insert() {
         let parsed =
@@ -234,56 +560,246 @@ mod tests {
         assert_eq!(parsed.tables, vec!["first"]);
     }
 
+    #[test]
+    fn statement_types_cover_every_statement() {
+        let parsed = parse("SELECT 1; DROP TABLE users").expect("parse multi");
+        assert_eq!(parsed.statement_types, ["SelectStmt", "DropStmt"]);
+    }
+
+    #[test]
+    fn output_columns_follow_the_select_list() {
+        let parsed = parse("SELECT id, email AS e FROM users").expect("parse columns");
+        assert_eq!(
+            parsed.output_columns(),
+            [
+                ColumnRef {
+                    table: None,
+                    column: "id".to_string(),
+                    alias: None,
+                },
+                ColumnRef {
+                    table: None,
+                    column: "email".to_string(),
+                    alias: Some("e".to_string()),
+                },
+            ]
+        );
+
+        let parsed = parse("SELECT u.name, 1 FROM users").expect("parse qualified");
+        let columns = parsed.output_columns();
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns[0].table.as_deref(), Some("u"));
+        assert_eq!(columns[0].column, "name");
+
+        let parsed = parse("DELETE FROM users").expect("parse delete");
+        assert!(parsed.output_columns().is_empty());
+    }
+
+    #[test]
+    fn output_columns_mark_wildcards() {
+        let parsed = parse("SELECT * FROM t").expect("parse star");
+        let columns = parsed.output_columns();
+        assert_eq!(columns.len(), 1);
+        assert!(columns[0].is_wildcard());
+        assert_eq!(columns[0].table, None);
+
+        let parsed = parse("SELECT t.* FROM t").expect("parse qualified star");
+        let columns = parsed.output_columns();
+        assert!(columns[0].is_wildcard());
+        assert_eq!(columns[0].table.as_deref(), Some("t"));
+    }
+
+    fn tree(parsed: &ParsedQuery) -> &Arc<ParseResult> {
+        match &parsed.syntax {
+            Syntax::Tree(ast) => ast,
+            Syntax::Text(query) => panic!("no tree cached for {query}"),
+        }
+    }
+
+    fn local_cache() -> ParserCache {
+        ParserCache::new(NonZeroUsize::new(8).unwrap())
+    }
+
     #[test]
     fn cache_hits_reuse_ast() {
-        let parsed_one = parse("SELECT * FROM cache_hit").expect("parse cache hit 1");
-        let parsed_two = parse("SELECT * FROM cache_hit").expect("parse cache hit 2");
-        assert!(Arc::ptr_eq(&parsed_one.ast, &parsed_two.ast));
+        let cache = local_cache();
+        let parsed_one = cache
+            .parse("SELECT * FROM cache_hit")
+            .expect("parse cache hit 1");
+        let parsed_two = cache
+            .parse("SELECT * FROM cache_hit")
+            .expect("parse cache hit 2");
+        assert!(Arc::ptr_eq(tree(&parsed_one), tree(&parsed_two)));
     }
 
     #[test]
     fn cache_is_byte_exact() {
-        let parsed_one = parse("SELECT * FROM cache_exact").expect("parse cache exact 1");
-        let parsed_two = parse("SELECT  * FROM cache_exact").expect("parse cache exact 2");
-        assert!(!Arc::ptr_eq(&parsed_one.ast, &parsed_two.ast));
+        let cache = local_cache();
+        let parsed_one = cache
+            .parse("SELECT * FROM cache_exact")
+            .expect("parse cache exact 1");
+        let parsed_two = cache
+            .parse("SELECT  * FROM cache_exact")
+            .expect("parse cache exact 2");
+        assert!(!Arc::ptr_eq(tree(&parsed_one), tree(&parsed_two)));
+    }
+
+    #[test]
+    fn normalized_keys_ignore_whitespace() {
+        let cache = local_cache();
+        let parsed_one = cache
+            .parse_normalized("SELECT * FROM cache_normalized WHERE id = 1")
+            .expect("parse normalized 1");
+        let parsed_two = cache
+            .parse_normalized("SELECT  *\n  FROM cache_normalized WHERE id = 2")
+            .expect("parse normalized 2");
+        assert_eq!(parsed_one.tables, vec!["cache_normalized"]);
+        assert_eq!(parsed_two.tables, parsed_one.tables);
+
+        // The shared entry holds no tree: each query keeps its own text.
+        let Syntax::Text(text) = &parsed_two.syntax else {
+            panic!("normalized entry kept a tree");
+        };
+        assert_eq!(&**text, "SELECT  *\n  FROM cache_normalized WHERE id = 2");
+
+        // Exact keys don't see the normalized entry.
+        let exact = cache
+            .parse("SELECT * FROM cache_normalized WHERE id = 1")
+            .expect("parse exact");
+        tree(&exact);
+        assert_eq!(cache.len(), 2);
+
+        let err = cache
+            .parse_normalized("SELEC 1")
+            .expect_err("invalid syntax");
+        assert!(err.message().contains("syntax error"));
+    }
+
+    #[test]
+    fn normalized_output_columns_are_the_querys_own() {
+        let cache = local_cache();
+        let first = cache
+            .parse_normalized("SELECT id AS first_id FROM cache_aliases WHERE id = 1")
+            .expect("parse normalized 1");
+        let second = cache
+            .parse_normalized("SELECT id AS second_id FROM cache_aliases WHERE id = 2")
+            .expect("parse normalized 2");
+        assert_eq!(first.output_columns()[0].alias.as_deref(), Some("first_id"));
+        assert_eq!(
+            second.output_columns()[0].alias.as_deref(),
+            Some("second_id")
+        );
     }
 
     #[test]
     fn cache_evicts_least_recently_used() {
-        analytics::reset_parse_cache_counts();
         let cache = ParserCache::new(NonZeroUsize::new(2).unwrap());
 
         let first = Arc::new(ParsedQuery {
             statement_type: StatementType::Select,
+            read_only: true,
             tables: vec!["a".to_string()],
-            ast: Arc::new(pg_query::parse("SELECT 1").unwrap()),
+            statement_types: vec!["SelectStmt".to_string()],
+            functions: Vec::new(),
+            holds_cursor: false,
+            syntax: Syntax::Tree(Arc::new(pg_query::parse("SELECT 1").unwrap())),
         });
 
         let second = Arc::new(ParsedQuery {
             statement_type: StatementType::Select,
+            read_only: true,
             tables: vec!["b".to_string()],
-            ast: Arc::new(pg_query::parse("SELECT 2").unwrap()),
+            statement_types: vec!["SelectStmt".to_string()],
+            functions: Vec::new(),
+            holds_cursor: false,
+            syntax: Syntax::Tree(Arc::new(pg_query::parse("SELECT 2").unwrap())),
         });
 
         let third = Arc::new(ParsedQuery {
             statement_type: StatementType::Select,
+            read_only: true,
             tables: vec!["c".to_string()],
-            ast: Arc::new(pg_query::parse("SELECT 3").unwrap()),
+            statement_types: vec!["SelectStmt".to_string()],
+            functions: Vec::new(),
+            holds_cursor: false,
+            syntax: Syntax::Tree(Arc::new(pg_query::parse("SELECT 3").unwrap())),
         });
 
-        cache.insert_if_missing(b"one".to_vec(), first.clone());
-        cache.insert_if_missing(b"two".to_vec(), second.clone());
+        assert!(!cache.insert_if_missing(b"one".to_vec(), first.clone()).1);
+        assert!(!cache.insert_if_missing(b"two".to_vec(), second.clone()).1);
         assert_eq!(cache.len(), 2);
 
         cache.get(b"one");
-        cache.insert_if_missing(b"three".to_vec(), third.clone());
+        assert!(!cache.insert_if_missing(b"one".to_vec(), first.clone()).1);
+        assert!(cache.insert_if_missing(b"three".to_vec(), third.clone()).1);
 
         assert_eq!(cache.len(), 2);
         assert!(cache.get(b"one").is_some());
         assert!(cache.get(b"two").is_none());
         assert!(cache.get(b"three").is_some());
+    }
+
+    #[test]
+    fn clear_drops_every_entry() {
+        let cache = ParserCache::new(NonZeroUsize::new(8).unwrap());
+        for (key, sql) in [
+            (&b"one"[..], "SELECT 1"),
+            (b"two", "SELECT 2"),
+            (b"three", "SELECT 3"),
+        ] {
+            let parsed = Arc::new(ParsedQuery {
+                statement_type: StatementType::Select,
+                read_only: true,
+                tables: Vec::new(),
+                statement_types: vec!["SelectStmt".to_string()],
+                functions: Vec::new(),
+                holds_cursor: false,
+                syntax: Syntax::Tree(Arc::new(pg_query::parse(sql).unwrap())),
+            });
+            cache.insert_if_missing(key.to_vec(), parsed);
+        }
 
-        let stats = analytics::snapshot();
-        assert_eq!(stats.evictions, 1);
+        assert_eq!(cache.clear(), 3);
+        assert_eq!(cache.len(), 0);
+        assert!(cache.get(b"one").is_none());
+    }
+
+    #[test]
+    fn cleared_queries_parse_again() {
+        let cache = local_cache();
+        let before = cache
+            .parse("SELECT * FROM clear_me")
+            .expect("parse before clear");
+        assert_eq!(cache.clear(), 1);
+
+        let after = cache
+            .parse("SELECT * FROM clear_me")
+            .expect("parse after clear");
+        assert!(!Arc::ptr_eq(tree(&before), tree(&after)));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn parse_error_carries_message_and_position() {
+        let err = parse("  SELEC 1").expect_err("invalid syntax");
+        assert!(err.message().contains("syntax error"));
+        assert_eq!(err.position(), Some(3));
+    }
+
+    #[test]
+    fn error_position_from_message() {
+        assert_eq!(
+            error_position("SELECT 1 FRM t", "syntax error at or near \"FRM\""),
+            Some(10)
+        );
+        assert_eq!(
+            error_position("SELECT é FRM t", "syntax error at or near \"FRM\""),
+            Some(10)
+        );
+        assert_eq!(
+            error_position("SELECT 1 +", "syntax error at end of input"),
+            Some(11)
+        );
+        assert_eq!(error_position("SELECT 1", "something else"), None);
     }
 }
@@ -0,0 +1,295 @@
+use std::{fs, io, net::SocketAddr, path::PathBuf, sync::Arc};
+use tokio::net::{TcpListener, UnixListener, UnixStream};
+use tokio::signal::unix::{Signal, SignalKind, signal};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::config::shards::ShardsConfig;
+use crate::config::types::{ListenAddr, LogFormat, LogLevel};
+use crate::config::users::UsersConfig;
+use crate::frontend::{ClientLimiter, reject_too_many_clients};
+use crate::gateway::GatewayPools;
+use crate::{Config, FrontendConnection, parser, tls};
+
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+const APP_NAME: &str = "🦀 PgCrab";
+
+const DEFAULT_PARSER_CACHE_CAPACITY: usize = 1024;
+
+// -----------------------------------------------------------------------------
+// ----- Server ----------------------------------------------------------------
+
+/// The pooler, run in-process. Config is process-wide, so there's one
+/// `Server` per process.
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    unix_listener: Option<UnixListener>,
+    reload_on_sighup: bool,
+}
+
+#[derive(Debug)]
+pub struct ServerBuilder {
+    listen_addr: Option<ListenAddr>,
+    config_path: Option<PathBuf>,
+    log_level: LogLevel,
+    log_format: LogFormat,
+    parser_cache_capacity: usize,
+    strict_parse: bool,
+    reload_on_sighup: bool,
+}
+
+// -----------------------------------------------------------------------------
+// ----- Server: Static --------------------------------------------------------
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            listen_addr: None,
+            config_path: None,
+            log_level: LogLevel::Info,
+            log_format: LogFormat::default(),
+            parser_cache_capacity: DEFAULT_PARSER_CACHE_CAPACITY,
+            strict_parse: false,
+            reload_on_sighup: false,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Server: Public --------------------------------------------------------
+
+impl Server {
+    /// The bound address, e.g. the port picked for `127.0.0.1:0`.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves clients until `shutdown` is cancelled, then stops accepting;
+    /// clients already connected finish on their own. With
+    /// `reload_on_sighup`, SIGHUP reloads the config and the TLS certificate;
+    /// otherwise the signal is left to the embedding process.
+    pub async fn run(self, shutdown: CancellationToken) -> io::Result<()> {
+        let config = Config::handle();
+
+        let pools = Arc::new(GatewayPools::with_users(
+            ShardsConfig::snapshot(),
+            &UsersConfig::snapshot(),
+            config.server.application_name_prefix.as_deref(),
+        ));
+        pools.warm_all(config.server.warmup_concurrency).await;
+        pools.spawn_maintenance();
+
+        let limiter = ClientLimiter::new(config.server.max_clients);
+        let mut hangup = if self.reload_on_sighup {
+            Some(signal(SignalKind::hangup())?)
+        } else {
+            None
+        };
+
+        info!("{} :: Listening on {}", APP_NAME, self.local_addr()?);
+        if let Some(path) = &config.server.unix_socket_path {
+            info!("{} :: Listening on {}", APP_NAME, path.display());
+        }
+
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    info!("{} :: Shutting down", APP_NAME);
+                    break;
+                }
+
+                _ = recv_hangup(hangup.as_mut()) => {
+                    info!("{} :: Reloading config", APP_NAME);
+                    Config::reload().await;
+                    if Config::handle().server.max_clients != config.server.max_clients {
+                        warn!("max_clients changed; requires restart");
+                    }
+                    pools.reweight(&ShardsConfig::snapshot());
+                    pools.forget_describes();
+                    tls::reload();
+                }
+
+                accept_res = self.listener.accept() => {
+                    let (stream, peer) = match accept_res {
+                        Ok(v) => v,
+                        Err(e) => { error!("accept error: {e}"); continue; }
+                    };
+
+                    let current = Config::handle();
+                    if let Err(e) = current.server.apply_to_stream(&stream) {
+                        error!("client {peer} socket options: {e}");
+                    }
+
+                    let Some(slot) = limiter.try_acquire() else {
+                        warn!("rejecting client {peer}: max_clients reached");
+                        let hint = current.server.retry_after_hint.clone();
+                        tokio::spawn(reject_too_many_clients(stream, hint));
+                        continue;
+                    };
+
+                    let pools = pools.clone();
+                    tokio::spawn(async move {
+                        let _slot = slot;
+                        let conn = FrontendConnection::new(stream, pools);
+
+                        if let Err(e) = conn.serve().await {
+                            error!("client {peer} error: {e}");
+                        }
+                    });
+                }
+
+                accept_res = accept_unix(self.unix_listener.as_ref()) => {
+                    let stream = match accept_res {
+                        Ok(stream) => stream,
+                        Err(e) => { error!("unix socket accept error: {e}"); continue; }
+                    };
+
+                    let Some(slot) = limiter.try_acquire() else {
+                        warn!("rejecting unix socket client: max_clients reached");
+                        let hint = Config::handle().server.retry_after_hint.clone();
+                        tokio::spawn(reject_too_many_clients(stream, hint));
+                        continue;
+                    };
+
+                    let pools = pools.clone();
+                    tokio::spawn(async move {
+                        let _slot = slot;
+                        let conn = FrontendConnection::from_unix(stream, pools);
+
+                        if let Err(e) = conn.serve().await {
+                            error!("unix socket client error: {e}");
+                        }
+                    });
+                }
+            }
+        }
+
+        if let Some(path) = &config.server.unix_socket_path
+            && let Err(e) = fs::remove_file(path)
+        {
+            warn!("could not remove {}: {e}", path.display());
+        }
+
+        Ok(())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- ServerBuilder: Public -------------------------------------------------
+
+impl ServerBuilder {
+    /// Wins over the config file's `[server] listen_addr`.
+    pub fn listen(mut self, addr: SocketAddr) -> Self {
+        self.listen_addr = Some(ListenAddr::Fixed(addr));
+        self
+    }
+
+    /// Used unless the config file's `[server] listen_addr` says otherwise,
+    /// like `--host`/`--port`.
+    pub fn default_listen(mut self, addr: SocketAddr) -> Self {
+        self.listen_addr = Some(ListenAddr::Default(addr));
+        self
+    }
+
+    pub fn config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Used unless the config file's `[server] log_level` says otherwise.
+    pub fn log_level(mut self, level: LogLevel) -> Self {
+        self.log_level = level;
+        self
+    }
+
+    pub fn log_format(mut self, format: LogFormat) -> Self {
+        self.log_format = format;
+        self
+    }
+
+    pub fn parser_cache_capacity(mut self, capacity: usize) -> Self {
+        self.parser_cache_capacity = capacity;
+        self
+    }
+
+    /// Reject SQL the parser can't understand instead of forwarding it.
+    pub fn strict_parse(mut self, strict: bool) -> Self {
+        self.strict_parse = strict;
+        self
+    }
+
+    /// Reload the config and the TLS certificate on SIGHUP, as the `pgcrab`
+    /// binary does. Off by default, so an embedding process keeps the signal.
+    pub fn reload_on_sighup(mut self, reload: bool) -> Self {
+        self.reload_on_sighup = reload;
+        self
+    }
+
+    /// Loads the config and binds the listeners. Config is process-wide, so
+    /// a second `build` in the same process is an error. Panics on an
+    /// invalid config file.
+    pub async fn build(self) -> io::Result<Server> {
+        let Some(listen_addr) = self.listen_addr else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Server::builder() needs a listen address",
+            ));
+        };
+        let Some(config_path) = self.config_path else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Server::builder() needs a config file",
+            ));
+        };
+
+        if Config::is_initialized() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a Server was already built in this process",
+            ));
+        }
+
+        Config::init(
+            listen_addr,
+            self.log_level,
+            self.log_format,
+            self.parser_cache_capacity,
+            self.strict_parse,
+            config_path,
+        )
+        .await;
+        parser::init_cache(self.parser_cache_capacity);
+
+        let config = Config::handle();
+        Ok(Server {
+            listener: config.server.listen(config.listen_addr)?,
+            unix_listener: config.server.listen_unix()?,
+            reload_on_sighup: self.reload_on_sighup,
+        })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Private Helpers -------------------------------------------------------
+
+/// Next client on the Unix socket; never resolves without one.
+async fn accept_unix(listener: Option<&UnixListener>) -> io::Result<UnixStream> {
+    match listener {
+        Some(listener) => listener.accept().await.map(|(stream, _)| stream),
+        None => std::future::pending().await,
+    }
+}
+
+/// Next SIGHUP; never resolves when reloading is off.
+async fn recv_hangup(hangup: Option<&mut Signal>) -> Option<()> {
+    match hangup {
+        Some(hangup) => hangup.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
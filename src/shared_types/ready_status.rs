@@ -4,7 +4,7 @@
 /// Maps to the ReadyForQuery transaction status byte that Postgres
 /// sends after each command. We emit it so clients know whether the connection
 /// is idle, in a transaction, or in a failed transaction block.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReadyStatus {
     Idle,
     InTransaction,
@@ -15,6 +15,15 @@ pub enum ReadyStatus {
 // ----- ReadyStatus: Static ---------------------------------------------------
 
 impl ReadyStatus {
+    pub(crate) fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            b'I' => Some(ReadyStatus::Idle),
+            b'T' => Some(ReadyStatus::InTransaction),
+            b'E' => Some(ReadyStatus::FailedTransaction),
+            _ => None,
+        }
+    }
+
     pub(crate) fn as_byte(self) -> u8 {
         match self {
             ReadyStatus::Idle => b'I',
@@ -22,6 +31,11 @@ impl ReadyStatus {
             ReadyStatus::FailedTransaction => b'E',
         }
     }
+
+    /// True for both an open and a failed transaction block.
+    pub fn in_transaction(self) -> bool {
+        !matches!(self, ReadyStatus::Idle)
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -1,20 +1,88 @@
-use md5::Context;
+use sha2::{Digest, Sha256};
 
 // -----------------------------------------------------------------------------
 // ----- StatementSignature ----------------------------------------------------
 
+/// Identity of a prepared statement for backend reuse: SHA-256 over the exact
+/// query bytes and the parameter type OIDs.
+///
+/// The query is hashed byte-for-byte, not normalized, so two statements only
+/// share a backend prepare when Postgres would have parsed the same text.
+/// The OID count is hashed ahead of the OIDs so `[]` and `[0]` never collide.
+///
+/// Collision policy: the full 256-bit digest is stored and compared, and a
+/// collision is treated as impossible. No fallback comparison of the query
+/// text is done.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub struct StatementSignature(pub(crate) [u8; 16]);
+pub struct StatementSignature(pub(crate) [u8; 32]);
 
 impl StatementSignature {
     pub fn new(sql: &str, param_type_oids: &[i32]) -> Self {
-        let mut ctx = Context::new();
-        ctx.consume(sql.as_bytes());
-        ctx.consume([0]);
+        let mut hasher = Sha256::new();
+        hasher.update(sql.as_bytes());
+        hasher.update([0]);
+        hasher.update((param_type_oids.len() as u32).to_be_bytes());
         for oid in param_type_oids {
-            ctx.consume(oid.to_be_bytes());
+            hasher.update(oid.to_be_bytes());
         }
-        StatementSignature(ctx.compute().0)
+        StatementSignature(hasher.finalize().into())
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_query_and_oids_are_equal() {
+        let a = StatementSignature::new("SELECT $1::int", &[23]);
+        let b = StatementSignature::new("SELECT $1::int", &[23]);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn distinct_queries_are_distinct() {
+        let queries = [
+            "SELECT 1",
+            "SELECT 2",
+            "SELECT  1",
+            "select 1",
+            "SELECT 1;",
+            "",
+        ];
+        for (i, a) in queries.iter().enumerate() {
+            for b in &queries[i + 1..] {
+                assert_ne!(
+                    StatementSignature::new(a, &[]),
+                    StatementSignature::new(b, &[]),
+                    "{a:?} vs {b:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn distinct_oids_are_distinct() {
+        let sql = "SELECT $1";
+        assert_ne!(
+            StatementSignature::new(sql, &[23]),
+            StatementSignature::new(sql, &[25])
+        );
+        assert_ne!(
+            StatementSignature::new(sql, &[]),
+            StatementSignature::new(sql, &[0])
+        );
+    }
+
+    #[test]
+    fn oids_do_not_bleed_into_query() {
+        assert_ne!(
+            StatementSignature::new("SELECT 1", &[0]),
+            StatementSignature::new("SELECT 1\0", &[])
+        );
     }
 }
 
@@ -2,37 +2,198 @@ use std::env;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
-use std::sync::OnceLock;
+use std::sync::{Arc, OnceLock};
+
+use parking_lot::RwLock;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
 
 use tokio_rustls::TlsAcceptor;
-use tokio_rustls::rustls::ServerConfig;
-use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
-use tracing::error;
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use tokio_rustls::rustls::crypto::{
+    WebPkiSupportedAlgorithms, verify_tls12_signature, verify_tls13_signature,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{
+    ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme,
+};
+use tracing::{error, info, warn};
+
+use crate::config::shards::SslMode;
 
 // -----------------------------------------------------------------------------
 // ----- Constants -------------------------------------------------------------
 
-static TLS_ACCEPTOR: OnceLock<Option<TlsAcceptor>> = OnceLock::new();
+static SERVER_TLS: OnceLock<TlsSlot> = OnceLock::new();
+
+/// DER-encoded signature algorithm OIDs (RFC 3279, 4055, 5758) and the hash
+/// `tls-server-end-point` uses for each. MD5 and SHA-1 are upgraded to
+/// SHA-256 per RFC 5929 section 4.1.
+const SIGNATURE_HASHES: &[(&[u8], EndPointHash)] = &[
+    // md5WithRSAEncryption, sha1WithRSAEncryption
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x04],
+        EndPointHash::Sha256,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x05],
+        EndPointHash::Sha256,
+    ),
+    // sha{256,384,512,224}WithRSAEncryption
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b],
+        EndPointHash::Sha256,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c],
+        EndPointHash::Sha384,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d],
+        EndPointHash::Sha512,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0e],
+        EndPointHash::Sha224,
+    ),
+    // ecdsa-with-SHA1, ecdsa-with-SHA{224,256,384,512}
+    (
+        &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x01],
+        EndPointHash::Sha256,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x01],
+        EndPointHash::Sha224,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02],
+        EndPointHash::Sha256,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03],
+        EndPointHash::Sha384,
+    ),
+    (
+        &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04],
+        EndPointHash::Sha512,
+    ),
+];
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_OID: u8 = 0x06;
+
+// -----------------------------------------------------------------------------
+// ----- ServerTls -------------------------------------------------------------
+
+/// Frontend TLS loaded from `PGCRAB_TLS_CERT`/`PGCRAB_TLS_KEY`.
+struct ServerTls {
+    acceptor: TlsAcceptor,
+    /// `tls-server-end-point` binding data for the served certificate;
+    /// `None` when its signature algorithm has no usable hash (e.g. Ed25519).
+    end_point: Option<Vec<u8>>,
+}
+
+/// The frontend TLS new connections are handed. `reload` swaps it whole;
+/// connections already accepted keep the acceptor they started with.
+struct TlsSlot(RwLock<Option<Arc<ServerTls>>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EndPointHash {
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
 
 // -----------------------------------------------------------------------------
 // ----- TLS: Exported ---------------------------------------------------------
 
 pub fn acceptor() -> Option<TlsAcceptor> {
-    TLS_ACCEPTOR
-        .get_or_init(|| match load_from_env() {
-            Ok(acceptor) => acceptor,
-            Err(err) => {
-                error!("tls disabled: {err}");
-                None
+    server_tls().acceptor()
+}
+
+/// The acceptor with its certificate's `tls-server-end-point` binding data,
+/// for SCRAM-SHA-256-PLUS. Read together, so a reload in between can't pair
+/// one certificate's acceptor with another's binding data.
+pub fn acceptor_with_end_point() -> Option<(TlsAcceptor, Option<Vec<u8>>)> {
+    let tls = server_tls().current()?;
+    Some((tls.acceptor.clone(), tls.end_point.clone()))
+}
+
+/// Re-reads `PGCRAB_TLS_CERT`/`PGCRAB_TLS_KEY` for new connections, e.g.
+/// after a certificate rotation. A pair that fails to load keeps the
+/// previous certificate.
+pub fn reload() {
+    match load_from_env() {
+        Ok(tls) => {
+            let enabled = tls.is_some();
+            server_tls().replace(tls);
+            if enabled {
+                info!("tls certificate reloaded");
             }
-        })
-        .clone()
+        }
+        Err(err) => error!("tls reload failed; keeping previous certificate: {err}"),
+    }
+}
+
+/// RFC 5929 `tls-server-end-point`: the certificate hashed with the hash of
+/// its own signature algorithm.
+pub fn tls_server_end_point(cert: &CertificateDer<'_>) -> Result<Vec<u8>, String> {
+    let hash = signature_hash(cert.as_ref())?;
+    let digest = match hash {
+        EndPointHash::Sha224 => Sha224::digest(cert).to_vec(),
+        EndPointHash::Sha256 => Sha256::digest(cert).to_vec(),
+        EndPointHash::Sha384 => Sha384::digest(cert).to_vec(),
+        EndPointHash::Sha512 => Sha512::digest(cert).to_vec(),
+    };
+    Ok(digest)
+}
+
+/// Client side for backend connections; `None` for `sslmode = "disable"`.
+pub fn client_config(
+    sslmode: SslMode,
+    sslrootcert: Option<&Path>,
+) -> Result<Option<Arc<ClientConfig>>, String> {
+    let builder = ClientConfig::builder();
+    let config = match sslmode {
+        SslMode::Disable => return Ok(None),
+        SslMode::Require => {
+            let algorithms = builder.crypto_provider().signature_verification_algorithms;
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(AcceptAnyServerCert { algorithms }))
+                .with_no_client_auth()
+        }
+        SslMode::VerifyFull => {
+            let path = sslrootcert.ok_or("sslmode verify-full requires sslrootcert")?;
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(path)? {
+                roots
+                    .add(cert)
+                    .map_err(|e| format!("invalid CA cert in {}: {e}", path.display()))?;
+            }
+            builder.with_root_certificates(roots).with_no_client_auth()
+        }
+    };
+
+    Ok(Some(Arc::new(config)))
 }
 
 // -----------------------------------------------------------------------------
 // ----- TLS: Private helpers --------------------------------------------------
 
-fn load_from_env() -> Result<Option<TlsAcceptor>, String> {
+fn server_tls() -> &'static TlsSlot {
+    SERVER_TLS.get_or_init(|| match load_from_env() {
+        Ok(tls) => TlsSlot::new(tls),
+        Err(err) => {
+            error!("tls disabled: {err}");
+            TlsSlot::new(None)
+        }
+    })
+}
+
+fn load_from_env() -> Result<Option<ServerTls>, String> {
     let cert_path = env::var("PGCRAB_TLS_CERT").ok();
     let key_path = env::var("PGCRAB_TLS_KEY").ok();
 
@@ -43,18 +204,33 @@ fn load_from_env() -> Result<Option<TlsAcceptor>, String> {
     let cert_path = cert_path.ok_or("PGCRAB_TLS_CERT is required when enabling TLS")?;
     let key_path = key_path.ok_or("PGCRAB_TLS_KEY is required when enabling TLS")?;
 
-    let certs = load_certs(Path::new(&cert_path))?;
-    let key = load_key(Path::new(&key_path))?;
+    load_from_paths(Path::new(&cert_path), Path::new(&key_path)).map(Some)
+}
+
+fn load_from_paths(cert_path: &Path, key_path: &Path) -> Result<ServerTls, String> {
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    let end_point = match tls_server_end_point(&certs[0]) {
+        Ok(end_point) => Some(end_point),
+        Err(err) => {
+            warn!("channel binding disabled: {err}");
+            None
+        }
+    };
 
     let config = ServerConfig::builder()
         .with_no_client_auth()
         .with_single_cert(certs, key)
         .map_err(|e| format!("invalid tls key/cert pair: {e}"))?;
 
-    Ok(Some(TlsAcceptor::from(std::sync::Arc::new(config))))
+    Ok(ServerTls {
+        acceptor: TlsAcceptor::from(Arc::new(config)),
+        end_point,
+    })
 }
 
-fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+pub(crate) fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
     let file =
         File::open(path).map_err(|e| format!("failed to open tls cert {}: {e}", path.display()))?;
     let mut reader = BufReader::new(file);
@@ -69,7 +245,7 @@ fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
     Ok(certs)
 }
 
-fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+pub(crate) fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
     let file =
         File::open(path).map_err(|e| format!("failed to open tls key {}: {e}", path.display()))?;
     let mut reader = BufReader::new(file);
@@ -80,5 +256,213 @@ fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
     Ok(key)
 }
 
+/// Hash for the certificate's `signatureAlgorithm`:
+/// `Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }`.
+fn signature_hash(cert: &[u8]) -> Result<EndPointHash, String> {
+    let malformed = || "malformed certificate".to_string();
+
+    let (tag, certificate, _) = der_element(cert).ok_or_else(malformed)?;
+    if tag != DER_SEQUENCE {
+        return Err(malformed());
+    }
+    let (_, _, rest) = der_element(certificate).ok_or_else(malformed)?;
+    let (tag, algorithm, _) = der_element(rest).ok_or_else(malformed)?;
+    if tag != DER_SEQUENCE {
+        return Err(malformed());
+    }
+    let (tag, oid, _) = der_element(algorithm).ok_or_else(malformed)?;
+    if tag != DER_OID {
+        return Err(malformed());
+    }
+
+    SIGNATURE_HASHES
+        .iter()
+        .find(|(known, _)| *known == oid)
+        .map(|(_, hash)| *hash)
+        .ok_or_else(|| "certificate signature algorithm has no channel binding hash".to_string())
+}
+
+/// Splits one DER element into `(tag, contents, rest)`.
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, input) = input.split_first()?;
+
+    let (len, input) = if first & 0x80 == 0 {
+        (first as usize, input)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > size_of::<usize>() || input.len() < count {
+            return None;
+        }
+        let (bytes, input) = input.split_at(count);
+        let len = bytes.iter().fold(0usize, |len, &b| (len << 8) | b as usize);
+        (len, input)
+    };
+
+    if input.len() < len {
+        return None;
+    }
+    let (contents, rest) = input.split_at(len);
+    Some((tag, contents, rest))
+}
+
+// -----------------------------------------------------------------------------
+// ----- TlsSlot ---------------------------------------------------------------
+
+impl TlsSlot {
+    fn new(tls: Option<ServerTls>) -> Self {
+        TlsSlot(RwLock::new(tls.map(Arc::new)))
+    }
+
+    fn current(&self) -> Option<Arc<ServerTls>> {
+        self.0.read().clone()
+    }
+
+    fn acceptor(&self) -> Option<TlsAcceptor> {
+        self.current().map(|tls| tls.acceptor.clone())
+    }
+
+    fn replace(&self, tls: Option<ServerTls>) {
+        *self.0.write() = tls.map(Arc::new);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- AcceptAnyServerCert ---------------------------------------------------
+
+/// `sslmode = "require"`: the channel is encrypted, but like libpq we take
+/// the server's word for who it is. Handshake signatures are still checked.
+#[derive(Debug)]
+struct AcceptAnyServerCert {
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for AcceptAnyServerCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    fn fixture_cert() -> CertificateDer<'static> {
+        load_certs(&fixture("server.crt")).unwrap().remove(0)
+    }
+
+    /// Wraps `contents` in a DER element.
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if contents.len() < 0x80 {
+            out.push(contents.len() as u8);
+        } else {
+            out.push(0x82);
+            out.extend_from_slice(&(contents.len() as u16).to_be_bytes());
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    fn cert_signed_with(oid: &[u8]) -> Vec<u8> {
+        let tbs = der(DER_SEQUENCE, &[0u8; 200]);
+        let algorithm = der(DER_SEQUENCE, &der(DER_OID, oid));
+        let signature = der(0x03, &[0, 1, 2, 3]);
+        der(DER_SEQUENCE, &[tbs, algorithm, signature].concat())
+    }
+
+    #[test]
+    fn end_point_hashes_the_fixture_with_its_signature_hash() {
+        // The fixture is signed with ecdsa-with-SHA256.
+        let cert = fixture_cert();
+        let end_point = tls_server_end_point(&cert).unwrap();
+        assert_eq!(end_point, Sha256::digest(&cert).to_vec());
+        assert_eq!(end_point.len(), 32);
+    }
+
+    #[test]
+    fn weak_signature_hashes_are_upgraded_to_sha256() {
+        let sha1_rsa = cert_signed_with(&[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x05]);
+        assert_eq!(signature_hash(&sha1_rsa), Ok(EndPointHash::Sha256));
+
+        let sha384_ecdsa = cert_signed_with(&[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03]);
+        let cert = CertificateDer::from(sha384_ecdsa);
+        assert_eq!(tls_server_end_point(&cert).unwrap().len(), 48);
+    }
+
+    #[test]
+    fn unknown_or_malformed_certificates_have_no_end_point() {
+        // Ed25519 signs without a separate hash.
+        let ed25519 = cert_signed_with(&[0x2b, 0x65, 0x70]);
+        assert!(signature_hash(&ed25519).is_err());
+
+        let truncated = &fixture_cert()[..40];
+        assert_eq!(
+            signature_hash(truncated),
+            Err("malformed certificate".to_string())
+        );
+        assert!(signature_hash(&[]).is_err());
+    }
+
+    #[test]
+    fn replacing_the_slot_hands_out_the_new_certificate() {
+        let first = load_from_paths(&fixture("server.crt"), &fixture("server.key")).unwrap();
+        let slot = TlsSlot::new(Some(first));
+        let held = slot.acceptor().unwrap();
+
+        let rotated = load_from_paths(&fixture("rotated.crt"), &fixture("rotated.key")).unwrap();
+        let rotated_config = rotated.acceptor.config().clone();
+        slot.replace(Some(rotated));
+
+        let fresh = slot.acceptor().unwrap();
+        assert!(Arc::ptr_eq(fresh.config(), &rotated_config));
+        assert!(!Arc::ptr_eq(held.config(), fresh.config()));
+
+        let rotated_cert = load_certs(&fixture("rotated.crt")).unwrap().remove(0);
+        assert_eq!(
+            slot.current().unwrap().end_point,
+            Some(Sha256::digest(&rotated_cert).to_vec())
+        );
+    }
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
@@ -0,0 +1,119 @@
+use bytes::{BufMut, BytesMut};
+
+use super::{put_cstr, put_header};
+
+// -----------------------------------------------------------------------------
+// ----- build_bind ------------------------------------------------------------
+
+/// Appends a Bind ('B') frame. Format codes follow the wire rules (empty = all
+/// text, one = applies to all, otherwise one per param/column); `None` params
+/// are sent as SQL NULL. Returns the number of bytes written.
+pub fn build_bind(
+    out: &mut BytesMut,
+    portal: &str,
+    statement: &str,
+    param_formats: &[i16],
+    params: &[Option<&[u8]>],
+    result_formats: &[i16],
+) -> usize {
+    let params_len: usize = params
+        .iter()
+        .map(|param| 4 + param.map_or(0, <[u8]>::len))
+        .sum();
+    let body_len = portal.len()
+        + 1
+        + statement.len()
+        + 1
+        + 2
+        + 2 * param_formats.len()
+        + 2
+        + params_len
+        + 2
+        + 2 * result_formats.len();
+
+    let total = put_header(out, b'B', body_len);
+    put_cstr(out, portal);
+    put_cstr(out, statement);
+
+    out.put_i16(param_formats.len() as i16);
+    for &format in param_formats {
+        out.put_i16(format);
+    }
+
+    out.put_i16(params.len() as i16);
+    for param in params {
+        match param {
+            Some(value) => {
+                out.put_i32(value.len() as i32);
+                out.extend_from_slice(value);
+            }
+            None => out.put_i32(-1),
+        }
+    }
+
+    out.put_i16(result_formats.len() as i16);
+    for &format in result_formats {
+        out.put_i16(format);
+    }
+
+    total
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::observers::bind::{BindFrameObserver, ParamView};
+
+    #[test]
+    fn round_trips_mixed_params() {
+        let mut out = BytesMut::new();
+        let written = build_bind(
+            &mut out,
+            "p1",
+            "s1",
+            &[0, 1, 0],
+            &[Some(b"42"), Some(&[0, 1, 2]), None],
+            &[1],
+        );
+        assert_eq!(written, out.len());
+
+        let observer = BindFrameObserver::new(&out).expect("valid Bind");
+        assert_eq!(observer.portal(), "p1");
+        assert_eq!(observer.statement(), "s1");
+        assert_eq!(observer.param_count(), 3);
+        assert!(matches!(observer.param(0), ParamView::Text("42")));
+        assert!(matches!(observer.param(1), ParamView::Binary([0, 1, 2])));
+        assert!(matches!(observer.param(2), ParamView::Null));
+        assert_eq!(observer.result_format_count(), 1);
+        assert!(observer.result_is_binary(5));
+    }
+
+    #[test]
+    fn round_trips_unnamed_without_params() {
+        let mut out = BytesMut::new();
+        let written = build_bind(&mut out, "", "", &[], &[], &[]);
+
+        assert_eq!(BindFrameObserver::peek(&out), Some(written));
+        let observer = BindFrameObserver::new(&out).expect("valid Bind");
+        assert_eq!(observer.portal(), "");
+        assert_eq!(observer.statement(), "");
+        assert_eq!(observer.param_count(), 0);
+        assert_eq!(observer.result_format_count(), 0);
+    }
+
+    #[test]
+    fn single_format_code_applies_to_all_params() {
+        let mut out = BytesMut::new();
+        build_bind(&mut out, "", "s", &[1], &[Some(&[7]), Some(&[8])], &[]);
+
+        let observer = BindFrameObserver::new(&out).expect("valid Bind");
+        assert!(observer.param_is_binary(0));
+        assert!(observer.param_is_binary(1));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
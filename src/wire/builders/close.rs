@@ -0,0 +1,53 @@
+use bytes::{BufMut, BytesMut};
+
+use super::{put_cstr, put_header};
+use crate::wire::observers::close::CloseTarget;
+
+// -----------------------------------------------------------------------------
+// ----- build_close -----------------------------------------------------------
+
+/// Appends a Close ('C') frame. Returns the number of bytes written.
+pub fn build_close(out: &mut BytesMut, target: CloseTarget, name: &str) -> usize {
+    let body_len = 1 + name.len() + 1;
+    let total = put_header(out, b'C', body_len);
+    out.put_u8(match target {
+        CloseTarget::Portal => b'P',
+        CloseTarget::Statement => b'S',
+    });
+    put_cstr(out, name);
+    total
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::observers::close::CloseFrameObserver;
+
+    #[test]
+    fn round_trips_statement() {
+        let mut out = BytesMut::new();
+        let written = build_close(&mut out, CloseTarget::Statement, "s1");
+        assert_eq!(written, out.len());
+
+        let observer = CloseFrameObserver::new(&out).expect("valid Close");
+        assert_eq!(observer.target(), CloseTarget::Statement);
+        assert_eq!(observer.name(), "s1");
+    }
+
+    #[test]
+    fn round_trips_portal() {
+        let mut out = BytesMut::new();
+        let written = build_close(&mut out, CloseTarget::Portal, "p1");
+
+        assert_eq!(CloseFrameObserver::peek(&out), Some(written));
+        let observer = CloseFrameObserver::new(&out).expect("valid Close");
+        assert_eq!(observer.target(), CloseTarget::Portal);
+        assert_eq!(observer.name(), "p1");
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,53 @@
+use bytes::{BufMut, BytesMut};
+
+use super::{put_cstr, put_header};
+use crate::wire::observers::describe::DescribeTarget;
+
+// -----------------------------------------------------------------------------
+// ----- build_describe --------------------------------------------------------
+
+/// Appends a Describe ('D') frame. Returns the number of bytes written.
+pub fn build_describe(out: &mut BytesMut, target: DescribeTarget, name: &str) -> usize {
+    let body_len = 1 + name.len() + 1;
+    let total = put_header(out, b'D', body_len);
+    out.put_u8(match target {
+        DescribeTarget::Portal => b'P',
+        DescribeTarget::Statement => b'S',
+    });
+    put_cstr(out, name);
+    total
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::observers::describe::DescribeFrameObserver;
+
+    #[test]
+    fn round_trips_statement() {
+        let mut out = BytesMut::new();
+        let written = build_describe(&mut out, DescribeTarget::Statement, "s1");
+        assert_eq!(written, out.len());
+
+        let observer = DescribeFrameObserver::new(&out).expect("valid Describe");
+        assert_eq!(observer.target(), DescribeTarget::Statement);
+        assert_eq!(observer.name(), "s1");
+    }
+
+    #[test]
+    fn round_trips_unnamed_portal() {
+        let mut out = BytesMut::new();
+        let written = build_describe(&mut out, DescribeTarget::Portal, "");
+
+        assert_eq!(DescribeFrameObserver::peek(&out), Some(written));
+        let observer = DescribeFrameObserver::new(&out).expect("valid Describe");
+        assert_eq!(observer.target(), DescribeTarget::Portal);
+        assert_eq!(observer.name(), "");
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,50 @@
+use bytes::{BufMut, BytesMut};
+
+use super::{put_cstr, put_header};
+
+// -----------------------------------------------------------------------------
+// ----- build_execute ---------------------------------------------------------
+
+/// Appends an Execute ('E') frame; `max_rows` 0 means no limit. Returns the
+/// number of bytes written.
+pub fn build_execute(out: &mut BytesMut, portal: &str, max_rows: i32) -> usize {
+    let body_len = portal.len() + 1 + 4;
+    let total = put_header(out, b'E', body_len);
+    put_cstr(out, portal);
+    out.put_i32(max_rows);
+    total
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::observers::execute::ExecuteFrameObserver;
+
+    #[test]
+    fn round_trips_through_observer() {
+        let mut out = BytesMut::new();
+        let written = build_execute(&mut out, "p1", 1);
+        assert_eq!(written, out.len());
+
+        let observer = ExecuteFrameObserver::new(&out).expect("valid Execute");
+        assert_eq!(observer.portal(), "p1");
+        assert_eq!(observer.max_rows(), 1);
+    }
+
+    #[test]
+    fn unnamed_portal_without_limit() {
+        let mut out = BytesMut::new();
+        let written = build_execute(&mut out, "", 0);
+
+        assert_eq!(ExecuteFrameObserver::peek(&out), Some(written));
+        let observer = ExecuteFrameObserver::new(&out).expect("valid Execute");
+        assert_eq!(observer.portal(), "");
+        assert_eq!(observer.max_rows(), 0);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
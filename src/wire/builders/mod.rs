@@ -0,0 +1,40 @@
+pub mod bind;
+pub mod close;
+pub mod describe;
+pub mod execute;
+pub mod parse;
+pub mod query;
+pub mod sync;
+
+pub use bind::build_bind;
+pub use close::build_close;
+pub use describe::build_describe;
+pub use execute::build_execute;
+pub use parse::build_parse;
+pub use query::build_query;
+pub use sync::build_sync;
+
+use bytes::{BufMut, BytesMut};
+
+// -----------------------------------------------------------------------------
+// ----- Shared Helpers --------------------------------------------------------
+
+/// Writes tag + length header for a body of `body_len` bytes, reserving room
+/// for the whole frame. Returns the total frame length.
+#[inline]
+fn put_header(out: &mut BytesMut, tag: u8, body_len: usize) -> usize {
+    let total = 1 + 4 + body_len;
+    out.reserve(total);
+    out.put_u8(tag);
+    out.put_u32((4 + body_len) as u32);
+    total
+}
+
+#[inline]
+fn put_cstr(out: &mut BytesMut, value: &str) {
+    out.extend_from_slice(value.as_bytes());
+    out.put_u8(0);
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,70 @@
+use bytes::{BufMut, BytesMut};
+
+use super::{put_cstr, put_header};
+
+// -----------------------------------------------------------------------------
+// ----- build_parse -----------------------------------------------------------
+
+/// Appends a Parse ('P') frame. Returns the number of bytes written.
+pub fn build_parse(
+    out: &mut BytesMut,
+    statement: &str,
+    query: &str,
+    param_type_oids: &[i32],
+) -> usize {
+    let body_len = statement.len() + 1 + query.len() + 1 + 2 + 4 * param_type_oids.len();
+    let total = put_header(out, b'P', body_len);
+    put_cstr(out, statement);
+    put_cstr(out, query);
+    out.put_i16(param_type_oids.len() as i16);
+    for &oid in param_type_oids {
+        out.put_i32(oid);
+    }
+    total
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::observers::parse::ParseFrameObserver;
+
+    #[test]
+    fn round_trips_through_observer() {
+        let mut out = BytesMut::new();
+        let written = build_parse(&mut out, "s1", "SELECT $1::int, $2", &[23, 0]);
+        assert_eq!(written, out.len());
+
+        let observer = ParseFrameObserver::new(&out).expect("valid Parse");
+        assert_eq!(observer.statement(), "s1");
+        assert_eq!(observer.query(), "SELECT $1::int, $2");
+        assert_eq!(observer.param_type_count(), 2);
+        assert_eq!(observer.param_type_oid(0), 23);
+        assert_eq!(observer.param_type_oid(1), 0);
+    }
+
+    #[test]
+    fn unnamed_without_param_types() {
+        let mut out = BytesMut::new();
+        let written = build_parse(&mut out, "", "SELECT 1", &[]);
+
+        assert_eq!(ParseFrameObserver::peek(&out), Some(written));
+        let observer = ParseFrameObserver::new(&out).expect("valid Parse");
+        assert_eq!(observer.statement(), "");
+        assert_eq!(observer.param_type_count(), 0);
+    }
+
+    #[test]
+    fn appends_after_existing_bytes() {
+        let mut out = BytesMut::from(&b"xyz"[..]);
+        let written = build_parse(&mut out, "s", "SELECT 1", &[]);
+
+        assert_eq!(out.len(), 3 + written);
+        assert!(ParseFrameObserver::new(&out[3..]).is_ok());
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -0,0 +1,35 @@
+use bytes::BytesMut;
+
+use super::{put_cstr, put_header};
+
+// -----------------------------------------------------------------------------
+// ----- build_query -----------------------------------------------------------
+
+/// Appends a Query ('Q') frame. Returns the number of bytes written.
+pub fn build_query(out: &mut BytesMut, query: &str) -> usize {
+    let total = put_header(out, b'Q', query.len() + 1);
+    put_cstr(out, query);
+    total
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::observers::query::QueryFrameObserver;
+
+    #[test]
+    fn round_trips_through_observer() {
+        let mut out = BytesMut::new();
+        let written = build_query(&mut out, "SELECT 1");
+        assert_eq!(written, out.len());
+
+        let observer = QueryFrameObserver::new(&out).expect("valid Query");
+        assert_eq!(observer.query(), "SELECT 1");
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
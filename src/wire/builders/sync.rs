@@ -0,0 +1,32 @@
+use bytes::BytesMut;
+
+use super::put_header;
+
+// -----------------------------------------------------------------------------
+// ----- build_sync ------------------------------------------------------------
+
+/// Appends a Sync ('S') frame. Returns the number of bytes written.
+pub fn build_sync(out: &mut BytesMut) -> usize {
+    put_header(out, b'S', 0)
+}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::observers::sync::SyncFrameObserver;
+
+    #[test]
+    fn round_trips_through_observer() {
+        let mut out = BytesMut::new();
+        let written = build_sync(&mut out);
+
+        assert_eq!(SyncFrameObserver::peek(&out), Some(written));
+        assert!(SyncFrameObserver::new(&out).is_ok());
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -1,3 +1,4 @@
+pub mod builders;
 pub mod observers;
 pub mod types;
 pub mod utils;
@@ -1,9 +1,9 @@
 // src/wire/frontend/frames/bind_observer.rs
 
-use memchr::memchr;
 use std::{fmt, str};
 
-use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame};
+use crate::wire::utils::read_cstr::{MAX_NAME_LEN, ReadCStrError};
+use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame, read_cstr_take};
 
 // -----------------------------------------------------------------------------
 // ----- BindFrameObserver -----------------------------------------------------
@@ -23,6 +23,9 @@ pub struct BindFrameObserver<'a> {
 
     result_format_count: usize,
     result_format_codes_start: usize,
+
+    /// Text params were checked for UTF-8 in construction.
+    text_validated: bool,
 }
 
 // -----------------------------------------------------------------------------
@@ -47,6 +50,17 @@ impl<'a> BindFrameObserver<'a> {
 
     /// Validate and build zero-copy observer over a complete frame slice.
     pub fn new(frame: &'a [u8]) -> Result<Self, NewBindObserverError> {
+        Self::build(frame, true)
+    }
+
+    /// Like `new`, without the UTF-8 pass over text params: for callers that
+    /// only relay the raw bytes. Text is checked when `param_text` or `param`
+    /// reads it instead.
+    pub fn new_unvalidated(frame: &'a [u8]) -> Result<Self, NewBindObserverError> {
+        Self::build(frame, false)
+    }
+
+    fn build(frame: &'a [u8], validate_text: bool) -> Result<Self, NewBindObserverError> {
         let meta = match parse_tagged_frame(frame, b'B') {
             Ok(meta) => meta,
             Err(TaggedFrameError::UnexpectedTag(tag)) => {
@@ -61,18 +75,12 @@ impl<'a> BindFrameObserver<'a> {
         let mut pos = 5;
 
         // portal
-        let rel =
-            memchr(0, &frame[pos..meta.total_len]).ok_or(NewBindObserverError::UnexpectedEof)?;
-        let portal =
-            str::from_utf8(&frame[pos..pos + rel]).map_err(NewBindObserverError::InvalidUtf8)?;
-        pos += rel + 1;
+        let (portal, rest) = read_cstr_take(&frame[pos..meta.total_len], Some(MAX_NAME_LEN))?;
+        pos = meta.total_len - rest.len();
 
         // statement
-        let rel =
-            memchr(0, &frame[pos..meta.total_len]).ok_or(NewBindObserverError::UnexpectedEof)?;
-        let statement =
-            str::from_utf8(&frame[pos..pos + rel]).map_err(NewBindObserverError::InvalidUtf8)?;
-        pos += rel + 1;
+        let (statement, rest) = read_cstr_take(&frame[pos..meta.total_len], Some(MAX_NAME_LEN))?;
+        pos = meta.total_len - rest.len();
 
         // param format count
         if pos + 2 > meta.total_len {
@@ -147,7 +155,7 @@ impl<'a> BindFrameObserver<'a> {
                 }
             };
 
-            if !is_bin {
+            if validate_text && !is_bin {
                 let s = &frame[pos..pos + n];
                 let _ = str::from_utf8(s).map_err(NewBindObserverError::InvalidUtf8)?;
             }
@@ -189,6 +197,7 @@ impl<'a> BindFrameObserver<'a> {
             param_values_start,
             result_format_count,
             result_format_codes_start,
+            text_validated: validate_text,
         })
     }
 }
@@ -269,26 +278,48 @@ impl<'a> BindFrameObserver<'a> {
         unreachable!("validated in new()");
     }
 
-    /// Panics in debug if called for a binary param (we validated text UTF-8 in new()).
+    /// All raw params in order (None = SQL NULL), in a single pass over the frame.
+    pub fn params_raw(&self) -> impl Iterator<Item = Option<&'a [u8]>> + 'a {
+        let frame = self.frame;
+        let mut pos = self.param_values_start;
+        (0..self.param_count).map(move |_| {
+            let n = be_i32(&frame[pos..]);
+            pos += 4;
+            if n < 0 {
+                return None;
+            }
+            let n = n as usize;
+            let value = &frame[pos..pos + n];
+            pos += n;
+            Some(value)
+        })
+    }
+
+    /// Panics in debug if called for a binary param. `None` is SQL NULL, or
+    /// on an unvalidated observer, text that isn't UTF-8.
     pub fn param_text(&self, index: usize) -> Option<&'a str> {
         debug_assert!(!self.param_is_binary(index));
-        match self.param_raw(index) {
-            None => None,
-            Some(bytes) => Some(unsafe { str::from_utf8_unchecked(bytes) }),
+        let bytes = self.param_raw(index)?;
+        if self.text_validated {
+            // SAFETY: every text param was checked for UTF-8 in new().
+            Some(unsafe { str::from_utf8_unchecked(bytes) })
+        } else {
+            str::from_utf8(bytes).ok()
         }
     }
 
+    /// On an unvalidated observer, a text param that isn't UTF-8 comes back
+    /// as `Binary`, its raw bytes.
     pub fn param(&self, index: usize) -> ParamView<'a> {
+        let Some(bytes) = self.param_raw(index) else {
+            return ParamView::Null;
+        };
         if self.param_is_binary(index) {
-            match self.param_raw(index) {
-                None => ParamView::Null,
-                Some(b) => ParamView::Binary(b),
-            }
-        } else {
-            match self.param_text(index) {
-                None => ParamView::Null,
-                Some(s) => ParamView::Text(s),
-            }
+            return ParamView::Binary(bytes);
+        }
+        match self.param_text(index) {
+            Some(s) => ParamView::Text(s),
+            None => ParamView::Binary(bytes),
         }
     }
 }
@@ -303,6 +334,7 @@ pub enum NewBindObserverError {
     InvalidParamLength(i32),
     InvalidUtf8(str::Utf8Error),
     ParamFormatCountMismatch { count: usize, expected: usize },
+    NameTooLong(usize),
     UnexpectedEof,
     UnexpectedLength,
     UnexpectedTag(u8),
@@ -320,6 +352,7 @@ impl fmt::Display for NewBindObserverError {
                 f,
                 "parameter format count mismatch: expected {expected}, got {count}"
             ),
+            NameTooLong(max) => write!(f, "name longer than {max} bytes"),
             UnexpectedEof => write!(f, "unexpected EOF"),
             UnexpectedLength => write!(f, "unexpected length"),
             UnexpectedTag(t) => write!(f, "unexpected tag: {t:#X}"),
@@ -329,6 +362,16 @@ impl fmt::Display for NewBindObserverError {
 
 impl std::error::Error for NewBindObserverError {}
 
+impl From<ReadCStrError> for NewBindObserverError {
+    fn from(err: ReadCStrError) -> Self {
+        match err {
+            ReadCStrError::UnexpectedEof => NewBindObserverError::UnexpectedEof,
+            ReadCStrError::Utf8Error(e) => NewBindObserverError::InvalidUtf8(e),
+            ReadCStrError::TooLong { max } => NewBindObserverError::NameTooLong(max),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- Internal: Helpers -----------------------------------------------------
 
@@ -471,6 +514,50 @@ mod tests {
         matches!(err, NewBindObserverError::InvalidUtf8(_));
     }
 
+    #[test]
+    fn unvalidated_observer_reads_text_lazily() {
+        let frame = build_frame(|b| {
+            b.put_u8(0);
+            b.put_u8(0);
+            b.put_u16(0); // all text
+            b.put_u16(2);
+            b.put_i32(2);
+            b.extend_from_slice(&[0xFF, 0xFE]);
+            b.put_i32(2);
+            b.extend_from_slice(b"ok");
+            b.put_u16(0);
+        });
+        assert!(BindFrameObserver::new(&frame).is_err());
+
+        let obs = BindFrameObserver::new_unvalidated(&frame).unwrap();
+        assert_eq!(obs.param_raw(0), Some(&[0xFF, 0xFE][..]));
+        assert_eq!(obs.param_text(0), None);
+        assert!(matches!(obs.param(0), ParamView::Binary([0xFF, 0xFE])));
+        assert_eq!(obs.param_text(1), Some("ok"));
+    }
+
+    #[test]
+    fn large_text_param_parses_either_way() {
+        let value = "é".repeat(4 * 1024 * 1024);
+        let frame = build_frame(|b| {
+            b.put_u8(0);
+            b.put_u8(0);
+            b.put_u16(0);
+            b.put_u16(1);
+            b.put_i32(value.len() as i32);
+            b.extend_from_slice(value.as_bytes());
+            b.put_u16(0);
+        });
+
+        for obs in [
+            BindFrameObserver::new(&frame).unwrap(),
+            BindFrameObserver::new_unvalidated(&frame).unwrap(),
+        ] {
+            assert_eq!(obs.param_count(), 1);
+            assert_eq!(obs.param_text(0), Some(value.as_str()));
+        }
+    }
+
     #[test]
     fn real_world_bind_with_uuid_email_pairs_text_params() {
         // INSERT INTO users (id, email)
@@ -815,6 +902,28 @@ mod tests {
         assert!(ptr >= base && ptr < base + frame_slice.len());
     }
 
+    #[test]
+    fn params_raw_matches_indexed_access() {
+        let frame = build_frame(|b| {
+            b.put_u8(0); // portal
+            b.put_u8(0); // statement
+            b.put_u16(0); // fmt count
+            b.put_u16(3); // param_count
+            b.put_i32(1);
+            b.extend_from_slice(b"a");
+            b.put_i32(-1);
+            b.put_i32(0);
+            b.put_u16(0); // result fmts
+        });
+
+        let observer = BindFrameObserver::new(&frame).unwrap();
+        let params: Vec<_> = observer.params_raw().collect();
+        assert_eq!(params, vec![Some(&b"a"[..]), None, Some(&b""[..])]);
+        for (idx, param) in params.iter().enumerate() {
+            assert_eq!(observer.param_raw(idx), *param);
+        }
+    }
+
     #[test]
     fn reject_param_format_count_mismatch() {
         // 2 params but 3 per-param format codes => protocol violation
@@ -836,7 +945,13 @@ mod tests {
 
         let total = BindFrameObserver::peek(&frame).unwrap();
         let err = BindFrameObserver::new(&frame[..total]).unwrap_err();
-        matches!(err, NewBindObserverError::ParamFormatCountMismatch { .. });
+        assert!(matches!(
+            err,
+            NewBindObserverError::ParamFormatCountMismatch {
+                count: 3,
+                expected: 2
+            }
+        ));
     }
 
     #[test]
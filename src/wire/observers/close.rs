@@ -1,7 +1,7 @@
-use memchr::memchr;
 use std::{fmt, str};
 
-use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame};
+use crate::wire::utils::read_cstr::{MAX_NAME_LEN, ReadCStrError};
+use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame, read_cstr_take};
 
 // -----------------------------------------------------------------------------
 // ----- CloseFrameObserver ----------------------------------------------------
@@ -57,11 +57,8 @@ impl<'a> CloseFrameObserver<'a> {
         };
         pos += 1;
         // name
-        let rel =
-            memchr(0, &frame[pos..meta.total_len]).ok_or(NewCloseObserverError::UnexpectedEof)?;
-        let name =
-            str::from_utf8(&frame[pos..pos + rel]).map_err(NewCloseObserverError::InvalidUtf8)?;
-        pos += rel + 1;
+        let (name, rest) = read_cstr_take(&frame[pos..meta.total_len], Some(MAX_NAME_LEN))?;
+        pos = meta.total_len - rest.len();
         if pos != meta.total_len {
             return Err(NewCloseObserverError::UnexpectedLength);
         }
@@ -96,6 +93,7 @@ impl<'a> CloseFrameObserver<'a> {
 pub enum NewCloseObserverError {
     InvalidTarget(u8),
     InvalidUtf8(str::Utf8Error),
+    NameTooLong(usize),
     UnexpectedEof,
     UnexpectedLength,
     UnexpectedTag(u8),
@@ -107,6 +105,7 @@ impl fmt::Display for NewCloseObserverError {
         match self {
             InvalidTarget(t) => write!(f, "invalid target: {t:#X}"),
             InvalidUtf8(e) => write!(f, "utf8: {e}"),
+            NameTooLong(max) => write!(f, "name longer than {max} bytes"),
             UnexpectedEof => write!(f, "unexpected EOF"),
             UnexpectedLength => write!(f, "unexpected length"),
             UnexpectedTag(t) => write!(f, "unexpected tag: {t:#X}"),
@@ -116,6 +115,16 @@ impl fmt::Display for NewCloseObserverError {
 
 impl std::error::Error for NewCloseObserverError {}
 
+impl From<ReadCStrError> for NewCloseObserverError {
+    fn from(err: ReadCStrError) -> Self {
+        match err {
+            ReadCStrError::UnexpectedEof => NewCloseObserverError::UnexpectedEof,
+            ReadCStrError::Utf8Error(e) => NewCloseObserverError::InvalidUtf8(e),
+            ReadCStrError::TooLong { max } => NewCloseObserverError::NameTooLong(max),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- Tests -----------------------------------------------------------------
 
@@ -160,6 +169,22 @@ mod tests {
         assert_eq!(obs.name(), "");
     }
 
+    #[test]
+    fn name_length_is_capped() {
+        let at_limit = "n".repeat(MAX_NAME_LEN);
+        let frame = build_frame(CloseTarget::Statement, &at_limit);
+        let obs = CloseFrameObserver::new(&frame).unwrap();
+        assert_eq!(obs.name().len(), MAX_NAME_LEN);
+
+        let past_limit = "n".repeat(MAX_NAME_LEN + 1);
+        let frame = build_frame(CloseTarget::Statement, &past_limit);
+        let err = CloseFrameObserver::new(&frame).unwrap_err();
+        assert!(matches!(
+            err,
+            NewCloseObserverError::NameTooLong(MAX_NAME_LEN)
+        ));
+    }
+
     #[test]
     fn invalid_target_rejected() {
         let mut frame = build_frame(CloseTarget::Portal, "my_portal");
@@ -0,0 +1,193 @@
+use memchr::memchr;
+use std::{fmt, str};
+
+use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame};
+
+// -----------------------------------------------------------------------------
+// ----- CommandCompleteFrameObserver ------------------------------------------
+
+/// Backend CommandComplete. Shares the `C` tag with the frontend Close, so
+/// only use it on the backend stream.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandCompleteFrameObserver<'a> {
+    _frame: &'a [u8],
+
+    tag: &'a str,
+}
+
+// -----------------------------------------------------------------------------
+// ----- CommandCompleteFrameObserver: Static ----------------------------------
+
+impl<'a> CommandCompleteFrameObserver<'a> {
+    /// Cheap, peeks at the header-only. Returns total frame length if fully present.
+    #[inline]
+    pub fn peek(buf: &[u8]) -> Option<usize> {
+        peek_tagged_frame(buf, b'C').map(|meta| meta.total_len)
+    }
+
+    /// Validate and build zero-copy observer over a complete frame slice.
+    pub fn new(frame: &'a [u8]) -> Result<Self, NewCommandCompleteObserverError> {
+        let meta = match parse_tagged_frame(frame, b'C') {
+            Ok(meta) => meta,
+            Err(TaggedFrameError::UnexpectedTag(tag)) => {
+                return Err(NewCommandCompleteObserverError::UnexpectedTag(tag));
+            }
+            Err(TaggedFrameError::UnexpectedLength | TaggedFrameError::InvalidLength(_)) => {
+                return Err(NewCommandCompleteObserverError::UnexpectedLength);
+            }
+        };
+
+        let mut pos = 5;
+
+        // tag
+        let rel = memchr(0, &frame[pos..meta.total_len])
+            .ok_or(NewCommandCompleteObserverError::UnexpectedEof)?;
+        let tag = str::from_utf8(&frame[pos..pos + rel])
+            .map_err(NewCommandCompleteObserverError::InvalidUtf8)?;
+        pos += rel + 1;
+
+        if pos != meta.total_len {
+            return Err(NewCommandCompleteObserverError::UnexpectedLength);
+        }
+
+        Ok(Self { _frame: frame, tag })
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- CommandCompleteFrameObserver: Public ----------------------------------
+
+impl<'a> CommandCompleteFrameObserver<'a> {
+    /// Command tag as sent, e.g. `INSERT 0 5` or `BEGIN`.
+    #[inline]
+    pub fn tag(&self) -> &'a str {
+        self.tag
+    }
+
+    /// First word of the tag, e.g. `INSERT`.
+    #[inline]
+    pub fn command(&self) -> &'a str {
+        self.tag.split(' ').next().unwrap_or_default()
+    }
+
+    /// Row count at the end of the tag; `None` for tags without one, like
+    /// `BEGIN` or `CREATE TABLE`.
+    pub fn affected_rows(&self) -> Option<u64> {
+        let (_, count) = self.tag.rsplit_once(' ')?;
+        count.parse().ok()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- Errors ----------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum NewCommandCompleteObserverError {
+    InvalidUtf8(str::Utf8Error),
+    UnexpectedEof,
+    UnexpectedLength,
+    UnexpectedTag(u8),
+}
+
+impl fmt::Display for NewCommandCompleteObserverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use NewCommandCompleteObserverError::*;
+        match self {
+            InvalidUtf8(e) => write!(f, "utf8: {e}"),
+            UnexpectedEof => write!(f, "unexpected EOF"),
+            UnexpectedLength => write!(f, "unexpected length"),
+            UnexpectedTag(t) => write!(f, "unexpected tag: {t:#X}"),
+        }
+    }
+}
+
+impl std::error::Error for NewCommandCompleteObserverError {}
+
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::{BufMut, BytesMut};
+
+    fn build_frame(tag: &str) -> Vec<u8> {
+        let mut frame = BytesMut::new();
+        frame.put_u8(b'C');
+        frame.put_u32((4 + tag.len() + 1) as u32);
+        frame.extend_from_slice(tag.as_bytes());
+        frame.put_u8(0);
+        frame.to_vec()
+    }
+
+    fn observe(tag: &str) -> (String, String, Option<u64>) {
+        let frame = build_frame(tag);
+        let len = CommandCompleteFrameObserver::peek(&frame).unwrap();
+        assert_eq!(len, frame.len());
+        let obs = CommandCompleteFrameObserver::new(&frame).unwrap();
+        (
+            obs.tag().to_string(),
+            obs.command().to_string(),
+            obs.affected_rows(),
+        )
+    }
+
+    #[test]
+    fn insert_counts_the_last_number() {
+        let (tag, command, rows) = observe("INSERT 0 5");
+        assert_eq!(tag, "INSERT 0 5");
+        assert_eq!(command, "INSERT");
+        assert_eq!(rows, Some(5));
+    }
+
+    #[test]
+    fn single_count_tags() {
+        assert_eq!(observe("UPDATE 3").2, Some(3));
+        assert_eq!(observe("DELETE 2").2, Some(2));
+        assert_eq!(observe("SELECT 10").2, Some(10));
+        assert_eq!(observe("SELECT 0").2, Some(0));
+        assert_eq!(observe("MERGE 4").2, Some(4));
+        assert_eq!(observe("COPY 7").2, Some(7));
+        assert_eq!(observe("FETCH 1").1, "FETCH");
+    }
+
+    #[test]
+    fn tags_without_a_count() {
+        assert_eq!(observe("BEGIN"), ("BEGIN".into(), "BEGIN".into(), None));
+        assert_eq!(observe("SET").2, None);
+        assert_eq!(observe("CREATE TABLE").2, None);
+        assert_eq!(observe("").2, None);
+    }
+
+    #[test]
+    fn two_frames_back_to_back_in_a_stream() {
+        let stream = [build_frame("UPDATE 3"), build_frame("COMMIT")].concat();
+
+        let t1 = CommandCompleteFrameObserver::peek(&stream).unwrap();
+        let obs1 = CommandCompleteFrameObserver::new(&stream[..t1]).unwrap();
+        assert_eq!(obs1.affected_rows(), Some(3));
+
+        let t2 = CommandCompleteFrameObserver::peek(&stream[t1..]).unwrap();
+        let obs2 = CommandCompleteFrameObserver::new(&stream[t1..t1 + t2]).unwrap();
+        assert_eq!(obs2.tag(), "COMMIT");
+        assert_eq!(obs2.affected_rows(), None);
+        assert_eq!(t1 + t2, stream.len());
+    }
+
+    #[test]
+    fn new_rejects_wrong_tag_and_trailing_bytes() {
+        let bogus = vec![b'Z', 0, 0, 0, 5, b'I'];
+        assert!(CommandCompleteFrameObserver::peek(&bogus).is_none());
+        assert!(matches!(
+            CommandCompleteFrameObserver::new(&bogus),
+            Err(NewCommandCompleteObserverError::UnexpectedTag(b'Z'))
+        ));
+
+        let mut with_junk = build_frame("SELECT 1");
+        with_junk.push(0);
+        assert!(CommandCompleteFrameObserver::new(&with_junk).is_err());
+    }
+}
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
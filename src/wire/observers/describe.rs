@@ -1,7 +1,7 @@
-use memchr::memchr;
 use std::{fmt, str};
 
-use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame};
+use crate::wire::utils::read_cstr::{MAX_NAME_LEN, ReadCStrError};
+use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame, read_cstr_take};
 
 // -----------------------------------------------------------------------------
 // ----- DescribeFrameObserver -------------------------------------------------
@@ -56,11 +56,8 @@ impl<'a> DescribeFrameObserver<'a> {
         };
         pos += 1;
         // name
-        let rel = memchr(0, &frame[pos..meta.total_len])
-            .ok_or(NewDescribeObserverError::UnexpectedEof)?;
-        let name = str::from_utf8(&frame[pos..pos + rel])
-            .map_err(NewDescribeObserverError::InvalidUtf8)?;
-        pos += rel + 1;
+        let (name, rest) = read_cstr_take(&frame[pos..meta.total_len], Some(MAX_NAME_LEN))?;
+        pos = meta.total_len - rest.len();
         if pos != meta.total_len {
             return Err(NewDescribeObserverError::UnexpectedLength);
         }
@@ -93,6 +90,7 @@ impl<'a> DescribeFrameObserver<'a> {
 pub enum NewDescribeObserverError {
     InvalidTarget(u8),
     InvalidUtf8(str::Utf8Error),
+    NameTooLong(usize),
     UnexpectedEof,
     UnexpectedLength,
     UnexpectedTag(u8),
@@ -104,6 +102,7 @@ impl fmt::Display for NewDescribeObserverError {
         match self {
             InvalidTarget(t) => write!(f, "invalid target: {t:#X}"),
             InvalidUtf8(e) => write!(f, "utf8: {e}"),
+            NameTooLong(max) => write!(f, "name longer than {max} bytes"),
             UnexpectedEof => write!(f, "unexpected EOF"),
             UnexpectedLength => write!(f, "unexpected length"),
             UnexpectedTag(t) => write!(f, "unexpected tag: {t:#X}"),
@@ -113,6 +112,16 @@ impl fmt::Display for NewDescribeObserverError {
 
 impl std::error::Error for NewDescribeObserverError {}
 
+impl From<ReadCStrError> for NewDescribeObserverError {
+    fn from(err: ReadCStrError) -> Self {
+        match err {
+            ReadCStrError::UnexpectedEof => NewDescribeObserverError::UnexpectedEof,
+            ReadCStrError::Utf8Error(e) => NewDescribeObserverError::InvalidUtf8(e),
+            ReadCStrError::TooLong { max } => NewDescribeObserverError::NameTooLong(max),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- Tests -----------------------------------------------------------------
 
@@ -1,7 +1,7 @@
-use memchr::memchr;
 use std::{fmt, str};
 
-use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame};
+use crate::wire::utils::read_cstr::{MAX_NAME_LEN, ReadCStrError};
+use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame, read_cstr_take};
 
 // -----------------------------------------------------------------------------
 // ----- ExecuteFrameObserver --------------------------------------------------
@@ -36,11 +36,8 @@ impl<'a> ExecuteFrameObserver<'a> {
         };
         let mut pos = 5;
         // portal
-        let rel =
-            memchr(0, &frame[pos..meta.total_len]).ok_or(NewExecuteObserverError::UnexpectedEof)?;
-        let portal =
-            str::from_utf8(&frame[pos..pos + rel]).map_err(NewExecuteObserverError::InvalidUtf8)?;
-        pos += rel + 1;
+        let (portal, rest) = read_cstr_take(&frame[pos..meta.total_len], Some(MAX_NAME_LEN))?;
+        pos = meta.total_len - rest.len();
         // max_rows
         if pos + 4 > meta.total_len {
             return Err(NewExecuteObserverError::UnexpectedEof);
@@ -79,6 +76,7 @@ impl<'a> ExecuteFrameObserver<'a> {
 #[derive(Debug)]
 pub enum NewExecuteObserverError {
     InvalidUtf8(str::Utf8Error),
+    NameTooLong(usize),
     UnexpectedEof,
     UnexpectedLength,
     UnexpectedTag(u8),
@@ -89,6 +87,7 @@ impl fmt::Display for NewExecuteObserverError {
         use NewExecuteObserverError::*;
         match self {
             InvalidUtf8(e) => write!(f, "utf8: {e}"),
+            NameTooLong(max) => write!(f, "name longer than {max} bytes"),
             UnexpectedEof => write!(f, "unexpected EOF"),
             UnexpectedLength => write!(f, "unexpected length"),
             UnexpectedTag(t) => write!(f, "unexpected tag: {t:#X}"),
@@ -98,6 +97,16 @@ impl fmt::Display for NewExecuteObserverError {
 
 impl std::error::Error for NewExecuteObserverError {}
 
+impl From<ReadCStrError> for NewExecuteObserverError {
+    fn from(err: ReadCStrError) -> Self {
+        match err {
+            ReadCStrError::UnexpectedEof => NewExecuteObserverError::UnexpectedEof,
+            ReadCStrError::Utf8Error(e) => NewExecuteObserverError::InvalidUtf8(e),
+            ReadCStrError::TooLong { max } => NewExecuteObserverError::NameTooLong(max),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- Internal: Helpers -----------------------------------------------------
 
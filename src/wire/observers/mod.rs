@@ -1,6 +1,7 @@
 pub mod bind;
 pub mod cancel_request;
 pub mod close;
+pub mod command_complete;
 pub mod copy_data;
 pub mod copy_done;
 pub mod copy_fail;
@@ -1,7 +1,8 @@
 use memchr::memchr;
 use std::{fmt, str};
 
-use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame};
+use crate::wire::utils::read_cstr::{MAX_NAME_LEN, ReadCStrError};
+use crate::wire::utils::{TaggedFrameError, parse_tagged_frame, peek_tagged_frame, read_cstr_take};
 
 // -----------------------------------------------------------------------------
 // ----- ParseFrameObserver ----------------------------------------------------
@@ -43,11 +44,8 @@ impl<'a> ParseFrameObserver<'a> {
         let mut pos = 5;
 
         // statement
-        let rel =
-            memchr(0, &frame[pos..meta.total_len]).ok_or(NewParseObserverError::UnexpectedEof)?;
-        let statement =
-            str::from_utf8(&frame[pos..pos + rel]).map_err(NewParseObserverError::InvalidUtf8)?;
-        pos += rel + 1;
+        let (statement, rest) = read_cstr_take(&frame[pos..meta.total_len], Some(MAX_NAME_LEN))?;
+        pos = meta.total_len - rest.len();
 
         // query
         let rel =
@@ -124,6 +122,7 @@ impl<'a> ParseFrameObserver<'a> {
 pub enum NewParseObserverError {
     InvalidCount(i16),
     InvalidUtf8(str::Utf8Error),
+    NameTooLong(usize),
     UnexpectedEof,
     UnexpectedLength,
     UnexpectedTag(u8),
@@ -135,6 +134,7 @@ impl fmt::Display for NewParseObserverError {
         match self {
             InvalidCount(c) => write!(f, "invalid count: {c}"),
             InvalidUtf8(e) => write!(f, "utf8: {e}"),
+            NameTooLong(max) => write!(f, "name longer than {max} bytes"),
             UnexpectedEof => write!(f, "unexpected EOF"),
             UnexpectedLength => write!(f, "unexpected length"),
             UnexpectedTag(t) => write!(f, "unexpected tag: {t:#X}"),
@@ -144,6 +144,16 @@ impl fmt::Display for NewParseObserverError {
 
 impl std::error::Error for NewParseObserverError {}
 
+impl From<ReadCStrError> for NewParseObserverError {
+    fn from(err: ReadCStrError) -> Self {
+        match err {
+            ReadCStrError::UnexpectedEof => NewParseObserverError::UnexpectedEof,
+            ReadCStrError::Utf8Error(e) => NewParseObserverError::InvalidUtf8(e),
+            ReadCStrError::TooLong { max } => NewParseObserverError::NameTooLong(max),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- Internal: Helpers -----------------------------------------------------
 
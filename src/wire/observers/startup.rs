@@ -5,6 +5,11 @@ use std::{fmt, str};
 // ----- Constants -------------------------------------------------------------
 
 const PROTOCOL_VERSION: i32 = 196608; // 3.0
+const PROTOCOL_MAJOR: i32 = PROTOCOL_VERSION >> 16;
+
+/// Startup keys in this namespace are protocol options, not run-time
+/// parameters; unknown ones are reported back via NegotiateProtocolVersion.
+const PROTOCOL_OPTION_PREFIX: &str = "_pq_.";
 
 // -----------------------------------------------------------------------------
 // ----- StartupFrameObserver --------------------------------------------------
@@ -32,7 +37,7 @@ impl<'a> StartupFrameObserver<'a> {
         }
 
         let version = be_i32(&buf[4..]);
-        if version != PROTOCOL_VERSION {
+        if version >> 16 != PROTOCOL_MAJOR {
             return None;
         }
 
@@ -50,8 +55,9 @@ impl<'a> StartupFrameObserver<'a> {
             return Err(NewStartupObserverError::UnexpectedLength);
         }
 
+        // Any 3.x is accepted; the proxy negotiates newer minors down to 3.0.
         let version = be_i32(&frame[4..]);
-        if version != PROTOCOL_VERSION {
+        if version >> 16 != PROTOCOL_MAJOR {
             return Err(NewStartupObserverError::UnexpectedVersion(version));
         }
 
@@ -94,6 +100,64 @@ impl<'a> StartupFrameObserver<'a> {
         be_i32(&self.frame[4..])
     }
 
+    /// Minor version the client asked for; anything above 0 needs negotiating.
+    #[inline]
+    pub fn minor_version(&self) -> i32 {
+        self.protocol_version() & 0xFFFF
+    }
+
+    /// `_pq_.*` protocol options the client requested, in frame order.
+    pub fn protocol_options(&self) -> impl Iterator<Item = &'a str> {
+        self.params()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(PROTOCOL_OPTION_PREFIX))
+    }
+
+    /// Every `(key, value)` pair, protocol options included, in frame order.
+    pub fn params(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let frame = self.frame;
+        let mut pos = self.params_start;
+        std::iter::from_fn(move || {
+            let rel = memchr(0, &frame[pos..]).unwrap(); // validated
+            if rel == 0 {
+                // terminating nul
+                return None;
+            }
+            let key = unsafe { str::from_utf8_unchecked(&frame[pos..pos + rel]) };
+            pos += rel + 1;
+            let rel = memchr(0, &frame[pos..]).unwrap(); // validated
+            let value = unsafe { str::from_utf8_unchecked(&frame[pos..pos + rel]) };
+            pos += rel + 1;
+            Some((key, value))
+        })
+    }
+
+    /// `-c name=value` and `--name=value` settings from the `options`
+    /// parameter, split on whitespace with backslash escapes like libpq's
+    /// `PGOPTIONS`. Other command-line switches are skipped.
+    pub fn option_settings(&self) -> Vec<(String, String)> {
+        let Some(options) = self.param("options") else {
+            return Vec::new();
+        };
+
+        let mut words = split_options(options).into_iter();
+        let mut settings = Vec::new();
+        while let Some(word) = words.next() {
+            let setting = if word == "-c" {
+                words.next()
+            } else if let Some(setting) = word.strip_prefix("--") {
+                Some(setting.to_string())
+            } else {
+                word.strip_prefix("-c").map(str::to_string)
+            };
+
+            if let Some((name, value)) = setting.as_deref().and_then(|s| s.split_once('=')) {
+                settings.push((name.to_string(), value.to_string()));
+            }
+        }
+        settings
+    }
+
     pub fn param(&self, key: &str) -> Option<&'a str> {
         let mut pos = self.params_start;
         loop {
@@ -148,6 +212,29 @@ fn be_i32(x: &[u8]) -> i32 {
     i32::from_be_bytes([x[0], x[1], x[2], x[3]])
 }
 
+/// Whitespace-separated words; a backslash keeps the next character,
+/// spaces included.
+fn split_options(options: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut chars = options.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => word.extend(chars.next()),
+            c if c.is_ascii_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
 // -----------------------------------------------------------------------------
 // ----- Tests -----------------------------------------------------------------
 
@@ -157,8 +244,12 @@ mod tests {
     use bytes::{BufMut, BytesMut};
 
     fn build_frame(params: &[(&str, &str)]) -> Vec<u8> {
+        build_versioned_frame(PROTOCOL_VERSION, params)
+    }
+
+    fn build_versioned_frame(version: i32, params: &[(&str, &str)]) -> Vec<u8> {
         let mut body = BytesMut::new();
-        body.put_i32(PROTOCOL_VERSION);
+        body.put_i32(version);
         for &(k, v) in params {
             body.extend_from_slice(k.as_bytes());
             body.put_u8(0);
@@ -191,6 +282,32 @@ mod tests {
         assert_eq!(obs.param("user"), Some("postgres"));
         assert_eq!(obs.param("database"), Some("mydb"));
         assert_eq!(obs.param("nonexistent"), None);
+        let params: Vec<_> = obs.params().collect();
+        assert_eq!(params, [("user", "postgres"), ("database", "mydb")]);
+    }
+
+    #[test]
+    fn option_settings_follow_pgoptions_quoting() {
+        let frame = build_frame(&[]);
+        let obs = StartupFrameObserver::new(&frame).unwrap();
+        assert!(obs.option_settings().is_empty());
+
+        let options = r"-c pgcrab.shard=alpha -csearch_path=a\ b  --work_mem=4MB -d 2 -c broken";
+        let frame = build_frame(&[("options", options)]);
+        let obs = StartupFrameObserver::new(&frame).unwrap();
+        let settings = obs.option_settings();
+        let settings: Vec<_> = settings
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            settings,
+            [
+                ("pgcrab.shard", "alpha"),
+                ("search_path", "a b"),
+                ("work_mem", "4MB"),
+            ]
+        );
     }
 
     #[test]
@@ -266,6 +383,35 @@ mod tests {
         matches!(err, NewStartupObserverError::UnexpectedVersion(12345));
     }
 
+    #[test]
+    fn newer_minor_version_is_accepted_with_its_options() {
+        let frame = build_versioned_frame(
+            PROTOCOL_VERSION + 1,
+            &[("user", "crab"), ("_pq_.bogus", "on"), ("_pq_.other", "1")],
+        );
+        let len = StartupFrameObserver::peek(&frame).unwrap();
+        let obs = StartupFrameObserver::new(&frame[..len]).unwrap();
+        assert_eq!(obs.minor_version(), 1);
+        assert_eq!(obs.param("user"), Some("crab"));
+        let options: Vec<_> = obs.protocol_options().collect();
+        assert_eq!(options, ["_pq_.bogus", "_pq_.other"]);
+
+        let frame = build_frame(&[("user", "crab")]);
+        let obs = StartupFrameObserver::new(&frame).unwrap();
+        assert_eq!(obs.minor_version(), 0);
+        assert_eq!(obs.protocol_options().count(), 0);
+    }
+
+    #[test]
+    fn other_major_versions_are_rejected() {
+        let frame = build_versioned_frame(4 << 16, &[]);
+        assert!(StartupFrameObserver::peek(&frame).is_none());
+        assert!(matches!(
+            StartupFrameObserver::new(&frame),
+            Err(NewStartupObserverError::UnexpectedVersion(v)) if v == 4 << 16
+        ));
+    }
+
     #[test]
     fn non_ascii_param() {
         let frame = build_frame(&[("user", "ã��ã�¼ã�¿ã�«")]);
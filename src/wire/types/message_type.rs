@@ -65,6 +65,40 @@ pub enum MessageType {
     CopyDone, // 'c'
 }
 
+impl MessageType {
+    /// Backend message for a Byte1 tag; `None` if the backend never sends it.
+    pub fn from_backend_tag(tag: u8) -> Option<Self> {
+        let message_type = match tag {
+            b'R' => Self::Authentication,
+            b'K' => Self::BackendKeyData,
+            b'2' => Self::BindComplete,
+            b'3' => Self::CloseComplete,
+            b'C' => Self::CommandComplete,
+            b'G' => Self::CopyInResponse,
+            b'H' => Self::CopyOutResponse,
+            b'W' => Self::CopyBothResponse,
+            b'D' => Self::DataRow,
+            b'I' => Self::EmptyQueryResponse,
+            b'E' => Self::ErrorResponse,
+            b'V' => Self::FunctionCallResponse,
+            b'v' => Self::NegotiateProtocolVersion,
+            b'n' => Self::NoData,
+            b'N' => Self::NoticeResponse,
+            b'A' => Self::NotificationResponse,
+            b't' => Self::ParameterDescription,
+            b'S' => Self::ParameterStatus,
+            b'1' => Self::ParseComplete,
+            b's' => Self::PortalSuspended,
+            b'Z' => Self::ReadyForQuery,
+            b'T' => Self::RowDescription,
+            b'd' => Self::CopyData,
+            b'c' => Self::CopyDone,
+            _ => return None,
+        };
+        Some(message_type)
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- AuthenticationType ----------------------------------------------------
 
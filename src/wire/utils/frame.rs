@@ -19,11 +19,7 @@ pub fn peek_tagged_frame(buf: &[u8], tag: u8) -> Option<TaggedFrame> {
     }
 
     let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
-    if len < 4 {
-        return None;
-    }
-
-    let total_len = 1 + len;
+    let total_len = total_len(len).ok()?;
     if buf.len() < total_len {
         return None;
     }
@@ -41,14 +37,132 @@ pub fn parse_tagged_frame(frame: &[u8], tag: u8) -> Result<TaggedFrame, TaggedFr
     }
 
     let len = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    let total_len = total_len(len)?;
+    if frame.len() != total_len {
+        return Err(TaggedFrameError::UnexpectedLength);
+    }
+
+    Ok(TaggedFrame { len, total_len })
+}
+
+/// Tag byte plus `len`. The length counts its own 4 bytes, so anything
+/// shorter is invalid, as is one whose total doesn't fit in `usize`.
+fn total_len(len: usize) -> Result<usize, TaggedFrameError> {
     if len < 4 {
         return Err(TaggedFrameError::InvalidLength(len));
     }
+    len.checked_add(1)
+        .ok_or(TaggedFrameError::InvalidLength(len))
+}
 
-    let total_len = 1 + len;
-    if frame.len() != total_len {
-        return Err(TaggedFrameError::UnexpectedLength);
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn frame(tag: u8, len: u32, body: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        out.extend_from_slice(&len.to_be_bytes());
+        out.extend_from_slice(body);
+        out
     }
 
-    Ok(TaggedFrame { len, total_len })
+    #[test]
+    fn length_below_four_is_invalid() {
+        for len in 0..4 {
+            let bytes = frame(b'Q', len, &[0; 8]);
+            assert!(peek_tagged_frame(&bytes, b'Q').is_none());
+            assert!(matches!(
+                parse_tagged_frame(&bytes, b'Q'),
+                Err(TaggedFrameError::InvalidLength(n)) if n == len as usize
+            ));
+        }
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        for cut in 0..5 {
+            let bytes = &frame(b'Q', 4, &[])[..cut];
+            assert!(peek_tagged_frame(bytes, b'Q').is_none());
+            assert!(matches!(
+                parse_tagged_frame(bytes, b'Q'),
+                Err(TaggedFrameError::UnexpectedLength)
+            ));
+        }
+    }
+
+    #[test]
+    fn huge_length_waits_instead_of_overflowing() {
+        let bytes = frame(b'Q', u32::MAX, &[0; 16]);
+        assert!(peek_tagged_frame(&bytes, b'Q').is_none());
+        assert!(matches!(
+            parse_tagged_frame(&bytes, b'Q'),
+            Err(TaggedFrameError::UnexpectedLength)
+        ));
+    }
+
+    #[test]
+    fn total_len_overflow_is_invalid() {
+        assert!(matches!(
+            total_len(usize::MAX),
+            Err(TaggedFrameError::InvalidLength(usize::MAX))
+        ));
+        assert_eq!(total_len(4).unwrap(), 5);
+    }
+
+    #[test]
+    fn wrong_tag_and_trailing_bytes() {
+        let bytes = frame(b'Q', 4, &[]);
+        assert!(peek_tagged_frame(&bytes, b'P').is_none());
+        assert!(matches!(
+            parse_tagged_frame(&bytes, b'P'),
+            Err(TaggedFrameError::UnexpectedTag(b'Q'))
+        ));
+
+        let bytes = frame(b'Q', 4, &[0]);
+        assert_eq!(peek_tagged_frame(&bytes, b'Q').unwrap().total_len, 5);
+        assert!(matches!(
+            parse_tagged_frame(&bytes, b'Q'),
+            Err(TaggedFrameError::UnexpectedLength)
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_bytes_never_panic(bytes in proptest::collection::vec(any::<u8>(), 0..64)) {
+            let tag = bytes.first().copied().unwrap_or(b'Q');
+
+            if let Some(meta) = peek_tagged_frame(&bytes, tag) {
+                prop_assert!(meta.total_len <= bytes.len());
+                prop_assert_eq!(meta.total_len, meta.len + 1);
+                prop_assert!(meta.len >= 4);
+            }
+
+            if let Ok(meta) = parse_tagged_frame(&bytes, tag) {
+                prop_assert_eq!(meta.total_len, bytes.len());
+                prop_assert!(meta.len >= 4);
+            }
+        }
+
+        #[test]
+        fn well_formed_frames_round_trip(
+            tag in any::<u8>(),
+            body in proptest::collection::vec(any::<u8>(), 0..64),
+            trailing in proptest::collection::vec(any::<u8>(), 0..8),
+        ) {
+            let bytes = frame(tag, 4 + body.len() as u32, &body);
+            let meta = parse_tagged_frame(&bytes, tag).unwrap();
+            prop_assert_eq!(meta.total_len, bytes.len());
+
+            let stream = [bytes.as_slice(), &trailing].concat();
+            let peeked = peek_tagged_frame(&stream, tag).unwrap();
+            prop_assert_eq!(peeked, meta);
+        }
+    }
 }
+
+// -----------------------------------------------------------------------------
+// -----------------------------------------------------------------------------
@@ -4,6 +4,6 @@ pub mod peek_frontend;
 pub mod read_cstr;
 
 pub use frame::{TaggedFrame, TaggedFrameError, parse_tagged_frame, peek_tagged_frame};
-pub use peek_backend::peek_backend;
+pub use peek_backend::{UnknownBackendTag, peek_backend, try_peek_backend};
 pub use peek_frontend::peek_frontend;
 pub use read_cstr::{read_cstr, read_cstr_take};
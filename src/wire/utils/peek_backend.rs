@@ -1,31 +1,61 @@
-pub fn peek_backend(bytes: &[u8]) -> Option<(u8, usize)> {
+use crate::wire::types::MessageType;
+
+// -----------------------------------------------------------------------------
+// ----- Structs ---------------------------------------------------------------
+
+/// The backend sent a Byte1 tag that is not part of the v3 protocol; the
+/// stream can't be framed past it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownBackendTag(pub u8);
+
+impl std::fmt::Display for UnknownBackendTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unexpected backend message type {:?}", self.0 as char)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// ----- peek_backend ----------------------------------------------------------
+
+/// Type and declared length (excluding the tag byte) of the backend frame at
+/// the front of `bytes`. `None` until the whole frame is buffered, and for a
+/// tag the backend never sends.
+pub fn peek_backend(bytes: &[u8]) -> Option<(MessageType, usize)> {
+    try_peek_backend(bytes).ok().flatten()
+}
+
+/// Like `peek_backend`, but reports an unknown tag as soon as the header is
+/// buffered instead of waiting on a frame that can never be classified.
+pub fn try_peek_backend(bytes: &[u8]) -> Result<Option<(MessageType, usize)>, UnknownBackendTag> {
     if bytes.len() < 5 {
-        return None;
+        return Ok(None);
     }
 
     let tag = bytes[0];
+    let message_type = MessageType::from_backend_tag(tag).ok_or(UnknownBackendTag(tag))?;
+
     let len = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
     if len < 4 {
-        return None;
+        return Ok(None);
     }
 
     let total = 1 + len;
     if bytes.len() < total {
-        return None;
+        return Ok(None);
     }
 
-    Some((tag, len))
+    Ok(Some((message_type, len)))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::peek_backend;
+    use super::*;
 
     #[test]
     fn peek_backend_full_frame() {
         let frame = [b'Z', 0, 0, 0, 5, b'I'];
-        let (tag, len) = peek_backend(&frame).expect("expected frame");
-        assert_eq!(tag, b'Z');
+        let (message_type, len) = peek_backend(&frame).expect("expected frame");
+        assert_eq!(message_type, MessageType::ReadyForQuery);
         assert_eq!(len, 5);
     }
 
@@ -46,4 +76,51 @@ mod tests {
         let frame = [b'Z', 0, 0, 0, 5];
         assert!(peek_backend(&frame).is_none());
     }
+
+    #[test]
+    fn peek_backend_types_every_backend_tag() {
+        let tags = [
+            (b'R', MessageType::Authentication),
+            (b'K', MessageType::BackendKeyData),
+            (b'2', MessageType::BindComplete),
+            (b'3', MessageType::CloseComplete),
+            (b'C', MessageType::CommandComplete),
+            (b'G', MessageType::CopyInResponse),
+            (b'H', MessageType::CopyOutResponse),
+            (b'W', MessageType::CopyBothResponse),
+            (b'D', MessageType::DataRow),
+            (b'I', MessageType::EmptyQueryResponse),
+            (b'E', MessageType::ErrorResponse),
+            (b'V', MessageType::FunctionCallResponse),
+            (b'v', MessageType::NegotiateProtocolVersion),
+            (b'n', MessageType::NoData),
+            (b'N', MessageType::NoticeResponse),
+            (b'A', MessageType::NotificationResponse),
+            (b't', MessageType::ParameterDescription),
+            (b'S', MessageType::ParameterStatus),
+            (b'1', MessageType::ParseComplete),
+            (b's', MessageType::PortalSuspended),
+            (b'Z', MessageType::ReadyForQuery),
+            (b'T', MessageType::RowDescription),
+            (b'd', MessageType::CopyData),
+            (b'c', MessageType::CopyDone),
+        ];
+
+        for (tag, expected) in tags {
+            let frame = [tag, 0, 0, 0, 4];
+            assert_eq!(
+                peek_backend(&frame),
+                Some((expected, 4)),
+                "tag {}",
+                tag as char
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_tag_fails_before_the_body_arrives() {
+        let header = [b'Q', 0, 0, 1, 0];
+        assert_eq!(try_peek_backend(&header), Err(UnknownBackendTag(b'Q')));
+        assert!(peek_backend(&header).is_none());
+    }
 }
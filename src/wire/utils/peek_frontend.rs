@@ -14,6 +14,12 @@ use super::super::observers::{
     sync::SyncFrameObserver, terminate::TerminateFrameObserver,
 };
 
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Postgres' own cap on a startup packet; anything longer isn't one.
+const MAX_STARTUP_PACKET_LEN: usize = 10_000;
+
 // -----------------------------------------------------------------------------
 // ----- Structs ---------------------------------------------------------------
 
@@ -69,9 +75,32 @@ fn peek_frontend_startup(bytes: &[u8]) -> Option<PeekResult> {
         });
     }
 
+    // A startup for a protocol major the proxy doesn't speak (2.0, 4.x) is
+    // still one packet: hand it on to be refused rather than wait on it.
+    if let Some(len) = get_startup_len(bytes) {
+        return Some(PeekResult {
+            message_type: MessageType::Startup,
+            len,
+        });
+    }
+
     None
 }
 
+/// Length of a complete untagged startup packet of plausible size.
+fn get_startup_len(buf: &[u8]) -> Option<usize> {
+    if buf.len() < 8 {
+        return None;
+    }
+
+    let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    if !(8..=MAX_STARTUP_PACKET_LEN).contains(&len) || buf.len() < len {
+        return None;
+    }
+
+    Some(len)
+}
+
 // -----------------------------------------------------------------------------
 // ----- peek_frontend: Authenticating -----------------------------------------
 
@@ -222,5 +251,82 @@ fn peek_frontend_ready(bytes: &[u8]) -> Option<PeekResult> {
     }
 }
 
+// -----------------------------------------------------------------------------
+// ----- Tests -----------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::fs;
+    use std::path::Path;
+
+    const STAGES: [AuthStage; 3] = [
+        AuthStage::Startup,
+        AuthStage::Authenticating,
+        AuthStage::Ready,
+    ];
+
+    /// Frames `bytes` the way the inbox does, and decodes each frame the
+    /// way the fuzz target does: every frame must move the cursor forward
+    /// without running past the end, and decoding may fail but not panic.
+    fn frame_all(stage: AuthStage, bytes: &[u8]) {
+        let mut rest = bytes;
+        while let Some(peek) = peek_frontend(stage, rest) {
+            assert!(
+                (1..=rest.len()).contains(&peek.len),
+                "{stage:?} frame of {} bytes with {} left",
+                peek.len,
+                rest.len()
+            );
+            let (frame, tail) = rest.split_at(peek.len);
+            decode(peek.message_type, frame);
+            rest = tail;
+        }
+    }
+
+    fn decode(message_type: MessageType, frame: &[u8]) {
+        match message_type {
+            MessageType::Startup => drop(StartupFrameObserver::new(frame)),
+            MessageType::Query => drop(QueryFrameObserver::new(frame)),
+            MessageType::Parse => drop(ParseFrameObserver::new(frame)),
+            MessageType::Bind => drop(BindFrameObserver::new(frame)),
+            MessageType::Describe => drop(DescribeFrameObserver::new(frame)),
+            MessageType::Execute => drop(ExecuteFrameObserver::new(frame)),
+            MessageType::Close => drop(CloseFrameObserver::new(frame)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn fuzz_corpus_frames_cleanly() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/frontend_frames");
+        let mut replayed = 0;
+        for entry in fs::read_dir(&corpus).expect("fuzz corpus") {
+            let bytes = fs::read(entry.unwrap().path()).unwrap();
+            for stage in STAGES {
+                frame_all(stage, &bytes);
+            }
+            replayed += 1;
+        }
+        assert!(replayed > 0);
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_bytes_frame_or_stop(bytes in proptest::collection::vec(any::<u8>(), 0..128)) {
+            for stage in STAGES {
+                frame_all(stage, &bytes);
+            }
+        }
+
+        #[test]
+        fn short_startup_lengths_stop(len in 0u32..8, tail in proptest::collection::vec(any::<u8>(), 4..16)) {
+            let bytes = [&len.to_be_bytes()[..], &tail].concat();
+            frame_all(AuthStage::Startup, &bytes);
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 // -----------------------------------------------------------------------------
@@ -3,18 +3,29 @@
 use memchr::memchr;
 use std::{error::Error as StdError, fmt, str};
 
+// -----------------------------------------------------------------------------
+// ----- Constants -------------------------------------------------------------
+
+/// Longest statement or portal name the observers accept. Postgres truncates
+/// identifiers to 63 bytes; anything near this is a malformed frame.
+pub const MAX_NAME_LEN: usize = 64 * 1024;
+
 // -----------------------------------------------------------------------------
 // ----- read_cstr (mutates input) ---------------------------------------------
 
 /// Read a NUL-terminated UTF-8 string from the front of `input_bytes`.
 ///
 /// Returns a `&str` borrowed from `input_bytes` and advances `input_bytes` to
-/// start **after** the NUL terminator.
+/// start **after** the NUL terminator. With `max_len`, a string longer than
+/// that many bytes fails without scanning past it.
 #[inline]
-pub fn read_cstr<'a>(input_bytes: &mut &'a [u8]) -> Result<&'a str, ReadCStrError> {
+pub fn read_cstr<'a>(
+    input_bytes: &mut &'a [u8],
+    max_len: Option<usize>,
+) -> Result<&'a str, ReadCStrError> {
     let unread_bytes = *input_bytes;
 
-    let nul_index = memchr(0, unread_bytes).ok_or(ReadCStrError::UnexpectedEof)?;
+    let nul_index = find_nul(unread_bytes, max_len)?;
 
     let (bytes_before_nul, bytes_from_nul) = unread_bytes.split_at(nul_index);
 
@@ -31,8 +42,11 @@ pub fn read_cstr<'a>(input_bytes: &mut &'a [u8]) -> Result<&'a str, ReadCStrErro
 ///
 /// This version does **not** mutate the caller’s slice; it returns `(value, remainder)`.
 #[inline]
-pub fn read_cstr_take<'a>(input_bytes: &'a [u8]) -> Result<(&'a str, &'a [u8]), ReadCStrError> {
-    let nul_index = memchr(0, input_bytes).ok_or(ReadCStrError::UnexpectedEof)?;
+pub fn read_cstr_take<'a>(
+    input_bytes: &'a [u8],
+    max_len: Option<usize>,
+) -> Result<(&'a str, &'a [u8]), ReadCStrError> {
+    let nul_index = find_nul(input_bytes, max_len)?;
 
     let (bytes_before_nul, bytes_from_nul) = input_bytes.split_at(nul_index);
 
@@ -41,6 +55,25 @@ pub fn read_cstr_take<'a>(input_bytes: &'a [u8]) -> Result<(&'a str, &'a [u8]),
     Ok((parsed, &bytes_from_nul[1..]))
 }
 
+// -----------------------------------------------------------------------------
+// ----- Internal: Helpers -----------------------------------------------------
+
+/// Index of the terminating NUL, looking at no more than `max_len` bytes
+/// before it.
+#[inline]
+fn find_nul(bytes: &[u8], max_len: Option<usize>) -> Result<usize, ReadCStrError> {
+    let Some(max) = max_len else {
+        return memchr(0, bytes).ok_or(ReadCStrError::UnexpectedEof);
+    };
+
+    let window = &bytes[..bytes.len().min(max.saturating_add(1))];
+    match memchr(0, window) {
+        Some(nul_index) => Ok(nul_index),
+        None if bytes.len() > max => Err(ReadCStrError::TooLong { max }),
+        None => Err(ReadCStrError::UnexpectedEof),
+    }
+}
+
 // -----------------------------------------------------------------------------
 // ----- Error -----------------------------------------------------------------
 
@@ -48,12 +81,17 @@ pub fn read_cstr_take<'a>(input_bytes: &'a [u8]) -> Result<(&'a str, &'a [u8]),
 pub enum ReadCStrError {
     UnexpectedEof,
     Utf8Error(str::Utf8Error),
+    /// No NUL within the first `max` bytes.
+    TooLong {
+        max: usize,
+    },
 }
 
 impl fmt::Display for ReadCStrError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ReadCStrError::UnexpectedEof => write!(f, "unexpected EOF"),
+            ReadCStrError::TooLong { max } => write!(f, "string longer than {max} bytes"),
             ReadCStrError::Utf8Error(e) => write!(f, "UTF-8 error: {e}"),
         }
     }
@@ -87,7 +125,7 @@ mod tests {
         buffer.extend_from_slice(b"world"); // "hello\0world"
 
         let mut input: &[u8] = &buffer;
-        let got = read_cstr(&mut input).unwrap();
+        let got = read_cstr(&mut input, None).unwrap();
 
         assert_eq!(got, "hello");
         assert_eq!(input, b"world");
@@ -99,7 +137,7 @@ mod tests {
         let mut buffer = Vec::from(c_hello.as_bytes_with_nul());
         buffer.extend_from_slice(b"world");
 
-        let (got, rest) = read_cstr_take(&buffer).unwrap();
+        let (got, rest) = read_cstr_take(&buffer, None).unwrap();
 
         assert_eq!(got, "hello");
         assert_eq!(rest, b"world");
@@ -109,7 +147,7 @@ mod tests {
     fn eof_without_nul() {
         let c = CString::new("no-nul").unwrap();
         let mut input: &[u8] = c.as_bytes(); // no trailing NUL
-        let err = read_cstr(&mut input).unwrap_err();
+        let err = read_cstr(&mut input, None).unwrap_err();
 
         assert!(matches!(err, ReadCStrError::UnexpectedEof));
     }
@@ -119,18 +157,60 @@ mod tests {
         let bytes = vec![0xFF, 0xFE]; // invalid UTF-8
         let c = CString::new(bytes).unwrap(); // interior NULs not allowed; invalid UTF-8 is fine
         let mut input: &[u8] = c.as_bytes_with_nul(); // includes trailing NUL
-        let err = read_cstr(&mut input).unwrap_err();
+        let err = read_cstr(&mut input, None).unwrap_err();
 
         assert!(matches!(err, ReadCStrError::Utf8Error(_)));
     }
 
+    // ----- max_len -----
+
+    #[test]
+    fn name_at_the_limit_is_accepted() {
+        let mut buffer = vec![b'n'; 8];
+        buffer.push(0);
+        buffer.extend_from_slice(b"rest");
+
+        let (got, rest) = read_cstr_take(&buffer, Some(8)).unwrap();
+        assert_eq!(got, "nnnnnnnn");
+        assert_eq!(rest, b"rest");
+
+        let mut input: &[u8] = &buffer;
+        assert_eq!(read_cstr(&mut input, Some(8)).unwrap(), "nnnnnnnn");
+        assert_eq!(input, b"rest");
+    }
+
+    #[test]
+    fn name_past_the_limit_is_rejected() {
+        let mut buffer = vec![b'n'; 9];
+        buffer.push(0);
+
+        let err = read_cstr_take(&buffer, Some(8)).unwrap_err();
+        assert!(matches!(err, ReadCStrError::TooLong { max: 8 }));
+
+        let mut input: &[u8] = &buffer;
+        let err = read_cstr(&mut input, Some(8)).unwrap_err();
+        assert!(matches!(err, ReadCStrError::TooLong { max: 8 }));
+        assert_eq!(input.len(), buffer.len());
+    }
+
+    #[test]
+    fn short_input_without_nul_is_still_eof() {
+        let err = read_cstr_take(b"abc", Some(8)).unwrap_err();
+        assert!(matches!(err, ReadCStrError::UnexpectedEof));
+
+        // Unbounded reads scan to the end.
+        let long = vec![b'n'; MAX_NAME_LEN * 2];
+        let err = read_cstr_take(&long, None).unwrap_err();
+        assert!(matches!(err, ReadCStrError::UnexpectedEof));
+    }
+
     // ----- Bytes / BytesMut integration -----
 
     #[test]
     fn bytes_read_cstr_mut() {
         let frozen = Bytes::from_static(b"hello\0world");
         let mut buffer: &[u8] = frozen.as_ref();
-        let parsed = read_cstr(&mut buffer).unwrap();
+        let parsed = read_cstr(&mut buffer, None).unwrap();
 
         assert_eq!(&frozen[..], b"hello\0world");
         assert_eq!(parsed, "hello");
@@ -140,7 +220,7 @@ mod tests {
     #[test]
     fn bytes_read_cstr_take() {
         let frozen = Bytes::from_static(b"hello\0world");
-        let (parsed, remaining) = read_cstr_take(frozen.as_ref()).unwrap();
+        let (parsed, remaining) = read_cstr_take(frozen.as_ref(), None).unwrap();
 
         assert_eq!(&frozen[..], b"hello\0world");
         assert_eq!(parsed, "hello");
@@ -153,7 +233,7 @@ mod tests {
         buffer.extend_from_slice(b"hello\0world");
 
         let mut input: &[u8] = &buffer[..]; // borrow as slice
-        let parsed = read_cstr(&mut input).unwrap();
+        let parsed = read_cstr(&mut input, None).unwrap();
 
         assert_eq!(&buffer[..], b"hello\0world");
         assert_eq!(parsed, "hello");
@@ -165,7 +245,7 @@ mod tests {
         let mut buffer = BytesMut::with_capacity(16);
         buffer.extend_from_slice(b"hello\0world");
 
-        let (parsed, remaining) = read_cstr_take(&buffer[..]).unwrap();
+        let (parsed, remaining) = read_cstr_take(&buffer[..], None).unwrap();
 
         assert_eq!(&buffer[..], b"hello\0world");
         assert_eq!(parsed, "hello");
@@ -178,7 +258,7 @@ mod tests {
         buffer.extend_from_slice(b"hello\0world");
         let frozen: Bytes = buffer.freeze(); // O(1)
 
-        let (parsed, remaining) = read_cstr_take(frozen.as_ref()).unwrap();
+        let (parsed, remaining) = read_cstr_take(frozen.as_ref(), None).unwrap();
 
         assert_eq!(&frozen[..], b"hello\0world");
         assert_eq!(parsed, "hello");
@@ -0,0 +1,57 @@
+mod support;
+
+use std::time::Duration;
+
+use pgcrab::config::shards::ShardRecord;
+use pgcrab::gateway::{GatewayPools, GatewaySession};
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn reset_gives_next_checkout_a_clean_prepared_namespace() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+
+    // One backend so both checkouts land on the same server process.
+    let pools = GatewayPools::new(vec![ShardRecord {
+        min_connections: 1,
+        max_connections: 1,
+        ..shard.record()
+    }]);
+    let pool = pools.get(&shard.name).expect("pool");
+
+    let mut first = GatewaySession::from_pool(&pool)
+        .await
+        .expect("first checkout");
+    let first_pid = support::simple_query(
+        first.backend(),
+        "PREPARE leaked AS SELECT 1; SELECT pg_backend_pid()",
+    )
+    .await;
+    first.reset().await.expect("reset");
+    drop(first);
+
+    for _ in 0..50 {
+        if pool.stats().await.idle == 1 {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+
+    let mut second = GatewaySession::from_pool(&pool)
+        .await
+        .expect("second checkout");
+    let second_pid = support::simple_query(second.backend(), "SELECT pg_backend_pid()").await;
+    assert_eq!(first_pid, second_pid, "expected the same backend");
+
+    let prepared = support::simple_query(
+        second.backend(),
+        "SELECT count(*) FROM pg_prepared_statements",
+    )
+    .await;
+    assert_eq!(prepared, "0");
+}
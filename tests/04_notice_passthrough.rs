@@ -0,0 +1,68 @@
+mod support;
+
+use std::future::poll_fn;
+
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, NoTls};
+
+#[tokio::test]
+async fn backend_notice_reaches_client_before_completion() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .first()
+        .cloned()
+        .expect("expected at least one [[users]] entry");
+
+    let port = support::reserve_port(&shard.host);
+    let mut child = support::spawn_pgcrab(&shard.host, port);
+    support::wait_for_listen(&shard.host, port).await;
+
+    let conn_str = format!(
+        "host={} port={} user={} password={} dbname={}",
+        shard.host, port, user.username, user.password, shard.name
+    );
+
+    let (client, mut connection) = tokio_postgres::connect(&conn_str, NoTls)
+        .await
+        .expect("connect should succeed");
+
+    let (notices, mut received) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(Ok(message)) = poll_fn(|cx| connection.poll_message(cx)).await {
+            if let AsyncMessage::Notice(notice) = message {
+                let _ = notices.send(notice);
+            }
+        }
+    });
+
+    client
+        .batch_execute("DO $$ BEGIN RAISE NOTICE 'crab says hi'; END $$")
+        .await
+        .expect("DO block should succeed");
+
+    // The connection task relays messages in wire order, so the notice is
+    // already queued by the time CommandComplete/ReadyForQuery resolved the call.
+    let notice = received.try_recv().expect("notice before completion");
+    assert_eq!(notice.severity(), "NOTICE");
+    assert_eq!(notice.message(), "crab says hi");
+
+    // The notice didn't consume a ReadyForQuery: the session is still in step.
+    let rows = client
+        .simple_query("select 1")
+        .await
+        .expect("follow-up query should succeed");
+    assert!(
+        rows.iter()
+            .any(|msg| matches!(msg, tokio_postgres::SimpleQueryMessage::Row(_)))
+    );
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,96 @@
+mod support;
+
+use bytes::Bytes;
+use futures_util::SinkExt;
+use tokio_postgres::NoTls;
+
+#[tokio::test]
+async fn copy_from_stdin_streams_through_pgcrab() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .first()
+        .cloned()
+        .expect("expected at least one [[users]] entry");
+
+    let port = support::reserve_port(&shard.host);
+    let mut child = support::spawn_pgcrab(&shard.host, port);
+    support::wait_for_listen(&shard.host, port).await;
+
+    let conn_str = format!(
+        "host={} port={} user={} password={} dbname={}",
+        shard.host, port, user.username, user.password, shard.name
+    );
+
+    let (client, connection) = tokio_postgres::connect(&conn_str, NoTls)
+        .await
+        .expect("connect should succeed");
+
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    // Not a temp table: each statement may land on a different backend.
+    client
+        .batch_execute(
+            "DROP TABLE IF EXISTS pgcrab_copy_users; \
+             CREATE TABLE pgcrab_copy_users (id int PRIMARY KEY, name text NOT NULL)",
+        )
+        .await
+        .expect("create table");
+
+    let sink = client
+        .copy_in("COPY pgcrab_copy_users (id, name) FROM STDIN")
+        .await
+        .expect("enter COPY IN");
+    futures_util::pin_mut!(sink);
+    for id in 1..=500 {
+        let line = format!("{id}\tcrab-{id}\n");
+        sink.send(Bytes::from(line)).await.expect("send CopyData");
+    }
+    let copied = sink.finish().await.expect("finish COPY");
+    assert_eq!(copied, 500);
+
+    // Session accounting survived COPY: the next query answers normally.
+    let row = client
+        .query_one(
+            "SELECT count(*), max(name) FROM pgcrab_copy_users WHERE name LIKE 'crab-%'",
+            &[],
+        )
+        .await
+        .expect("count rows");
+    assert_eq!(row.get::<_, i64>(0), 500);
+    assert_eq!(row.get::<_, String>(1), "crab-99");
+
+    // Aborted: dropping the sink unfinished sends CopyFail, the backend
+    // rolls the COPY back, and the session carries on.
+    {
+        let sink = client
+            .copy_in::<_, Bytes>("COPY pgcrab_copy_users (id, name) FROM STDIN")
+            .await
+            .expect("enter COPY IN");
+        futures_util::pin_mut!(sink);
+        sink.send(Bytes::from_static(b"1000\tcrab-aborted\n"))
+            .await
+            .expect("send CopyData");
+    }
+    let row = client
+        .query_one("SELECT count(*) FROM pgcrab_copy_users", &[])
+        .await
+        .expect("query after CopyFail");
+    assert_eq!(row.get::<_, i64>(0), 500);
+
+    client
+        .batch_execute("DROP TABLE pgcrab_copy_users")
+        .await
+        .expect("drop table");
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,130 @@
+mod support;
+
+use std::time::Duration;
+
+use bytes::{BufMut, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn flush_pushes_extended_replies_before_sync() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .first()
+        .cloned()
+        .expect("expected at least one [[users]] entry");
+
+    let port = support::reserve_port(&shard.host);
+    let mut child = support::spawn_pgcrab(&shard.host, port);
+    support::wait_for_listen(&shard.host, port).await;
+
+    let mut stream = TcpStream::connect((shard.host.as_str(), port))
+        .await
+        .expect("connect to pgcrab");
+    stream
+        .write_all(&startup(&user.username, &shard.name))
+        .await
+        .unwrap();
+    expect_tag(&mut stream, b'R').await;
+    stream.write_all(&password(&user.password)).await.unwrap();
+    read_until(&mut stream, b'Z').await;
+
+    // Parse/Describe/Flush, no Sync: the replies must arrive anyway.
+    let mut batch = BytesMut::new();
+    frame(&mut batch, b'P', |body| {
+        body.extend_from_slice(b"flushed\0SELECT 1::int AS one\0");
+        body.put_i16(0);
+    });
+    frame(&mut batch, b'D', |body| {
+        body.extend_from_slice(b"Sflushed\0")
+    });
+    frame(&mut batch, b'H', |_| {});
+    stream.write_all(&batch).await.unwrap();
+
+    let tags = read_until(&mut stream, b'T').await;
+    assert_eq!(tags, [b'1', b't', b'T']);
+
+    // The Sync that follows is what earns a ReadyForQuery.
+    let mut sync = BytesMut::new();
+    frame(&mut sync, b'S', |_| {});
+    stream.write_all(&sync).await.unwrap();
+    assert_eq!(read_until(&mut stream, b'Z').await, [b'Z']);
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+fn frame(out: &mut BytesMut, tag: u8, body: impl FnOnce(&mut BytesMut)) {
+    let mut payload = BytesMut::new();
+    body(&mut payload);
+    out.put_u8(tag);
+    out.put_u32((4 + payload.len()) as u32);
+    out.extend_from_slice(&payload);
+}
+
+fn startup(user: &str, database: &str) -> BytesMut {
+    let mut body = BytesMut::new();
+    body.put_i32(196608);
+    for (key, value) in [("user", user), ("database", database)] {
+        body.extend_from_slice(key.as_bytes());
+        body.put_u8(0);
+        body.extend_from_slice(value.as_bytes());
+        body.put_u8(0);
+    }
+    body.put_u8(0);
+
+    let mut out = BytesMut::new();
+    out.put_u32((4 + body.len()) as u32);
+    out.extend_from_slice(&body);
+    out
+}
+
+fn password(password: &str) -> BytesMut {
+    let mut out = BytesMut::new();
+    frame(&mut out, b'p', |body| {
+        body.extend_from_slice(password.as_bytes());
+        body.put_u8(0);
+    });
+    out
+}
+
+/// Reads one backend message and asserts its tag.
+async fn expect_tag(stream: &mut TcpStream, tag: u8) {
+    let (found, _) = read_message(stream).await;
+    assert_eq!(found, tag);
+}
+
+/// Tags of every message up to and including `last`; fails on errors.
+async fn read_until(stream: &mut TcpStream, last: u8) -> Vec<u8> {
+    let mut tags = Vec::new();
+    loop {
+        let (tag, body) = read_message(stream).await;
+        assert_ne!(tag, b'E', "error: {}", String::from_utf8_lossy(&body));
+        if !matches!(tag, b'S' | b'K' | b'N') {
+            tags.push(tag);
+        }
+        if tag == last {
+            return tags;
+        }
+    }
+}
+
+async fn read_message(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    timeout(Duration::from_secs(5), async {
+        let tag = stream.read_u8().await.expect("read tag");
+        let len = stream.read_u32().await.expect("read length") as usize;
+        let mut body = vec![0u8; len - 4];
+        stream.read_exact(&mut body).await.expect("read body");
+        (tag, body)
+    })
+    .await
+    .expect("pgcrab did not answer in time")
+}
@@ -0,0 +1,72 @@
+mod support;
+
+use std::fs;
+
+use tokio_postgres::NoTls;
+
+#[tokio::test]
+async fn select_over_the_unix_socket() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .first()
+        .cloned()
+        .expect("expected at least one [[users]] entry");
+
+    // libpq-style clients look for `<dir>/.s.PGSQL.<port>`.
+    let port = support::reserve_port(&shard.host);
+    let dir = tempfile::tempdir().expect("socket dir");
+    let socket_path = dir.path().join(format!(".s.PGSQL.{port}"));
+
+    let raw = support::config_text().expect("read pgcrab.toml");
+    let config_path = dir.path().join("pgcrab.toml");
+    fs::write(
+        &config_path,
+        format!("{raw}\n[server]\nunix_socket_path = {:?}\n", socket_path),
+    )
+    .expect("write config");
+
+    let mut child =
+        support::spawn_pgcrab_with_config(&shard.host, port, config_path.to_str().unwrap());
+    support::wait_for_unix_listen(&socket_path).await;
+
+    let conn_str = format!(
+        "host={} port={} user={} password={} dbname={}",
+        dir.path().display(),
+        port,
+        user.username,
+        user.password,
+        shard.name
+    );
+
+    let (client, connection) = tokio_postgres::connect(&conn_str, NoTls)
+        .await
+        .expect("connect over the unix socket should succeed");
+
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let rows = client
+        .simple_query("select 1")
+        .await
+        .expect("select 1 should succeed");
+
+    let value = rows
+        .iter()
+        .find_map(|msg| match msg {
+            tokio_postgres::SimpleQueryMessage::Row(row) => row.get(0),
+            _ => None,
+        })
+        .expect("expected a row");
+    assert_eq!(value, "1");
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,71 @@
+mod support;
+
+use pgcrab::Server;
+use tokio_postgres::NoTls;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test]
+async fn embedded_server_answers_and_shuts_down() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .first()
+        .cloned()
+        .expect("expected at least one [[users]] entry");
+
+    let server = Server::builder()
+        .listen(format!("{}:0", shard.host).parse().expect("listen address"))
+        .config(support::config_path().expect("config path"))
+        .build()
+        .await
+        .expect("build server");
+    let addr = server.local_addr().expect("bound address");
+
+    let again = Server::builder()
+        .listen(format!("{}:0", shard.host).parse().expect("listen address"))
+        .config(support::config_path().expect("config path"))
+        .build()
+        .await
+        .expect_err("config is process-wide");
+    assert_eq!(again.kind(), std::io::ErrorKind::AlreadyExists);
+
+    let shutdown = CancellationToken::new();
+    let running = tokio::spawn(server.run(shutdown.clone()));
+
+    let conn_str = format!(
+        "host={} port={} user={} password={} dbname={}",
+        addr.ip(),
+        addr.port(),
+        user.username,
+        user.password,
+        shard.name
+    );
+    let (client, connection) = tokio_postgres::connect(&conn_str, NoTls)
+        .await
+        .expect("connect should succeed");
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let rows = client
+        .simple_query("select 1")
+        .await
+        .expect("select 1 should succeed");
+    let value = rows.iter().find_map(|msg| match msg {
+        tokio_postgres::SimpleQueryMessage::Row(row) => row.get(0).map(str::to_string),
+        _ => None,
+    });
+    assert_eq!(value.as_deref(), Some("1"));
+
+    shutdown.cancel();
+    running
+        .await
+        .expect("server task")
+        .expect("server stops cleanly");
+}
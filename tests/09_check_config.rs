@@ -0,0 +1,42 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn check_config(fixture: &str) -> Output {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/config")
+        .join(fixture);
+
+    Command::new(env!("CARGO_BIN_EXE_pgcrab"))
+        .args(["admin", "check-config", "--config"])
+        .arg(path)
+        .env_remove("PGCRAB_CONFIG_FILE")
+        .output()
+        .expect("run pgcrab admin check-config")
+}
+
+#[test]
+fn check_config_accepts_a_valid_file() {
+    let output = check_config("valid.toml");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("config is valid"), "{stdout}");
+}
+
+#[test]
+fn check_config_reports_each_error_and_fails() {
+    let output = check_config("invalid.toml");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    assert!(
+        stdout.contains("invalid [server] tcp_keepalive"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("duplicate [[users]] entry for user 'pgcrab'"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("shards     ok"), "{stdout}");
+    assert!(stdout.contains("2 errors"), "{stdout}");
+}
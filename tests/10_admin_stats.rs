@@ -0,0 +1,94 @@
+#[cfg(feature = "live-stats")]
+mod support;
+
+use std::process::Command;
+
+#[test]
+fn admin_stats_prints_json_with_the_flag() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pgcrab"))
+        .args(["admin", "stats", "--json"])
+        .output()
+        .expect("run pgcrab admin stats --json");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+
+    let json: serde_json::Value = serde_json::from_str(stdout.trim()).expect("valid JSON");
+    assert!(json["parse_cache_hits"].is_u64(), "{stdout}");
+}
+
+#[test]
+fn admin_stats_defaults_to_text() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pgcrab"))
+        .args(["admin", "stats"])
+        .output()
+        .expect("run pgcrab admin stats");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.starts_with("parse_cache_hits="), "{stdout}");
+}
+
+#[cfg(feature = "live-stats")]
+#[tokio::test]
+async fn admin_stats_reads_a_running_server() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .iter()
+        .find(|user| !user.admin)
+        .cloned()
+        .expect("expected a non-admin [[users]] entry");
+    let admin = cfg
+        .users
+        .iter()
+        .find(|user| user.admin)
+        .cloned()
+        .expect("expected an admin [[users]] entry");
+
+    let port = support::reserve_port(&shard.host);
+    let mut child = support::spawn_pgcrab(&shard.host, port);
+    support::wait_for_listen(&shard.host, port).await;
+
+    let conn_str = format!(
+        "host={} port={} user={} password={} dbname={}",
+        shard.host, port, user.username, user.password, shard.name
+    );
+    let (client, connection) = tokio_postgres::connect(&conn_str, tokio_postgres::NoTls)
+        .await
+        .expect("connect should succeed");
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    for _ in 0..3 {
+        client
+            .simple_query("select 1")
+            .await
+            .expect("select 1 should succeed");
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgcrab"))
+        .args(["admin", "stats", "--json", "--connect"])
+        .arg(format!("{}:{port}", shard.host))
+        .args(["--user", &admin.username])
+        .args(["--dbname", &shard.name])
+        .env("PGCRAB_ADMIN_PASSWORD", &admin.password)
+        .output()
+        .expect("run pgcrab admin stats --connect");
+    let _ = child.kill();
+    let _ = child.wait();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{stdout}{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let json: serde_json::Value = serde_json::from_str(stdout.trim()).expect("valid JSON");
+    assert!(json["parse_cache_hits"].as_u64().unwrap() > 0, "{stdout}");
+    assert!(json["queries_select"].as_u64().unwrap() >= 3, "{stdout}");
+}
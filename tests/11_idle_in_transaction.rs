@@ -0,0 +1,99 @@
+mod support;
+
+use std::fs;
+use std::time::Duration;
+
+use tokio::time::{sleep, timeout};
+use tokio_postgres::error::SqlState;
+use tokio_postgres::{NoTls, SimpleQueryMessage};
+
+#[tokio::test]
+async fn idle_transaction_is_closed_after_the_timeout() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .first()
+        .cloned()
+        .expect("expected at least one [[users]] entry");
+
+    let dir = tempfile::tempdir().expect("config dir");
+    let raw = support::config_text().expect("read pgcrab.toml");
+    let config_path = dir.path().join("pgcrab.toml");
+    fs::write(
+        &config_path,
+        format!("{raw}\n[server]\nidle_in_transaction_timeout = 300\n"),
+    )
+    .expect("write config");
+
+    let port = support::reserve_port(&shard.host);
+    let mut child =
+        support::spawn_pgcrab_with_config(&shard.host, port, config_path.to_str().unwrap());
+    support::wait_for_listen(&shard.host, port).await;
+
+    let conn_str = format!(
+        "host={} port={} user={} password={} dbname={}",
+        shard.host, port, user.username, user.password, shard.name
+    );
+    let (client, connection) = tokio_postgres::connect(&conn_str, NoTls)
+        .await
+        .expect("connect should succeed");
+    let connection = tokio::spawn(connection);
+
+    let messages = client
+        .simple_query("BEGIN; SELECT pg_backend_pid()")
+        .await
+        .expect("BEGIN should succeed");
+    let backend_pid = messages
+        .iter()
+        .find_map(|message| match message {
+            SimpleQueryMessage::Row(row) => row.get(0).map(str::to_string),
+            _ => None,
+        })
+        .expect("expected the backend pid");
+
+    // Idle past the timeout: pgcrab ends the connection with 25P03.
+    let closed = timeout(Duration::from_secs(5), connection)
+        .await
+        .expect("pgcrab should close the idle connection")
+        .unwrap();
+    let error = closed.expect_err("connection should end with an error");
+    assert_eq!(
+        error.code(),
+        Some(&SqlState::IDLE_IN_TRANSACTION_SESSION_TIMEOUT),
+        "{error}"
+    );
+
+    // And the backend that held the transaction is gone.
+    let shard_conn = format!(
+        "host={} port={} user={} password={} dbname={}",
+        shard.host, shard.port, shard.user, shard.password, shard.name
+    );
+    let (direct, direct_connection) = tokio_postgres::connect(&shard_conn, NoTls)
+        .await
+        .expect("connect to the shard");
+    tokio::spawn(direct_connection);
+
+    let query = format!("SELECT 1 FROM pg_stat_activity WHERE pid = {backend_pid}");
+    let mut gone = false;
+    for _ in 0..50 {
+        let rows = direct.simple_query(&query).await.expect("pg_stat_activity");
+        if !rows
+            .iter()
+            .any(|message| matches!(message, SimpleQueryMessage::Row(_)))
+        {
+            gone = true;
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+    assert!(gone, "backend {backend_pid} still connected");
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,53 @@
+mod support;
+
+use std::time::Duration;
+
+use pgcrab::config::shards::ShardRecord;
+use pgcrab::gateway::{GatewayPools, GatewaySession};
+use tokio::time::sleep;
+
+#[tokio::test]
+async fn on_connect_sets_up_every_session() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .first()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+
+    let record = |on_connect: &[&str]| ShardRecord {
+        min_connections: 1,
+        max_connections: 1,
+        checkout_timeout: Duration::from_secs(2),
+        on_connect: on_connect.iter().map(|s| s.to_string()).collect(),
+        ..shard.record()
+    };
+
+    let pools = GatewayPools::new(vec![record(&["SET search_path TO app"])]);
+    let pool = pools.get(&shard.name).expect("pool");
+
+    let mut first = GatewaySession::from_pool(&pool).await.expect("checkout");
+    let search_path = support::simple_query(first.backend(), "SHOW search_path").await;
+    assert_eq!(search_path, "app");
+    first.reset().await.expect("reset");
+    drop(first);
+
+    for _ in 0..50 {
+        if pool.stats().await.idle == 1 {
+            break;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+
+    // DISCARD ALL on release would have put it back; on_connect ran again.
+    let mut second = GatewaySession::from_pool(&pool).await.expect("checkout");
+    let search_path = support::simple_query(second.backend(), "SHOW search_path").await;
+    assert_eq!(search_path, "app");
+    drop(second);
+
+    // A failing statement fails the checkout.
+    let pools = GatewayPools::new(vec![record(&["SET no_such_setting = 1"])]);
+    let pool = pools.get(&shard.name).expect("pool");
+    assert!(GatewaySession::from_pool(&pool).await.is_err());
+}
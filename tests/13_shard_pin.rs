@@ -0,0 +1,70 @@
+mod support;
+
+use tokio_postgres::error::SqlState;
+use tokio_postgres::{NoTls, SimpleQueryMessage};
+
+#[tokio::test]
+async fn pinned_clients_stay_on_their_shard() {
+    support::ensure_shards_accessible().await;
+    let cfg = support::load_config().expect("load pgcrab.toml");
+    let shard = cfg
+        .shards
+        .last()
+        .cloned()
+        .expect("expected at least one [[shards]] entry");
+    let user = cfg
+        .users
+        .first()
+        .cloned()
+        .expect("expected at least one [[users]] entry");
+
+    let port = support::reserve_port(&shard.host);
+    let mut child = support::spawn_pgcrab(&shard.host, port);
+    support::wait_for_listen(&shard.host, port).await;
+
+    let conn_str = |pin: &str| {
+        format!(
+            "host={} port={} user={} password={} dbname={} options='-c pgcrab.shard={pin}'",
+            shard.host, port, user.username, user.password, shard.name
+        )
+    };
+
+    let (client, connection) = tokio_postgres::connect(&conn_str(&shard.name), NoTls)
+        .await
+        .expect("connect should succeed");
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    // Every shard is its own database, so each checkout reports where it ran.
+    for _ in 0..20 {
+        let messages = client
+            .simple_query("SELECT current_database()")
+            .await
+            .expect("query should succeed");
+        let database = messages
+            .iter()
+            .find_map(|message| match message {
+                SimpleQueryMessage::Row(row) => row.get(0).map(str::to_string),
+                _ => None,
+            })
+            .expect("expected a row");
+        assert_eq!(database, shard.name);
+    }
+
+    let (client, connection) = tokio_postgres::connect(&conn_str("no_such_shard"), NoTls)
+        .await
+        .expect("connect should succeed");
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let error = client
+        .simple_query("SELECT 1")
+        .await
+        .expect_err("an unknown shard can't run queries");
+    assert_eq!(error.code(), Some(&SqlState::UNDEFINED_OBJECT), "{error}");
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
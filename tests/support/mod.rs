@@ -1,5 +1,15 @@
+use bytes::{BufMut, BytesMut};
+use pgcrab::backend::BackendConnection;
+use pgcrab::config::shards::ShardRecord;
+use secrecy::SecretString;
 use serde::Deserialize;
-use std::{env, fs, net::TcpListener, path::PathBuf, process::Command, time::Duration};
+use std::{
+    env, fs,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
 use tokio::sync::OnceCell;
 use tokio::time::sleep;
 use tokio_postgres::NoTls;
@@ -26,8 +36,13 @@ pub fn reserve_port(host: &str) -> u16 {
 
 #[allow(dead_code)]
 pub fn spawn_pgcrab(host: &str, port: u16) -> std::process::Child {
-    let exe = env!("CARGO_BIN_EXE_pgcrab");
     let config_path = std::env::var("PGCRAB_CONFIG_FILE").unwrap_or_else(|_| "pgcrab.toml".into());
+    spawn_pgcrab_with_config(host, port, &config_path)
+}
+
+#[allow(dead_code)]
+pub fn spawn_pgcrab_with_config(host: &str, port: u16, config_path: &str) -> std::process::Child {
+    let exe = env!("CARGO_BIN_EXE_pgcrab");
 
     Command::new(exe)
         .env("PGCRAB_HOST", host)
@@ -49,6 +64,24 @@ pub async fn wait_for_listen(host: &str, port: u16) {
     panic!("pgcrab did not start listening on {addr}");
 }
 
+#[allow(dead_code)]
+pub async fn wait_for_unix_listen(path: &Path) {
+    for _ in 0..50 {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return;
+        }
+        sleep(Duration::from_millis(50)).await;
+    }
+    panic!("pgcrab did not start listening on {}", path.display());
+}
+
+#[allow(dead_code)]
+pub fn config_text() -> Result<String, String> {
+    let config_path = config_path()?;
+    fs::read_to_string(&config_path)
+        .map_err(|e| format!("failed to read {}: {e}", config_path.display()))
+}
+
 pub fn load_config() -> Result<ConfigFile, String> {
     let config_path = config_path()?;
     let raw = fs::read_to_string(&config_path)
@@ -100,7 +133,47 @@ async fn check_shards() -> Result<(), String> {
     Ok(())
 }
 
-fn config_path() -> Result<PathBuf, String> {
+/// Runs a simple query and returns the first column of the last DataRow.
+#[allow(dead_code)]
+pub async fn simple_query(backend: &mut BackendConnection, sql: &str) -> String {
+    let mut frame = BytesMut::new();
+    frame.put_u8(b'Q');
+    frame.put_u32((4 + sql.len() + 1) as u32);
+    frame.extend_from_slice(sql.as_bytes());
+    frame.put_u8(0);
+    backend.send(&frame).await.expect("send query");
+
+    let mut last_value = None;
+    loop {
+        while backend.buffer().len() >= 5 {
+            let buf = backend.buffer();
+            let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+            if buf.len() < 1 + len {
+                break;
+            }
+            match buf[0] {
+                b'D' => {
+                    let value_len = i32::from_be_bytes([buf[7], buf[8], buf[9], buf[10]]) as usize;
+                    let value = &buf[11..11 + value_len];
+                    last_value = Some(String::from_utf8_lossy(value).into_owned());
+                }
+                b'E' => panic!("query failed: {sql}"),
+                b'Z' => {
+                    backend.consume(1 + len);
+                    return last_value.expect("expected a row");
+                }
+                _ => {}
+            }
+            backend.consume(1 + len);
+        }
+
+        let n = backend.read().await.expect("read");
+        assert!(n > 0, "backend closed");
+    }
+}
+
+#[allow(dead_code)]
+pub fn config_path() -> Result<PathBuf, String> {
     if let Ok(path) = env::var("PGCRAB_CONFIG_FILE") {
         return Ok(PathBuf::from(path));
     }
@@ -113,6 +186,7 @@ pub struct ConfigFile {
     #[serde(default)]
     pub shards: Vec<ShardEntry>,
     #[serde(default)]
+    #[allow(dead_code)]
     pub users: Vec<UserEntry>,
 }
 
@@ -125,9 +199,27 @@ pub struct ShardEntry {
     pub password: String,
 }
 
+impl ShardEntry {
+    /// This shard's connection details, with everything else defaulted.
+    #[allow(dead_code)]
+    pub fn record(&self) -> ShardRecord {
+        ShardRecord {
+            shard_name: self.name.clone(),
+            host: self.host.clone(),
+            port: self.port,
+            user: self.user.clone(),
+            password: SecretString::new(self.password.clone().into_boxed_str()),
+            ..ShardRecord::default()
+        }
+    }
+}
+
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Clone)]
 pub struct UserEntry {
     #[serde(alias = "name")]
     pub username: String,
     pub password: String,
+    #[serde(default)]
+    pub admin: bool,
 }